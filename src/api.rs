@@ -1,48 +1,134 @@
 use anyhow::{Context, Result};
 
-use log::{error, info, warn};
+use log::{debug, error, info, warn};
 
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::collections::HashSet;
+use std::sync::Arc;
 
 use futures::stream::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
 
 pub mod client;
+pub mod judilibre;
+pub mod output;
 /// This is the module containing the datatypes
 /// for the API
 pub mod piste;
+pub mod schema;
+pub mod texts;
 
+use crate::concurrency::Concurrency;
+use crate::logging::progress_bar;
+use crate::shutdown;
 use client::{AuthenticatedClient, PageQuery, get_full_text, get_search_result};
+use output::ResultWriter;
+use texts::TextWriter;
+
+/// Items waiting for the writer thread, beyond which the tasks
+/// sending them wait
+const WRITER_QUEUE: usize = 64;
+
+/// The output of a run, owned by a thread of its own: the tasks send
+/// it what to write over a bounded channel, and wait while it is
+/// behind, instead of sharing the output behind a lock
+struct WriterThread<T> {
+    tx: tokio::sync::mpsc::Sender<T>,
+    handle: tokio::task::JoinHandle<Result<()>>,
+}
+
+impl<T: Send + 'static> WriterThread<T> {
+    /// Start the thread, which calls `write` on every item sent to it,
+    /// in the order they are received, then `finish` once every sender
+    /// is dropped. A failed write is logged, and the first error is
+    /// returned by [`WriterThread::finish`].
+    fn spawn<W, F, G>(mut output: W, mut write: F, finish: G) -> Self
+    where
+        W: Send + 'static,
+        F: FnMut(&mut W, T) -> Result<()> + Send + 'static,
+        G: FnOnce(W) -> Result<()> + Send + 'static,
+    {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(WRITER_QUEUE);
+        let handle = tokio::task::spawn_blocking(move || {
+            let mut failed = None;
+            while let Some(item) = rx.blocking_recv() {
+                if let Err(e) = write(&mut output, item) {
+                    error!("Error: {:#}", e);
+                    failed.get_or_insert(e);
+                }
+            }
+            finish(output)?;
+            failed.map_or(Ok(()), Err)
+        });
+        WriterThread { tx, handle }
+    }
+
+    fn sender(&self) -> tokio::sync::mpsc::Sender<T> {
+        self.tx.clone()
+    }
+
+    /// Wait for the items sent so far to be written, and finish the
+    /// output. The other senders must have been dropped.
+    async fn finish(self) -> Result<()> {
+        drop(self.tx);
+        self.handle.await.context("The writer thread panicked")?
+    }
+}
+
+/// The chronological id of a search result, which identifies the
+/// document whatever its version
+fn result_cid(result: &piste::SearchResult) -> Option<&str> {
+    result.titles.first().map(|title| title.cid.as_str())
+}
+
+/// The results whose cid was not seen yet, adding their cids to
+/// `seen`. Results shift between the pages of a query while it is
+/// paginated, so the same document can be on two pages. Results
+/// without a cid are always kept.
+fn new_results<'a>(
+    results: &'a [piste::SearchResult],
+    seen: &mut HashSet<String>,
+) -> Vec<&'a piste::SearchResult> {
+    results
+        .iter()
+        .filter(|result| result_cid(result).is_none_or(|cid| seen.insert(cid.to_string())))
+        .collect()
+}
 
-type SharedBufferedWriter<T> = Arc<Mutex<T>>;
+/// Start the thread writing the results of the pages it is sent
+/// with `writer`, skipping the ones already written (see [`new_results`])
+fn spawn_result_writer(writer: Box<dyn ResultWriter>) -> WriterThread<Vec<piste::SearchResult>> {
+    // every document is written once, even if it is on several pages
+    let mut seen = HashSet::new();
+    WriterThread::spawn(
+        writer,
+        move |writer: &mut Box<dyn ResultWriter>, page: Vec<piste::SearchResult>| {
+            let results = new_results(&page, &mut seen);
+            if results.len() < page.len() {
+                debug!("Skipping {} results already written", page.len() - results.len());
+            }
+            results.into_iter().try_for_each(|doc| writer.write(doc))
+        },
+        |writer| writer.finish(),
+    )
+}
 
-async fn get_page_and_write<T>(
+/// Send the results of a page to the writer, returning the number
+/// of results of the page
+async fn get_page_and_write(
     aclient: &AuthenticatedClient,
-    out: SharedBufferedWriter<T>,
+    out: &tokio::sync::mpsc::Sender<Vec<piste::SearchResult>>,
     pq: &PageQuery,
-) -> Result<usize>
-where
-    T: std::io::Write + std::marker::Send + 'static,
-{
+) -> Result<usize> {
     let res = get_search_result(aclient, pq)
         .await
         .context("Unable to serialize search result")?;
     let total = res.total_result_number;
     info!("Total Results: {} for {:?}", total, pq);
-    if let Ok(mut writer) = out.lock() {
-        for doc in res.results.iter() {
-            serde_json::to_writer(&mut *writer, doc)
-                .context("Unable to serialize search result")?;
-            writer.write_all(b"\n").context("Unable to write newline")?;
-        }
-        Ok(res.results.len())
-    } else {
-        error!("Unable to lock the writer for {:?}", pq);
-        return Err(anyhow::anyhow!("Unable to lock the writer"));
-    }
+    let count = res.results.len();
+    out.send(res.results)
+        .await
+        .map_err(|_| anyhow::anyhow!("The writer stopped"))?;
+    Ok(count)
 }
 
 /// A PageQuery can only be used to get at most 100 results.
@@ -87,7 +173,7 @@ async fn compute_pagination(
                 page: page as u8, // this cast is safe because page <= 100
                 start_year: pq.start_year,
                 end_year: pq.end_year,
-                fond: pq.fond.clone(),
+                fond: pq.fond,
             }
         })
         .collect::<Vec<_>>();
@@ -110,7 +196,7 @@ fn split_year_ranges(pq: &PageQuery, step_size: usize) -> impl Iterator<Item = P
             page: pq.page,
             start_year: Some(start),
             end_year: Some(end),
-            fond: pq.fond.clone(),
+            fond: pq.fond,
         }
     })
 }
@@ -127,6 +213,29 @@ fn compute_step_size(total: u64) -> usize {
     (step_size / 3).max(1) // to be extra safe divide by 3
 }
 
+/// A year range of a query plan, and the number of pages
+/// of results requested for it
+#[derive(Debug, Clone)]
+pub struct PlannedRange {
+    pub start_year: Option<u64>,
+    pub end_year: Option<u64>,
+    pub pages: usize,
+    /// Why the pagination of the range could not be computed, its
+    /// results are then missing (e.g. more than 100 pages)
+    pub error: Option<String>,
+}
+
+/// The requests made to get all the results of a query
+#[derive(Debug, Clone)]
+pub struct QueryPlan {
+    /// Number of results claimed by the API
+    pub total: u64,
+    /// The year ranges the query is split into, in chronological order
+    pub ranges: Vec<PlannedRange>,
+    /// The page requests to send
+    pub queries: Vec<PageQuery>,
+}
+
 /// Compute a probably correct list of queries
 /// to execute in order to get all the results.
 ///
@@ -134,10 +243,11 @@ fn compute_step_size(total: u64) -> usize {
 /// 2. It uses it to compute a step size
 /// 3. For every year range, it computes a pagination
 /// 4. It returns a list of PageQuery
-async fn compute_query_plan(
+pub async fn compute_query_plan(
     aclient: &AuthenticatedClient,
     pq: &PageQuery,
-) -> Result<(u64, Vec<PageQuery>)> {
+    requests: usize,
+) -> Result<QueryPlan> {
     info!("Computing query plan for {:?}", pq);
     // get the total number of results
     let res = get_search_result(aclient, pq).await?;
@@ -150,59 +260,75 @@ async fn compute_query_plan(
 
     // The list of queries to run in the end
     let mut queries = vec![];
+    let mut ranges = vec![];
 
     // create a stream of futures responsible
     // for computing the pagination for each year range
-    // and execute them in parallel by using at most 10
-    // concurrent tasks
+    // and execute them in parallel by using at most
+    // `requests` concurrent tasks
     let stream = futures::stream::iter(split_year_ranges(pq, step_size)).map(|q| {
         let aclient = &aclient;
         async move {
             // compute the pagination for each year range
-            let pqs = compute_pagination(aclient, &q).await?;
-            Ok(pqs)
+            let pqs = compute_pagination(aclient, &q).await;
+            (q, pqs)
         }
     });
 
     // execute the stream using ordered concurrency
-    // with a limit of 10 concurrent tasks
-    let results = stream
-        .buffer_unordered(10)
-        .collect::<Vec<Result<Vec<PageQuery>>>>()
+    let mut results = stream
+        .buffer_unordered(requests)
+        .collect::<Vec<(PageQuery, Result<Vec<PageQuery>>)>>()
         .await;
-    info!(
-        "Finished computing pagination, {} queries will be run",
-        results.len()
-    );
-    for res in results {
+    results.sort_by_key(|(q, _)| q.start_year);
+    for (q, res) in results {
+        let mut range = PlannedRange {
+            start_year: q.start_year,
+            end_year: q.end_year,
+            pages: 0,
+            error: None,
+        };
         match res {
             Ok(pqs) => {
+                range.pages = pqs.len();
                 queries.extend(pqs);
             }
             Err(e) => {
                 error!("Could not correctly compute pagination! {}", e);
+                range.error = Some(e.to_string());
             }
         }
+        ranges.push(range);
     }
+    info!(
+        "Finished computing pagination, {} queries will be run",
+        queries.len()
+    );
 
-    Ok((total, queries))
+    Ok(QueryPlan {
+        total,
+        ranges,
+        queries,
+    })
 }
 
-async fn store_all_to_file<T>(
+async fn store_all_to_file(
     aclient: &AuthenticatedClient,
-    out: SharedBufferedWriter<T>,
+    out: tokio::sync::mpsc::Sender<Vec<piste::SearchResult>>,
     bar: &ProgressBar,
     pqs: &[PageQuery],
-) -> Result<()>
-where
-    T: std::io::Write + std::marker::Send + 'static,
-{
+    requests: usize,
+) -> Result<()> {
     let stream = futures::stream::iter(pqs).map(|pq| {
         let aclient = &aclient;
-        let out = out.clone();
+        let out = &out;
         let bar = &bar;
         async move {
-            match get_page_and_write(aclient, out, &pq).await {
+            // the pages already requested are still written
+            if shutdown::is_interrupted() {
+                return Ok(());
+            }
+            match get_page_and_write(aclient, out, pq).await {
                 Ok(res_count) => {
                     bar.inc(res_count as u64);
                     Ok(())
@@ -216,27 +342,31 @@ where
     });
 
     // execute the stream using ordered concurrency
-    // with a limit of 20 concurrent tasks
     let _ = stream
-        .buffer_unordered(20)
+        .buffer_unordered(requests)
         .collect::<Vec<Result<()>>>()
         .await;
     Ok(())
 }
 
-pub async fn call_search_endpoint<T>(
+/// Write all the results of the query with `writer`
+/// (see [`output::result_writer`]), sending at most
+/// [`Concurrency::requests`] requests at once
+pub async fn call_search_endpoint(
     aclient: &AuthenticatedClient,
-    writer: T,
+    writer: Box<dyn ResultWriter>,
     pq: &PageQuery,
-) -> Result<()>
-where
-    T: std::io::Write + std::marker::Send + 'static,
-{
-    let out = Arc::new(Mutex::new(writer));
+    concurrency: &Concurrency,
+) -> Result<()> {
+    let out = spawn_result_writer(writer);
 
     // get the total number of results
     // and compute the pagination
-    let (total, pqs) = compute_query_plan(aclient, pq)
+    let QueryPlan {
+        total,
+        queries: pqs,
+        ..
+    } = compute_query_plan(aclient, pq, concurrency.requests)
         .await
         .context("Unable to compute query plan")?;
 
@@ -246,7 +376,7 @@ where
         pqs.len()
     );
 
-    let bar = ProgressBar::new(0);
+    let bar = progress_bar(0);
     bar.set_style(
         ProgressStyle::default_bar()
             .template("{spinner:.green} {msg} [{elapsed_precise}] {wide_bar} {pos}/{len} ({eta})")
@@ -263,15 +393,22 @@ where
         pqs.len()
     ));
 
-    store_all_to_file(aclient, out.clone(), &bar, &pqs)
+    store_all_to_file(aclient, out.sender(), &bar, &pqs, concurrency.requests)
         .await
         .context("Unable to store results to file")?;
 
-    Ok(())
+    out.finish().await.context("Unable to finish writing results")
 }
 
-///
-pub async fn get_full_texts<R>(aclient: AuthenticatedClient, dir: &PathBuf, reader: R) -> Result<()>
+/// Read search results (one JSON object per line) from `reader`
+/// and store the full text of each of them with `writer` (see
+/// [`texts::text_writer`]), with [`Concurrency::requests`] workers.
+pub async fn get_full_texts<R>(
+    aclient: AuthenticatedClient,
+    writer: Box<dyn TextWriter>,
+    reader: R,
+    concurrency: &Concurrency,
+) -> Result<()>
 where
     R: std::io::Read + std::marker::Send + 'static,
 {
@@ -279,7 +416,7 @@ where
     use std::io::BufRead;
     let reader = std::io::BufReader::new(reader);
 
-    let pb = ProgressBar::new(0);
+    let pb = progress_bar(0);
     pb.set_style(
         ProgressStyle::default_bar()
             .template("{spinner:.green} {msg} [{elapsed_precise}] {wide_bar} {pos}/{len} ({eta})")
@@ -294,35 +431,41 @@ where
     let (tx, rx): (Sender<String>, Receiver<String>) = bounded(50000);
 
     let aclient = Arc::new(aclient);
+    let out = WriterThread::spawn(
+        writer,
+        |writer: &mut Box<dyn TextWriter>, (cid, search_result, text): (String, piste::SearchResult, String)| {
+            writer.write(&cid, &search_result, &text)?;
+            info!("Wrote full text of {}", cid);
+            Ok(())
+        },
+        |writer| writer.finish(),
+    );
 
     info!("Starting workers");
     let mut handles = vec![];
-    for _ in 0..5 {
+    for _ in 0..concurrency.requests.max(1) {
         let aclient = aclient.clone();
         let arx = rx.clone();
         let pb = pb.clone();
-        let dir = dir.clone();
+        let out = out.sender();
         let handle = tokio::task::spawn(async move {
+            // the texts already requested are still written
             while let Ok(txt) = arx.recv() {
+                if shutdown::is_interrupted() {
+                    break;
+                }
                 if let Ok(search_result) = serde_json::from_str::<piste::SearchResult>(&txt) {
                     if let Some(fond) = search_result.fond {
-                        if let Some(cid) = search_result.titles.get(0).map(|t| t.cid.clone()) {
+                        if let Some(cid) = search_result.titles.first().map(|t| t.cid.clone()) {
                             info!("Got cid {}", cid);
                             match get_full_text(&aclient, &cid, &fond).await {
                                 Ok(text) => {
                                     pb.inc(1);
                                     info!("Got full text for {}", cid);
-                                    let filename = format!("{}.txt", cid);
-                                    let filepath = dir.join(filename);
-                                    let file =
-                                        File::create(&filepath).expect("Unable to create file");
-                                    let mut writer = BufWriter::new(file);
-                                    writer
-                                        .write_all(text.as_bytes())
-                                        .expect("Unable to write full text");
-                                    writer.flush().expect("Unable to flush writer");
-
-                                    info!("Wrote full text to {}", filepath.display());
+                                    if out.send((cid, search_result, text)).await.is_err() {
+                                        error!("Error: the writer stopped");
+                                        break;
+                                    }
                                 }
                                 Err(e) => {
                                     error!("Error: {}", e);
@@ -342,18 +485,35 @@ where
 
     info!("Workers started");
     let mut count = 0;
+    // the full text of a document is fetched once, even if it
+    // is on several lines of the input
+    let mut seen = HashSet::new();
+    let mut duplicates = 0;
     // read the input file line by line
     // and send the cids to the channel
     for line in reader.lines() {
+        if shutdown::is_interrupted() {
+            warn!("Interrupted, the texts from line {} are not requested", count + 1);
+            break;
+        }
         count += 1;
         pb.set_length(count as u64);
         let line = line?;
         let cid = line.trim().to_string();
-        if !cid.is_empty() {
-            tx.send(cid).context("Unable to send cid to channel")?;
+        if cid.is_empty() {
+            continue;
+        }
+        if let Ok(result) = serde_json::from_str::<piste::SearchResult>(&cid)
+            && let Some(result_cid) = result_cid(&result)
+            && !seen.insert(result_cid.to_string())
+        {
+            duplicates += 1;
+            pb.inc(1);
+            continue;
         }
+        tx.send(cid).context("Unable to send cid to channel")?;
     }
-    info!("Finished reading input file");
+    info!("Finished reading input file, {} duplicates skipped", duplicates);
 
     // close the channel
     // this will signal the workers to stop
@@ -365,5 +525,65 @@ where
     }
     info!("All workers finished");
 
-    Ok(())
+    out.finish().await.context("Unable to finish writing full texts")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_results() {
+        let result = |cid: &str| -> piste::SearchResult {
+            serde_json::from_value(serde_json::json!({
+                "titles": if cid.is_empty() { vec![] } else {
+                    vec![serde_json::json!({"title": "Décision", "id": format!("{}-v2", cid), "cid": cid})]
+                },
+            }))
+            .unwrap()
+        };
+        let mut seen = HashSet::new();
+        let first = [result("CETATEXT1"), result("CETATEXT2")];
+        assert_eq!(new_results(&first, &mut seen).len(), 2);
+        // the results shifted: CETATEXT2 is also on the next page
+        let second = [result("CETATEXT2"), result("CETATEXT3"), result(""), result("")];
+        let kept: Vec<_> = new_results(&second, &mut seen).into_iter().map(result_cid).collect();
+        assert_eq!(kept, [Some("CETATEXT3"), None, None]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_writer_thread() {
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        let out = WriterThread::spawn(
+            vec![],
+            |written: &mut Vec<u32>, item: u32| {
+                if item == 13 {
+                    return Err(anyhow::anyhow!("unlucky {}", item));
+                }
+                written.push(item);
+                Ok(())
+            },
+            move |written| Ok(done_tx.send(written)?),
+        );
+        let tasks: Vec<_> = (0..4)
+            .map(|task| {
+                let out = out.sender();
+                tokio::spawn(async move {
+                    for item in (0..100).filter(|item| item % 4 == task) {
+                        out.send(item).await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        // the output is finished despite the failed write, which is reported
+        let error = out.finish().await.unwrap_err();
+        assert_eq!(error.to_string(), "unlucky 13");
+        let mut written = done_rx.recv().unwrap();
+        written.sort();
+        assert_eq!(written.len(), 99);
+        assert!(!written.contains(&13));
+    }
 }
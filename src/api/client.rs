@@ -11,13 +11,82 @@ use serde_json::json;
 
 use log::{debug, error, info, warn};
 
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
 use crate::api::piste::*;
+use crate::api::schema::{SchemaDrift, SchemaMode};
+use crate::http::HttpConfig;
+
+/// How many times a request rate limited by PISTE (HTTP 429)
+/// is sent again before giving up
+pub const RATE_LIMIT_RETRIES: usize = 5;
+
+/// How long to wait before sending again a rate limited request
+/// whose response has no (valid) `Retry-After` header
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// Waits longer than this are shortened, a wrong header should
+/// not stall a crawl for hours
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// The wait asked by a `Retry-After` header: a number of seconds,
+/// or an HTTP date (e.g. `Wed, 21 Oct 2015 07:28:00 GMT`)
+pub fn parse_retry_after(value: &str, now: chrono::DateTime<chrono::Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.to_utc() - now).to_std().unwrap_or(Duration::ZERO))
+}
+
+/// The time spent waiting for the rate limits of the API, shared
+/// by all the requests of a client
+#[derive(Debug, Default)]
+pub struct RateLimitStats {
+    /// Number of responses with the status 429
+    limited: AtomicU64,
+    /// Milliseconds spent waiting before sending them again
+    waited: AtomicU64,
+}
+
+impl RateLimitStats {
+    fn record(&self, wait: Duration) {
+        self.limited.fetch_add(1, Ordering::Relaxed);
+        self.waited.fetch_add(wait.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Number of requests that were rate limited
+    pub fn limited(&self) -> u64 {
+        self.limited.load(Ordering::Relaxed)
+    }
+
+    /// Total time spent waiting for the rate limits
+    pub fn waited(&self) -> Duration {
+        Duration::from_millis(self.waited.load(Ordering::Relaxed))
+    }
+
+    /// A line for the end of a run, if the API throttled the client
+    pub fn summary(&self) -> Option<String> {
+        (self.limited() > 0).then(|| {
+            format!(
+                "Throttled for {} seconds ({} requests rate limited by the API)",
+                self.waited().as_secs(),
+                self.limited()
+            )
+        })
+    }
+}
 
 /// An authenticated client to the API. This is the
 /// data structure that should be used to interact with the API.
 pub struct AuthenticatedClient {
     client: Client,
     token: AuthResponse,
+    rate_limits: Arc<RateLimitStats>,
+    schema_drift: Arc<SchemaDrift>,
 }
 
 /// Authenticate to the API to get a token
@@ -68,16 +137,82 @@ impl AuthenticatedClient {
     }
 
     pub fn from_token(client: Client, token: AuthResponse) -> Self {
-        AuthenticatedClient { client, token }
+        AuthenticatedClient {
+            client,
+            token,
+            rate_limits: Default::default(),
+            schema_drift: Default::default(),
+        }
     }
 
     pub async fn from_secret(id: &str, secret: &str) -> Result<Self> {
+        Self::from_config(&HttpConfig::default(), id, secret).await
+    }
+
+    /// Authenticate using a client built from the given `HttpConfig`
+    /// (proxy, certificates, timeouts).
+    pub async fn from_config(config: &HttpConfig, id: &str, secret: &str) -> Result<Self> {
         info!("Authenticating to the API");
-        let client = Client::new();
+        let client = config.build_client()?;
         let token = authenticate(&client, id, secret)
             .await
             .context("Unable to authenticate")?;
-        Ok(AuthenticatedClient { client, token })
+        Ok(AuthenticatedClient::from_token(client, token))
+    }
+
+    /// The statistics of the rate limits met by the requests of this
+    /// client, which can be kept after the client is moved
+    pub fn rate_limits(&self) -> Arc<RateLimitStats> {
+        self.rate_limits.clone()
+    }
+
+    /// What to do with the fields of the search responses unknown to
+    /// [`SearchResponse`] (see [`SchemaMode`])
+    pub fn set_schema_mode(&mut self, mode: SchemaMode) {
+        self.schema_drift = Arc::new(SchemaDrift::new(mode));
+    }
+
+    /// The fields unknown to [`SearchResponse`] met by the search
+    /// responses of this client, which can be kept after the client is
+    /// moved
+    pub fn schema_drift(&self) -> Arc<SchemaDrift> {
+        self.schema_drift.clone()
+    }
+
+    /// Send a request, waiting and sending it again when the API
+    /// answers that the client is rate limited (HTTP 429), as long as
+    /// the `Retry-After` header asks for
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let mut attempt = 0;
+        loop {
+            // the bodies of the requests to the API are never streamed
+            let Some(current) = request.try_clone() else {
+                return Ok(request.send().await?);
+            };
+            let response = current.send().await?;
+            if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS
+                || attempt >= RATE_LIMIT_RETRIES
+            {
+                return Ok(response);
+            }
+            attempt += 1;
+            let wait = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| parse_retry_after(value, chrono::Utc::now()))
+                .unwrap_or(DEFAULT_RETRY_AFTER)
+                .min(MAX_RETRY_AFTER);
+            warn!(
+                "Rate limited by the API on {}, retrying in {:?} ({}/{})",
+                response.url().path(),
+                wait,
+                attempt,
+                RATE_LIMIT_RETRIES
+            );
+            self.rate_limits.record(wait);
+            tokio::time::sleep(wait).await;
+        }
     }
 
     pub async fn post_json_request(&self, entpoint: &str, body: &str) -> Result<reqwest::Response> {
@@ -102,13 +237,18 @@ impl AuthenticatedClient {
             .post(url)
             .headers(headers)
             .body(body.to_string());
-        let response = request.send().await?;
-        Ok(response)
+        self.send(request).await
     }
 
     pub async fn get_request(&self, endpoint: &str) -> Result<reqwest::Response> {
+        self.get_request_at(API_URL, endpoint).await
+    }
+
+    /// GET request to another API of PISTE (e.g. Judilibre), which
+    /// accepts the same token
+    pub async fn get_request_at(&self, api_url: &str, endpoint: &str) -> Result<reqwest::Response> {
         let mut headers = reqwest::header::HeaderMap::new();
-        let url = format!("{}{}", API_URL, endpoint);
+        let url = format!("{}{}", api_url, endpoint);
         headers.insert(
             reqwest::header::AUTHORIZATION,
             reqwest::header::HeaderValue::from_str(&format!("Bearer {}", self.token.access_token))?,
@@ -117,14 +257,13 @@ impl AuthenticatedClient {
             reqwest::header::CONTENT_TYPE,
             reqwest::header::HeaderValue::from_static("application/json"),
         );
-        let response = self.client.get(url).headers(headers).send().await?;
-        Ok(response)
+        self.send(self.client.get(url).headers(headers)).await
     }
 }
 
 pub async fn ping_api(aclient: &AuthenticatedClient, endpoint: &str) -> Result<()> {
     info!("Pinging the API {endpoint}");
-    let response = aclient.get_request(&endpoint).await?;
+    let response = aclient.get_request(endpoint).await?;
     if response.status().is_success() {
         Ok(())
     } else {
@@ -170,11 +309,11 @@ impl From<&PageQuery> for SearchQuery {
             _ => FilterType::PublicationDate,
         };
 
-        let filters = if pq.start_year.is_some() && pq.end_year.is_some() {
+        let filters = if let (Some(start_year), Some(end_year)) = (pq.start_year, pq.end_year) {
             Some(vec![Filter {
                 dates: DateRange {
-                    start: format!("{}-01-01", pq.start_year.unwrap()),
-                    end: format!("{}-01-01", pq.end_year.unwrap()),
+                    start: format!("{}-01-01", start_year),
+                    end: format!("{}-01-01", end_year),
                 },
                 facette: sort_facette,
             }])
@@ -229,8 +368,7 @@ pub async fn get_search_result(
 
     if response.status().is_success() {
         let text = response.text().await?;
-        let res: SearchResponse = serde_json::from_str(&text)?;
-        Ok(res)
+        aclient.schema_drift.parse("search", &text)
     } else {
         let status = response.status();
         let text = response.text().await?;
@@ -286,3 +424,30 @@ pub async fn get_full_text(
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_after() {
+        let now = chrono::DateTime::parse_from_rfc3339("2015-10-21T07:27:30Z").unwrap().to_utc();
+        assert_eq!(parse_retry_after("120", now), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon", now), None);
+
+        let stats = RateLimitStats::default();
+        assert_eq!(stats.summary(), None);
+        stats.record(Duration::from_secs(30));
+        stats.record(Duration::from_millis(2500));
+        assert_eq!(stats.limited(), 2);
+        assert_eq!(
+            stats.summary().unwrap(),
+            "Throttled for 32 seconds (2 requests rate limited by the API)"
+        );
+    }
+}
@@ -0,0 +1,279 @@
+/// Client of the Judilibre API of the Cour de cassation, which is also
+/// hosted by PISTE and accepts the same token as the Légifrance API.
+/// Judilibre publishes richer metadata than the dumps for the decisions
+/// of the Cour de cassation and of the cours d'appel: the themes (the
+/// "matières" of the decision), the solution ("Cassation", "Rejet", ...)
+/// and the zones of the text (introduction, exposé du litige, moyens,
+/// motivations, dispositif, annexes).
+///
+/// The API is documented at
+/// <https://github.com/Cour-de-cassation/judilibre-search/blob/dev/public/JUDILIBRE-public.json>
+///
+/// As for the Légifrance API, only the parts we need are implemented.
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use log::{debug, warn};
+
+use std::collections::BTreeMap;
+
+use crate::api::client::AuthenticatedClient;
+
+/// The endpoint for the Judilibre API (production).
+pub const JUDILIBRE_URL: &str = "https://api.piste.gouv.fr/cassation/judilibre/v1.0";
+
+/// The courts whose decisions are published by Judilibre.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Jurisdiction {
+    /// Cour de cassation
+    Cc,
+    /// Cours d'appel
+    Ca,
+    /// Tribunaux judiciaires
+    Tj,
+}
+
+impl Jurisdiction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Jurisdiction::Cc => "cc",
+            Jurisdiction::Ca => "ca",
+            Jurisdiction::Tj => "tj",
+        }
+    }
+}
+
+/// A span of the text of a decision, in characters.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Zone {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A decision as returned by the `/search` and `/decision` endpoints.
+/// The search results do not contain the zones.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct JudilibreDecision {
+    /// The identifier of the decision in Judilibre
+    pub id: String,
+    /// The court (e.g. "cc")
+    #[serde(default)]
+    pub jurisdiction: Option<String>,
+    /// The chamber (e.g. "civ1", "soc", "cr")
+    #[serde(default)]
+    pub chamber: Option<String>,
+    /// The formation (e.g. "f", "fs", "fp")
+    #[serde(default)]
+    pub formation: Option<String>,
+    /// The main number of the decision, the number of the
+    /// "pourvoi" for the Cour de cassation (e.g. "21-12.345")
+    #[serde(default)]
+    pub number: Option<String>,
+    /// All the numbers of the joined cases
+    #[serde(default)]
+    pub numbers: Vec<String>,
+    #[serde(default)]
+    pub ecli: Option<String>,
+    /// The date of the decision in the format YYYY-MM-DD
+    #[serde(default)]
+    pub decision_date: Option<String>,
+    /// The solution (e.g. "cassation", "rejet")
+    #[serde(default)]
+    pub solution: Option<String>,
+    /// The levels of publication (e.g. "b" for the Bulletin)
+    #[serde(default)]
+    pub publication: Vec<String>,
+    /// The themes ("matières") of the decision
+    #[serde(default)]
+    pub themes: Vec<String>,
+    /// The zones of the text, by name (e.g. "motivations")
+    #[serde(default)]
+    pub zones: Option<BTreeMap<String, Vec<Zone>>>,
+}
+
+/// A page of results of the `/search` endpoint.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct JudilibreSearchResponse {
+    #[serde(default)]
+    pub total: u64,
+    #[serde(default)]
+    pub results: Vec<JudilibreDecision>,
+}
+
+/// Normalize a case number for comparisons: "21-12.345", "21-12345"
+/// and "2112345" are the same number.
+pub fn normalize_number(number: &str) -> String {
+    number.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_uppercase()
+}
+
+/// A number of "pourvoi" as written by Judilibre ("21-12.345"), which
+/// the dumps sometimes write without separators
+fn pourvoi_number(number: &str) -> String {
+    let digits = normalize_number(number);
+    if digits.len() == 7 && digits.chars().all(|c| c.is_ascii_digit()) {
+        format!("{}-{}.{}", &digits[..2], &digits[2..4], &digits[4..])
+    } else {
+        number.trim().to_string()
+    }
+}
+
+/// How a decision of the dumps is looked up in Judilibre: by ECLI,
+/// or by case number (and date, as several decisions can be rendered
+/// on the same "pourvoi").
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct JudilibreKey {
+    pub jurisdiction: Jurisdiction,
+    pub ecli: Option<String>,
+    pub number: Option<String>,
+    pub date: Option<String>,
+}
+
+impl JudilibreKey {
+    /// Whether the decision can be looked up at all
+    pub fn is_empty(&self) -> bool {
+        self.ecli.is_none() && self.number.is_none()
+    }
+
+    /// Whether `decision` is the decision of the key: same ECLI, or
+    /// same number and date
+    pub fn matches(&self, decision: &JudilibreDecision) -> bool {
+        if let (Some(ecli), Some(other)) = (&self.ecli, &decision.ecli) {
+            return ecli.eq_ignore_ascii_case(other);
+        }
+        let Some(number) = self.number.as_deref().map(normalize_number) else {
+            return false;
+        };
+        let same_number = decision
+            .number
+            .iter()
+            .chain(decision.numbers.iter())
+            .any(|other| normalize_number(other) == number);
+        let same_date = match (&self.date, &decision.decision_date) {
+            (Some(date), Some(other)) => date == other,
+            _ => true,
+        };
+        same_number && same_date
+    }
+}
+
+/// The searches of the decision of `key`, by number then by ECLI, as
+/// the numbers of the dumps are not always the ones of Judilibre
+fn search_endpoints(key: &JudilibreKey) -> Result<Vec<String>> {
+    let number = key.number.as_deref().map(|number| match key.jurisdiction {
+        Jurisdiction::Cc => pourvoi_number(number),
+        _ => number.trim().to_string(),
+    });
+    let ecli = key.ecli.as_deref().map(|ecli| ecli.trim().to_string());
+    number
+        .into_iter()
+        .chain(ecli)
+        .map(|query| {
+            let params = [
+                ("query", query.as_str()),
+                ("operator", "exact"),
+                ("jurisdiction", key.jurisdiction.as_str()),
+                ("page_size", "50"),
+            ];
+            Ok(format!("/search?{}", serde_urlencoded::to_string(params)?))
+        })
+        .collect()
+}
+
+/// The decisions found by a search `endpoint` (see [`search_endpoints`])
+async fn search(aclient: &AuthenticatedClient, endpoint: &str) -> Result<Vec<JudilibreDecision>> {
+    debug!("Judilibre query: {}", endpoint);
+    let response = aclient.get_request_at(JUDILIBRE_URL, endpoint).await?;
+    if response.status().is_success() {
+        let text = response.text().await?;
+        let res: JudilibreSearchResponse = serde_json::from_str(&text)?;
+        Ok(res.results)
+    } else {
+        let status = response.status();
+        let text = response.text().await?;
+        warn!("Error: unable to search Judilibre {}", status);
+        warn!("Response: {:?}", text);
+        Err(anyhow::anyhow!("Error: unable to search Judilibre {}", status))
+    }
+}
+
+/// Get a decision, with its zones, by its identifier in Judilibre
+pub async fn get_decision(aclient: &AuthenticatedClient, id: &str) -> Result<JudilibreDecision> {
+    let endpoint = format!("/decision?{}", serde_urlencoded::to_string([("id", id)])?);
+    let response = aclient.get_request_at(JUDILIBRE_URL, &endpoint).await?;
+    if response.status().is_success() {
+        let text = response.text().await?;
+        Ok(serde_json::from_str(&text)?)
+    } else {
+        let status = response.status();
+        let text = response.text().await?;
+        warn!("Error: unable to get Judilibre decision {} {}", id, status);
+        warn!("Response: {:?}", text);
+        Err(anyhow::anyhow!("Error: unable to get Judilibre decision {}", status))
+    }
+}
+
+/// The decision of `key` in Judilibre, with its zones, if it is published
+pub async fn find_decision(
+    aclient: &AuthenticatedClient,
+    key: &JudilibreKey,
+) -> Result<Option<JudilibreDecision>> {
+    for endpoint in search_endpoints(key)? {
+        let results = search(aclient, &endpoint).await?;
+        if let Some(decision) = results.iter().find(|decision| key.matches(decision)) {
+            return Ok(Some(get_decision(aclient, &decision.id).await?));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_judilibre_matching() {
+        let response: JudilibreSearchResponse = serde_json::from_str(
+            r#"{"page":0,"page_size":50,"total":2,"results":[
+                {"id":"6079a8a79ba5988459c4a0c1","jurisdiction":"cc","chamber":"civ1",
+                 "number":"19-21.060","numbers":["19-21.060"],"decision_date":"2021-03-10",
+                 "solution":"cassation","themes":["Contrats"],"publication":["b"]},
+                {"id":"6079a8a79ba5988459c4a0c2","jurisdiction":"cc","chamber":"civ1",
+                 "number":"19-21.060","decision_date":"2022-01-05","ecli":"ECLI:FR:CCASS:2022:C100012"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(response.total, 2);
+        assert_eq!(response.results[0].themes, ["Contrats"]);
+        assert!(response.results[0].zones.is_none());
+
+        let by_number = JudilibreKey {
+            jurisdiction: Jurisdiction::Cc,
+            ecli: None,
+            number: Some("1921060".to_string()),
+            date: Some("2021-03-10".to_string()),
+        };
+        let found: Vec<&str> = response
+            .results
+            .iter()
+            .filter(|d| by_number.matches(d))
+            .map(|d| d.id.as_str())
+            .collect();
+        assert_eq!(found, ["6079a8a79ba5988459c4a0c1"]);
+
+        let by_ecli = JudilibreKey {
+            ecli: Some("ECLI:FR:CCASS:2022:C100012".to_string()),
+            number: None,
+            date: None,
+            ..by_number.clone()
+        };
+        assert!(!by_ecli.matches(&response.results[0]));
+        assert!(by_ecli.matches(&response.results[1]));
+        assert_eq!(
+            search_endpoints(&by_number).unwrap(),
+            ["/search?query=19-21.060&operator=exact&jurisdiction=cc&page_size=50"]
+        );
+        let both = JudilibreKey { number: Some("C100012".to_string()), ..by_ecli };
+        assert_eq!(search_endpoints(&both).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,252 @@
+/// Output formats of the search results of the API. Besides the raw
+/// JSON lines answered by the API, results can be written as a flat
+/// table ([`ApiRecord`]) in CSV or Parquet, which loads directly in
+/// pandas or duckdb.
+use anyhow::{Context, Result};
+use arrow_array::{ArrayRef, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+use serde::{Deserialize, Serialize};
+
+use std::io::Write;
+use std::sync::Arc;
+
+use super::piste::SearchResult;
+
+/// Format of the search results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// The results as answered by the API, one JSON object per line
+    #[default]
+    Jsonl,
+    /// One row per result, see [`ApiRecord`]
+    Csv,
+    /// One row per result, see [`ApiRecord`]
+    Parquet,
+}
+
+/// A search result flattened into columns. Only the first
+/// title of the result is kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiRecord {
+    pub id: Option<String>,
+    pub cid: Option<String>,
+    pub title: Option<String>,
+    pub fond: Option<String>,
+    pub nature: Option<String>,
+    pub doctype: Option<String>,
+    pub legal_status: Option<String>,
+    pub date: Option<String>,
+    pub date_publication: Option<String>,
+    pub date_signature: Option<String>,
+    pub date_diffusion: Option<String>,
+    /// The text of the result, an excerpt of the document
+    pub snippet: Option<String>,
+}
+
+/// Names of the columns of [`ApiRecord`], in order
+const COLUMNS: &[&str] = &[
+    "id",
+    "cid",
+    "title",
+    "fond",
+    "nature",
+    "doctype",
+    "legal_status",
+    "date",
+    "date_publication",
+    "date_signature",
+    "date_diffusion",
+    "snippet",
+];
+
+impl From<&SearchResult> for ApiRecord {
+    fn from(result: &SearchResult) -> Self {
+        let title = result.titles.first();
+        ApiRecord {
+            id: title.map(|t| t.id.clone()),
+            cid: title.map(|t| t.cid.clone()),
+            title: title.map(|t| t.title.clone()),
+            fond: result.fond.map(|f| f.as_str().to_string()),
+            nature: result.nature.clone(),
+            doctype: result.doctype.clone(),
+            legal_status: result.legal_status.clone(),
+            date: result.date.clone(),
+            date_publication: result.date_publication.clone(),
+            date_signature: result.date_signature.clone(),
+            date_diffusion: result.date_diffusion.clone(),
+            snippet: result.text.clone(),
+        }
+    }
+}
+
+impl ApiRecord {
+    /// The values of the columns, in the order of [`COLUMNS`]
+    fn values(&self) -> [&Option<String>; 12] {
+        [
+            &self.id,
+            &self.cid,
+            &self.title,
+            &self.fond,
+            &self.nature,
+            &self.doctype,
+            &self.legal_status,
+            &self.date,
+            &self.date_publication,
+            &self.date_signature,
+            &self.date_diffusion,
+            &self.snippet,
+        ]
+    }
+}
+
+/// Where the search results are written
+pub trait ResultWriter: Send {
+    fn write(&mut self, result: &SearchResult) -> Result<()>;
+
+    /// Write the pending results and close the output
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+/// Create the writer of results in `format` to `out`
+pub fn result_writer<W>(format: OutputFormat, out: W) -> Result<Box<dyn ResultWriter>>
+where
+    W: Write + Send + 'static,
+{
+    Ok(match format {
+        OutputFormat::Jsonl => Box::new(JsonlResultWriter { out }),
+        OutputFormat::Csv => Box::new(CsvResultWriter {
+            writer: csv::Writer::from_writer(out),
+        }),
+        OutputFormat::Parquet => Box::new(ParquetResultWriter::new(out)?),
+    })
+}
+
+struct JsonlResultWriter<W> {
+    out: W,
+}
+
+impl<W: Write + Send> ResultWriter for JsonlResultWriter<W> {
+    fn write(&mut self, result: &SearchResult) -> Result<()> {
+        serde_json::to_writer(&mut self.out, result).context("Unable to serialize search result")?;
+        self.out.write_all(b"\n").context("Unable to write newline")?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+struct CsvResultWriter<W: Write> {
+    writer: csv::Writer<W>,
+}
+
+impl<W: Write + Send> ResultWriter for CsvResultWriter<W> {
+    fn write(&mut self, result: &SearchResult) -> Result<()> {
+        self.writer.serialize(ApiRecord::from(result))?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Number of results written at once in a row group
+const PARQUET_BATCH_SIZE: usize = 10_000;
+
+struct ParquetResultWriter<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    schema: Arc<Schema>,
+    pending: Vec<ApiRecord>,
+}
+
+impl<W: Write + Send> ParquetResultWriter<W> {
+    fn new(out: W) -> Result<Self> {
+        let fields: Vec<Field> = COLUMNS
+            .iter()
+            .map(|name| Field::new(*name, DataType::Utf8, true))
+            .collect();
+        let schema = Arc::new(Schema::new(fields));
+        let writer = ArrowWriter::try_new(out, schema.clone(), None)?;
+        Ok(ParquetResultWriter {
+            writer,
+            schema,
+            pending: vec![],
+        })
+    }
+
+    fn write_pending(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let columns: Vec<ArrayRef> = (0..COLUMNS.len())
+            .map(|i| {
+                let values = self.pending.iter().map(|r| r.values()[i].as_deref());
+                Arc::new(values.collect::<StringArray>()) as ArrayRef
+            })
+            .collect();
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        self.writer.write(&batch)?;
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> ResultWriter for ParquetResultWriter<W> {
+    fn write(&mut self, result: &SearchResult) -> Result<()> {
+        self.pending.push(ApiRecord::from(result));
+        if self.pending.len() >= PARQUET_BATCH_SIZE {
+            self.write_pending()?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.write_pending()?;
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    #[test]
+    fn test_result_writers() {
+        let result: SearchResult = serde_json::from_str(
+            r#"{"date": "2024-03-21", "titles": [{"title": "Conseil d'État, 21/03/2024",
+                "id": "CETATEXT000049314894", "cid": "CETATEXT000049314894"}],
+                "text": "antennes relais", "origin": "CETAT", "nature": "Decision"}"#,
+        )
+        .unwrap();
+        let record = ApiRecord::from(&result);
+        assert_eq!(record.cid.as_deref(), Some("CETATEXT000049314894"));
+        assert_eq!(record.fond.as_deref(), Some("CETAT"));
+        assert_eq!(record.values().len(), COLUMNS.len());
+
+        let tmp = temp_dir::TempDir::new().unwrap();
+        for format in [OutputFormat::Jsonl, OutputFormat::Csv, OutputFormat::Parquet] {
+            let path = tmp.path().join(format!("{:?}", format));
+            let file = std::fs::File::create(&path).unwrap();
+            let mut writer = result_writer(format, std::io::BufWriter::new(file)).unwrap();
+            writer.write(&result).unwrap();
+            writer.write(&result).unwrap();
+            writer.finish().unwrap();
+        }
+
+        let csv = std::fs::read_to_string(tmp.path().join("Csv")).unwrap();
+        assert_eq!(csv.lines().next().unwrap(), COLUMNS.join(","));
+        assert_eq!(csv.lines().count(), 3);
+        let jsonl = std::fs::read_to_string(tmp.path().join("Jsonl")).unwrap();
+        assert_eq!(jsonl.lines().count(), 2);
+        let parquet = std::fs::File::open(tmp.path().join("Parquet")).unwrap();
+        let reader = SerializedFileReader::new(parquet).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+    }
+}
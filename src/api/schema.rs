@@ -0,0 +1,174 @@
+/// Detection of the changes of shape of the responses of the API of
+/// Légifrance. The responses are deserialized into the types of
+/// [`crate::api::piste`], which ignore the fields they do not know: a
+/// new field of the API would be dropped from the datasets without
+/// notice. The fields of a response that do
+/// not survive a round trip through its type are reported instead (once
+/// per field, and at the end of the run), and make the response an
+/// error in strict mode.
+use anyhow::Result;
+use log::warn;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// What to do with the fields of the responses that the types do not know
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SchemaMode {
+    /// Ignore them, after reporting them
+    #[default]
+    Lenient,
+    /// Reject the responses that have some
+    Strict,
+}
+
+/// The paths of the fields of the response `raw` that are missing in
+/// `known` (its round trip through its type), such as
+/// `results[].titles[].legalStatus`
+pub fn unknown_fields(raw: &Value, known: &Value) -> Vec<String> {
+    let mut fields = vec![];
+    collect_unknown_fields(raw, known, "", &mut fields);
+    fields.sort();
+    fields.dedup();
+    fields
+}
+
+fn collect_unknown_fields(raw: &Value, known: &Value, path: &str, fields: &mut Vec<String>) {
+    match (raw, known) {
+        (Value::Object(raw), Value::Object(known)) => {
+            for (key, value) in raw {
+                let field = match path {
+                    "" => key.clone(),
+                    _ => format!("{}.{}", path, key),
+                };
+                match known.get(key) {
+                    Some(known) => collect_unknown_fields(value, known, &field, fields),
+                    None => fields.push(field),
+                }
+            }
+        }
+        (Value::Array(raw), Value::Array(known)) => {
+            let field = format!("{}[]", path);
+            for (raw, known) in raw.iter().zip(known) {
+                collect_unknown_fields(raw, known, &field, fields);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The unexpected fields met by the responses of a client
+#[derive(Debug, Default)]
+pub struct SchemaDrift {
+    mode: SchemaMode,
+    /// Number of responses with the field, by kind of response and field
+    fields: Mutex<BTreeMap<(String, String), u64>>,
+}
+
+impl SchemaDrift {
+    pub fn new(mode: SchemaMode) -> Self {
+        SchemaDrift {
+            mode,
+            fields: Mutex::default(),
+        }
+    }
+
+    pub fn mode(&self) -> SchemaMode {
+        self.mode
+    }
+
+    /// Parse the `text` of a response of the kind `response` (e.g.
+    /// "search"), recording the fields that its type does not know
+    pub fn parse<T: DeserializeOwned + Serialize>(&self, response: &str, text: &str) -> Result<T> {
+        let raw: Value = serde_json::from_str(text)?;
+        let parsed = T::deserialize(&raw)?;
+        let unknown = unknown_fields(&raw, &serde_json::to_value(&parsed)?);
+        if unknown.is_empty() {
+            return Ok(parsed);
+        }
+        {
+            let mut fields = self.fields.lock().expect("schema drift lock poisoned");
+            for field in unknown.iter() {
+                let count = fields.entry((response.to_string(), field.clone())).or_insert(0);
+                if *count == 0 {
+                    warn!("Unexpected field {} in the {} responses of the API", field, response);
+                }
+                *count += 1;
+            }
+        }
+        match self.mode {
+            SchemaMode::Lenient => Ok(parsed),
+            SchemaMode::Strict => Err(anyhow::anyhow!(
+                "Unexpected fields in a {} response of the API, which may have changed: {}",
+                response,
+                unknown.join(", ")
+            )),
+        }
+    }
+
+    /// The unexpected fields met so far: the kind of response, the
+    /// field and the number of responses that had it
+    pub fn unexpected(&self) -> Vec<(String, String, u64)> {
+        let fields = self.fields.lock().expect("schema drift lock poisoned");
+        fields
+            .iter()
+            .map(|((response, field), count)| (response.clone(), field.clone(), *count))
+            .collect()
+    }
+
+    /// A report for the end of a run, if some responses had unexpected fields
+    pub fn summary(&self) -> Option<String> {
+        let unexpected = self.unexpected();
+        (!unexpected.is_empty()).then(|| {
+            let fields: Vec<String> = unexpected
+                .iter()
+                .map(|(response, field, count)| format!("  {} {} ({} responses)", response, field, count))
+                .collect();
+            format!(
+                "Fields of the API responses that are not kept (the API may have changed):\n{}",
+                fields.join("\n")
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::piste::SearchResponse;
+
+    #[test]
+    fn test_schema_drift() {
+        let known = r#"{"totalResultNumber":1,"results":[{"date":null,"datePublication":"2024-01-01",
+            "dateSignature":null,"dateDiffusion":null,"titles":[{"title":"Décret","id":"JORFTEXT000000000001",
+            "cid":"JORFTEXT000000000001"}],"text":null,"etat":null,"origin":"JORF","type":null,"nature":"DECRET"}]}"#;
+        let lenient = SchemaDrift::default();
+        let response: SearchResponse = lenient.parse("search", known).unwrap();
+        assert_eq!(response.results.len(), 1);
+        assert!(lenient.summary().is_none());
+
+        let drifted = r#"{"totalResultNumber":2,"executionTime":12,"results":[
+            {"titles":[{"title":"Décret","id":"JORFTEXT000000000001","cid":"JORFTEXT000000000001","legalStatus":"VIGUEUR"}]},
+            {"titles":[],"score":1.5}]}"#;
+        let response: SearchResponse = lenient.parse("search", drifted).unwrap();
+        assert_eq!(response.total_result_number, 2);
+        lenient.parse::<SearchResponse>("search", drifted).unwrap();
+        assert_eq!(
+            lenient.unexpected(),
+            [
+                ("search".to_string(), "executionTime".to_string(), 2),
+                ("search".to_string(), "results[].score".to_string(), 2),
+                ("search".to_string(), "results[].titles[].legalStatus".to_string(), 2),
+            ]
+        );
+        assert!(lenient.summary().unwrap().contains("search results[].score (2 responses)"));
+
+        let strict = SchemaDrift::new(SchemaMode::Strict);
+        assert!(strict.parse::<SearchResponse>("search", known).is_ok());
+        let error = strict.parse::<SearchResponse>("search", drifted).unwrap_err().to_string();
+        assert!(error.ends_with("executionTime, results[].score, results[].titles[].legalStatus"));
+    }
+}
@@ -0,0 +1,163 @@
+/// Storage of the full texts fetched by [`super::get_full_texts`]:
+/// one file per cid, directly in the output directory or sharded by
+/// fond and year (e.g. `JURI/2024/JURITEXT000049314894.txt`), or a
+/// single JSON lines file of records holding the metadata of the
+/// search result together with the text.
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::output::ApiRecord;
+use super::piste::SearchResult;
+
+/// Name of the JSON lines file of the texts, in the output directory
+pub const TEXTS_FILE: &str = "texts.jsonl";
+
+/// Directory of the texts whose fond or year is not known
+const UNKNOWN: &str = "unknown";
+
+/// Where the files of the texts are written in the output directory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum TextLayout {
+    /// `{cid}.txt`
+    #[default]
+    Flat,
+    /// `{fond}/{year}/{cid}.txt`
+    FondYear,
+}
+
+/// A text with the metadata of its search result
+#[derive(Debug, Clone, Serialize)]
+pub struct TextRecord<'a> {
+    #[serde(flatten)]
+    pub record: ApiRecord,
+    pub text: &'a str,
+}
+
+/// Year of a search result, from its date (e.g. `2024-03-21` or
+/// `2024-03-21T00:00:00.000+0000`)
+fn year(result: &SearchResult) -> Option<&str> {
+    let date = result
+        .date
+        .as_deref()
+        .or(result.date_publication.as_deref())
+        .or(result.date_signature.as_deref())?;
+    let year = date.get(..4)?;
+    year.chars().all(|c| c.is_ascii_digit()).then_some(year)
+}
+
+/// Where the full texts are written
+pub trait TextWriter: Send {
+    /// Write the text of the document with the chronological id `cid`
+    fn write(&mut self, cid: &str, result: &SearchResult, text: &str) -> Result<()>;
+
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+/// Create the writer of the texts in `dir`: one file per text laid
+/// out by `layout`, or the [`TEXTS_FILE`] JSON lines file with `jsonl`
+pub fn text_writer(dir: &Path, layout: TextLayout, jsonl: bool) -> Result<Box<dyn TextWriter>> {
+    std::fs::create_dir_all(dir).context(format!("Unable to create {}", dir.display()))?;
+    Ok(if jsonl {
+        let path = dir.join(TEXTS_FILE);
+        let file =
+            std::fs::File::create(&path).context(format!("Unable to create {}", path.display()))?;
+        Box::new(JsonlTextWriter {
+            out: BufWriter::new(file),
+        })
+    } else {
+        Box::new(FileTextWriter {
+            dir: dir.to_path_buf(),
+            layout,
+        })
+    })
+}
+
+struct FileTextWriter {
+    dir: PathBuf,
+    layout: TextLayout,
+}
+
+impl FileTextWriter {
+    fn path_for(&self, cid: &str, result: &SearchResult) -> PathBuf {
+        let filename = format!("{}.txt", cid);
+        match self.layout {
+            TextLayout::Flat => self.dir.join(filename),
+            TextLayout::FondYear => {
+                let fond = result.fond.map(|f| f.as_str()).unwrap_or(UNKNOWN);
+                let year = year(result).unwrap_or(UNKNOWN);
+                self.dir.join(fond).join(year).join(filename)
+            }
+        }
+    }
+}
+
+impl TextWriter for FileTextWriter {
+    fn write(&mut self, cid: &str, result: &SearchResult, text: &str) -> Result<()> {
+        let path = self.path_for(cid, result);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, text).context(format!("Unable to write {}", path.display()))
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        Ok(())
+    }
+}
+
+struct JsonlTextWriter {
+    out: BufWriter<std::fs::File>,
+}
+
+impl TextWriter for JsonlTextWriter {
+    fn write(&mut self, cid: &str, result: &SearchResult, text: &str) -> Result<()> {
+        let mut record = ApiRecord::from(result);
+        record.cid = Some(cid.to_string());
+        serde_json::to_writer(&mut self.out, &TextRecord { record, text })
+            .context("Unable to serialize text")?;
+        self.out.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_writers() {
+        let result: SearchResult = serde_json::from_str(
+            r#"{"date": "2024-03-21T00:00:00.000+0000", "titles": [{"title": "Cour de cassation",
+                "id": "JURITEXT000049314894", "cid": "JURITEXT000049314894"}],
+                "origin": "JURI"}"#,
+        )
+        .unwrap();
+        let cid = "JURITEXT000049314894";
+        let tmp = temp_dir::TempDir::new().unwrap();
+
+        let mut writer = text_writer(tmp.path(), TextLayout::FondYear, false).unwrap();
+        writer.write(cid, &result, "La Cour").unwrap();
+        writer.finish().unwrap();
+        let path = tmp.path().join("JURI/2024/JURITEXT000049314894.txt");
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "La Cour");
+
+        let mut writer = text_writer(tmp.path(), TextLayout::Flat, true).unwrap();
+        writer.write(cid, &result, "La Cour").unwrap();
+        writer.write(cid, &result, "La Cour").unwrap();
+        writer.finish().unwrap();
+        let jsonl = std::fs::read_to_string(tmp.path().join(TEXTS_FILE)).unwrap();
+        assert_eq!(jsonl.lines().count(), 2);
+        let record: serde_json::Value = serde_json::from_str(jsonl.lines().next().unwrap()).unwrap();
+        assert_eq!(record["cid"], cid);
+        assert_eq!(record["fond"], "JURI");
+        assert_eq!(record["text"], "La Cour");
+    }
+}
@@ -0,0 +1,200 @@
+/// Limits of the work done in parallel by the binaries: downloads,
+/// extractions, parsing threads, requests to the PISTE API and open
+/// files (see [`crate::open_files`]).
+///
+/// Without `--jobs`, the limits are the defaults of [`Concurrency`],
+/// which suit a laptop. `--jobs N` sets all of them to `N`, so that the
+/// tools can be slowed down on a small VM or sped up on a big server,
+/// and the options of a single step (e.g. `--concurrency` for the
+/// downloads) still take precedence.
+///
+/// `dilarxiv-oneshot --memory-budget` sizes its pipeline to the memory
+/// of the machine instead (see [`MemoryBudget`]).
+use clap::Args;
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct JobsConfig {
+    /// Number of parallel jobs: simultaneous downloads, extractions,
+    /// parsing threads and API requests
+    #[arg(long, env = "LEGIFRANCE_JOBS", global = true)]
+    pub jobs: Option<usize>,
+    /// Maximum number of files open at once by the extractions and the
+    /// indexing (by default, half of the limit of open files of the
+    /// process)
+    #[arg(long, env = "LEGIFRANCE_OPEN_FILES", global = true)]
+    pub open_files: Option<usize>,
+}
+
+impl JobsConfig {
+    pub fn concurrency(&self) -> Concurrency {
+        let concurrency = match self.jobs {
+            Some(jobs) => Concurrency::with_jobs(jobs),
+            None => Concurrency::default(),
+        };
+        Concurrency {
+            open_files: self.open_files.unwrap_or(concurrency.open_files),
+            ..concurrency
+        }
+    }
+}
+
+/// How much work is done in parallel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Concurrency {
+    /// Tarballs downloaded simultaneously
+    pub downloads: usize,
+    /// Tarballs (of different fonds) extracted simultaneously
+    pub extractions: usize,
+    /// Threads parsing the extracted documents
+    pub parsers: usize,
+    /// Threads indexing the extracted documents, 0 for one per core
+    pub indexers: usize,
+    /// Requests sent simultaneously to the API
+    pub requests: usize,
+    /// Files open at once, 0 for [`crate::open_files::default_limit`]
+    pub open_files: usize,
+}
+
+impl Default for Concurrency {
+    fn default() -> Self {
+        Concurrency {
+            downloads: 10,
+            extractions: 10,
+            parsers: 5,
+            indexers: 0,
+            requests: 20,
+            open_files: 0,
+        }
+    }
+}
+
+impl Concurrency {
+    /// Run `jobs` jobs of every kind at once
+    pub fn with_jobs(jobs: usize) -> Self {
+        let jobs = jobs.max(1);
+        Concurrency {
+            downloads: jobs,
+            extractions: jobs,
+            parsers: jobs,
+            indexers: jobs,
+            requests: jobs,
+            open_files: 0,
+        }
+    }
+}
+
+/// Heap of the tantivy writer of `dilarxiv-oneshot` without `--memory-budget`
+pub const DEFAULT_WRITER_HEAP: usize = 100_000_000;
+
+/// Smallest heap of a tantivy writer, for a single indexing thread
+const MIN_WRITER_HEAP: usize = 15_000_000;
+
+/// Largest heap given to the tantivy writer, beyond which the
+/// segments are not written faster
+const MAX_WRITER_HEAP: usize = 2_000_000_000;
+
+/// Memory used by a thread parsing documents, for the largest ones
+const PARSER_MEMORY: u64 = 64 << 20;
+
+/// Memory used by a tarball between two stages of the pipeline
+/// (buffers of its extraction and of the listing of its files)
+const TARBALL_MEMORY: u64 = 32 << 20;
+
+/// Most tarballs waiting between two stages of the pipeline
+const MAX_TARBALLS_IN_FLIGHT: usize = 64;
+
+/// How `dilarxiv-oneshot` shares its memory: a quarter for the heap of
+/// the tantivy writer, a quarter for the index in memory, a quarter
+/// for the parsing threads and a quarter for the tarballs in flight
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryBudget {
+    /// Heap of the tantivy writer, in bytes
+    pub writer_heap: usize,
+    /// Tarballs waiting between two stages of the pipeline
+    pub tarballs_in_flight: usize,
+    /// Threads parsing the documents to index, 0 for one per core
+    pub indexers: usize,
+    /// Threads parsing the documents to export
+    pub parsers: usize,
+}
+
+impl MemoryBudget {
+    /// Smallest budget, whose quarter is the smallest heap of a tantivy writer
+    pub const MIN_BYTES: u64 = 4 * MIN_WRITER_HEAP as u64;
+
+    /// Check that a budget of `bytes` leaves the tantivy writer its
+    /// minimum heap, so that `--memory-budget` is refused when it is
+    /// parsed rather than once the index is created
+    pub fn check(bytes: u64) -> anyhow::Result<u64> {
+        anyhow::ensure!(
+            bytes >= Self::MIN_BYTES,
+            "A memory budget of {} bytes is too small: a quarter of it goes to the index, \
+             which needs at least {} bytes, use at least {}M",
+            bytes,
+            MIN_WRITER_HEAP,
+            Self::MIN_BYTES.div_ceil(1 << 20)
+        );
+        Ok(bytes)
+    }
+
+    /// Share `bytes` of memory, with at most one parsing thread per core
+    pub fn new(bytes: u64) -> Self {
+        let quarter = bytes / 4;
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let parsers = ((quarter / PARSER_MEMORY) as usize).clamp(1, cores);
+        MemoryBudget {
+            writer_heap: (quarter.min(usize::MAX as u64) as usize).clamp(MIN_WRITER_HEAP, MAX_WRITER_HEAP),
+            tarballs_in_flight: ((quarter / TARBALL_MEMORY) as usize).clamp(1, MAX_TARBALLS_IN_FLIGHT),
+            indexers: parsers,
+            parsers,
+        }
+    }
+
+    /// The sizes used without budget, from the limits of `concurrency`
+    pub fn from_concurrency(concurrency: &Concurrency) -> Self {
+        MemoryBudget {
+            writer_heap: DEFAULT_WRITER_HEAP,
+            tarballs_in_flight: concurrency.extractions.max(1),
+            indexers: concurrency.indexers,
+            parsers: concurrency.parsers,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_budget() {
+        // a small CI runner
+        let small = MemoryBudget::new(256 << 20);
+        assert_eq!(small.writer_heap, 64 << 20);
+        assert_eq!(small.tarballs_in_flight, 2);
+        assert_eq!(small.parsers, 1);
+        assert_eq!(MemoryBudget::new(1 << 20).writer_heap, MIN_WRITER_HEAP);
+
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let large = MemoryBudget::new(64 << 30);
+        assert_eq!(large.writer_heap, MAX_WRITER_HEAP);
+        assert_eq!(large.tarballs_in_flight, MAX_TARBALLS_IN_FLIGHT);
+        assert_eq!(large.parsers, cores.min(256));
+        assert_eq!(large.indexers, large.parsers);
+
+        let default = MemoryBudget::from_concurrency(&Concurrency::with_jobs(3));
+        assert_eq!(default.writer_heap, DEFAULT_WRITER_HEAP);
+        assert_eq!((default.tarballs_in_flight, default.parsers), (3, 3));
+    }
+
+    #[test]
+    fn test_memory_budget_check() {
+        assert_eq!(MemoryBudget::check(256 << 20).unwrap(), 256 << 20);
+        assert_eq!(MemoryBudget::check(MemoryBudget::MIN_BYTES).unwrap(), MemoryBudget::MIN_BYTES);
+        assert!(MemoryBudget::new(MemoryBudget::MIN_BYTES).writer_heap >= MIN_WRITER_HEAP);
+
+        let err = MemoryBudget::check(32 << 20).unwrap_err().to_string();
+        assert!(err.contains("too small"), "{}", err);
+        assert!(err.contains("use at least 58M"), "{}", err);
+        assert!(MemoryBudget::check(0).is_err());
+    }
+}
@@ -1,8 +1,15 @@
 use legifrance::api::client::{AuthenticatedClient, PageQuery, ping_api};
+use legifrance::api::output::{OutputFormat, result_writer};
 use legifrance::api::piste::Fond;
-use legifrance::api::{call_search_endpoint, get_full_texts};
+use legifrance::api::schema::SchemaMode;
+use legifrance::api::texts::{TextLayout, text_writer};
+use legifrance::api::{call_search_endpoint, compute_query_plan, get_full_texts};
+use legifrance::concurrency::JobsConfig;
+use legifrance::http::HttpConfig;
+use legifrance::logging::LogConfig;
+use legifrance::shutdown;
 
-use clap::Parser;
+use clap::{CommandFactory, Parser, Subcommand};
 
 use std::path::PathBuf;
 
@@ -14,9 +21,9 @@ pub enum ParseableFond {
     P(Fond),
 }
 impl ParseableFond {
-    fn to_fond(&self) -> Fond {
+    fn to_fond(self) -> Fond {
         match self {
-            ParseableFond::P(f) => *f,
+            ParseableFond::P(f) => f,
         }
     }
 }
@@ -50,9 +57,18 @@ impl ValueEnum for ParseableFond {
 /// This is a simple program to search the Legifrance API
 /// it will search for relevant texts and return the results
 /// in JSON format.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the completion script of a shell
+    Completions { shell: clap_complete::Shell },
+}
+
 #[derive(Parser, Debug)]
 #[clap(author, version, about)]
 struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     #[arg(short, long)]
     query: Option<String>,
     #[arg(short, long)]
@@ -63,28 +79,79 @@ struct Cli {
     fond: Option<ParseableFond>,
     #[arg(short, long)]
     output: Option<String>,
+    /// Only print the plan of the query: the number of results, the
+    /// year ranges it is split into and the number of page requests,
+    /// without getting nor writing the results
+    #[arg(long, requires = "query")]
+    plan: bool,
+    /// Format of the search results: the JSON lines of the API,
+    /// or a flat table (id, cid, title, dates, fond, nature, snippet)
+    #[arg(long, value_enum, default_value = "jsonl")]
+    format: OutputFormat,
+    /// Get the full texts of the search results of this file
+    /// (as written by `--output` in the JSON lines format)
     #[arg(short, long)]
     texts: Option<String>,
+    /// Directory where the full texts are written
+    #[arg(long, default_value = "dilapi-full-texts")]
+    out_dir: PathBuf,
+    /// Layout of the full texts in `--out-dir`: `{cid}.txt`,
+    /// or `{fond}/{year}/{cid}.txt`
+    #[arg(long, value_enum, default_value = "flat")]
+    layout: TextLayout,
+    /// Write the full texts with the metadata of their search result
+    /// as JSON lines (in `{out-dir}/texts.jsonl`) instead of one file
+    /// per text
+    #[arg(long, conflicts_with = "layout")]
+    texts_jsonl: bool,
+    /// What to do with the fields of the search responses that are not
+    /// known (the API may have changed): report them at the end, or
+    /// fail on the first response that has some
+    #[arg(long, value_enum, env = "LEGIFRANCE_SCHEMA", default_value = "lenient")]
+    schema: SchemaMode,
+    #[clap(flatten)]
+    http: HttpConfig,
+    #[clap(flatten)]
+    jobs: JobsConfig,
+    #[clap(flatten)]
+    log: LogConfig,
 }
 
 #[tokio::main]
 async fn main() {
-    env_logger::init();
+    shutdown::install();
+    run().await;
+    if shutdown::is_interrupted() {
+        std::process::exit(shutdown::INTERRUPTED_EXIT_CODE);
+    }
+}
+
+async fn run() {
+    let cli = Cli::parse();
+
+    cli.log.init();
+
+    if let Some(Command::Completions { shell }) = cli.command {
+        clap_complete::generate(shell, &mut Cli::command(), "dilapi", &mut std::io::stdout());
+        return;
+    }
 
     let client_id = std::fs::read_to_string("client-id.txt").expect("Failed to read client-id.txt");
     let client_secret =
         std::fs::read_to_string("client-secret.txt").expect("Failed to read client-secret.txt");
 
-    let aclient = AuthenticatedClient::from_secret(&client_id, &client_secret)
+    let mut aclient = AuthenticatedClient::from_config(&cli.http, &client_id, &client_secret)
         .await
         .expect("Failed to create authenticated client");
+    aclient.set_schema_mode(cli.schema);
 
-    let _ping = ping_api(&aclient, "/search/ping")
+    let rate_limits = aclient.rate_limits();
+    let schema_drift = aclient.schema_drift();
+
+    ping_api(&aclient, "/search/ping")
         .await
         .expect("Failed to ping API");
 
-    let cli = Cli::parse();
-
     if let Some(query) = cli.query {
         let pq = PageQuery {
             text: query,
@@ -94,33 +161,63 @@ async fn main() {
             fond: cli.fond.map(|f| f.to_fond()),
         };
 
-        if let Some(output) = cli.output {
+        if cli.plan {
+            let plan = compute_query_plan(&aclient, &pq, cli.jobs.concurrency().requests)
+                .await
+                .expect("Failed to compute the query plan");
+            println!("{} results for {:?}", plan.total, pq.text);
+            for range in &plan.ranges {
+                let year = |y: Option<u64>| y.map(|y| y.to_string()).unwrap_or("?".to_string());
+                let pages = match &range.error {
+                    Some(e) => format!("error: {}", e),
+                    None => format!("{} pages", range.pages),
+                };
+                println!(
+                    "  {}-{}: {}",
+                    year(range.start_year),
+                    year(range.end_year),
+                    pages
+                );
+            }
+            println!(
+                "{} page requests in {} year ranges",
+                plan.queries.len(),
+                plan.ranges.len()
+            );
+            return;
+        }
+
+        let writer = if let Some(output) = cli.output {
             let dir = PathBuf::from(output);
             let file = std::fs::File::create(&dir).expect("Failed to create file");
-            let writer = std::io::BufWriter::new(file);
-            call_search_endpoint(&aclient, writer, &pq)
-                .await
-                .expect("Failed to call search endpoint");
+            result_writer(cli.format, std::io::BufWriter::new(file))
         } else {
             // use stdout
-            let writer = std::io::stdout();
-            call_search_endpoint(&aclient, writer, &pq)
-                .await
-                .expect("Failed to call search endpoint");
-        }
+            result_writer(cli.format, std::io::stdout())
+        };
+        let writer = writer.expect("Failed to create writer");
+        call_search_endpoint(&aclient, writer, &pq, &cli.jobs.concurrency())
+            .await
+            .expect("Failed to call search endpoint");
     }
 
     if let Some(texts) = cli.texts {
         let filename = PathBuf::from(texts);
         let file = std::fs::File::open(&filename).expect("Failed to open file");
         let reader = std::io::BufReader::new(file);
-        let dir = PathBuf::from("dilapi-full-texts");
-        if !dir.exists() {
-            std::fs::create_dir_all(&dir).expect("Failed to create directory");
-        }
+        let writer = text_writer(&cli.out_dir, cli.layout, cli.texts_jsonl)
+            .expect("Failed to create the output directory");
 
-        get_full_texts(aclient, &dir, reader)
+        get_full_texts(aclient, writer, reader, &cli.jobs.concurrency())
             .await
             .expect("Failed to get full texts");
     }
+
+    // the results may be written to the standard output
+    if let Some(summary) = rate_limits.summary() {
+        eprintln!("{}", summary);
+    }
+    if let Some(summary) = schema_drift.summary() {
+        eprintln!("{}", summary);
+    }
 }
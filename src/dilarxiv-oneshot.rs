@@ -8,54 +8,148 @@
 /// environments (e.g. github actions) and may
 /// be marginally faster than the dilarxiv program
 /// if the user is only interested in a single query.
-use clap::Parser;
+///
+/// The tarballs go through bounded stages that run at once
+/// (download, extraction, then indexing and search), a stage
+/// waiting while the next one is behind, so that only a few
+/// tarballs are on the disk and in memory at any time. The
+/// stages pass whole tarballs (see [`legifrance::dumps::pipeline`]),
+/// in the order of the listing.
+///
+/// With `--state-dir`, an interrupted run can be resumed
+/// by running the same command again.
+use clap::{CommandFactory, Parser, Subcommand};
 
 use anyhow::Result;
+use futures::StreamExt;
+use reqwest::Client;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::ProgressStyle;
 
-use log::{error, info, debug};
+use log::{error, info, debug, warn};
 
 use temp_dir::TempDir;
 
-use std::io::BufWriter;
-
-use legifrance::dumps::extractor::parse_file;
+use legifrance::dumps::extractor::{
+    Document, DocumentCsvWriter, DocumentWriter, ExtractError, parse_document_file,
+};
+use legifrance::dumps::extractor::export::DEFAULT_FLUSH_EVERY;
+use legifrance::dumps::failures::{ERRORS_FILE, FailureConfig, FailureLog, Stage};
 use legifrance::dumps::fonds::{FONDS, Fond};
-use legifrance::dumps::tarballs;
+use legifrance::dumps::ledger::{LEDGER_FILE, Ledger, read_ledger};
+use legifrance::dumps::lock::DataLock;
+use legifrance::dumps::pipeline::{Extracted, extract_stage};
+use legifrance::dumps::resume::{ResumeState, Run};
+use legifrance::dumps::tarballs::{self, Tarball};
+use legifrance::concurrency::{JobsConfig, MemoryBudget};
+use legifrance::http::HttpConfig;
+use legifrance::logging::{self, LogConfig};
+use legifrance::open_files;
+use legifrance::shutdown;
 
-use legifrance::dumps::extractor::PreDilaText;
 
 
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the completion script of a shell
+    Completions { shell: clap_complete::Shell },
+}
 
 #[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
+#[clap(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// The list of tarballs to download,
     /// the default (empty) list will download all tarballs
     #[clap(short, long, num_args(0..))]
     fond: Vec<Fond>,
 
     /// The query used to search the index
-    #[clap(short, long)]
-    query: String,
+    #[clap(short, long, required = true)]
+    query: Option<String>,
 
     /// Export the search results to a CSV
     /// file with the correct metadata
-    #[clap(short, long)]
-    to_csv: String,
+    #[clap(short, long, required = true)]
+    to_csv: Option<String>,
+
+    /// Maximal number of tarballs downloaded simultaneously
+    /// (10, or the number of `--jobs`)
+    #[clap(long)]
+    concurrency: Option<usize>,
+
+    /// Global bandwidth limit for downloads, in bytes per second
+    /// (accepts suffixes such as `500K`, `2M` or `1G`)
+    #[clap(long, value_parser = tarballs::parse_bytes)]
+    limit_rate: Option<u64>,
+
+    /// How many times failed downloads are retried
+    #[clap(long, default_value = "2")]
+    retries: usize,
+
+    /// Memory the run may use (accepts suffixes such as `512M` or `4G`),
+    /// which sets the heap of the index, the number of tarballs in
+    /// flight and the number of parsing threads instead of `--jobs`
+    /// (at least 58M, since a quarter of it goes to the index)
+    #[clap(long, value_parser = parse_memory_budget)]
+    memory_budget: Option<u64>,
+
+    /// Sync the CSV files every N documents, 0 to only write them at
+    /// the end
+    #[clap(long, value_name = "N", default_value_t = DEFAULT_FLUSH_EVERY)]
+    flush_every: usize,
+
+    /// Keep the extracted text as is in the CSV export, instead of
+    /// removing inline tags, decoding entities and collapsing whitespace
+    #[clap(long, default_value = "false")]
+    raw_text: bool,
+
+    /// Keep the files of the run and the list of processed tarballs in
+    /// this directory instead of a temporary one, so that running the
+    /// same command again after an interruption continues where it stopped
+    #[clap(long)]
+    state_dir: Option<PathBuf>,
+
+    #[clap(flatten)]
+    http: HttpConfig,
+
+    #[clap(flatten)]
+    jobs: JobsConfig,
+
+    #[clap(flatten)]
+    log: LogConfig,
+
+    #[clap(flatten)]
+    failures: FailureConfig,
+}
+
+/// Parse `--memory-budget`, refusing budgets too small for the index
+fn parse_memory_budget(s: &str) -> Result<u64> {
+    tarballs::parse_bytes(s).and_then(MemoryBudget::check)
 }
 
+/// What the workers send to the writer: a document, or the
+/// file that could not be parsed together with the reason
+type Parsed = std::result::Result<Document, (PathBuf, ExtractError)>;
+
+/// Files waiting for each parser, and documents waiting for the
+/// writer, beyond which the previous step waits
+const QUEUE_CAPACITY: usize = 64;
+
 /// create workers that will read and 
 /// process files in parallel.
 /// This creates `num_threads` threads, and returns their handles 
 /// together with channels to communicate with them
 fn span_parser_threads(
     num_threads : usize,
-    writer_channel: &std::sync::mpsc::Sender<PreDilaText>,
-    ) -> Vec<(std::thread::JoinHandle<()>, std::sync::mpsc::Sender<PathBuf>)> {
+    writer_channel: &std::sync::mpsc::SyncSender<Parsed>,
+    clean: bool,
+    ) -> Vec<(std::thread::JoinHandle<()>, std::sync::mpsc::SyncSender<PathBuf>)> {
     use std::thread;
     use std::sync::mpsc;
 
@@ -63,18 +157,24 @@ fn span_parser_threads(
 
     (0..num_threads)
         .map(|_| {
-            let (thread_tx, thread_rx) = mpsc::channel();
+            let (thread_tx, thread_rx) = mpsc::sync_channel(QUEUE_CAPACITY);
             let writer_channel = writer_channel.clone();
             let handle = thread::spawn(move || {
-                let mut file_buffer = String::new();
                 // This is a placeholder for any work that needs to be done
                 // in the worker threads. In this case, we do nothing.
                 while let Ok(file_path) = thread_rx.recv() {
-                    let content = parse_file(file_path, &mut file_buffer);
+                    let content = match parse_document_file(&file_path) {
+                        Ok(mut document) => {
+                            if clean {
+                                document.clean();
+                            }
+                            Ok(document)
+                        }
+                        Err(e) => Err((file_path, e)),
+                    };
                     writer_channel
                         .send(content)
                         .expect("Failed to send content to writer channel");
-                    file_buffer.clear();
                 }
             });
             (handle, thread_tx)
@@ -82,134 +182,342 @@ fn span_parser_threads(
         .collect()
 }
 
-fn spawn_writer_thread<T>(file_path : T) -> (std::thread::JoinHandle<()>, std::sync::mpsc::Sender<PreDilaText>)
+fn spawn_writer_thread<T>(file_path : T, flush_every: usize, failures: FailureLog) -> (std::thread::JoinHandle<()>, std::sync::mpsc::SyncSender<Parsed>)
     where 
         T : AsRef<Path>
 {
     use std::thread;
     use std::sync::mpsc;
 
-    let mut writer = csv::WriterBuilder::new()
-        .has_headers(true)
-        .from_path(file_path)
-        .expect("Failed to create CSV writer");
+    // the documents come in any order from the parsers, the export
+    // cannot be resumed
+    let mut writer = DocumentCsvWriter::new(file_path)
+        .expect("Failed to create CSV writer")
+        .with_flush_every(flush_every);
 
-    let (writer_tx, writer_rx) = mpsc::channel();
+    let (writer_tx, writer_rx) = mpsc::sync_channel::<Parsed>(QUEUE_CAPACITY);
     let handle = thread::spawn(move || {
         while let Ok(row) = writer_rx.recv(){
-            info!("Writing row to CSV: {:?}", row);
-            writer.serialize(row)
-                .expect("Failed to write row to CSV");
+            failures.processed(1);
+            match row {
+                Ok(row) if row.is_empty() => {
+                    debug!("Skipping the empty document {}", row.id());
+                }
+                Ok(row) => {
+                    info!("Writing row to CSV: {:?}", row);
+                    writer.write(&row)
+                        .expect("Failed to write row to CSV");
+                }
+                Err((path, e)) => {
+                    warn!("Could not parse {}: {}", path.display(), e);
+                    writer.write_error(&path.display().to_string(), &e)
+                        .expect("Failed to write error to CSV");
+                    failures.record(path.display().to_string(), Stage::Export, &e);
+                }
+            }
         }
-        info!("Flushing CSV writer");
-        writer.flush().expect("Failed to flush CSV writer");
+        info!("Completing the CSV files");
+        writer.flush().expect("Failed to complete the CSV files");
     });
     (handle, writer_tx)
 }
 
-fn result_file_to_csv<T>(edir: &PathBuf, result_file: T, output_file: T) -> Result<()>
+fn result_file_to_csv<T>(
+    edir: &Path,
+    ledger: T,
+    output_file: T,
+    clean: bool,
+    parsers: usize,
+    flush_every: usize,
+    failures: &FailureLog,
+) -> Result<()>
 where
     T: AsRef<Path>,
 {
-    use std::io::BufRead;
-
     info!("Converting result file to CSV: {}", output_file.as_ref().display());
 
-    // open the resulting file, create it if it does not exist, erase
-    // it if it did
-    let file = std::fs::OpenOptions::new()
-        .read(true)
-        .append(false)
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(result_file)?;
-
-    let mut reader = std::io::BufReader::new(file);
-    // buffer to allocate lines
-    let mut line = String::new();
-
-    let (writer_handle, writer_channel) = spawn_writer_thread(output_file);
-    let parsers = span_parser_threads(5, &writer_channel);
-
-    let mut i = 0;
-    while reader.read_line(&mut line)? != 0 {
-        let path = edir.join(&line);
+    // the documents of a tarball processed again when resuming are read once
+    let entries = read_ledger(ledger.as_ref())?;
+
+    let (writer_handle, writer_channel) = spawn_writer_thread(output_file, flush_every, failures.clone());
+    let parsers = span_parser_threads(parsers.max(1), &writer_channel, clean);
+
+    for (i, entry) in entries.iter().enumerate() {
+        let path = edir.join(&entry.path);
         if let Some((_, tx)) = parsers.get(i % parsers.len()) {
             // send the path to the worker thread
             tx.send(path).expect("Failed to send path to worker thread");
         } else {
             error!("No worker thread available to process file: {}", path.display());
         }
-        i += 1;
-        // clear the line buffer for the next iteration
-        line.clear();
     }
 
-    // wait for everyone to finish
-    for (handle, tx) in parsers {
+    // close the channels first: the threads drain them and stop
+    let (handles, senders): (Vec<_>, Vec<_>) = parsers.into_iter().unzip();
+    drop(senders);
+    for handle in handles {
         handle.join().expect("Failed to join parser thread");
-        drop(tx);
     }
-    writer_handle.join().expect("Failed to join writer thread");
     drop(writer_channel);
+    writer_handle.join().expect("Failed to join writer thread");
     info!("All worker threads finished processing");
 
     Ok(())
 }
 
+/// Download one tarball and check it, recording the failures
+async fn download_one(
+    client: &Client,
+    tarball: Tarball,
+    dl_dir: &Path,
+    dl_opts: &tarballs::DownloadOptions,
+    failures: &FailureLog,
+) -> Option<Tarball> {
+    let tarball_list = std::slice::from_ref(&tarball);
+    let report = tarballs::download_tarball_list(client, tarball_list, dl_dir, dl_opts)
+        .await
+        .expect("Failed to download tarballs");
+    failures.processed(1);
+    if let Some(tarballs::DownloadStatus::Failed(e)) = report.entries.first().map(|e| &e.status) {
+        error!("Failed to download {}", tarball.name);
+        failures.record(tarball.name.clone(), Stage::Download, e);
+        return None;
+    }
+    let broken = tarballs::repair_tarballs(client, dl_dir, tarball_list, dl_opts)
+        .await
+        .expect("Failed to validate tarballs");
+    if !broken.is_empty() {
+        error!("Skipping corrupted tarball {}", tarball.name);
+        return None;
+    }
+    Some(tarball)
+}
+
+/// First stage: download the tarballs (`dl_opts.concurrency` at once)
+/// in order, sending them to the extraction. The downloads wait while
+/// the extraction is behind.
+async fn download_stage(
+    client: Arc<Client>,
+    tarball_list: Vec<Tarball>,
+    dl_dir: Arc<PathBuf>,
+    dl_opts: tarballs::DownloadOptions,
+    failures: FailureLog,
+    downloaded: tokio::sync::mpsc::Sender<Tarball>,
+) {
+    let concurrency = dl_opts.concurrency.max(1);
+    let tarballs = futures::stream::iter(tarball_list)
+        .map(|tarball| {
+            let client = client.clone();
+            let dl_dir = dl_dir.clone();
+            // the clones share the bandwidth limit
+            let dl_opts = dl_opts.clone();
+            let failures = failures.clone();
+            async move { download_one(&client, tarball, &dl_dir, &dl_opts, &failures).await }
+        })
+        .buffered(concurrency);
+    let mut tarballs = std::pin::pin!(tarballs);
+    while let Some(tarball) = tarballs.next().await {
+        if shutdown::is_interrupted() {
+            break;
+        }
+        let Some(tarball) = tarball else {
+            continue;
+        };
+        // the next stages stopped
+        if downloaded.send(tarball).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Last stage: index the extracted tarballs one at a time in an index
+/// in RAM, keeping the documents that match `query` in `results_dir`.
+/// The tarballs are marked as processed once their results are saved.
+#[allow(clippy::too_many_arguments)]
+fn index_stage(
+    mut extracted: tokio::sync::mpsc::Receiver<Extracted>,
+    query: &str,
+    results_dir: &Path,
+    ledger: &mut Ledger,
+    mut state: Option<&mut ResumeState>,
+    pb: &indicatif::ProgressBar,
+    budget: &MemoryBudget,
+    failures: &FailureLog,
+) {
+    let (index, flds) = tarballs::init_tantivy_ram().expect("Failed to create index");
+    let mut writer = index.writer(budget.writer_heap).expect("Failed to create writer");
+    info!("Prepared the index and writer");
+
+    while let Some((tarball, dir)) = extracted.blocking_recv() {
+        if shutdown::is_interrupted() {
+            break;
+        }
+        pb.set_message(format!("Indexing {}", tarball.name));
+        if let Some(dir) = dir {
+            tarballs::index_files_in_dir(
+                &mut writer,
+                &flds,
+                &dir,
+                false,
+                &tarballs::IndexingOptions {
+                    threads: budget.indexers,
+                    failures: failures.clone(),
+                    ..Default::default()
+                },
+            )
+            .expect("Failed to index files");
+
+            // the interrupted tarball is processed again when resuming,
+            // the results of the previous ones are exported
+            if shutdown::is_interrupted() {
+                break;
+            }
+            writer.commit().expect("Failed to commit writer");
+
+            let matches = tarballs::matching_documents(
+                &[(&index, &flds)],
+                query,
+                &tarballs::SearchOptions::default(),
+            )
+            .expect("Failed to search index");
+            debug!("{} matches in {}", matches.len(), tarball.name);
+
+            // move the results of the search to the "results" directory
+            for entry in matches.iter() {
+                let infile = dir.join(&entry.path);
+                let outfile = results_dir.join(&entry.path);
+                debug!("Moving file from {} to {}", infile.display(), outfile.display());
+                if let Some(parent) = outfile.parent() {
+                    std::fs::create_dir_all(parent).expect("Failed to create parent directory");
+                }
+                std::fs::rename(&infile, &outfile).expect("Failed to move file");
+            }
+            std::fs::remove_dir_all(&dir).expect("Failed to remove directory");
+
+            // clear the index
+            writer
+                .delete_all_documents()
+                .expect("Failed to delete all documents");
+            writer.commit().expect("Failed to commit writer");
+
+            // the matches are saved before the tarball is marked as processed
+            ledger.append(&matches).expect("Failed to write result file");
+        }
+        // the tarballs that failed to download are tried again when resuming
+        if let Some(state) = state.as_mut() {
+            state
+                .mark_processed([tarball.name.as_str()])
+                .expect("Failed to record processed tarballs");
+        }
+        pb.inc(1);
+    }
+    if shutdown::is_interrupted() {
+        warn!("Interrupted, exporting the results of the processed tarballs");
+        if let Some(state) = state.as_ref() {
+            warn!("Run the same command again to resume from {}", state.dir().display());
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
-    // Initialize the logger
-    env_logger::init();
+    shutdown::install();
+    let args = Cli::parse();
+    let max_failure_rate = args.failures.max_failure_rate;
+    let failures = FailureLog::new();
+    run(args, &failures).await;
+    if let Err(e) = failures.report(Path::new(ERRORS_FILE), max_failure_rate) {
+        error!("{:#}", e);
+        std::process::exit(1);
+    }
+    if shutdown::is_interrupted() {
+        std::process::exit(shutdown::INTERRUPTED_EXIT_CODE);
+    }
+}
+
+async fn run(args: Cli, failures: &FailureLog) {
 
-    use futures::StreamExt;
-    use std::sync::Arc;
+    args.log.init();
 
     info!("Starting dilarxiv-oneshot...");
 
-    let args = Cli::parse();
+    if let Some(Command::Completions { shell }) = args.command {
+        let name = "dilarxiv-oneshot";
+        clap_complete::generate(shell, &mut Cli::command(), name, &mut std::io::stdout());
+        return;
+    }
+    let (Some(query), Some(to_csv)) = (args.query.clone(), args.to_csv.clone()) else {
+        unreachable!("the query and the output are required without subcommand");
+    };
 
-    let tmpdir_doc = TempDir::new().expect("Failed to create temporary directory");
+    let fonds = if args.fond.is_empty() {
+        FONDS
+    } else {
+        &args.fond
+    };
 
-    let tmpdir = tmpdir_doc.path().to_path_buf();
+    // two runs on the same state directory would process the same tarballs
+    let _lock = args.state_dir.as_ref().map(|dir| {
+        DataLock::acquire(dir, "oneshot").unwrap_or_else(|e| {
+            error!("{:#}", e);
+            std::process::exit(1);
+        })
+    });
+
+    // without state directory, everything happens in a temporary directory
+    let (_tmpdir_doc, mut state, tmpdir) = match &args.state_dir {
+        Some(dir) => {
+            let run = Run {
+                query: query.clone(),
+                fonds: fonds.to_vec(),
+            };
+            let state = ResumeState::open(dir, &run).expect("Failed to open state directory");
+            if !state.is_empty() {
+                info!("Resuming after {} processed tarballs", state.len());
+            }
+            (None, Some(state), dir.clone())
+        }
+        None => {
+            let tmp = TempDir::new().expect("Failed to create temporary directory");
+            let dir = tmp.path().to_path_buf();
+            (Some(tmp), None, dir)
+        }
+    };
 
     let dl_dir = Arc::new(tmpdir.join("tarballs"));
     let extract_dir = Arc::new(tmpdir.join("extracted"));
     let results_dir = Arc::new(tmpdir.join("results"));
 
-    // Create the directories if they don't exist
-    std::fs::create_dir(dl_dir.as_path()).expect("Failed to create download directory");
-    std::fs::create_dir(extract_dir.as_path()).expect("Failed to create extract directory");
-    std::fs::create_dir(results_dir.as_path()).expect("Failed to create results directory");
-
-    let result_file = tmpdir.join("results.txt");
-    let result_tmp = tmpdir.join("results_tmp.txt");
+    // Create the directories, the files left by an interrupted tarball
+    // are removed (the tarball is processed again)
+    for dir in [dl_dir.as_path(), extract_dir.as_path()] {
+        if dir.exists() {
+            std::fs::remove_dir_all(dir).expect("Failed to clean directory");
+        }
+    }
+    std::fs::create_dir_all(dl_dir.as_path()).expect("Failed to create download directory");
+    std::fs::create_dir_all(extract_dir.as_path()).expect("Failed to create extract directory");
+    std::fs::create_dir_all(results_dir.as_path()).expect("Failed to create results directory");
 
-    let result_file_out = Some(
-        result_tmp
-            .to_str()
-            .expect("Failed to convert path to string")
-            .to_string(),
-    );
-    let mut result_file_final = BufWriter::new(
-        std::fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .append(false)
-            .create(true)
-            .open(&result_file)
-            .expect("Failed to open result file"),
-    );
+    // the results of the previous runs are kept when resuming
+    let mut ledger = Ledger::open(&tmpdir.join(LEDGER_FILE)).expect("Failed to open result file");
 
     info!("Created all temporary directories");
 
-    let client = Arc::new(reqwest::Client::new());
-
-    let fonds = if args.fond.is_empty() {
-        FONDS
-    } else {
-        &args.fond
+    let client = Arc::new(args.http.build_client().expect("Failed to create HTTP client"));
+    let concurrency = args.jobs.concurrency();
+    open_files::set_limit(concurrency.open_files);
+    let budget = match args.memory_budget {
+        Some(bytes) => MemoryBudget::new(bytes),
+        None => MemoryBudget::from_concurrency(&concurrency),
+    };
+    info!("Sharing the memory as {:?}", budget);
+    let dl_opts = tarballs::DownloadOptions {
+        retries: args.retries,
+        ..tarballs::DownloadOptions::new(
+            args.concurrency.unwrap_or(concurrency.downloads),
+            args.limit_rate,
+        )
     };
 
     // First list tarballs to download (relatively small)
@@ -218,9 +526,16 @@ async fn main() {
         .filter_map(|fond| {
             let client = client.clone();
             async move {
-                match tarballs::list_tarballs(&client, fond).await {
-                    Ok(tarballs) => {
-                        info!("Found {} tarballs for {}", tarballs.len(), fond);
+                match tarballs::list_fond_listing(&client, fond).await {
+                    Ok(listing) => {
+                        info!("Found {} tarballs for {}", listing.tarballs.len(), fond);
+                        // the documents of these files would silently miss from the results
+                        for unparsable in listing.unparsable.iter() {
+                            error!("Not downloading {}: {}", unparsable.name, unparsable.error);
+                            failures.processed(1);
+                            failures.record(unparsable.name.clone(), Stage::Download, &unparsable.error);
+                        }
+                        let tarballs = listing.tarballs;
                         Some(async move { tarballs })
                     }
                     Err(e) => {
@@ -230,24 +545,19 @@ async fn main() {
                 }
             }
         })
-        .buffered(10)
+        .buffered(concurrency.downloads)
         .collect::<Vec<_>>()
         .await
         .into_iter()
         .flatten()
         .rev()
+        .filter(|tarball| !state.as_ref().is_some_and(|s| s.is_processed(&tarball.name)))
         .collect::<Vec<_>>();
 
     info!("Found {} tarballs to download", strm.len());
 
-    let (index, flds) = tarballs::init_tantivy_ram().expect("Failed to create index");
-
-    let mut writer = index.writer(100_000_000).expect("Failed to create writer");
-
-    info!("Prepared the index and writer");
-
     // create the progress bar
-    let pb = ProgressBar::new(strm.len() as u64);
+    let pb = logging::progress_bar(strm.len() as u64);
     pb.set_style(
         ProgressStyle::default_bar()
             .template(
@@ -257,112 +567,57 @@ async fn main() {
             .progress_chars("##-"),
     );
 
-    // now, for every block of x tarballs,
-    // download + extract them in parallel
-    for chunk in strm.chunks(10) {
-        pb.set_message(format!("Processing {} tarballs", chunk.len()));
-        // this download happens in parallel
-        let tblist = tarballs::download_tarball_list(&client, chunk, &dl_dir)
-            .await
-            .expect("Failed to download tarballs");
-
-        info!("Downloaded tarballs");
-
-        // extract them (also in parallel)
-        let _ = futures::stream::iter(tblist)
-            .map(|tarball_path| {
-                let dl_dir = dl_dir.clone();
-                let extract_dir = extract_dir.clone();
-                async move {
-                    let path = dl_dir.join(&tarball_path);
-                    tarballs::extract_tarball(&path, &extract_dir)
-                }
-            })
-            .buffer_unordered(10)
-            .collect::<Vec<_>>()
-            .await;
-
-        info!("Extracted tarballs from {}", dl_dir.display());
-        info!("Extracted to {}", extract_dir.display());
-
-        // index the extracted files
-        // (sequentially)
-        tarballs::index_files_in_dir(&mut writer, &flds, &extract_dir)
-            .expect("Failed to index files");
-        
-        info!("Indexed all the files");
-
-        // commit the writer
-        writer.commit().expect("Failed to commit writer");
-
-        // now search the index
-        match tarballs::search_index(&index, &flds, &result_file_out, &args.query) {
-            Ok(_) => {
-                info!("Search completed successfully");
-            }
-            Err(e) => {
-                error!("Failed to search index: {}", e);
-            }
-        }
-        info!("Search results written to {}", result_file.display());
-
-        debug!("Copying results to final result file");
-        // we should be `appending` results here...
-        std::io::copy(
-            &mut std::fs::File::open(&result_tmp).expect("Failed to open result file"),
-            &mut result_file_final,
-        )
-            .expect("Failed to copy result file");
-
-
-        // move the results of the search to the "results" directory
-        // this is done by reading all the lines in the
-        // `result_file` and performing std::fs operations
-
-        let results = std::fs::read_to_string(&result_tmp)
-            .expect("Failed to read results file");
-
-        for line in results.lines() {
-            let infile = extract_dir.join(line);
-            let outfile = results_dir.join(line);
-
-            debug!("Moving file from {} to {}", infile.display(), outfile.display());
-            // create the parent directory if it doesn't exist
-            if let Some(parent) = outfile.parent() {
-                debug!("Creating parent directory: {}", parent.display());
-                std::fs::create_dir_all(parent).expect("Failed to create parent directory");
-            }
-
-            // move the file
-            std::fs::rename(&infile, &outfile).expect("Failed to move file");
+    // the stages run at once, each one waiting while the next one is
+    // behind: the tarballs on the disk are the ones in progress and the
+    // ones waiting in the channels
+    let capacity = budget.tarballs_in_flight;
+    let (downloaded_tx, downloaded_rx) = tokio::sync::mpsc::channel(capacity);
+    let (extracted_tx, extracted_rx) = tokio::sync::mpsc::channel(capacity);
+    let indexing = tokio::task::spawn_blocking({
+        let results_dir = results_dir.clone();
+        let pb = pb.clone();
+        let failures = failures.clone();
+        move || {
+            index_stage(
+                extracted_rx,
+                &query,
+                &results_dir,
+                &mut ledger,
+                state.as_mut(),
+                &pb,
+                &budget,
+                &failures,
+            );
+            ledger
         }
-
-        // delete tarballs and extracted files
-        std::fs::remove_dir_all(dl_dir.as_path()).expect("Failed to remove download directory");
-        std::fs::remove_dir_all(extract_dir.as_path()).expect("Failed to remove directory");
-
-        // recreate them immediately
-        std::fs::create_dir_all(dl_dir.as_path()).expect("Failed to create download directory");
-        std::fs::create_dir_all(extract_dir.as_path()).expect("Failed to create extract directory");
-
-        // clear the index
-        writer
-            .delete_all_documents()
-            .expect("Failed to delete all documents");
-        writer.commit().expect("Failed to commit writer");
-
-        // clear the result file
-        std::fs::remove_file(&result_tmp)
-            .expect("Failed to remove temporary result file");
-
-        pb.inc(chunk.len() as u64);
-    }
+    });
+    let (_, _, ledger) = tokio::join!(
+        download_stage(client.clone(), strm, dl_dir.clone(), dl_opts, failures.clone(), downloaded_tx),
+        extract_stage(
+            downloaded_rx,
+            dl_dir.clone(),
+            extract_dir.clone(),
+            concurrency.extractions.min(budget.tarballs_in_flight),
+            failures.clone(),
+            extracted_tx,
+        ),
+        indexing,
+    );
+    let ledger = ledger.expect("Failed to join the indexing");
     pb.finish_with_message("All tarballs processed");
 
     info!("All tarballs processed, moving results to CSV");
 
-    result_file_to_csv(&results_dir, result_file.as_path(), args.to_csv.as_ref())
+    result_file_to_csv(
+        &results_dir,
+        ledger.path(),
+        to_csv.as_ref(),
+        !args.raw_text,
+        budget.parsers,
+        args.flush_every,
+        failures,
+    )
         .expect("Failed to convert result file to CSV");
 
-    info!("Results exported to {}", args.to_csv);
+    info!("Results exported to {}", to_csv);
 }
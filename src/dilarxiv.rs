@@ -1,101 +1,616 @@
-use clap::Parser;
+use clap::{CommandFactory, Parser, Subcommand};
 
-use anyhow::{Context, Result};
+use anyhow::Result;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 
-use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, error, info, warn};
 
-use log::{error, info, warn};
-
-use legifrance::dumps::extractor::{count_tags_in_file, parse_file};
+use legifrance::dumps::extractor::{
+    self, DocumentCsvWriter, DocumentJsonlWriter, DocumentWriter, count_tags_in_file,
+};
+use legifrance::dumps::extractor::anonymize::{Anonymization, DocumentAnonymizedWriter};
+use legifrance::dumps::extractor::arrow::DocumentArrowWriter;
+use legifrance::dumps::extractor::export::DEFAULT_FLUSH_EVERY;
+use legifrance::dumps::extractor::citation::{CitationFormat, DocumentCitationWriter};
+use legifrance::dumps::extractor::hf::{self, DocumentHfWriter};
+use legifrance::dumps::extractor::markdown::{DocumentFileWriter, TextFormat};
+use legifrance::dumps::extractor::segment::DocumentSegmentWriter;
+use legifrance::dumps::extractor::sqlite::DocumentSqliteWriter;
+#[cfg(feature = "object-store")]
+use legifrance::dumps::bucket::{Bucket, PACKED_FOLDER, TARBALLS_FOLDER};
+use legifrance::dumps::calendar;
+use legifrance::dumps::cas::ContentStore;
+use legifrance::dumps::corpus::{
+    DocumentSource, export_documents, extract_tarballs, get_tarballs, open_existing_indices,
+    update_and_index_data, write_documents,
+};
+use legifrance::dumps::diff;
+use legifrance::dumps::embeddings;
+use legifrance::dumps::failures::{ERRORS_FILE, FailureConfig, FailureLog, Stage};
+use legifrance::dumps::feed;
 use legifrance::dumps::fonds::{FONDS, Fond};
-use legifrance::dumps::tarballs::{self, Tarball};
-
-async fn update_and_index_data(
-    fonds: &[Fond],
-    tdir: &PathBuf,
-    idir: &PathBuf,
-    edir: &PathBuf,
-    tmpd: &PathBuf,
-) -> Result<()> {
-    // 1. download new tarballs
-    // 2. extract them in a temporary directory
-    // 3. index them
-    // 4. move them to the good directory
+#[cfg(feature = "api")]
+use legifrance::dumps::judilibre;
+use legifrance::dumps::lock::DataLock;
+use legifrance::dumps::manifest::{self, Manifest};
+use legifrance::dumps::mirror;
+use legifrance::dumps::notify::{self, Notifier};
+use legifrance::dumps::pack::{self, PackReader};
+use legifrance::dumps::queries;
+use legifrance::dumps::server;
+use legifrance::dumps::tarballs::{self, DownloadOptions, Tarball};
+use legifrance::dumps::tui;
+use legifrance::dumps::validate;
+use legifrance::concurrency::JobsConfig;
+use legifrance::http::HttpConfig;
+use legifrance::logging::LogConfig;
+use legifrance::open_files;
+use legifrance::shutdown;
 
-    let tb = get_tarballs(fonds, tdir).await?;
-    if tb.is_empty() {
-        info!("No new tarballs to download");
-        return Ok(());
-    }
-    info!("Downloaded {} tarballs", tb.len());
+/// Options of the downloads of tarballs
+#[derive(clap::Args, Debug)]
+struct DownloadArgs {
+    /// The fonds to download, the default (empty) list means all fonds
+    #[clap(short, long, num_args(0..))]
+    fond: Vec<Fond>,
 
-    let paths = tb.iter().map(|t| t.as_ref()).collect::<Vec<_>>();
+    /// Maximal number of tarballs downloaded simultaneously
+    /// (10, or the number of `--jobs`)
+    #[clap(long)]
+    concurrency: Option<usize>,
 
-    // Extract the tarballs
-    extract_tarballs(tdir, &paths, tmpd).context("Failed to extract tarballs")?;
+    /// Global bandwidth limit for downloads, in bytes per second
+    /// (accepts suffixes such as `500K`, `2M` or `1G`)
+    #[clap(long, value_parser = tarballs::parse_bytes)]
+    limit_rate: Option<u64>,
 
-    // create the index
-    let (index, flds) = tarballs::init_tantivy(&idir).expect("Failed to create index");
+    /// How many times failed downloads are retried
+    #[clap(long, default_value = "2")]
+    retries: usize,
 
-    info!("Index created at {}", idir.display());
+    /// Only print what would be downloaded, with an
+    /// estimation of the disk space needed
+    #[clap(long, default_value = "false")]
+    dry_run: bool,
+}
 
-    let mut writer = index.writer(50_000_000).expect("Failed to create writer");
+/// Options of the creation of the index
+#[derive(clap::Args, Debug)]
+struct IndexArgs {
+    /// Use one index per fond (in `index/JADE`, `index/CASS`, ...),
+    /// so that a fond can be reindexed alone
+    #[clap(long, default_value = "false")]
+    per_fond: bool,
 
-    tarballs::index_files_in_dir(&mut writer, &flds, &tmpd).expect("Failed to index files");
+    /// Index texts that embed their articles as one document per
+    /// article, listed as `path#article_id` in search results
+    #[clap(long, default_value = "false")]
+    per_article: bool,
 
-    writer.commit().expect("Failed to commit writer");
+    /// Reduce words to their stem, so that "constructions"
+    /// matches "construction"
+    #[clap(long, default_value = "false")]
+    stem: bool,
 
-    // Move the extracted files to the final directory
-    let mut dir_stack = Vec::new();
-    dir_stack.push(tmpd.clone());
-    while let Some(current_dir) = dir_stack.pop() {
-        for entry in std::fs::read_dir(&current_dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_file() {
-                // tmpdir/current_dir/file_name -> edir/current_dir/file_name
-                // 1) create the parent directory if it does not exist
-                let edir_current = edir.join(&current_dir.strip_prefix(tmpd)?);
-                std::fs::create_dir_all(&edir_current).context(format!(
-                    "Failed to create directory {}",
-                    edir_current.display()
-                ))?;
-                // 2) move the file
-                let target_path = edir_current.join(path.file_name().unwrap());
-                std::fs::rename(&path, &target_path).context(format!(
-                    "Failed to move file from {} to {}",
-                    path.display(),
-                    target_path.display()
-                ))?;
-            } else if path.is_dir() {
-                // Recursively move directories
-                dir_stack.push(path.clone());
-            }
-        }
+    /// TOML file of settings of the analyzer: extra stopwords, maximal
+    /// length of the words, removal of the accents and stemming
+    #[clap(long)]
+    analyzer: Option<PathBuf>,
+
+    /// Do not store the text of the documents in the index,
+    /// which halves its size but disables `similar`
+    #[clap(long, default_value = "false")]
+    no_store_body: bool,
+
+    /// Number of threads parsing the files, 0 for one per
+    /// core (or the number of `--jobs`)
+    #[clap(long, default_value = "0")]
+    index_threads: usize,
+
+    /// Commit the index every that many documents
+    /// (0 to commit once at the end)
+    #[clap(long, default_value = "100000")]
+    batch_size: usize,
+}
+
+/// A query and the documents it is restricted to
+#[derive(clap::Args, Debug)]
+struct QueryArgs {
+    /// The query, e.g. `"permis de construire" AND year:[2020 TO 2024]`
+    query: String,
+
+    /// The fonds searched when there is one index per fond,
+    /// the default (empty) list means all fonds
+    #[clap(short, long, num_args(0..))]
+    fond: Vec<Fond>,
+
+    /// Only documents dated on or after this day (YYYY-MM-DD)
+    #[clap(long)]
+    from: Option<chrono::NaiveDate>,
+
+    /// Only documents dated on or before this day (YYYY-MM-DD)
+    #[clap(long)]
+    to: Option<chrono::NaiveDate>,
+}
+
+/// Options of `watch`
+#[derive(clap::Args, Debug, Clone)]
+struct WatchArgs {
+    /// Time between two updates (e.g. `30m`, `24h` or `7d`)
+    #[clap(long, default_value = "24h", value_parser = tarballs::parse_duration)]
+    interval: std::time::Duration,
+
+    /// Saved queries whose number of results is logged after each update
+    #[clap(long, num_args(0..))]
+    saved: Vec<String>,
+
+    /// TOML file of saved queries
+    #[clap(long, default_value = queries::QUERIES_FILE)]
+    queries: PathBuf,
+
+    /// URLs receiving the new results of the saved queries as JSON
+    #[clap(long, num_args(0..))]
+    webhook: Vec<String>,
+
+    /// Shell commands run with the new results of the
+    /// saved queries as JSON on their standard input
+    #[clap(long, num_args(0..))]
+    exec: Vec<String>,
+
+    /// ntfy topics (names of topics of ntfy.sh, or URLs)
+    /// notified of the new results of the saved queries
+    #[clap(long, num_args(0..))]
+    ntfy: Vec<String>,
+
+    /// Directory where an Atom feed of the new results of every
+    /// saved query is kept (in `{name}.atom`)
+    #[clap(long)]
+    feeds: Option<PathBuf>,
+}
+
+impl WatchArgs {
+    fn notifiers(&self) -> Vec<Notifier> {
+        let webhooks = self.webhook.iter().cloned().map(Notifier::Webhook);
+        let commands = self.exec.iter().cloned().map(Notifier::Command);
+        let topics = self.ntfy.iter().cloned().map(Notifier::Ntfy);
+        webhooks.chain(commands).chain(topics).collect()
     }
+}
 
-    Ok(())
+/// Format of the exported documents
+#[derive(clap::Args, Debug)]
+struct ExportArgs {
+    /// Write JSON lines (in `{name}.jsonl`) instead of CSV files
+    #[clap(long, default_value = "false")]
+    jsonl: bool,
+
+    /// Add `amounts`, `dates` and `case_numbers` columns,
+    /// found in the text of the documents
+    #[clap(long, default_value = "false")]
+    entities: bool,
+
+    /// Split the text into paragraphs and sentences, written as JSON
+    /// lines with their offsets (in `{name}.segments.jsonl`)
+    #[clap(long, default_value = "false", conflicts_with = "jsonl")]
+    segments: bool,
+
+    /// Write one file per document instead, in the `{name}.md` (or
+    /// `{name}.txt`, `{name}.xml`) directory: Markdown with a YAML front
+    /// matter (id, date, jurisdiction, title), plain text, or Akoma Ntoso
+    #[clap(long, value_enum, conflicts_with_all = ["jsonl", "segments"])]
+    format: Option<TextFormat>,
+
+    /// Write a SQLite database (in `{name}.sqlite`) with `documents`,
+    /// `links` and `parties` tables instead of CSV files
+    #[clap(long, default_value = "false", conflicts_with_all = ["jsonl", "segments", "format"])]
+    sqlite: bool,
+
+    /// Write an Arrow IPC stream (in `{name}.arrows`), read with
+    /// `pyarrow.ipc.open_stream`, instead of CSV files
+    #[clap(long, default_value = "false", conflicts_with_all = ["jsonl", "segments", "format", "sqlite"])]
+    arrow: bool,
+
+    /// Write a HuggingFace dataset in the `{name}.hf` directory:
+    /// gzipped JSON lines shards with `id`, `text` and `metadata`,
+    /// and a `dataset_infos.json`
+    #[clap(long, default_value = "false", conflicts_with_all = ["jsonl", "segments", "format", "sqlite", "arrow"])]
+    hf: bool,
+
+    /// Number of documents per shard of `--hf`
+    #[clap(long, default_value_t = hf::HF_SHARD_SIZE, requires = "hf")]
+    shard_size: usize,
+
+    /// Write the citations of the documents instead, in `{name}.csl.json`
+    /// (CSL-JSON, for Zotero) or `{name}.bib` (biblatex)
+    #[clap(long, value_enum, value_name = "FORMAT", conflicts_with_all = ["jsonl", "segments", "format", "sqlite", "arrow", "hf"])]
+    cite: Option<CitationFormat>,
+
+    /// Write a training corpus in `{name}.anonymized.jsonl` instead, with
+    /// `id`, `text`, `metadata` and an `anonymized` flag: the names of
+    /// people in the text of decisions are masked by `[NOM]` (`mask`)
+    /// or removed with their honorific (`strip`)
+    #[clap(long, value_enum, value_name = "MODE", conflicts_with_all = ["jsonl", "segments", "format", "sqlite", "arrow", "hf", "cite"])]
+    anonymize: Option<Anonymization>,
+
+    /// Write the documents to a PostgreSQL database instead (connection
+    /// string, e.g. `postgresql://user@localhost/legifrance`), in
+    /// `documents`, `document_links` and `document_parties` tables
+    #[cfg(feature = "postgres")]
+    #[clap(long, value_name = "URL", conflicts_with_all = ["jsonl", "segments", "format", "sqlite", "arrow", "hf", "cite", "anonymize"])]
+    postgres: Option<String>,
+
+    /// Sync the CSV or JSON lines files and save the progress of the
+    /// export every N documents, so that an export stopped by a crash
+    /// continues from there when run again (0 to only write at the end)
+    #[clap(long, value_name = "N", default_value_t = DEFAULT_FLUSH_EVERY)]
+    flush_every: usize,
+
+    /// Keep the extracted text as is, instead of removing inline
+    /// tags, decoding entities and collapsing whitespace
+    #[clap(long, default_value = "false")]
+    raw_text: bool,
+
+    /// Export texts that embed their articles as one record per article
+    #[clap(long, default_value = "false")]
+    per_article: bool,
+
+    /// Read the documents from the `packed` folder
+    #[clap(long, default_value = "false")]
+    from_pack: bool,
+
+    /// Read the documents from the content addressable store
+    #[clap(long, default_value = "false", conflicts_with = "from_pack")]
+    content_addressed: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Download the tarballs of the dila server that are not
+    /// already in the `tarballs` folder
+    Download {
+        #[clap(flatten)]
+        download: DownloadArgs,
+
+        /// Make the tarball directory exactly match the dila server
+        /// for the selected fonds, deleting the tarballs that
+        /// disappeared upstream
+        #[clap(long, default_value = "false")]
+        mirror: bool,
+
+        /// Where to write the JSON report of `--mirror`
+        /// (defaults to `tarballs/mirror-report.json`)
+        #[clap(long, requires = "mirror")]
+        mirror_report: Option<PathBuf>,
+    },
+    /// Extract the downloaded tarballs in the `extracted` folder
+    Extract {
+        /// Store the extracted files in a content addressable store
+        /// (the `cas` folder) where identical documents are only
+        /// written once
+        #[clap(long, default_value = "false")]
+        content_addressed: bool,
+
+        /// Then pack the extracted documents into compressed
+        /// shards (the `packed` folder)
+        #[clap(long, default_value = "false", conflicts_with = "content_addressed")]
+        pack: bool,
+    },
+    /// Create the index of the extracted documents,
+    /// replacing the current one once complete
+    Index {
+        #[clap(flatten)]
+        index: IndexArgs,
+
+        /// Read the documents from the `packed` folder
+        #[clap(long, default_value = "false", conflicts_with = "per_fond")]
+        from_pack: bool,
+
+        /// Read the documents from the content addressable store
+        #[clap(long, default_value = "false", conflicts_with_all = ["per_fond", "from_pack"])]
+        content_addressed: bool,
+    },
+    /// Download, extract and index the new tarballs
+    Update {
+        #[clap(flatten)]
+        download: DownloadArgs,
+
+        #[clap(flatten)]
+        index: IndexArgs,
+    },
+    /// Update the index periodically, until the process is stopped
+    Watch {
+        #[clap(flatten)]
+        download: DownloadArgs,
+
+        #[clap(flatten)]
+        index: IndexArgs,
+
+        #[clap(flatten)]
+        watch: WatchArgs,
+    },
+    /// Search the index
+    Search {
+        #[clap(flatten)]
+        query: QueryArgs,
+
+        /// Print the number of results per year and per jurisdiction
+        #[clap(long, default_value = "false")]
+        facets: bool,
+
+        /// Order of the results: by relevance,
+        /// or by date with the newest first
+        #[clap(long, value_enum, default_value = "score")]
+        sort: tarballs::SortBy,
+
+        /// Number of results printed
+        #[clap(long, default_value = "10", value_parser = positive_count())]
+        limit: usize,
+
+        /// Number of best results skipped, to see the next pages
+        #[clap(long, default_value = "0")]
+        offset: usize,
+
+        /// Print the paths of all the results, sorted by path,
+        /// instead of the best ones
+        #[clap(long, default_value = "false")]
+        all: bool,
+
+        /// Save the paths of all the results in a file
+        #[clap(short, long)]
+        save: Option<String>,
+    },
+    /// Convert documents to CSV (or JSON lines): the results
+    /// of a query, or the documents of a result list
+    Export {
+        /// Export the results of this query
+        #[clap(long, required_unless_present = "list")]
+        query: Option<String>,
+
+        /// Where to write the results of `--query`
+        /// (`{output}.csv`, `{output}.jsonl`, ...)
+        #[clap(short, long, requires = "query", required_unless_present = "list")]
+        output: Option<String>,
+
+        /// Export the documents listed in this file (one path per line,
+        /// see `search --save`), written next to it
+        #[clap(long, conflicts_with = "query")]
+        list: Option<String>,
+
+        /// The fonds searched when there is one index per fond
+        #[clap(short, long, num_args(0..), requires = "query")]
+        fond: Vec<Fond>,
+
+        /// Only documents dated on or after this day (YYYY-MM-DD)
+        #[clap(long, requires = "query")]
+        from: Option<chrono::NaiveDate>,
+
+        /// Only documents dated on or before this day (YYYY-MM-DD)
+        #[clap(long, requires = "query")]
+        to: Option<chrono::NaiveDate>,
+
+        #[clap(flatten)]
+        format: ExportArgs,
+    },
+    /// Print the number of results of a query, in total or
+    /// per year or month, without retrieving any document
+    Stats {
+        #[clap(flatten)]
+        query: QueryArgs,
+
+        /// Count the results per year or per month
+        #[clap(long, value_name = "BUCKET")]
+        histogram: Option<tarballs::HistogramBucket>,
+    },
+    /// List the tarballs available on the dila server
+    List {
+        /// The fonds to list, the default (empty) list means all fonds
+        #[clap(short, long, num_args(0..))]
+        fond: Vec<Fond>,
+
+        /// Print the listing as JSON
+        #[clap(long, default_value = "false")]
+        json: bool,
+    },
+    /// Check the metadata of the extracted documents and
+    /// write a data quality report
+    Validate {
+        /// Where to write the JSON report
+        #[clap(long, default_value = "validation-report.json")]
+        report: PathBuf,
+    },
+    /// Compare two states of the corpus and report the documents
+    /// added, removed and modified, per fond
+    Diff {
+        /// The corpus before: an extraction directory, or a day
+        /// (YYYY-MM-DD) for the corpus of the tarballs published up to it
+        old: diff::SnapshotSource,
+
+        /// The corpus after: an extraction directory, or a day
+        new: diff::SnapshotSource,
+
+        /// Where to write the JSON report of the number of changes per fond
+        #[clap(long, default_value = "diff-report.json")]
+        report: PathBuf,
+
+        /// Where to write every change as JSON lines
+        /// (`path`, `fond` and `change`)
+        #[clap(long)]
+        changes: Option<PathBuf>,
+    },
+    /// Write the calendar of the articles of consolidated texts (LEGI)
+    /// entering into force or abrogated between two days
+    Calendar {
+        /// First day of the calendar (YYYY-MM-DD)
+        #[clap(long)]
+        from: chrono::NaiveDate,
+
+        /// Last day of the calendar (YYYY-MM-DD)
+        #[clap(long)]
+        to: chrono::NaiveDate,
+
+        /// Format of the calendar
+        #[clap(long, value_enum, default_value = "ics")]
+        format: calendar::CalendarFormat,
+
+        /// Where to write the calendar (defaults to
+        /// `calendar.ics` or `calendar.json`)
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Look up the extracted decisions of the Cour de cassation and of
+    /// the cours d'appel in Judilibre (PISTE), and write their themes,
+    /// solutions and zones as JSON lines
+    #[cfg(feature = "api")]
+    Judilibre {
+        /// The fonds of the decisions, the default (empty) list means
+        /// CASS, INCA and CAPP
+        #[clap(short, long, num_args(0..))]
+        fond: Vec<Fond>,
+
+        /// Where to write the metadata, the decisions already in this
+        /// file are not looked up again
+        #[clap(short, long, default_value = judilibre::JUDILIBRE_FILE)]
+        output: PathBuf,
+
+        /// File of the client id of the PISTE API
+        #[clap(long, default_value = "client-id.txt")]
+        client_id: PathBuf,
+
+        /// File of the client secret of the PISTE API
+        #[clap(long, default_value = "client-secret.txt")]
+        client_secret: PathBuf,
+    },
+    /// Write the manifest of the corpus: every tarball of the
+    /// `tarballs` folder (name, date, size and SHA-256) and the
+    /// settings of the extraction
+    Freeze {
+        /// Where to write the manifest
+        #[clap(short, long, default_value = manifest::MANIFEST_FILE)]
+        output: PathBuf,
+
+        /// The fonds of the corpus, the default (empty) list means all fonds
+        #[clap(short, long, num_args(0..))]
+        fond: Vec<Fond>,
+
+        /// Leave out the tarballs published after this day (YYYY-MM-DD)
+        #[clap(long)]
+        until: Option<chrono::NaiveDate>,
+
+        /// The tarballs are extracted in the content addressable store
+        #[clap(long, default_value = "false")]
+        content_addressed: bool,
+
+        /// The extracted documents are packed into shards
+        #[clap(long, default_value = "false", conflicts_with = "content_addressed")]
+        pack: bool,
+    },
+    /// Check that the tarballs of the `tarballs` folder
+    /// are the ones listed in a manifest
+    Verify {
+        #[clap(long, default_value = manifest::MANIFEST_FILE)]
+        manifest: PathBuf,
+    },
+    /// Download the tarballs of a manifest that are missing,
+    /// check them, and extract them with the settings of the manifest
+    Restore {
+        #[clap(long, default_value = manifest::MANIFEST_FILE)]
+        manifest: PathBuf,
+    },
+    /// Print the path and metadata of an indexed document
+    Get {
+        /// Identifier of the document (e.g. CETATEXT000049314894)
+        id: String,
+
+        /// Also print the original XML file
+        #[clap(long, default_value = "false")]
+        xml: bool,
+    },
+    /// Print the documents whose text is the most similar
+    /// to the text of a document
+    Similar {
+        /// Identifier (e.g. CETATEXT000049314894) or path of the document
+        document: String,
+
+        /// Number of documents printed
+        #[clap(long, default_value = "10")]
+        limit: usize,
+    },
+    /// Put back the index replaced by the last `index`
+    Rollback,
+    /// Answer searches on the index over HTTP with JSON
+    /// (`/search?q=…`, `/doc/{id}` and `/stats`)
+    Serve {
+        /// Address the server listens on
+        #[clap(long, default_value = "127.0.0.1:3000")]
+        address: String,
+    },
+    /// Browse the results of searches in the terminal
+    Tui {
+        /// Query searched at startup
+        query: Option<String>,
+    },
+    /// Answer the requests of LLM agents on the standard input and
+    /// output as an MCP server (tools `search_corpus`, `get_document`
+    /// and `get_full_text_api`)
+    #[cfg(feature = "mcp")]
+    Mcp {
+        /// File of the client id of the PISTE API, without which
+        /// `get_full_text_api` is disabled
+        #[clap(long, default_value = "client-id.txt")]
+        client_id: PathBuf,
+
+        /// File of the client secret of the PISTE API
+        #[clap(long, default_value = "client-secret.txt")]
+        client_secret: PathBuf,
+    },
+    /// Run a query saved under a name in the file of saved queries
+    RunSaved {
+        /// Name of the saved query
+        name: String,
+
+        /// TOML file of saved queries
+        #[clap(long, default_value = queries::QUERIES_FILE)]
+        queries: PathBuf,
+    },
+    /// Print the completion script of a shell (completing
+    /// subcommands, options and the names of the fonds)
+    Completions {
+        shell: clap_complete::Shell,
+    },
+    /// Infer the schema of the extracted documents of each fond
+    /// (tag paths, attributes and co-occurrences) and write it as JSON
+    Schema {
+        /// Where to write the JSON schema
+        #[clap(long, default_value = "schema.json")]
+        output: PathBuf,
+    },
 }
 
+// The flags are the interface of the previous releases, and are kept
+// hidden for one more release: the pipeline still runs on them, the
+// subcommands being translated into flags (see `Cli::use_flags`).
 #[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
+#[clap(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// Whether to download the tarballs
-    #[clap(short, long, default_value = "false")]
+    #[clap(short, long, default_value = "false", hide = true)]
     tarballs: bool,
 
     /// The list of tarballs to download,
     /// the default (empty) list will download all tarballs
-    #[clap(short, long, num_args(0..))]
+    #[clap(short, long, num_args(0..), hide = true)]
     fond: Vec<Fond>,
 
     /// Whether to extract the tarballs
-    #[clap(short, long, default_value = "false")]
+    #[clap(short, long, default_value = "false", hide = true)]
     extract: bool,
 
     /// Whether to index the extracted content
-    #[clap(short, long, default_value = "false")]
+    #[clap(short, long, default_value = "false", hide = true)]
     index: bool,
 
     /// Whether or not the request is to
@@ -105,44 +620,426 @@ struct Cli {
     /// implicitly set `--tarballs`, `--extract`, and `--index`
     /// to true, but only download and index tarballs
     /// that were not already downloaded.
-    #[clap(short, long, default_value = "false")]
+    #[clap(short, long, default_value = "false", hide = true)]
     update: bool,
 
+    /// Make the tarball directory exactly match the dila server
+    /// for the selected fonds: download new tarballs and delete
+    /// the ones that disappeared upstream.
+    #[clap(long, default_value = "false", hide = true)]
+    mirror: bool,
+
+    /// Where to write the JSON report of the mirror operation
+    /// (defaults to `tarballs/mirror-report.json`)
+    #[clap(long, hide = true)]
+    mirror_report: Option<PathBuf>,
+
+    /// Maximal number of tarballs downloaded simultaneously
+    #[clap(long, hide = true)]
+    concurrency: Option<usize>,
+
+    /// Global bandwidth limit for downloads, in bytes per second
+    /// (accepts suffixes such as `500K`, `2M` or `1G`)
+    #[clap(long, value_parser = tarballs::parse_bytes, hide = true)]
+    limit_rate: Option<u64>,
+
+    /// How many times failed downloads are retried
+    #[clap(long, default_value = "2", hide = true)]
+    retries: usize,
+
+    /// Store extracted files in a content addressable store
+    /// (the `cas` folder) where identical documents are only
+    /// written once. Applies to `--extract`, `--index` and `--csv`.
+    #[clap(long, default_value = "false", hide = true)]
+    content_addressed: bool,
+
+    /// Pack the extracted documents into compressed shards
+    /// (the `packed` folder), which is much friendlier to file
+    /// systems and backups than millions of small files
+    #[clap(long, default_value = "false", hide = true)]
+    pack: bool,
+
+    /// Read documents from the `packed` folder instead of
+    /// the `extracted` one when using `--index` and `--csv`
+    #[clap(long, default_value = "false", hide = true)]
+    from_pack: bool,
+
+    /// Only print what `--tarballs`, `--update` or `--mirror` would
+    /// download, with an estimation of the disk space needed
+    #[clap(long, default_value = "false", hide = true)]
+    dry_run: bool,
+
     /// The query used to search the index
-    #[clap(short, long)]
+    #[clap(short, long, hide = true)]
     query: Option<String>,
 
+    /// Only search documents dated on or after this day (YYYY-MM-DD)
+    #[clap(long, hide = true)]
+    from: Option<chrono::NaiveDate>,
+
+    /// Only search documents dated on or before this day (YYYY-MM-DD)
+    #[clap(long, hide = true)]
+    to: Option<chrono::NaiveDate>,
+
+    /// Print the number of results per year and per jurisdiction
+    #[clap(long, default_value = "false", hide = true)]
+    facets: bool,
+
+    /// Order of the results of `--query`: by relevance,
+    /// or by date with the newest first
+    #[clap(long, value_enum, default_value = "score", hide = true)]
+    sort: tarballs::SortBy,
+
+    /// Number of results printed by `--query`
+    #[clap(long, default_value = "10", value_parser = positive_count(), hide = true)]
+    limit: usize,
+
+    /// Number of best results skipped by `--query`, to see the next pages
+    #[clap(long, default_value = "0", hide = true)]
+    offset: usize,
+
+    /// Print the paths of all the results of `--query`, sorted by path,
+    /// instead of the best ones
+    #[clap(long, default_value = "false", hide = true)]
+    all: bool,
+
+    /// Only print the number of results of `--query`,
+    /// without retrieving any document
+    #[clap(long, default_value = "false", conflicts_with_all = ["all", "export"], hide = true)]
+    count: bool,
+
+    /// Print the number of results of `--query` per year or per
+    /// month, without retrieving any document
+    #[clap(long, value_name = "BUCKET", conflicts_with_all = ["all", "export", "count"], hide = true)]
+    histogram: Option<tarballs::HistogramBucket>,
+
     /// Whether to save *all* the search results in a file
-    #[clap(short, long)]
+    #[clap(short, long, hide = true)]
     save: Option<String>,
 
     /// Read a result list (one line per file) and create
     /// a CSV with the correct metadata
-    #[clap(short, long)]
+    #[clap(short, long, hide = true)]
     csv: Option<String>,
+
+    /// Convert all the results of `--query` to CSV (or JSON lines
+    /// with `--jsonl`) in one pass, written to `{name}.csv`
+    #[clap(long, value_name = "NAME", requires = "query", hide = true)]
+    export: Option<String>,
+
+    /// Write the documents of `--csv` and `--export` as JSON lines
+    /// (in `{result list}.jsonl`) instead of CSV files
+    #[clap(long, default_value = "false", hide = true)]
+    jsonl: bool,
+
+    /// Add `amounts`, `dates` and `case_numbers` columns to the
+    /// export of `--csv`, found in the text of the documents
+    #[clap(long, default_value = "false", hide = true)]
+    entities: bool,
+
+    /// Split the text of the documents of `--csv` into paragraphs and
+    /// sentences, written as JSON lines with their offsets
+    /// (in `{result list}.segments.jsonl`) instead of CSV files
+    #[clap(long, default_value = "false", conflicts_with = "jsonl", hide = true)]
+    segments: bool,
+
+    /// Write the documents of `--csv` and `--export` to one Markdown
+    /// or plain text file each, in the `{result list}.md` directory
+    #[clap(long, value_enum, conflicts_with_all = ["jsonl", "segments"], hide = true)]
+    format: Option<TextFormat>,
+
+    /// Write the documents of `--csv` and `--export` to a SQLite
+    /// database (in `{result list}.sqlite`) instead of CSV files
+    #[clap(long, default_value = "false", conflicts_with_all = ["jsonl", "segments", "format"], hide = true)]
+    sqlite: bool,
+
+    /// Write the documents of `--csv` and `--export` as an Arrow IPC
+    /// stream (in `{result list}.arrows`) instead of CSV files
+    #[clap(long, default_value = "false", conflicts_with_all = ["jsonl", "segments", "format", "sqlite"], hide = true)]
+    arrow: bool,
+
+    /// Write the documents of `--csv` and `--export` as a HuggingFace
+    /// dataset (in the `{result list}.hf` directory)
+    #[clap(long, default_value = "false", conflicts_with_all = ["jsonl", "segments", "format", "sqlite", "arrow"], hide = true)]
+    hf: bool,
+
+    /// Number of documents per shard of `--hf`
+    #[clap(long, default_value_t = hf::HF_SHARD_SIZE, hide = true)]
+    shard_size: usize,
+
+    /// Write the citations of the documents of `--csv` and `--export`
+    /// (in `{result list}.csl.json` or `{result list}.bib`)
+    #[clap(long, value_enum, conflicts_with_all = ["jsonl", "segments", "format", "sqlite", "arrow", "hf"], hide = true)]
+    cite: Option<CitationFormat>,
+
+    /// Write the documents of `--csv` and `--export` with the names of
+    /// people masked (in `{result list}.anonymized.jsonl`)
+    #[clap(long, value_enum, conflicts_with_all = ["jsonl", "segments", "format", "sqlite", "arrow", "hf", "cite"], hide = true)]
+    anonymize: Option<Anonymization>,
+
+    /// Write the documents of `--csv` and `--export` to a PostgreSQL
+    /// database (connection string)
+    #[cfg(feature = "postgres")]
+    #[clap(long, conflicts_with_all = ["jsonl", "segments", "format", "sqlite", "arrow", "hf", "cite", "anonymize"], hide = true)]
+    postgres: Option<String>,
+
+    /// Save the progress of the CSV and JSON lines exports of `--csv`
+    /// and `--export` every N documents
+    #[clap(long, default_value_t = DEFAULT_FLUSH_EVERY, hide = true)]
+    flush_every: usize,
+
+    /// Keep the extracted text as is in CSV exports, instead of
+    /// removing inline tags, decoding entities and collapsing whitespace
+    #[clap(long, default_value = "false", hide = true)]
+    raw_text: bool,
+
+    /// Work at article granularity: texts that embed their articles
+    /// are indexed and exported as one record per article, listed
+    /// as `path#article_id` in search results
+    #[clap(long, default_value = "false", hide = true)]
+    per_article: bool,
+
+    /// Use one index per fond (in `index/JADE`, `index/CASS`, ...),
+    /// so that a fond can be reindexed alone. Searches query the
+    /// indices of the fonds given with `--fond`, or all of them.
+    #[clap(long, default_value = "false", conflicts_with_all = ["from_pack", "content_addressed"], hide = true)]
+    per_fond: bool,
+
+    /// Reduce words to their stem when creating the index, so that
+    /// "constructions" matches "construction". The setting is saved
+    /// with the index and used by all the searches.
+    #[clap(long, default_value = "false", hide = true)]
+    stem: bool,
+
+    /// Do not store the text of the documents in the index when
+    /// creating it, which halves its size: the text is still searched,
+    /// but `similar` is not available. Saved with the index.
+    #[clap(long, default_value = "false", hide = true)]
+    no_store_body: bool,
+
+    /// TOML file of settings of the analyzer used when creating the
+    /// index: extra stopwords, maximal length of the words, removal of
+    /// the accents and stemming. They are saved with the index.
+    #[clap(long, hide = true)]
+    analyzer: Option<PathBuf>,
+
+    /// Number of threads parsing the files when indexing,
+    /// 0 for one per core
+    #[clap(long, default_value = "0", hide = true)]
+    index_threads: usize,
+
+    /// Commit the index every that many documents when indexing,
+    /// so that an interrupted `--update` keeps most of its work
+    /// (0 to commit once at the end)
+    #[clap(long, default_value = "100000", hide = true)]
+    batch_size: usize,
+
+    /// Keep a copy of the tarballs and of the packed corpus in a
+    /// bucket (e.g. `s3://team-bucket/legifrance`, `gs://…`, `az://…`):
+    /// the tarballs of the bucket are not downloaded again from the dila
+    /// server, and the new ones are uploaded to the bucket
+    #[cfg(feature = "object-store")]
+    #[clap(long, global = true, value_name = "URL")]
+    bucket: Option<String>,
+
+    /// Repair the index when it is damaged (e.g. after the process was
+    /// killed while writing it), dropping its broken segments
+    #[clap(long, default_value = "false", global = true)]
+    repair_index: bool,
+
+    /// Rebuild the index when it cannot be opened, even after
+    /// repairing it, from the documents extracted as the corpus
+    /// manifest (`corpus-manifest.json`) says, if there is one
+    #[clap(long, default_value = "false", global = true)]
+    rebuild_index: bool,
+
+    #[clap(flatten)]
+    http: HttpConfig,
+
+    #[clap(flatten)]
+    jobs: JobsConfig,
+
+    #[clap(flatten)]
+    log: LogConfig,
+
+    #[clap(flatten)]
+    failures: FailureConfig,
+
+    /// Set by the `watch` subcommand
+    #[clap(skip)]
+    watch: Option<WatchArgs>,
 }
 
-async fn get_tarballs(fonds: &[Fond], dir: &PathBuf) -> Result<Vec<Tarball>> {
-    // Create a new HTTP client
-    let client = reqwest::Client::new();
+impl Cli {
+    fn set_download(&mut self, download: DownloadArgs) {
+        self.fond = download.fond;
+        self.concurrency = download.concurrency;
+        self.limit_rate = download.limit_rate;
+        self.retries = download.retries;
+        self.dry_run = download.dry_run;
+    }
+
+    fn set_index(&mut self, index: IndexArgs) {
+        self.per_fond = index.per_fond;
+        self.per_article = index.per_article;
+        self.stem = index.stem;
+        self.analyzer = index.analyzer;
+        self.no_store_body = index.no_store_body;
+        self.index_threads = index.index_threads;
+        self.batch_size = index.batch_size;
+    }
+
+    /// Set the query and restrictions of a search. Searches use the
+    /// indices per fond when the index was created with `--per-fond`.
+    fn set_query(&mut self, query: QueryArgs, index_path: &Path) {
+        self.query = Some(query.query);
+        self.fond = query.fond;
+        self.from = query.from;
+        self.to = query.to;
+        self.per_fond = !index_path.join("meta.json").is_file()
+            && tarballs::list_fond_indices(index_path).is_ok_and(|fonds| !fonds.is_empty());
+    }
+
+    /// The operation of the run when it changes the tarballs, the
+    /// extracted documents or the index, for which the data directory
+    /// is locked (after `use_flags`)
+    fn mutating_operation(&self) -> Option<&'static str> {
+        match &self.command {
+            Some(Command::Restore { .. }) => return Some("restore"),
+            Some(Command::Rollback) => return Some("rollback"),
+            _ => {}
+        }
+        [
+            (self.watch.is_some(), "watch"),
+            (self.update, "update"),
+            (self.tarballs, "download"),
+            (self.mirror, "mirror"),
+            (self.extract, "extract"),
+            (self.pack, "pack"),
+            (self.index, "index"),
+            (self.repair_index, "index"),
+            (self.rebuild_index, "index"),
+        ]
+        .into_iter()
+        .find_map(|(enabled, operation)| enabled.then_some(operation))
+    }
 
-    let mut tarballs = Vec::new();
+    /// Whether the run writes the index, and may thus repair it
+    /// (after `use_flags`, with the data directory locked)
+    fn writes_index(&self) -> bool {
+        self.watch.is_some() || self.update || self.index || self.repair_index || self.rebuild_index
+    }
 
-    for fond in fonds {
-        info!("Downloading tarballs for {}", fond);
-        // Download the tarballs
-        match tarballs::download_tarballs(&client, dir, fond).await {
-            Ok(tarballs_list) => {
-                tarballs.extend(tarballs_list);
+    /// Translate the subcommands of the pipeline into the equivalent
+    /// flags, leaving the other subcommands untouched
+    fn use_flags(&mut self, index_path: &Path) {
+        match self.command.take() {
+            Some(Command::Download {
+                download,
+                mirror,
+                mirror_report,
+            }) => {
+                self.set_download(download);
+                self.tarballs = !mirror;
+                self.mirror = mirror;
+                self.mirror_report = mirror_report;
             }
-            Err(e) => {
-                error!("Error fetching tarballs: {}", e);
-                continue;
+            Some(Command::Extract {
+                content_addressed,
+                pack,
+            }) => {
+                self.extract = true;
+                self.content_addressed = content_addressed;
+                self.pack = pack;
+            }
+            Some(Command::Index {
+                index,
+                from_pack,
+                content_addressed,
+            }) => {
+                self.set_index(index);
+                self.index = true;
+                self.from_pack = from_pack;
+                self.content_addressed = content_addressed;
+            }
+            Some(Command::Update { download, index }) => {
+                self.set_download(download);
+                self.set_index(index);
+                self.update = true;
+            }
+            Some(Command::Watch {
+                download,
+                index,
+                watch,
+            }) => {
+                self.set_download(download);
+                self.set_index(index);
+                self.watch = Some(watch);
+            }
+            Some(Command::Search {
+                query,
+                facets,
+                sort,
+                limit,
+                offset,
+                all,
+                save,
+            }) => {
+                self.set_query(query, index_path);
+                self.facets = facets;
+                self.sort = sort;
+                self.limit = limit;
+                self.offset = offset;
+                self.all = all;
+                self.save = save;
+            }
+            Some(Command::Export {
+                query,
+                output,
+                list,
+                fond,
+                from,
+                to,
+                format,
+            }) => {
+                if let Some(query) = query {
+                    self.set_query(QueryArgs { query, fond, from, to }, index_path);
+                    self.export = output;
+                }
+                self.csv = list;
+                self.jsonl = format.jsonl;
+                self.entities = format.entities;
+                self.segments = format.segments;
+                self.format = format.format;
+                self.sqlite = format.sqlite;
+                self.arrow = format.arrow;
+                self.hf = format.hf;
+                self.shard_size = format.shard_size;
+                self.cite = format.cite;
+                self.anonymize = format.anonymize;
+                #[cfg(feature = "postgres")]
+                {
+                    self.postgres = format.postgres;
+                }
+                self.flush_every = format.flush_every;
+                self.raw_text = format.raw_text;
+                self.per_article = format.per_article;
+                self.from_pack = format.from_pack;
+                self.content_addressed = format.content_addressed;
             }
+            Some(Command::Stats { query, histogram }) => {
+                self.set_query(query, index_path);
+                self.count = histogram.is_none();
+                self.histogram = histogram;
+            }
+            command => self.command = command,
         }
     }
+}
 
-    Ok(tarballs)
+/// Parser of the numbers of results, which cannot be 0
+fn positive_count() -> clap::builder::RangedU64ValueParser<usize> {
+    clap::builder::RangedU64ValueParser::new().range(1..)
 }
 
 fn list_all_tarballs(idir: &PathBuf) -> Result<Vec<PathBuf>> {
@@ -164,59 +1061,146 @@ fn list_all_tarballs(idir: &PathBuf) -> Result<Vec<PathBuf>> {
     Ok(to_extract)
 }
 
-fn extract_tarballs<T>(idir: &PathBuf, to_extract: &[T], odir: &PathBuf) -> Result<()>
-where
-    T: AsRef<Path>
-{
-    let pb = ProgressBar::new(to_extract.len() as u64);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{msg} [{elapsed_precise}] {wide_bar} {pos}/{len} ({eta})")
-            .context("Error creating progress bar")?
-            .progress_chars("##-"),
-    );
-
-    for p in to_extract {
-        pb.set_message(format!("Extracting {}", p.as_ref().display()));
-        let path = idir.join(p);
-        if path.exists() {
-            match tarballs::extract_tarball(&path, &odir) {
-                Ok(_) => info!("Successfully extracted {:?}", path),
-                Err(e) => error!("Error extracting {:?}: {}", path, e),
-            }
+/// Upload the new files of `dir` to the `folder` of the bucket, if any
+#[cfg(feature = "object-store")]
+async fn push_to_bucket(bucket: Option<&Bucket>, dir: &Path, folder: &str) {
+    let Some(bucket) = bucket else {
+        return;
+    };
+    match bucket.push(dir, folder).await {
+        Ok(count) => info!("Uploaded {} files to {} in the bucket", count, folder),
+        Err(e) => error!("Failed to upload {} to the bucket: {:#}", folder, e),
+    }
+}
+
+/// Run a saved query, returning the paths of its results and the
+/// documents among them that are not in the `known` results
+/// (with an excerpt of their text read from `edir`)
+fn check_saved_query(
+    index_path: &Path,
+    edir: &Path,
+    saved: &queries::SavedQuery,
+    per_fond: bool,
+    analyzer: &tarballs::AnalyzerConfig,
+    known: Option<&HashSet<String>>,
+) -> Result<(HashSet<String>, Vec<notify::NewMatch>)> {
+    let indices = if per_fond {
+        let fonds = if saved.fonds.is_empty() {
+            tarballs::list_fond_indices(index_path)?
         } else {
-            warn!("Tarball {:?} does not exist", path);
-        }
-        pb.inc(1);
+            saved.fonds.iter().map(|f| f.to_string()).collect()
+        };
+        tarballs::open_fond_indices(index_path, &fonds, analyzer)?
+    } else {
+        vec![tarballs::init_tantivy(&index_path.to_path_buf())?]
+    };
+    let indices: Vec<_> = indices.iter().map(|(index, flds)| (index, flds)).collect();
+    let paths = tarballs::matching_paths(&indices, &saved.query, &saved.options())?;
+    let Some(known) = known else {
+        return Ok((paths.into_iter().collect(), vec![]));
+    };
+    let mut matches = vec![];
+    for path in paths.iter().filter(|p| !known.contains(*p)) {
+        let id = embeddings::document_key(path);
+        // indices created without identifiers only give the path
+        let stored = indices
+            .iter()
+            .find_map(|(index, flds)| tarballs::get_by_id(index, flds, &id).ok().flatten());
+        matches.push(notify::NewMatch {
+            title: stored.as_ref().and_then(|doc| {
+                doc.metadata.get("title").and_then(|t| t.as_str()).map(String::from)
+            }),
+            date: stored.and_then(|doc| doc.date),
+            summary: tui::load_document(edir, path).ok().and_then(|document| {
+                document
+                    .text()
+                    .map(|text| tui::snippet(text, &saved.query, tui::SNIPPET_LENGTH))
+            }),
+            id,
+            path: path.clone(),
+        });
     }
-    Ok(())
+    Ok((paths.into_iter().collect(), matches))
 }
 
-fn result_file_to_csv(edir: &PathBuf, result_file: &str, output_file: &str) -> Result<()> {
+/// The writer of the documents of `--csv` and `--export`, writing
+/// to files named after `name` in the format selected by `args`
+fn document_writer(name: &str, args: &Cli) -> Result<Box<dyn DocumentWriter>> {
+    #[cfg(feature = "postgres")]
+    if let Some(url) = args.postgres.as_ref() {
+        use legifrance::dumps::extractor::postgres::DocumentPostgresWriter;
+        return Ok(Box::new(DocumentPostgresWriter::new(url)?));
+    }
+    Ok(if let Some(format) = args.format {
+        Box::new(DocumentFileWriter::new(format!("{}.{}", name, format.extension()), format)?)
+    } else if args.sqlite {
+        Box::new(DocumentSqliteWriter::new(format!("{}.sqlite", name))?)
+    } else if args.arrow {
+        Box::new(DocumentArrowWriter::create(format!("{}.arrows", name))?)
+    } else if let Some(cite) = args.cite {
+        Box::new(DocumentCitationWriter::new(format!("{}.{}", name, cite.extension()), cite)?)
+    } else if let Some(mode) = args.anonymize {
+        Box::new(DocumentAnonymizedWriter::new(format!("{}.anonymized.jsonl", name), mode)?)
+    } else if args.hf {
+        Box::new(DocumentHfWriter::new(format!("{}.hf", name))?.with_shard_size(args.shard_size))
+    } else if args.segments {
+        Box::new(DocumentSegmentWriter::new(format!("{}.segments.jsonl", name))?)
+    } else if args.jsonl {
+        let writer = DocumentJsonlWriter::resume(format!("{}.jsonl", name))?;
+        Box::new(writer.with_entities(args.entities).with_flush_every(args.flush_every))
+    } else {
+        let writer = DocumentCsvWriter::resume(format!("{}.csv", name))?;
+        Box::new(writer.with_entities(args.entities).with_flush_every(args.flush_every))
+    })
+}
+
+/// Count the tags of a file, the parsing errors are reported
+/// when the file is converted
+fn count_tags(path: &Path, tcount: &mut std::collections::HashMap<String, usize>) {
+    if let Err(e) = count_tags_in_file(path, tcount) {
+        debug!("Could not count tags in {}: {}", path.display(), e);
+    }
+}
+
+/// Convert the documents listed in `result_file` to CSV. Lines of
+/// the form `path#article_id` select one article of a text, and with
+/// `per_article` every text is exported as one record per article.
+fn result_file_to_csv(
+    source: DocumentSource,
+    result_file: &str,
+    writer: &mut dyn DocumentWriter,
+    clean: bool,
+    per_article: bool,
+    failures: &FailureLog,
+) -> Result<()> {
     use std::io::BufRead;
 
     let file = std::fs::File::open(result_file)?;
     let mut reader = std::io::BufReader::new(file);
-    let mut writer = csv::WriterBuilder::new()
-        .has_headers(true)
-        .from_path(output_file)?;
 
     let mut tcount = std::collections::HashMap::new();
-    // buffer to allocate file contents
-    let mut buffer = String::new();
+    // documents of several fonds can be mixed in the same result file
+    let mut kinds = std::collections::BTreeMap::new();
     // buffer to allocate lines
     let mut line = String::new();
     while reader.read_line(&mut line)? != 0 {
-        let path = edir.join(&line);
-        info!("Processing file: {}", path.display());
-        count_tags_in_file(&path, &mut tcount);
-        let content = parse_file(&path, &mut buffer);
-        writer.serialize(content)?;
-        buffer.clear();
+        if shutdown::is_interrupted() {
+            warn!("Interrupted, the documents from {} are not converted", line.trim());
+            break;
+        }
+        let (doc_path, _) = extractor::split_article_path(&line);
+        if let Some(path) = source.file(doc_path)? {
+            count_tags(&path, &mut tcount);
+        }
+        let documents = source.read(&line, per_article)?;
+        write_documents(writer, &line, documents, clean, &mut kinds, failures)?;
         line.clear();
     }
     writer.flush()?;
 
+    for (kind, count) in kinds {
+        println!("Converted {} documents of kind {}", count, kind);
+    }
     println!("Found {} tags", tcount.len());
     for (tag, count) in tcount {
         println!("{}: {}", tag, count);
@@ -224,12 +1208,61 @@ fn result_file_to_csv(edir: &PathBuf, result_file: &str, output_file: &str) -> R
     Ok(())
 }
 
+/// Repair the index, or the indices per fond, when they are damaged
+fn repair_indices(index_path: &Path) {
+    let fonds = tarballs::list_fond_indices(index_path).unwrap_or_default();
+    let paths = std::iter::once(index_path.to_path_buf())
+        .chain(fonds.iter().map(|fond| tarballs::fond_index_path(index_path, fond)));
+    for path in paths {
+        if let Err(e) = tarballs::repair_damaged_index(&path) {
+            error!("{:#}", e);
+        }
+    }
+}
+
+/// Whether the index (or one of the indices per fond) cannot be
+/// opened, even after repairing it
+fn index_unusable(index_path: &Path, per_fond: bool, analyzer: &tarballs::AnalyzerConfig) -> bool {
+    let opened = if per_fond {
+        tarballs::list_fond_indices(index_path)
+            .and_then(|fonds| tarballs::open_fond_indices(index_path, &fonds, analyzer))
+            .map(|_| ())
+    } else {
+        tarballs::init_tantivy_with(&index_path.to_path_buf(), analyzer).map(|_| ())
+    };
+    match opened {
+        Ok(()) => false,
+        Err(e) => {
+            warn!("{:#}, rebuilding it", e);
+            true
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
-    // Initialize the logger
-    env_logger::init();
-
+    shutdown::install();
     let args = Cli::parse();
+    let max_failure_rate = args.failures.max_failure_rate;
+    let failures = FailureLog::new();
+    run(args, &failures).await;
+    if let Err(e) = failures.report(Path::new(ERRORS_FILE), max_failure_rate) {
+        error!("{:#}", e);
+        std::process::exit(1);
+    }
+    if shutdown::is_interrupted() {
+        std::process::exit(shutdown::INTERRUPTED_EXIT_CODE);
+    }
+}
+
+async fn run(mut args: Cli, failures: &FailureLog) {
+
+    args.log.init();
+
+    if let Some(Command::Completions { shell }) = args.command {
+        clap_complete::generate(shell, &mut Cli::command(), "dilarxiv", &mut std::io::stdout());
+        return;
+    }
 
     let cwd = std::env::current_dir().expect("Failed to get current directory");
 
@@ -239,33 +1272,691 @@ async fn main() {
 
     let index_path = cwd.join("index");
 
+    let cas_path = cwd.join("cas");
+
+    let pack_path = cwd.join("packed");
+
+    args.use_flags(&index_path);
+
+    // released when `run` returns
+    let lock = match args.mutating_operation().map(|operation| DataLock::acquire(&cwd, operation)) {
+        Some(Ok(lock)) => Some(lock),
+        Some(Err(e)) => {
+            error!("{:#}", e);
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    // only with the lock, as other processes may be reading the index
+    if lock.is_some() && args.writes_index() {
+        repair_indices(&index_path);
+    }
+
+    let concurrency = args.jobs.concurrency();
+    open_files::set_limit(concurrency.open_files);
+    let downloads = args.concurrency.unwrap_or(concurrency.downloads);
+    let dl_opts = DownloadOptions {
+        retries: args.retries,
+        ..DownloadOptions::new(downloads, args.limit_rate)
+    };
+    let client = args.http.build_client().expect("Failed to create HTTP client");
+
+    if let Some(Command::List { fond, json }) = &args.command {
+        let fonds = if fond.is_empty() { FONDS } else { fond };
+        let listing = tarballs::list_fonds_listing(&client, fonds)
+            .await
+            .expect("Failed to list tarballs");
+        if *json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&listing.tarballs).expect("Failed to serialize listing")
+            );
+        } else {
+            for tb in listing.tarballs {
+                println!("{}\t{}\t{:?}\t{}", tb.fond, tb.time, tb.kind, tb.name);
+            }
+        }
+        if !listing.unparsable.is_empty() {
+            eprintln!("Skipped {} files with unexpected names:", listing.unparsable.len());
+            for unparsable in listing.unparsable {
+                eprintln!("{}\t{}", unparsable.name, unparsable.error);
+            }
+        }
+        return;
+    }
+
+    if let Some(Command::Validate { report }) = &args.command {
+        let result = validate::validate_dir(&edir).expect("Failed to validate documents");
+        validate::write_report(&result, report).expect("Failed to write validation report");
+        for (fond, quality) in result.fonds.iter() {
+            println!(
+                "{}: {} documents, {} unparsable, {} missing fields, {} invalid dates",
+                fond,
+                quality.documents,
+                quality.unparsable,
+                quality.missing.values().sum::<usize>(),
+                quality.invalid_dates.values().sum::<usize>()
+            );
+        }
+        println!(
+            "{} problems in {} documents, report written to {}",
+            result.problems(),
+            result.documents(),
+            report.display()
+        );
+        return;
+    }
+
+    if let Some(Command::Calendar { from, to, format, output }) = &args.command {
+        let events = calendar::calendar(&edir, *from, *to).expect("Failed to read the articles");
+        let output = output.clone().unwrap_or_else(|| match format {
+            calendar::CalendarFormat::Ics => PathBuf::from("calendar.ics"),
+            calendar::CalendarFormat::Json => PathBuf::from("calendar.json"),
+        });
+        calendar::write_calendar(&events, *format, &output).expect("Failed to write the calendar");
+        println!("Wrote {} events to {}", events.len(), output.display());
+        return;
+    }
+
+    #[cfg(feature = "api")]
+    if let Some(Command::Judilibre {
+        fond,
+        output,
+        client_id,
+        client_secret,
+    }) = &args.command
+    {
+        use legifrance::api::client::AuthenticatedClient;
+
+        let id = std::fs::read_to_string(client_id).expect("Failed to read the client id");
+        let secret = std::fs::read_to_string(client_secret).expect("Failed to read the client secret");
+        let api = AuthenticatedClient::from_config(&args.http, &id, &secret)
+            .await
+            .expect("Failed to authenticate to the API");
+        let fonds = if fond.is_empty() { judilibre::JUDILIBRE_FONDS } else { fond.as_slice() };
+        let summary = judilibre::enrich(&api, &edir, fonds, output, concurrency.requests)
+            .await
+            .expect("Failed to look up the decisions in Judilibre");
+        println!(
+            "{} decisions found in Judilibre, {} not published, {} failed ({} already in {})",
+            summary.found,
+            summary.missing,
+            summary.failed,
+            summary.skipped,
+            output.display()
+        );
+        if let Some(summary) = api.rate_limits().summary() {
+            println!("{}", summary);
+        }
+        return;
+    }
+
+    if let Some(Command::Freeze {
+        output,
+        fond,
+        until,
+        content_addressed,
+        pack,
+    }) = &args.command
+    {
+        let settings = manifest::ExtractionSettings {
+            content_addressed: *content_addressed,
+            pack: *pack,
+        };
+        let frozen = Manifest::freeze(&dir, fond, *until, settings).expect("Failed to list the tarballs");
+        frozen.save(output).expect("Failed to write the manifest");
+        println!("Wrote the manifest of {} tarballs to {}", frozen.tarballs.len(), output.display());
+        return;
+    }
+
+    if let Some(Command::Verify { manifest }) = &args.command {
+        let manifest = Manifest::from_file(manifest).expect("Failed to read the manifest");
+        let mismatches = manifest.verify(&dir).expect("Failed to check the tarballs");
+        for mismatch in mismatches.iter() {
+            error!("{}", mismatch);
+        }
+        if !mismatches.is_empty() {
+            error!("{} of {} tarballs differ from the manifest", mismatches.len(), manifest.tarballs.len());
+            std::process::exit(1);
+        }
+        println!("The {} tarballs match the manifest", manifest.tarballs.len());
+        return;
+    }
+
+    if let Some(Command::Restore { manifest }) = &args.command {
+        let manifest = Manifest::from_file(manifest).expect("Failed to read the manifest");
+        let missing: Vec<Tarball> = manifest
+            .tarballs()
+            .into_iter()
+            .filter(|tarball| !dir.join(tarball).exists())
+            .collect();
+        if !missing.is_empty() {
+            let report = tarballs::download_tarball_list(&client, &missing, &dir, &dl_opts)
+                .await
+                .expect("Failed to download the tarballs");
+            info!("{}", report.summary());
+        }
+        let mismatches = manifest.verify(&dir).expect("Failed to check the tarballs");
+        if !mismatches.is_empty() {
+            for mismatch in mismatches.iter() {
+                error!("{}", mismatch);
+            }
+            error!("Cannot restore the corpus, {} tarballs differ from the manifest", mismatches.len());
+            std::process::exit(1);
+        }
+        let to_extract: Vec<PathBuf> = manifest.tarballs().iter().map(|t| dir.join(t)).collect();
+        if manifest.settings.content_addressed {
+            let mut store = ContentStore::open(&cas_path).expect("Failed to open content store");
+            for p in to_extract.iter() {
+                store.extract_tarball(p).expect("Failed to extract tarball");
+            }
+            store.save().expect("Failed to save content store");
+        } else {
+            extract_tarballs(&dir, &to_extract, &edir, concurrency.extractions, failures)
+                .expect("Could not extract all tarballs");
+        }
+        if manifest.settings.pack {
+            let count = pack::pack_directory(&edir, &pack_path, pack::DEFAULT_SHARD_SIZE)
+                .expect("Failed to pack extracted documents");
+            println!("Packed {} documents into {}", count, pack_path.display());
+        }
+        println!("Restored the {} tarballs of the manifest", to_extract.len());
+        return;
+    }
+
+    if let Some(Command::Diff { old, new, report, changes }) = &args.command {
+        let old = old.snapshot(&dir).expect("Failed to read the old corpus");
+        let new = new.snapshot(&dir).expect("Failed to read the new corpus");
+        let result = diff::diff(&old, &new);
+        diff::write_report(&result, report).expect("Failed to write diff report");
+        if let Some(changes) = changes {
+            diff::write_changes(&result, changes).expect("Failed to write the changes");
+        }
+        for (fond, counts) in result.fonds.iter() {
+            println!(
+                "{}: {} added, {} removed, {} modified, {} unchanged",
+                fond, counts.added, counts.removed, counts.modified, counts.unchanged
+            );
+        }
+        println!(
+            "{} changes ({} documents before, {} after), report written to {}",
+            result.total(),
+            old.len(),
+            new.len(),
+            report.display()
+        );
+        return;
+    }
+
+    if let Some(Command::Get { id, xml }) = &args.command {
+        let indices = open_existing_indices(&index_path).expect("Failed to open index");
+        let found = indices.iter().find_map(|(index, flds)| {
+            tarballs::get_by_id(index, flds, id).expect("Failed to look up document")
+        });
+        let Some(doc) = found else {
+            error!("Document {} is not in the index", id);
+            std::process::exit(1);
+        };
+        println!("path: {}", doc.path);
+        if let Some(date) = doc.date {
+            println!("date: {}", date);
+        }
+        if let Some(tarball) = doc.tarball.as_ref() {
+            println!("tarball: {}", tarball);
+        }
+        for (column, value) in doc.metadata.iter() {
+            println!("{}: {}", column, value.as_str().unwrap_or_default());
+        }
+        if *xml {
+            let (path, _) = extractor::split_article_path(&doc.path);
+            let content = std::fs::read(edir.join(path)).expect("Failed to read original XML");
+            println!("{}", extractor::encoding::decode(&content));
+        }
+        return;
+    }
+
+    if let Some(Command::Similar { document, limit }) = &args.command {
+        let indices = open_existing_indices(&index_path).expect("Failed to open index");
+        let indices: Vec<_> = indices.iter().map(|(index, flds)| (index, flds)).collect();
+        match tarballs::similar_documents(&indices, document, *limit) {
+            Ok(Some(hits)) => {
+                for (path, date) in hits {
+                    let date = date.map(|d| d.to_string()).unwrap_or("?".to_string());
+                    println!("Found: [{}] {}", date, path);
+                }
+            }
+            Ok(None) => error!("Document {} is not in the index", document),
+            Err(e) => error!("Error searching index: {}", e),
+        }
+        return;
+    }
+
+    if let Some(Command::Rollback) = &args.command {
+        tarballs::rollback_index(&index_path).expect("Failed to roll back the index");
+        println!("Restored the previous index at {}", index_path.display());
+        return;
+    }
+
+    if let Some(Command::Serve { address }) = &args.command {
+        let names = if index_path.join("meta.json").is_file() {
+            vec!["index".to_string()]
+        } else {
+            tarballs::list_fond_indices(&index_path).expect("Failed to list indices")
+        };
+        let indices = open_existing_indices(&index_path)
+            .expect("Failed to open index")
+            .into_iter()
+            .zip(names)
+            .map(|((index, fields), name)| server::NamedIndex {
+                name,
+                index,
+                fields,
+            })
+            .collect();
+        println!("Serving the index on http://{}", address);
+        server::serve(indices, address).await.expect("Failed to run the server");
+        return;
+    }
+
+    #[cfg(feature = "mcp")]
+    if let Some(Command::Mcp { client_id, client_secret }) = &args.command {
+        use legifrance::api::client::AuthenticatedClient;
+        use legifrance::dumps::corpus::{Corpus, CorpusConfig};
+        use legifrance::dumps::mcp::McpServer;
+
+        let credentials = std::fs::read_to_string(client_id)
+            .and_then(|id| Ok((id, std::fs::read_to_string(client_secret)?)));
+        let api = match credentials {
+            Ok((id, secret)) => Some(
+                AuthenticatedClient::from_config(&args.http, &id, &secret)
+                    .await
+                    .expect("Failed to authenticate to the API"),
+            ),
+            Err(e) => {
+                warn!(
+                    "No API credentials in {} and {} ({}), get_full_text_api is disabled",
+                    client_id.display(),
+                    client_secret.display(),
+                    e
+                );
+                None
+            }
+        };
+        let mut config = CorpusConfig::new(&cwd);
+        config.client = client.clone();
+        config.per_article = args.per_article;
+        config.per_fond = args.per_fond;
+        let corpus = Corpus::open(config).expect("Failed to open the corpus");
+        McpServer::new(corpus, api)
+            .serve_stdio()
+            .await
+            .expect("Failed to answer MCP requests");
+        return;
+    }
+
+    if let Some(Command::Tui { query }) = &args.command {
+        let indices = open_existing_indices(&index_path).expect("Failed to open index");
+        tui::run(indices, edir.clone(), query.clone()).expect("Failed to run the interface");
+        return;
+    }
+
+    if let Some(Command::Schema { output }) = &args.command {
+        let schema = extractor::schema::discover_schema(&edir).expect("Failed to infer schema");
+        let file = std::fs::File::create(output).expect("Failed to create schema file");
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), &schema)
+            .expect("Failed to write schema");
+        for (fond, schema) in schema.fonds.iter() {
+            println!(
+                "{}: {} documents, {} tag paths, {} errors",
+                fond,
+                schema.documents,
+                schema.paths.len(),
+                schema.errors
+            );
+        }
+        return;
+    }
+
+    if let Some(Command::RunSaved { name, queries }) = &args.command {
+        let saved = queries::SavedQueries::from_file(queries).expect("Failed to read saved queries");
+        let saved = saved.get(name).expect("Failed to find saved query").clone();
+        let options = saved.options();
+        let query = QueryArgs {
+            query: saved.query,
+            fond: saved.fonds,
+            from: options.from,
+            to: options.to,
+        };
+        args.set_query(query, &index_path);
+        args.sort = options.sort;
+        args.limit = options.limit;
+        if !args.per_fond && !args.fond.is_empty() {
+            warn!("The index is not split per fond, searching all the fonds");
+        }
+    }
+
+    if args.dry_run {
+        let fonds = if args.fond.is_empty() {
+            FONDS
+        } else {
+            &args.fond
+        };
+        let listing = tarballs::list_fonds_listing(&client, fonds)
+            .await
+            .expect("Failed to list tarballs");
+        let plan = tarballs::plan_downloads(&dir, &listing.tarballs);
+        for tb in plan.tarballs.iter() {
+            println!(
+                "{}\t{}",
+                tb.name,
+                tb.size.map(tarballs::format_bytes).unwrap_or("?".to_string())
+            );
+        }
+        println!("{} tarballs would be downloaded", plan.tarballs.len());
+        println!(
+            "Compressed size: {}{}",
+            tarballs::format_bytes(plan.compressed_bytes),
+            if plan.unknown_sizes > 0 {
+                format!(" (+ {} tarballs of unknown size)", plan.unknown_sizes)
+            } else {
+                String::new()
+            }
+        );
+        println!(
+            "Estimated extracted size: {}",
+            tarballs::format_bytes(plan.estimated_uncompressed_bytes)
+        );
+        println!(
+            "Estimated index growth: {}",
+            tarballs::format_bytes(plan.estimated_index_bytes)
+        );
+        if !listing.unparsable.is_empty() {
+            println!("{} files with unexpected names would not be downloaded:", listing.unparsable.len());
+            for unparsable in listing.unparsable {
+                println!("{}\t{}", unparsable.name, unparsable.error);
+            }
+        }
+        return;
+    }
+
     if !index_path.exists() {
         std::fs::create_dir_all(&index_path).expect("Failed to create index directory");
     }
 
+    #[cfg(feature = "object-store")]
+    let bucket = args
+        .bucket
+        .as_deref()
+        .map(|url| Bucket::from_url(url).expect("Failed to open the bucket"));
+    #[cfg(feature = "object-store")]
+    if let Some(bucket) = bucket.as_ref() {
+        if args.tarballs || args.mirror || args.extract || args.update || args.watch.is_some() {
+            let count = bucket
+                .pull(TARBALLS_FOLDER, &dir)
+                .await
+                .expect("Failed to download the tarballs of the bucket");
+            info!("Downloaded {} tarballs from the bucket", count);
+        }
+        if args.from_pack {
+            bucket
+                .pull(PACKED_FOLDER, &pack_path)
+                .await
+                .expect("Failed to download the packed corpus of the bucket");
+        }
+    }
+
     if args.tarballs && !args.update {
         let fonds = if args.fond.is_empty() {
             FONDS
         } else {
             &args.fond
         };
-        let _ = get_tarballs(fonds, &dir)
+        let _ = get_tarballs(&client, fonds, &dir, &dl_opts, failures)
             .await
             .expect("Failed to get tarballs");
     }
 
+    if args.mirror {
+        let fonds = if args.fond.is_empty() {
+            FONDS
+        } else {
+            &args.fond
+        };
+        let report = mirror::mirror_fonds(&client, &dir, fonds, &dl_opts)
+            .await
+            .expect("Failed to mirror tarballs");
+        let report_path = args
+            .mirror_report
+            .clone()
+            .unwrap_or_else(|| dir.join("mirror-report.json"));
+        mirror::write_report(&report, &report_path).expect("Failed to write mirror report");
+        for fond in report.fonds.iter() {
+            println!(
+                "{}: {} upstream, {} downloaded, {} failed, {} deleted{}",
+                fond.fond,
+                fond.upstream,
+                fond.downloaded.len(),
+                fond.failed.len(),
+                fond.deleted.len(),
+                fond.error
+                    .as_ref()
+                    .map(|e| format!(" (error: {})", e))
+                    .unwrap_or_default()
+            );
+        }
+    }
+
+    #[cfg(feature = "object-store")]
+    if (args.tarballs && !args.update) || args.mirror {
+        push_to_bucket(bucket.as_ref(), &dir, TARBALLS_FOLDER).await;
+    }
+
     if args.extract && !args.update {
         let to_extract = list_all_tarballs(&dir).expect("Failed to list tarballs to extract");
-        extract_tarballs(&dir, &to_extract, &edir).expect("Could not extract all tarballs");
+        let known = to_extract
+            .iter()
+            .filter_map(|p| p.file_name()?.to_str())
+            .filter_map(|name| Tarball::from_name(name).ok())
+            .collect::<Vec<_>>();
+        let broken = tarballs::repair_tarballs(&client, &dir, &known, &dl_opts)
+            .await
+            .expect("Failed to validate tarballs");
+        for tarball in broken {
+            error!("Skipping corrupted tarball {}", tarball.name);
+        }
+        if args.content_addressed {
+            let mut store = ContentStore::open(&cas_path).expect("Failed to open content store");
+            for p in to_extract.iter().filter(|p| p.exists()) {
+                failures.processed(1);
+                match store.extract_tarball(p) {
+                    Ok(n) => info!("Extracted {} new documents from {}", n, p.display()),
+                    Err(e) => {
+                        error!("Error extracting {}: {}", p.display(), e);
+                        failures.record(p.display().to_string(), Stage::Extract, e);
+                    }
+                }
+            }
+            store.save().expect("Failed to save content store");
+        } else {
+            extract_tarballs(&dir, &to_extract, &edir, concurrency.extractions, failures)
+                .expect("Could not extract all tarballs");
+        }
+    }
+
+    if args.pack {
+        let count = pack::pack_directory(&edir, &pack_path, pack::DEFAULT_SHARD_SIZE)
+            .expect("Failed to pack extracted documents");
+        println!("Packed {} documents into {}", count, pack_path.display());
+        #[cfg(feature = "object-store")]
+        push_to_bucket(bucket.as_ref(), &pack_path, PACKED_FOLDER).await;
     }
 
-    let (index, flds) = tarballs::init_tantivy(&index_path).expect("Failed to create index");
+    let mut analyzer = match &args.analyzer {
+        Some(path) => {
+            tarballs::AnalyzerConfig::from_file(path).expect("Failed to read analyzer settings")
+        }
+        None => tarballs::AnalyzerConfig::default(),
+    };
+    analyzer.stemming |= args.stem;
+    analyzer.store_body &= !args.no_store_body;
+    let indexing = tarballs::IndexingOptions {
+        threads: match args.index_threads {
+            0 => concurrency.indexers,
+            threads => threads,
+        },
+        batch_size: args.batch_size,
+        failures: failures.clone(),
+    };
 
-    if args.index && !args.update {
+    let unusable = args.rebuild_index && index_unusable(&index_path, args.per_fond, &analyzer);
+    if unusable && !args.per_fond && !args.from_pack && !args.content_addressed {
+        // the index is built from where the corpus was extracted
+        let path = Path::new(manifest::MANIFEST_FILE);
+        if path.exists() {
+            let manifest = Manifest::from_file(path).expect("Failed to read the manifest");
+            args.from_pack = manifest.settings.pack;
+            args.content_addressed = manifest.settings.content_addressed && !manifest.settings.pack;
+        }
+        // and keeps its analyzer settings, unless new ones are given
+        if args.analyzer.is_none() && !args.stem && !args.no_store_body {
+            analyzer = tarballs::AnalyzerConfig::load(&index_path).unwrap_or(analyzer);
+        }
+    }
+
+    if (args.index && !args.update) || unusable {
+        // the new index replaces the current one once complete
         info!("Creating index at {}", index_path.display());
+        tarballs::rebuild_index(&index_path, |building| {
+            if args.per_fond {
+                let fonds = tarballs::index_fonds_in_dir(
+                    building,
+                    &edir,
+                    50_000_000,
+                    args.per_article,
+                    &analyzer,
+                    &indexing,
+                )?;
+                println!("Indexed fonds: {}", fonds.join(", "));
+                return Ok(());
+            }
+            let (index, flds) = tarballs::init_tantivy_with(&building.to_path_buf(), &analyzer)?;
+            let mut writer = index.writer(50_000_000)?;
+            if args.from_pack {
+                let reader = PackReader::open(&pack_path)?;
+                tarballs::index_pack(&mut writer, &flds, &reader, args.per_article)
+            } else if args.content_addressed {
+                let store = ContentStore::open(&cas_path)?;
+                tarballs::index_content_store(&mut writer, &flds, &store, args.per_article)
+            } else {
+                tarballs::index_files_in_dir(&mut writer, &flds, &edir, args.per_article, &indexing)
+            }
+        })
+        .expect("Failed to index files");
+    }
 
-        let mut writer = index.writer(50_000_000).expect("Failed to create writer");
-        tarballs::index_files_in_dir(&mut writer, &flds, &edir).expect("Failed to index files");
+    if let Some(watch) = args.watch.as_ref() {
+        let fonds = if args.fond.is_empty() {
+            FONDS
+        } else {
+            &args.fond
+        };
+        let saved = if watch.saved.is_empty() {
+            queries::SavedQueries::default()
+        } else {
+            queries::SavedQueries::from_file(&watch.queries).expect("Failed to read saved queries")
+        };
+        for name in watch.saved.iter() {
+            saved.get(name).expect("Failed to find saved query");
+        }
+        let notifiers = watch.notifiers();
+        if let Some(feeds) = watch.feeds.as_ref() {
+            std::fs::create_dir_all(feeds).expect("Failed to create the directory of feeds");
+        }
+        // the results of the saved queries at the previous update
+        let mut known: std::collections::BTreeMap<&str, HashSet<String>> = Default::default();
+        info!(
+            "Updating index at {} every {:?}",
+            index_path.display(),
+            watch.interval
+        );
+        loop {
+            let start = std::time::Instant::now();
+            // errors are logged and the next cycle tries again
+            let tmpd = temp_dir::TempDir::new().expect("Failed to create temporary directory");
+            let updated = update_and_index_data(
+                &client,
+                fonds,
+                &dir,
+                &index_path,
+                &edir,
+                tmpd.path(),
+                &dl_opts,
+                args.per_article,
+                args.per_fond,
+                &analyzer,
+                &indexing,
+                concurrency.extractions,
+            )
+            .await;
+            drop(tmpd);
+            match updated {
+                Ok(count) => info!(
+                    "Update done in {:.0?}: {} new tarballs indexed",
+                    start.elapsed(),
+                    count
+                ),
+                Err(e) => error!("Update failed after {:.0?}: {:#}", start.elapsed(), e),
+            }
+            #[cfg(feature = "object-store")]
+            push_to_bucket(bucket.as_ref(), &dir, TARBALLS_FOLDER).await;
+            for name in watch.saved.iter() {
+                let query = saved.get(name).expect("saved queries were checked");
+                let previous = known.get(name.as_str());
+                let checked =
+                    check_saved_query(&index_path, &edir, query, args.per_fond, &analyzer, previous);
+                let (paths, matches) = match checked {
+                    Ok(checked) => checked,
+                    Err(e) => {
+                        error!("Failed to run saved query {}: {:#}", name, e);
+                        continue;
+                    }
+                };
+                info!(
+                    "Saved query {}: {} results{}",
+                    name,
+                    paths.len(),
+                    previous
+                        .map(|_| format!(" ({} new since the last update)", matches.len()))
+                        .unwrap_or_default()
+                );
+                known.insert(name, paths);
+                if let Some(feeds) = watch.feeds.as_ref() {
+                    let path = feeds.join(format!("{}.atom", name));
+                    if let Err(e) = feed::update_feed(&path, name, &matches, chrono::Utc::now()) {
+                        error!("Failed to update the feed {}: {:#}", path.display(), e);
+                    }
+                }
+                if matches.is_empty() {
+                    continue;
+                }
+                let notification = notify::Notification {
+                    query: name.clone(),
+                    matches,
+                };
+                for notifier in notifiers.iter() {
+                    if let Err(e) = notifier.notify(&client, &notification).await {
+                        error!("Failed to notify {:?}: {:#}", notifier, e);
+                    }
+                }
+            }
+            info!("Next update in {:?}", watch.interval);
+            tokio::select! {
+                _ = tokio::time::sleep(watch.interval) => {}
+                _ = shutdown::wait() => break,
+            }
+        }
+        return;
     }
 
     if args.update {
@@ -284,26 +1975,145 @@ async fn main() {
             .path()
             .to_path_buf();
 
-        update_and_index_data(fonds, &dir, &index_path, &edir, &tmpd)
-            .await
+        update_and_index_data(
+            &client,
+            fonds,
+            &dir,
+            &index_path,
+            &edir,
+            &tmpd,
+            &dl_opts,
+            args.per_article,
+            args.per_fond,
+            &analyzer,
+            &indexing,
+            concurrency.extractions,
+        )
+        .await
             .expect("Failed to update and index data");
+        #[cfg(feature = "object-store")]
+        push_to_bucket(bucket.as_ref(), &dir, TARBALLS_FOLDER).await;
     }
 
-    if let Some(query) = args.query {
-        match tarballs::search_index(&index, &flds, &args.save, &query) {
-            Ok((count, results)) => {
-                println!("Found {} results for query '{}'", count, query);
-                for (path, year) in results {
-                    println!("Found: [{}] {}", year, path);
+    if let Some(query) = args.query.clone() {
+        let options = tarballs::SearchOptions {
+            from: args.from,
+            to: args.to,
+            facets: args.facets,
+            limit: args.limit,
+            offset: args.offset,
+            sort: args.sort,
+        };
+        let indices = if args.per_fond {
+            let fonds = if args.fond.is_empty() {
+                tarballs::list_fond_indices(&index_path).expect("Failed to list indices")
+            } else {
+                args.fond.iter().map(|f| f.to_string()).collect()
+            };
+            tarballs::open_fond_indices(&index_path, &fonds, &analyzer)
+                .expect("Failed to open indices")
+        } else {
+            vec![tarballs::init_tantivy(&index_path).expect("Failed to create index")]
+        };
+        let indices: Vec<_> = indices.iter().map(|(index, flds)| (index, flds)).collect();
+        if args.count {
+            let count = tarballs::count_matches(&indices, &query, &options)
+                .expect("Failed to search index");
+            println!("{}", count);
+            return;
+        }
+        if let Some(bucket) = args.histogram {
+            let counts = tarballs::date_histogram(&indices, &query, &options, bucket)
+                .expect("Failed to search index");
+            for (key, count) in counts {
+                println!("{}: {}", key, count);
+            }
+            return;
+        }
+        if let Some(name) = &args.export {
+            let paths = tarballs::matching_paths(&indices, &query, &options)
+                .expect("Failed to search index");
+            println!("Found {} results for query '{}'", paths.len(), query);
+            let mut writer = document_writer(name, &args)
+                .expect("Failed to create writer");
+            let store = args
+                .content_addressed
+                .then(|| ContentStore::open(&cas_path).expect("Failed to open content store"));
+            let packed = args
+                .from_pack
+                .then(|| PackReader::open(&pack_path).expect("Failed to open packed corpus"));
+            let source = match (&packed, &store) {
+                (Some(packed), _) => DocumentSource::Pack(packed),
+                (None, Some(store)) => DocumentSource::Store(store),
+                (None, None) => DocumentSource::Directory(&edir),
+            };
+            let kinds = export_documents(
+                source,
+                &paths,
+                writer.as_mut(),
+                !args.raw_text,
+                args.per_article,
+                failures,
+            )
+                .expect("Failed to export results");
+            for (kind, count) in kinds {
+                println!("Converted {} documents of kind {}", count, kind);
+            }
+            return;
+        }
+        if args.all {
+            let stdout = std::io::stdout();
+            let mut out = std::io::BufWriter::new(stdout.lock());
+            tarballs::export_matches(&indices, &query, &options, &mut out)
+                .expect("Failed to export results");
+            return;
+        }
+        match tarballs::search_indices(&indices, &args.save, &query, &options) {
+            Ok(results) => {
+                println!("Found {} results for query '{}'", results.count, query);
+                for (path, date) in results.hits {
+                    let date = date.map(|d| d.to_string()).unwrap_or("?".to_string());
+                    println!("Found: [{}] {}", date, path);
+                }
+                if let Some(facets) = results.facets {
+                    println!("Results per year:");
+                    for (year, count) in facets.years {
+                        println!("  {}: {}", year, count);
+                    }
+                    println!("Results per jurisdiction:");
+                    let mut jurisdictions: Vec<_> = facets.jurisdictions.into_iter().collect();
+                    jurisdictions.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+                    for (jurisdiction, count) in jurisdictions {
+                        println!("  {}: {}", jurisdiction, count);
+                    }
                 }
             }
             Err(e) => error!("Error searching index: {}", e),
         }
     }
 
-    if let Some(result_file) = args.csv {
-        let output_file = format!("{}.csv", result_file);
-        result_file_to_csv(&edir, &result_file, &output_file)
+    if let Some(result_file) = args.csv.clone() {
+        let mut writer = document_writer(&result_file, &args)
+            .expect("Failed to create writer");
+        let store = args
+            .content_addressed
+            .then(|| ContentStore::open(&cas_path).expect("Failed to open content store"));
+        let packed = args
+            .from_pack
+            .then(|| PackReader::open(&pack_path).expect("Failed to open packed corpus"));
+        let source = match (&packed, &store) {
+            (Some(packed), _) => DocumentSource::Pack(packed),
+            (None, Some(store)) => DocumentSource::Store(store),
+            (None, None) => DocumentSource::Directory(&edir),
+        };
+        result_file_to_csv(
+            source,
+            &result_file,
+            writer.as_mut(),
+            !args.raw_text,
+            args.per_article,
+            failures,
+        )
             .expect("Failed to convert result file to CSV");
     }
 }
@@ -1,3 +1,37 @@
+#[cfg(feature = "object-store")]
+pub mod bucket;
+pub mod calendar;
+pub mod cas;
+#[cfg(feature = "index")]
+pub mod corpus;
+pub mod diff;
+#[cfg(feature = "index")]
+pub mod embeddings;
 pub mod extractor;
+pub mod failures;
+pub mod feed;
 pub mod fonds;
+#[cfg(feature = "index")]
+pub mod index;
+#[cfg(feature = "api")]
+pub mod judilibre;
+pub mod ledger;
+pub mod lock;
+pub mod manifest;
+#[cfg(feature = "mcp")]
+pub mod mcp;
+pub mod mirror;
+pub mod notify;
+pub mod pack;
+pub mod pipeline;
+pub mod provenance;
+#[cfg(feature = "index")]
+pub mod queries;
+pub mod resume;
+#[cfg(feature = "index")]
+pub mod server;
 pub mod tarballs;
+#[cfg(feature = "index")]
+pub mod tui;
+pub mod validate;
+pub mod winpath;
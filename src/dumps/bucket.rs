@@ -0,0 +1,159 @@
+/// Copy of the tarballs and packed corpora in an object store (S3, GCS
+/// or Azure), so that the mirror of a team lives in a shared bucket.
+/// The local folders are kept as a cache of the bucket: before a
+/// download, [`Bucket::pull`] fetches the tarballs already in the bucket
+/// (which are then not downloaded again from the dila server), and
+/// [`Bucket::push`] uploads the new ones afterwards.
+///
+/// Buckets are given by URL, e.g. `s3://team-bucket/legifrance`,
+/// `gs://team-bucket/legifrance` or `az://container/legifrance`, and
+/// configured by the usual environment variables of their provider
+/// (`AWS_ACCESS_KEY_ID`, `AWS_REGION`, `GOOGLE_SERVICE_ACCOUNT`,
+/// `AZURE_STORAGE_ACCOUNT_NAME`, ...).
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use log::{debug, info};
+use object_store::ObjectStore;
+use object_store::buffered::BufWriter;
+use object_store::path::Path as ObjectPath;
+use tokio::io::AsyncWriteExt;
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Folder of the tarballs, in the bucket and in the data directory
+pub const TARBALLS_FOLDER: &str = "tarballs";
+/// Folder of the packed corpus, in the bucket and in the data directory
+pub const PACKED_FOLDER: &str = "packed";
+
+/// A folder of an object store
+pub struct Bucket {
+    store: Arc<dyn ObjectStore>,
+    prefix: ObjectPath,
+}
+
+impl Bucket {
+    pub fn new(store: Arc<dyn ObjectStore>, prefix: ObjectPath) -> Self {
+        Bucket { store, prefix }
+    }
+
+    /// The bucket at `url`, configured by the environment variables
+    pub fn from_url(url: &str) -> Result<Self> {
+        let parsed = reqwest::Url::parse(url).context(format!("Invalid bucket URL {}", url))?;
+        // the builders expect the lower case names of the variables
+        let options = std::env::vars().map(|(key, value)| (key.to_ascii_lowercase(), value));
+        let (store, prefix) = object_store::parse_url_opts(&parsed, options)
+            .context(format!("Unable to open the bucket {}", url))?;
+        Ok(Bucket::new(Arc::from(store), prefix))
+    }
+
+    fn folder_path(&self, folder: &str) -> ObjectPath {
+        self.prefix.child(folder)
+    }
+
+    /// The files of `folder` in the bucket, and their size. The
+    /// subfolders (e.g. the quarantined tarballs) are left out.
+    pub async fn list(&self, folder: &str) -> Result<BTreeMap<String, u64>> {
+        let prefix = self.folder_path(folder);
+        let listing = self.store.list_with_delimiter(Some(&prefix)).await?;
+        Ok(listing
+            .objects
+            .into_iter()
+            .filter_map(|meta| Some((meta.location.filename()?.to_string(), meta.size)))
+            .collect())
+    }
+
+    /// Download the files of `folder` that are missing in `dir`, or
+    /// whose size differs. Returns the number of files downloaded.
+    pub async fn pull(&self, folder: &str, dir: &Path) -> Result<usize> {
+        let mut count = 0;
+        for (name, size) in self.list(folder).await? {
+            let path = dir.join(&name);
+            if std::fs::metadata(&path).is_ok_and(|m| m.len() == size) {
+                continue;
+            }
+            info!("Downloading {}/{} from the bucket", folder, name);
+            std::fs::create_dir_all(dir)?;
+            // an interrupted download does not leave a truncated file
+            let partial = path.with_extension("part");
+            let mut file = tokio::fs::File::create(&partial).await?;
+            let location = self.folder_path(folder).child(name.as_str());
+            let mut chunks = self.store.get(&location).await?.into_stream();
+            while let Some(chunk) = chunks.next().await {
+                file.write_all(&chunk?).await?;
+            }
+            file.flush().await?;
+            drop(file);
+            std::fs::rename(&partial, &path).context(format!("Failed to write {}", path.display()))?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Upload the files of `dir` (but not of its subfolders) that are
+    /// missing in `folder`, or whose size differs. Returns the number
+    /// of files uploaded.
+    pub async fn push(&self, dir: &Path, folder: &str) -> Result<usize> {
+        if !dir.exists() {
+            return Ok(0);
+        }
+        let remote = self.list(folder).await?;
+        let mut count = 0;
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !metadata.is_file()
+                || name.ends_with(".part")
+                || remote.get(&name) == Some(&metadata.len())
+            {
+                continue;
+            }
+            info!("Uploading {}/{} to the bucket", folder, name);
+            let path = entry.path();
+            let location = self.folder_path(folder).child(name.as_str());
+            // large files are sent in several parts
+            let mut writer = BufWriter::new(self.store.clone(), location);
+            let mut file = tokio::fs::File::open(&path).await?;
+            tokio::io::copy(&mut file, &mut writer).await?;
+            writer.shutdown().await.context(format!("Failed to upload {}", name))?;
+            debug!("Uploaded {} ({} bytes)", name, metadata.len());
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+
+    #[tokio::test]
+    async fn test_bucket() {
+        let bucket = Bucket::new(Arc::new(InMemory::new()), ObjectPath::from("team/legifrance"));
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let local = tmp.path().join("tarballs");
+        std::fs::create_dir_all(local.join("quarantine")).unwrap();
+        std::fs::write(local.join("JADE_20240101-000000.tar.gz"), b"jade").unwrap();
+        std::fs::write(local.join("CASS_20240101-000000.tar.gz"), b"cass!").unwrap();
+        std::fs::write(local.join("quarantine/CASS_20230101-000000.tar.gz"), b"broken").unwrap();
+
+        assert_eq!(bucket.push(&local, TARBALLS_FOLDER).await.unwrap(), 2);
+        // nothing changed
+        assert_eq!(bucket.push(&local, TARBALLS_FOLDER).await.unwrap(), 0);
+        let listed = bucket.list(TARBALLS_FOLDER).await.unwrap();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed.get("CASS_20240101-000000.tar.gz"), Some(&5));
+        assert!(bucket.list(PACKED_FOLDER).await.unwrap().is_empty());
+
+        let other = tmp.path().join("other");
+        std::fs::create_dir_all(&other).unwrap();
+        std::fs::write(other.join("JADE_20240101-000000.tar.gz"), b"jade").unwrap();
+        assert_eq!(bucket.pull(TARBALLS_FOLDER, &other).await.unwrap(), 1);
+        let pulled = std::fs::read(other.join("CASS_20240101-000000.tar.gz")).unwrap();
+        assert_eq!(pulled, b"cass!");
+        assert!(!other.join("CASS_20240101-000000.tar.part").exists());
+    }
+}
@@ -0,0 +1,273 @@
+/// Calendar of the consolidated texts (LEGI) entering into force or
+/// being abrogated, for compliance watch. Every version of an article
+/// gives the day it starts applying (`DATE_DEBUT`) and, for abrogated
+/// articles, the day it stops applying (`DATE_FIN`). The articles of a
+/// text changing on the same day are grouped in one event, written as
+/// an iCalendar file (RFC 5545) or as JSON.
+use anyhow::{Context, Result};
+use chrono::{NaiveDate, Utc};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+use crate::dumps::extractor::identifiers::permalink;
+use crate::dumps::extractor::legi::LegiArticle;
+use crate::dumps::extractor::{self, Document};
+use crate::dumps::tarballs::walk_files;
+
+/// Formats of the calendar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum CalendarFormat {
+    /// iCalendar, for calendar applications
+    Ics,
+    Json,
+}
+
+/// What happens to the articles of an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// A version of the articles starts applying
+    EntryIntoForce,
+    /// The articles stop applying
+    Abrogation,
+}
+
+impl EventKind {
+    fn label(&self) -> &'static str {
+        match self {
+            EventKind::EntryIntoForce => "Entrée en vigueur",
+            EventKind::Abrogation => "Abrogation",
+        }
+    }
+}
+
+/// The articles of a text entering into force or abrogated on a day
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CalendarEvent {
+    pub date: NaiveDate,
+    pub kind: EventKind,
+    /// Identifier of the text (e.g. "LEGITEXT000006074075")
+    pub text_id: String,
+    pub text_title: Option<String>,
+    /// Identifiers of the versions of the articles
+    pub articles: Vec<String>,
+    /// Numbers of the articles (e.g. "L110-1"), when known
+    pub numbers: Vec<String>,
+}
+
+impl CalendarEvent {
+    fn summary(&self) -> String {
+        let title = self.text_title.as_deref().unwrap_or(&self.text_id);
+        match self.numbers.as_slice() {
+            [number] => format!("{} : {}, article {}", self.kind.label(), title, number),
+            _ => format!("{} : {}, {} articles", self.kind.label(), title, self.articles.len()),
+        }
+    }
+}
+
+/// The events of an article version within `from`..=`to`
+fn article_events(article: &LegiArticle, from: NaiveDate, to: NaiveDate) -> Vec<(NaiveDate, EventKind)> {
+    let parse = |date: Option<&str>| {
+        date.and_then(|d| NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d").ok())
+            .filter(|d| (from..=to).contains(d))
+    };
+    let mut events = vec![];
+    if let Some(start) = parse(article.date_debut.as_deref()) {
+        events.push((start, EventKind::EntryIntoForce));
+    }
+    // the versions replaced by a new one also have an end, which is
+    // the entry into force of the next version
+    let abrogated = article.etat.as_deref().is_some_and(|etat| etat.starts_with("ABROGE"));
+    if abrogated && let Some(end) = parse(article.date_fin.as_deref()) {
+        events.push((end, EventKind::Abrogation));
+    }
+    events
+}
+
+/// The events of the articles of the extraction directory `dir`
+/// within `from`..=`to` (both included), sorted by date
+pub fn calendar(dir: &Path, from: NaiveDate, to: NaiveDate) -> Result<Vec<CalendarEvent>> {
+    type Key = (NaiveDate, EventKind, String);
+    let grouped = walk_files(dir)
+        .par_bridge()
+        .try_fold(BTreeMap::<Key, CalendarEvent>::new, |mut events, file| {
+            let file = file?;
+            let is_article = file
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("LEGIARTI") && name.ends_with(".xml"));
+            if !is_article {
+                return Ok(events);
+            }
+            // unparsable files are reported by `validate`
+            let Ok(Document::LegiArticle(article)) = extractor::parse_document_file(&file) else {
+                return Ok(events);
+            };
+            for (date, kind) in article_events(&article, from, to) {
+                let text_id = article.parent_text.clone().unwrap_or_default();
+                let event = events
+                    .entry((date, kind, text_id.clone()))
+                    .or_insert_with(|| CalendarEvent {
+                        date,
+                        kind,
+                        text_id,
+                        text_title: article.parent_title.clone(),
+                        articles: vec![],
+                        numbers: vec![],
+                    });
+                event.articles.push(article.id.clone());
+                if let Some(num) = article.num.as_ref() {
+                    event.numbers.push(num.trim().to_string());
+                }
+            }
+            Ok::<_, anyhow::Error>(events)
+        })
+        .try_reduce(BTreeMap::new, |mut a, b| {
+            for (key, event) in b {
+                match a.get_mut(&key) {
+                    Some(existing) => {
+                        existing.articles.extend(event.articles);
+                        existing.numbers.extend(event.numbers);
+                    }
+                    None => {
+                        a.insert(key, event);
+                    }
+                }
+            }
+            Ok(a)
+        })?;
+    Ok(grouped
+        .into_values()
+        .map(|mut event| {
+            event.articles.sort();
+            event.numbers.sort();
+            event.numbers.dedup();
+            event
+        })
+        .collect())
+}
+
+/// Escape a text value of iCalendar
+fn ics_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// Fold a content line of iCalendar in lines of at most 75 bytes
+fn ics_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+/// The events as an iCalendar file, one all-day event each
+pub fn to_ics(events: &[CalendarEvent]) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ");
+    let mut ics = String::new();
+    for line in ["BEGIN:VCALENDAR", "VERSION:2.0", "PRODID:-//legifrance-rs//dilarxiv//FR"] {
+        ics.push_str(&ics_line(line));
+    }
+    for event in events {
+        let day = event.date.format("%Y%m%d");
+        let next = event.date.succ_opt().unwrap_or(event.date).format("%Y%m%d");
+        let kind = match event.kind {
+            EventKind::EntryIntoForce => "vigueur",
+            EventKind::Abrogation => "abrogation",
+        };
+        let mut description = event.articles.join(", ");
+        if !event.numbers.is_empty() {
+            description = format!("Articles {}\n{}", event.numbers.join(", "), description);
+        }
+        ics.push_str(&ics_line("BEGIN:VEVENT"));
+        ics.push_str(&ics_line(&format!("UID:{}-{}-{}@legifrance-rs", event.text_id, day, kind)));
+        ics.push_str(&ics_line(&format!("DTSTAMP:{}", stamp)));
+        ics.push_str(&ics_line(&format!("DTSTART;VALUE=DATE:{}", day)));
+        ics.push_str(&ics_line(&format!("DTEND;VALUE=DATE:{}", next)));
+        ics.push_str(&ics_line(&format!("SUMMARY:{}", ics_escape(&event.summary()))));
+        ics.push_str(&ics_line(&format!("DESCRIPTION:{}", ics_escape(&description))));
+        if let Some(url) = permalink(&event.text_id) {
+            ics.push_str(&ics_line(&format!("URL:{}", url)));
+        }
+        ics.push_str(&ics_line("END:VEVENT"));
+    }
+    ics.push_str(&ics_line("END:VCALENDAR"));
+    ics
+}
+
+/// Write the events in the given format
+pub fn write_calendar(events: &[CalendarEvent], format: CalendarFormat, path: &Path) -> Result<()> {
+    let file = std::fs::File::create(path)
+        .context(format!("Failed to create calendar {}", path.display()))?;
+    let mut writer = std::io::BufWriter::new(file);
+    match format {
+        CalendarFormat::Ics => writer.write_all(to_ics(events).as_bytes())?,
+        CalendarFormat::Json => serde_json::to_writer_pretty(&mut writer, events)?,
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article(id: &str, num: &str, etat: &str, debut: &str, fin: &str) -> String {
+        format!(
+            "<ARTICLE><META><META_COMMUN><ID>{id}</ID><NATURE>Article</NATURE></META_COMMUN>\
+             <META_SPEC><META_ARTICLE><NUM>{num}</NUM><ETAT>{etat}</ETAT>\
+             <DATE_DEBUT>{debut}</DATE_DEBUT><DATE_FIN>{fin}</DATE_FIN></META_ARTICLE></META_SPEC></META>\
+             <CONTEXTE><TEXTE cid=\"LEGITEXT000006074075\"><TITRE_TXT>Code de l'urbanisme</TITRE_TXT></TEXTE></CONTEXTE>\
+             <BLOC_TEXTUEL><CONTENU>Texte.</CONTENU></BLOC_TEXTUEL></ARTICLE>"
+        )
+    }
+
+    #[test]
+    fn test_calendar() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let dir = tmp.path().join("legi/global/code_et_TNC_en_vigueur");
+        std::fs::create_dir_all(&dir).unwrap();
+        let files = [
+            ("LEGIARTI000000000001", "L110-1", "VIGUEUR", "2024-07-01", "2999-01-01"),
+            ("LEGIARTI000000000002", "L110-2", "VIGUEUR", "2024-07-01", "2999-01-01"),
+            ("LEGIARTI000000000003", "L110-3", "ABROGE", "2001-01-01", "2024-07-15"),
+            ("LEGIARTI000000000004", "L110-4", "MODIFIE", "2001-01-01", "2024-07-01"),
+            ("LEGIARTI000000000005", "L110-5", "VIGUEUR", "2023-01-01", "2999-01-01"),
+        ];
+        for (id, num, etat, debut, fin) in files {
+            std::fs::write(dir.join(format!("{}.xml", id)), article(id, num, etat, debut, fin)).unwrap();
+        }
+
+        let from = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        let to = NaiveDate::from_ymd_opt(2024, 12, 31).unwrap();
+        let events = calendar(tmp.path(), from, to).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].kind, EventKind::EntryIntoForce);
+        assert_eq!(events[0].date, NaiveDate::from_ymd_opt(2024, 7, 1).unwrap());
+        assert_eq!(events[0].text_id, "LEGITEXT000006074075");
+        assert_eq!(events[0].numbers, ["L110-1", "L110-2"]);
+        assert_eq!(events[1].kind, EventKind::Abrogation);
+        assert_eq!(events[1].articles, ["LEGIARTI000000000003"]);
+
+        let ics = to_ics(&events);
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.contains("DTSTART;VALUE=DATE:20240715\r\nDTEND;VALUE=DATE:20240716\r\n"));
+        assert!(ics.contains("SUMMARY:Abrogation : Code de l'urbanisme\\, article L110-3\r\n"));
+        assert!(ics.lines().all(|line| line.len() <= 75));
+    }
+}
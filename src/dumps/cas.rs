@@ -0,0 +1,182 @@
+/// Content addressable storage for extracted XML files.
+///
+/// Delta tarballs contain many identical copies of documents that
+/// did not change. Instead of writing every entry at its path in the
+/// archive, we store each distinct content once, in a blob named
+/// after its SHA-256 hash, and keep a map from the relative path of
+/// the document (as found in the archive) to the hash of its content.
+///
+/// Layout of a store:
+///
+/// ```text
+/// root/
+///   map.tsv                  relative path <TAB> hash, one per line
+///   blobs/ab/abcdef….xml     content of a document
+/// ```
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+use log::{debug, warn};
+use std::collections::BTreeMap;
+use std::io::{BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::dumps::tarballs::sanitize_entry;
+
+pub struct ContentStore {
+    root: PathBuf,
+    map: BTreeMap<String, String>,
+}
+
+/// Hex encoded SHA-256 of some content
+pub fn content_hash(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+impl ContentStore {
+    /// Open the store at `root`, creating it if needed
+    pub fn open(root: &Path) -> Result<Self> {
+        std::fs::create_dir_all(root.join("blobs"))
+            .context(format!("Failed to create store {}", root.display()))?;
+        let mut map = BTreeMap::new();
+        let map_path = root.join("map.tsv");
+        if map_path.exists() {
+            let file = std::fs::File::open(&map_path)
+                .context(format!("Failed to open {}", map_path.display()))?;
+            for line in std::io::BufReader::new(file).lines() {
+                let line = line?;
+                if let Some((path, hash)) = line.split_once('\t') {
+                    map.insert(path.to_string(), hash.to_string());
+                }
+            }
+        }
+        Ok(ContentStore {
+            root: root.to_path_buf(),
+            map,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Location of the blob holding a given hash
+    pub fn blob_path(&self, hash: &str) -> PathBuf {
+        self.root
+            .join("blobs")
+            .join(&hash[..2.min(hash.len())])
+            .join(format!("{}.xml", hash))
+    }
+
+    /// Store `content` under the relative path `path`.
+    /// Returns true if a new blob had to be written.
+    pub fn insert(&mut self, path: &str, content: &[u8]) -> Result<bool> {
+        let hash = content_hash(content);
+        let blob = self.blob_path(&hash);
+        let written = if blob.exists() {
+            debug!("{} already stored as {}", path, hash);
+            false
+        } else {
+            if let Some(parent) = blob.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&blob, content)
+                .context(format!("Failed to write blob {}", blob.display()))?;
+            true
+        };
+        self.map.insert(path.to_string(), hash);
+        Ok(written)
+    }
+
+    /// Resolve the relative path of a document into the blob storing it
+    pub fn resolve(&self, path: &str) -> Option<PathBuf> {
+        self.map.get(path.trim()).map(|h| self.blob_path(h))
+    }
+
+    /// Iterate over (relative path, blob path) pairs
+    pub fn documents(&self) -> impl Iterator<Item = (&str, PathBuf)> {
+        self.map
+            .iter()
+            .map(|(path, hash)| (path.as_str(), self.blob_path(hash)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Persist the path → hash map
+    pub fn save(&self) -> Result<()> {
+        let map_path = self.root.join("map.tsv");
+        let tmp_path = self.root.join("map.tsv.tmp");
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&tmp_path)?);
+        for (path, hash) in self.map.iter() {
+            writeln!(writer, "{}\t{}", path, hash)?;
+        }
+        writer.flush()?;
+        drop(writer);
+        std::fs::rename(&tmp_path, &map_path)
+            .context(format!("Failed to write {}", map_path.display()))?;
+        Ok(())
+    }
+
+    /// Extract every file of a tarball into the store.
+    /// Returns the number of new blobs that were written.
+    pub fn extract_tarball(&mut self, tarball: &Path) -> Result<usize> {
+        // the tarball, and the blob being written
+        let _open = crate::open_files::acquire(2);
+        let file = std::fs::File::open(tarball)
+            .context(format!("Failed to open tarball {}", tarball.display()))?;
+        let gzip = flate2::read::GzDecoder::new(file);
+        let mut tar = tar::Archive::new(gzip);
+
+        let mut written = 0;
+        let mut buffer = vec![];
+        for entry in tar.entries()? {
+            let mut entry = entry.context(format!("Corrupted entry in {}", tarball.display()))?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let path = match sanitize_entry(&entry) {
+                Ok(path) => path.to_string_lossy().to_string(),
+                Err(e) => {
+                    warn!("Rejected entry of {}: {:#}", tarball.display(), e);
+                    continue;
+                }
+            };
+            buffer.clear();
+            entry.read_to_end(&mut buffer)?;
+            if self.insert(&path, &buffer)? {
+                written += 1;
+            }
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_store_deduplicates() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let mut store = ContentStore::open(tmp.path()).unwrap();
+        assert!(store.insert("a/doc1.xml", b"<ID>1</ID>").unwrap());
+        assert!(!store.insert("b/doc1.xml", b"<ID>1</ID>").unwrap());
+        assert!(store.insert("a/doc2.xml", b"<ID>2</ID>").unwrap());
+        assert_eq!(store.resolve("a/doc1.xml"), store.resolve("b/doc1.xml"));
+        store.save().unwrap();
+
+        let store = ContentStore::open(tmp.path()).unwrap();
+        assert_eq!(store.len(), 3);
+        let blob = store.resolve("a/doc2.xml").unwrap();
+        assert_eq!(std::fs::read(blob).unwrap(), b"<ID>2</ID>");
+    }
+}
@@ -0,0 +1,926 @@
+/// A local copy of the dila open data, for the programs using the
+/// library: a [`Corpus`] keeps the tarballs, the extracted documents
+/// and the index under one directory. [`Corpus::sync`] downloads,
+/// extracts and indexes the new tarballs, then the corpus answers
+/// searches, returns documents by identifier and exports the results
+/// of a query, with the same steps as `dilarxiv`, which is built on
+/// the functions of this module.
+use anyhow::{Context, Result};
+use indicatif::ProgressStyle;
+use log::{debug, error, info, warn};
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::dumps::extractor::{
+    self, Document, DocumentKind, DocumentWriter, parse_articles_file, parse_document_file,
+};
+use crate::dumps::cas::ContentStore;
+use crate::dumps::failures::{FailureLog, Stage};
+use crate::dumps::fonds::{FONDS, Fond};
+use crate::dumps::pack::PackReader;
+use crate::dumps::provenance;
+use crate::dumps::tarballs::{
+    self, AnalyzerConfig, DownloadOptions, IndexFields, IndexingOptions, SearchOptions,
+    SearchResults, Tarball,
+};
+use crate::dumps::winpath;
+use crate::logging;
+use crate::shutdown;
+
+/// Folders of a corpus, in its root directory
+pub const TARBALLS_DIR: &str = "tarballs";
+pub const EXTRACTED_DIR: &str = "extracted";
+pub const INDEX_DIR: &str = "index";
+
+/// Size of the heap of the index writers, in bytes
+const WRITER_HEAP_SIZE: usize = 50_000_000;
+
+/// Settings of a [`Corpus`], the same as the options of `dilarxiv`
+#[derive(Debug, Clone)]
+pub struct CorpusConfig {
+    /// Directory of the `tarballs`, `extracted` and `index` folders
+    pub root: PathBuf,
+    /// The fonds downloaded by [`Corpus::sync`], all of them when empty
+    pub fonds: Vec<Fond>,
+    pub client: reqwest::Client,
+    pub download: DownloadOptions,
+    /// Settings of the analyzer of a new index
+    pub analyzer: AnalyzerConfig,
+    pub indexing: IndexingOptions,
+    /// Number of fonds extracted in parallel
+    pub extractions: usize,
+    /// Index and export the articles of the texts separately
+    pub per_article: bool,
+    /// Use one index per fond
+    pub per_fond: bool,
+}
+
+impl CorpusConfig {
+    /// The default settings for a corpus in `root`
+    pub fn new<T: Into<PathBuf>>(root: T) -> Self {
+        CorpusConfig {
+            root: root.into(),
+            fonds: vec![],
+            client: reqwest::Client::new(),
+            download: DownloadOptions::default(),
+            analyzer: AnalyzerConfig::default(),
+            indexing: IndexingOptions::default(),
+            extractions: 1,
+            per_article: false,
+            per_fond: false,
+        }
+    }
+}
+
+/// The tarballs, extracted documents and index of a directory
+pub struct Corpus {
+    config: CorpusConfig,
+    indices: Vec<(tantivy::Index, IndexFields)>,
+}
+
+impl Corpus {
+    /// Open the corpus in the root directory of `config`, creating
+    /// its folders when they do not exist
+    pub fn open(config: CorpusConfig) -> Result<Self> {
+        let mut corpus = Corpus {
+            config,
+            indices: vec![],
+        };
+        for dir in [corpus.tarballs_dir(), corpus.extracted_dir(), corpus.index_dir()] {
+            std::fs::create_dir_all(&dir).context(format!("Unable to create {}", dir.display()))?;
+        }
+        corpus.reload()?;
+        Ok(corpus)
+    }
+
+    pub fn tarballs_dir(&self) -> PathBuf {
+        self.config.root.join(TARBALLS_DIR)
+    }
+
+    pub fn extracted_dir(&self) -> PathBuf {
+        self.config.root.join(EXTRACTED_DIR)
+    }
+
+    pub fn index_dir(&self) -> PathBuf {
+        self.config.root.join(INDEX_DIR)
+    }
+
+    /// The files that could not be processed since the corpus was opened
+    pub fn failures(&self) -> &FailureLog {
+        &self.config.indexing.failures
+    }
+
+    /// Open the index again, after it changed
+    fn reload(&mut self) -> Result<()> {
+        self.indices = open_existing_indices(&self.index_dir())?;
+        Ok(())
+    }
+
+    fn indices(&self) -> Vec<(&tantivy::Index, &IndexFields)> {
+        self.indices.iter().map(|(index, flds)| (index, flds)).collect()
+    }
+
+    /// Download the new tarballs of the fonds, extract and index
+    /// them, as `dilarxiv update` does. Returns the number of new
+    /// tarballs.
+    pub async fn sync(&mut self) -> Result<usize> {
+        let config = &self.config;
+        let fonds = if config.fonds.is_empty() {
+            FONDS
+        } else {
+            &config.fonds
+        };
+        let tmpd = temp_dir::TempDir::new().context("Failed to create temporary directory")?;
+        let count = update_and_index_data(
+            &config.client,
+            fonds,
+            &self.tarballs_dir(),
+            &self.index_dir(),
+            &self.extracted_dir(),
+            tmpd.path(),
+            &config.download,
+            config.per_article,
+            config.per_fond,
+            &config.analyzer,
+            &config.indexing,
+            config.extractions,
+        )
+        .await?;
+        self.reload()?;
+        Ok(count)
+    }
+
+    /// Index all the extracted documents again, as `dilarxiv index`
+    /// does. The new index replaces the current one once complete.
+    pub fn index(&mut self) -> Result<()> {
+        let config = &self.config;
+        let edir = self.extracted_dir();
+        tarballs::rebuild_index(&self.index_dir(), |building| {
+            if config.per_fond {
+                tarballs::index_fonds_in_dir(
+                    building,
+                    &edir,
+                    WRITER_HEAP_SIZE,
+                    config.per_article,
+                    &config.analyzer,
+                    &config.indexing,
+                )?;
+                return Ok(());
+            }
+            let (index, flds) = tarballs::init_tantivy_with(&building.to_path_buf(), &config.analyzer)?;
+            let mut writer = index.writer(WRITER_HEAP_SIZE)?;
+            tarballs::index_files_in_dir(&mut writer, &flds, &edir, config.per_article, &config.indexing)
+        })?;
+        self.reload()
+    }
+
+    /// Search the index, see the syntax of the queries of `dilarxiv search`
+    pub fn search(&self, query: &str, options: &SearchOptions) -> Result<SearchResults> {
+        tarballs::search_indices(&self.indices(), &None, query, options)
+    }
+
+    /// The document with the identifier `id` (e.g. `CETATEXT000049314894`),
+    /// read from the extracted files
+    pub fn document(&self, id: &str) -> Result<Option<Document>> {
+        let stored = self
+            .indices
+            .iter()
+            .find_map(|(index, flds)| tarballs::get_by_id(index, flds, id).transpose())
+            .transpose()?;
+        let Some(stored) = stored else {
+            return Ok(None);
+        };
+        let edir = self.extracted_dir();
+        let documents = DocumentSource::Directory(&edir).read(&stored.path, false)??;
+        Ok(documents.into_iter().next())
+    }
+
+    /// Write all the documents matching `query` with `writer`, as
+    /// `dilarxiv export` does. Returns the number of documents written
+    /// per kind.
+    pub fn export(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+        writer: &mut dyn DocumentWriter,
+    ) -> Result<BTreeMap<DocumentKind, usize>> {
+        let paths = tarballs::matching_paths(&self.indices(), query, options)?;
+        let edir = self.extracted_dir();
+        export_documents(
+            DocumentSource::Directory(&edir),
+            &paths,
+            writer,
+            true,
+            self.config.per_article,
+            self.failures(),
+        )
+    }
+}
+
+/// Download the tarballs of `fonds` that are not in `tdir`, and add
+/// them to the index at `idir` and to the extracted files of `edir`
+/// (see [`index_new_tarballs`]). Returns the number of new tarballs.
+#[allow(clippy::too_many_arguments)]
+pub async fn update_and_index_data(
+    client: &reqwest::Client,
+    fonds: &[Fond],
+    tdir: &Path,
+    idir: &PathBuf,
+    edir: &Path,
+    tmpd: &Path,
+    opts: &DownloadOptions,
+    per_article: bool,
+    per_fond: bool,
+    analyzer: &AnalyzerConfig,
+    indexing: &IndexingOptions,
+    extractions: usize,
+) -> Result<usize> {
+    let tb = get_tarballs(client, fonds, tdir, opts, &indexing.failures).await?;
+    if tb.is_empty() {
+        info!("No new tarballs to download");
+        return Ok(0);
+    }
+    info!("Downloaded {} tarballs", tb.len());
+
+    index_new_tarballs(
+        &tb,
+        tdir,
+        idir,
+        edir,
+        tmpd,
+        per_article,
+        per_fond,
+        analyzer,
+        indexing,
+        extractions,
+    )?;
+    Ok(tb.len())
+}
+
+/// Extract the tarballs `tb` of `tdir`, each in its own directory of
+/// `tmpd`, then, in the order of their publication, add their documents
+/// to the index at `idir`, remove the documents of their suppression
+/// lists from the index and from `edir`, and move their files to
+/// `edir`. A document removed by a delta and published again by a
+/// later one is thus kept, and the other way around.
+#[allow(clippy::too_many_arguments)]
+pub fn index_new_tarballs(
+    tb: &[Tarball],
+    tdir: &Path,
+    idir: &PathBuf,
+    edir: &Path,
+    tmpd: &Path,
+    per_article: bool,
+    per_fond: bool,
+    analyzer: &AnalyzerConfig,
+    indexing: &IndexingOptions,
+    extractions: usize,
+) -> Result<()> {
+    let mut tb = tb.to_vec();
+    tb.sort_by_key(Tarball::application_order);
+
+    // the suppression lists of the deltas of a fond have the same name
+    let dir_of = |tarball: &Path| tmpd.join(tarball.file_name().unwrap_or_default());
+    extract_tarballs_to(tdir, &tb, dir_of, extractions, &indexing.failures)
+        .context("Failed to extract tarballs")?;
+
+    for tarball in tb.iter() {
+        let dir = dir_of(tarball.as_ref());
+        if !dir.is_dir() {
+            // the failure to extract it was recorded
+            continue;
+        }
+        let indices = if per_fond {
+            let fonds = tarballs::index_fonds_in_dir(
+                idir,
+                &dir,
+                WRITER_HEAP_SIZE,
+                per_article,
+                analyzer,
+                indexing,
+            )
+            .context("Failed to index files")?;
+            tarballs::open_fond_indices(idir, &fonds, analyzer)?
+        } else {
+            let (index, flds) =
+                tarballs::init_tantivy_with(idir, analyzer).context("Failed to create index")?;
+            let mut writer = index.writer(WRITER_HEAP_SIZE).context("Failed to create writer")?;
+            tarballs::index_files_in_dir(&mut writer, &flds, &dir, per_article, indexing)
+                .context("Failed to index files")?;
+            vec![(index, flds)]
+        };
+
+        // documents removed upstream are listed in the delta tarballs
+        let lists: Vec<PathBuf> = tarballs::walk_files(&dir)
+            .filter_map(Result::ok)
+            .filter(|p| tarballs::is_suppression_list(p))
+            .collect();
+        if !lists.is_empty() {
+            for (index, flds) in indices.iter() {
+                let mut writer =
+                    index.writer(WRITER_HEAP_SIZE).context("Failed to create writer")?;
+                for list in lists.iter() {
+                    tarballs::apply_suppression_list(&writer, flds, list)?;
+                }
+                writer.commit().context("Failed to commit writer")?;
+            }
+            for list in lists.iter() {
+                let count = remove_suppressed_files(edir, list)?;
+                info!("Removed {} documents listed in {} of {}", count, list.display(), tarball.name);
+            }
+        }
+
+        move_extracted_files(&dir, edir)?;
+    }
+    if shutdown::is_interrupted() {
+        // the tarballs are not downloaded again by the next update
+        warn!("Interrupted, the index misses documents of the new tarballs: run `dilarxiv index` to rebuild it");
+    }
+    Ok(())
+}
+
+/// Remove from the extraction directory `dir` the files listed in the
+/// suppression list `list`. Returns the number of files removed.
+fn remove_suppressed_files(dir: &Path, list: &Path) -> Result<usize> {
+    let content = std::fs::read_to_string(list)
+        .context(format!("Failed to read suppression list {}", list.display()))?;
+    let mut removed = 0;
+    for path in tarballs::suppressed_paths(&content) {
+        let path = PathBuf::from(path);
+        if !path.components().all(|c| matches!(c, std::path::Component::Normal(_))) {
+            warn!("Not removing {}, listed in {}", path.display(), list.display());
+            continue;
+        }
+        // the files renamed on Windows are extracted to their portable path
+        let mut paths = vec![dir.join(&path)];
+        if cfg!(windows) {
+            paths.push(dir.join(winpath::portable_path(&path)));
+        }
+        for path in paths {
+            match std::fs::remove_file(&path) {
+                Ok(()) => removed += 1,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(e).context(format!("Failed to remove {}", path.display()));
+                }
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// Move the files extracted in `dir` to the extraction directory `edir`,
+/// merging their provenance and path mapping logs
+fn move_extracted_files(dir: &Path, edir: &Path) -> Result<()> {
+    std::fs::create_dir_all(edir)
+        .context(format!("Failed to create directory {}", edir.display()))?;
+    let mut dir_stack = Vec::new();
+    dir_stack.push(dir.to_path_buf());
+    while let Some(current_dir) = dir_stack.pop() {
+        for entry in std::fs::read_dir(&current_dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path == dir.join(provenance::PROVENANCE_FILE) {
+                provenance::merge_into(&path, edir)?;
+            } else if path == dir.join(winpath::PATH_MAPPING_FILE) {
+                winpath::merge_into(&path, edir)?;
+            } else if path.is_file() {
+                // dir/current_dir/file_name -> edir/current_dir/file_name
+                // 1) create the parent directory if it does not exist
+                let edir_current = edir.join(current_dir.strip_prefix(dir)?);
+                std::fs::create_dir_all(&edir_current).context(format!(
+                    "Failed to create directory {}",
+                    edir_current.display()
+                ))?;
+                // 2) move the file
+                let target_path = edir_current.join(path.file_name().unwrap());
+                std::fs::rename(&path, &target_path).context(format!(
+                    "Failed to move file from {} to {}",
+                    path.display(),
+                    target_path.display()
+                ))?;
+            } else if path.is_dir() {
+                // Recursively move directories
+                dir_stack.push(path.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Download the tarballs of `fonds` that are not in `dir` yet
+pub async fn get_tarballs(
+    client: &reqwest::Client,
+    fonds: &[Fond],
+    dir: &Path,
+    opts: &DownloadOptions,
+    failures: &FailureLog,
+) -> Result<Vec<Tarball>> {
+    let mut tarballs = Vec::new();
+
+    for fond in fonds {
+        info!("Downloading tarballs for {}", fond);
+        // Download the tarballs
+        match tarballs::download_tarballs(client, dir, fond, opts).await {
+            Ok(report) => {
+                info!("{}: {}", fond, report.summary());
+                failures.processed(report.entries.len() + report.unparsable.len());
+                // a delta left out would silently miss from the corpus
+                for unparsable in report.unparsable.iter() {
+                    error!("Not downloading {}: {}", unparsable.name, unparsable.error);
+                    failures.record(unparsable.name.clone(), Stage::Download, &unparsable.error);
+                }
+                for entry in report.entries.iter() {
+                    if let tarballs::DownloadStatus::Failed(e) = &entry.status {
+                        error!(
+                            "Failed to download {} after {} attempts: {}",
+                            entry.tarball, entry.attempts, e
+                        );
+                        failures.record(entry.tarball.name.clone(), Stage::Download, e);
+                    }
+                }
+                tarballs.extend(report.downloaded());
+            }
+            Err(e) => {
+                error!("Error fetching tarballs: {}", e);
+                continue;
+            }
+        }
+    }
+
+    Ok(tarballs)
+}
+
+/// Extract the tarballs in `odir`. The tarballs of different fonds are
+/// extracted in parallel (`jobs` at once), and the tarballs of a fond
+/// one after the other in the order of their publication, so that the
+/// files of the latest ones are kept.
+pub fn extract_tarballs<T>(
+    idir: &Path,
+    to_extract: &[T],
+    odir: &Path,
+    jobs: usize,
+    failures: &FailureLog,
+) -> Result<()>
+where
+    T: AsRef<Path> + Sync
+{
+    extract_tarballs_to(idir, to_extract, |_| odir.to_path_buf(), jobs, failures)
+}
+
+/// Extract the tarballs as [`extract_tarballs`] does, each one in the
+/// directory `odir` gives for its path
+fn extract_tarballs_to<T, F>(
+    idir: &Path,
+    to_extract: &[T],
+    odir: F,
+    jobs: usize,
+    failures: &FailureLog,
+) -> Result<()>
+where
+    T: AsRef<Path> + Sync,
+    F: Fn(&Path) -> PathBuf + Sync,
+{
+    use rayon::prelude::*;
+
+    let pb = logging::progress_bar(to_extract.len() as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{msg} [{elapsed_precise}] {wide_bar} {pos}/{len} ({eta})")
+            .context("Error creating progress bar")?
+            .progress_chars("##-"),
+    );
+
+    // tarballs are named after their fond (e.g. JADE_20240101-000000.tar.gz
+    // or Freemium_jade_global_20240101-000000.tar.gz), the ones with an
+    // unexpected name are extracted last, in the given order
+    let mut by_fond: std::collections::BTreeMap<String, Vec<(_, &T)>> = Default::default();
+    for p in to_extract {
+        let name = p.as_ref().file_name().unwrap_or_default().to_string_lossy();
+        let (fond, order) = match tarballs::Tarball::from_name(&name) {
+            Ok(tarball) => (tarball.fond.to_string(), Some(tarball.application_order())),
+            Err(_) => (name.split('_').next().unwrap_or_default().to_string(), None),
+        };
+        by_fond.entry(fond).or_default().push((order, p));
+    }
+    let by_fond: std::collections::BTreeMap<String, Vec<&T>> = by_fond
+        .into_iter()
+        .map(|(fond, mut tarballs)| {
+            // a stable sort, None (unexpected names) last
+            tarballs.sort_by_key(|(order, _)| (order.is_none(), order.clone()));
+            (fond, tarballs.into_iter().map(|(_, p)| p).collect())
+        })
+        .collect();
+
+    // the provenance log is shared by all the fonds
+    let provenance_lock = std::sync::Mutex::new(());
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs.max(1)).build()?;
+    pool.install(|| {
+        by_fond.par_iter().try_for_each(|(_, fond_tarballs)| -> Result<()> {
+            for p in fond_tarballs {
+                let p = p.as_ref();
+                pb.set_message(format!("Extracting {}", p.display()));
+                let path = idir.join(p);
+                failures.processed(1);
+                if path.exists() {
+                    let odir = odir(p);
+                    match tarballs::extract_tarball(&path, &odir) {
+                        Ok(files) => {
+                            info!("Successfully extracted {:?}", path);
+                            let name = p.file_name().unwrap_or_default().to_string_lossy();
+                            let _log = provenance_lock.lock().expect("provenance lock poisoned");
+                            provenance::record(&odir, &name, &files)?;
+                        }
+                        Err(e) => {
+                            error!("Error extracting {:?}: {}", path, e);
+                            failures.record(path.display().to_string(), Stage::Extract, e);
+                        }
+                    }
+                } else {
+                    warn!("Tarball {:?} does not exist", path);
+                }
+                pb.inc(1);
+            }
+            Ok(())
+        })
+    })
+}
+
+/// Open the index, either a single index or one index per fond
+/// (see `--per-fond`), depending on how it was created
+pub fn open_existing_indices(
+    index_path: &PathBuf,
+) -> Result<Vec<(tantivy::Index, IndexFields)>> {
+    if index_path.join("meta.json").is_file() {
+        return Ok(vec![tarballs::init_tantivy(index_path)?]);
+    }
+    let fonds = tarballs::list_fond_indices(index_path)?;
+    tarballs::open_fond_indices(index_path, &fonds, &AnalyzerConfig::default())
+}
+
+/// Where the documents listed in a result file should be read from
+pub enum DocumentSource<'a> {
+    Directory(&'a Path),
+    Store(&'a ContentStore),
+    Pack(&'a PackReader),
+}
+
+impl DocumentSource<'_> {
+    /// The file of a document, for the sources keeping documents as files
+    pub fn file(&self, doc_path: &str) -> Result<Option<PathBuf>> {
+        match self {
+            DocumentSource::Pack(_) => Ok(None),
+            DocumentSource::Store(store) => store
+                .resolve(doc_path)
+                .map(Some)
+                .ok_or_else(|| anyhow::anyhow!("{} is not in the store", doc_path)),
+            DocumentSource::Directory(edir) => Ok(Some(edir.join(doc_path))),
+        }
+    }
+
+    /// Read the documents listed on a line of a result file: `path`,
+    /// or `path#article_id` for one article of a text. With `per_article`
+    /// every text is read as one document per article. The outer error
+    /// is an error of the source, the inner one an invalid document.
+    pub fn read(
+        &self,
+        line: &str,
+        per_article: bool,
+    ) -> Result<Result<Vec<Document>, extractor::ExtractError>> {
+        let (doc_path, article_id) = extractor::split_article_path(line);
+        let split = per_article || article_id.is_some();
+        let documents = if let DocumentSource::Pack(pack) = self {
+            debug!("Processing document: {}", line.trim());
+            let content = pack.get_by_path(doc_path)?;
+            if split {
+                extractor::parse_articles(&content)
+            } else {
+                extractor::parse_document(&content).map(|d| vec![d])
+            }
+        } else {
+            let path = self.file(doc_path)?.expect("documents of this source are files");
+            debug!("Processing file: {}", path.display());
+            if split {
+                parse_articles_file(&path)
+            } else {
+                parse_document_file(&path).map(|d| vec![d])
+            }
+        };
+        Ok(documents.map(|documents| {
+            documents
+                .into_iter()
+                .filter(|document| match (article_id, document) {
+                    (Some(id), Document::LegiArticle(article)) => article.id == id,
+                    _ => true,
+                })
+                .collect()
+        }))
+    }
+}
+
+/// Write the documents read from the line `line` of a result file,
+/// or the reason why they could not be read. Placeholder documents
+/// (see [`Document::is_empty`]) are left out. Returns the kinds of
+/// the documents written.
+pub fn write_documents(
+    writer: &mut dyn DocumentWriter,
+    line: &str,
+    documents: Result<Vec<Document>, extractor::ExtractError>,
+    clean: bool,
+    kinds: &mut BTreeMap<DocumentKind, usize>,
+    failures: &FailureLog,
+) -> Result<()> {
+    failures.processed(1);
+    match documents {
+        Ok(documents) => {
+            for mut document in documents {
+                if document.is_empty() {
+                    debug!("Skipping the empty document {}", document.id());
+                    continue;
+                }
+                if clean {
+                    document.clean();
+                }
+                *kinds.entry(document.document_kind()).or_insert(0) += 1;
+                writer.write(&document)?;
+            }
+        }
+        Err(e) => {
+            warn!("Could not parse {}: {}", line.trim(), e);
+            writer.write_error(line.trim(), &e)?;
+            failures.record(line.trim(), Stage::Export, &e);
+        }
+    }
+    Ok(())
+}
+
+/// Number of documents parsed in parallel before being written
+const EXPORT_CHUNK_SIZE: usize = 1024;
+
+/// Convert the documents at `paths` (the results of a search) in one
+/// pass: documents are parsed in parallel, and written in the order
+/// of the paths. Returns the number of documents written per kind.
+pub fn export_documents(
+    source: DocumentSource,
+    paths: &[String],
+    writer: &mut dyn DocumentWriter,
+    clean: bool,
+    per_article: bool,
+    failures: &FailureLog,
+) -> Result<BTreeMap<DocumentKind, usize>> {
+    use rayon::prelude::*;
+
+    let pb = logging::progress_bar(paths.len() as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{msg} [{elapsed_precise}] {wide_bar} {pos}/{len} ({eta})")
+            .context("Error creating progress bar")?
+            .progress_chars("##-"),
+    );
+    pb.set_message("Exporting results");
+
+    let mut kinds = BTreeMap::new();
+    for (i, chunk) in paths.chunks(EXPORT_CHUNK_SIZE).enumerate() {
+        if shutdown::is_interrupted() {
+            let left = paths.len() - i * EXPORT_CHUNK_SIZE;
+            warn!("Interrupted, {} documents are not exported", left);
+            break;
+        }
+        let parsed: Vec<_> = chunk
+            .par_iter()
+            .map(|path| source.read(path, per_article))
+            .collect();
+        for (path, documents) in chunk.iter().zip(parsed) {
+            write_documents(writer, path, documents?, clean, &mut kinds, failures)?;
+            pb.inc(1);
+        }
+    }
+    writer.flush()?;
+    pb.finish();
+    Ok(kinds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dumps::extractor::DocumentJsonlWriter;
+
+    /// Write a tarball with the given files
+    fn tarball(path: &Path, files: &[(&str, &str)]) {
+        let file = std::fs::File::create(path).unwrap();
+        let gzip = flate2::write::GzEncoder::new(file, flate2::Compression::fast());
+        let mut builder = tar::Builder::new(gzip);
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    /// A decision of JADE with the identifier `CETATEXT00000000000{n}`
+    fn decision(n: usize, text: &str) -> (String, String) {
+        let id = format!("CETATEXT{:012}", n);
+        let xml = format!(
+            "<TEXTE_JURI_ADMIN><META><META_COMMUN><ID>{}</ID></META_COMMUN></META>\
+             <TEXTE><BLOC_TEXTUEL><CONTENU>{}</CONTENU></BLOC_TEXTUEL></TEXTE>\
+             </TEXTE_JURI_ADMIN>",
+            id, text
+        );
+        (format!("jade/global/{}.xml", id), xml)
+    }
+
+    /// Write the tarball `name` in `tdir` with the decisions and the
+    /// suppression list, returning it
+    fn jade_tarball(tdir: &Path, name: &str, decisions: &[(usize, &str)], suppressed: &[usize]) -> Tarball {
+        let mut files: Vec<(String, String)> =
+            decisions.iter().map(|(n, text)| decision(*n, text)).collect();
+        if !suppressed.is_empty() {
+            let list: String =
+                suppressed.iter().map(|n| format!("jade/global/CETATEXT{:012}\n", n)).collect();
+            files.push(("liste_suppression_jade.dat".to_string(), list));
+        }
+        let files: Vec<(&str, &str)> = files.iter().map(|(p, c)| (p.as_str(), c.as_str())).collect();
+        tarball(&tdir.join(name), &files);
+        Tarball::from_name(name).unwrap()
+    }
+
+    /// The names of the extracted decisions
+    fn extracted(edir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(edir.join("jade/global"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// The paths of the indexed documents matching `query`
+    fn indexed(idir: &PathBuf, query: &str) -> Vec<String> {
+        let indices = open_existing_indices(idir).unwrap();
+        let indices: Vec<_> = indices.iter().map(|(index, flds)| (index, flds)).collect();
+        let mut paths = tarballs::matching_paths(&indices, query, &SearchOptions::default()).unwrap();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn test_index_new_tarballs() {
+        for per_fond in [false, true] {
+            let tmp = temp_dir::TempDir::new().unwrap();
+            let (tdir, edir, idir) =
+                (tmp.path().join("tarballs"), tmp.path().join("extracted"), tmp.path().join("index"));
+            std::fs::create_dir_all(&tdir).unwrap();
+            std::fs::create_dir_all(&idir).unwrap();
+            let stock = jade_tarball(
+                &tdir,
+                "Freemium_jade_global_20240101-100000.tar.gz",
+                &[(1, "Une décision"), (2, "Une décision")],
+                &[],
+            );
+            // the first delta removes 1 and adds 3, the second one
+            // publishes 1 again and removes 2 and 3
+            let first = jade_tarball(
+                &tdir,
+                "JADE_20240108-210000.tar.gz",
+                &[(3, "Une décision")],
+                &[1],
+            );
+            let second = jade_tarball(
+                &tdir,
+                "JADE_20240109-210000.tar.gz",
+                &[(1, "Une décision rectifiée")],
+                &[2, 3],
+            );
+            let index = |tb: &[Tarball]| {
+                let tmpd = temp_dir::TempDir::new().unwrap();
+                let indexing = IndexingOptions::default();
+                index_new_tarballs(
+                    tb,
+                    &tdir,
+                    &idir,
+                    &edir,
+                    tmpd.path(),
+                    false,
+                    per_fond,
+                    &AnalyzerConfig::default(),
+                    &indexing,
+                    2,
+                )
+                .unwrap();
+            };
+
+            // the deltas are applied in the order of their publication
+            index(&[second, first, stock]);
+            assert_eq!(extracted(&edir), ["CETATEXT000000000001.xml"]);
+            assert_eq!(indexed(&idir, "décision"), ["jade/global/CETATEXT000000000001.xml"]);
+            assert_eq!(indexed(&idir, "rectifiée"), ["jade/global/CETATEXT000000000001.xml"]);
+            let text = std::fs::read_to_string(edir.join("jade/global/CETATEXT000000000001.xml")).unwrap();
+            assert!(text.contains("rectifiée"));
+        }
+    }
+
+    #[test]
+    fn test_watch_cycles() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let (tdir, edir, idir) =
+            (tmp.path().join("tarballs"), tmp.path().join("extracted"), tmp.path().join("index"));
+        std::fs::create_dir_all(&tdir).unwrap();
+        std::fs::create_dir_all(&idir).unwrap();
+        let saved = crate::dumps::queries::SavedQuery {
+            query: "antennes".to_string(),
+            ..Default::default()
+        };
+        // every cycle of `dilarxiv watch` indexes the tarballs it
+        // downloaded, extracted in a new temporary directory, then
+        // runs the saved queries on the index opened again
+        let cycle = |tb: &[Tarball]| {
+            let tmpd = temp_dir::TempDir::new().unwrap();
+            index_new_tarballs(
+                tb,
+                &tdir,
+                &idir,
+                &edir,
+                tmpd.path(),
+                false,
+                false,
+                &AnalyzerConfig::default(),
+                &IndexingOptions::default(),
+                1,
+            )
+            .unwrap();
+            let indices = open_existing_indices(&idir).unwrap();
+            let indices: Vec<_> = indices.iter().map(|(index, flds)| (index, flds)).collect();
+            let mut paths = tarballs::matching_paths(&indices, &saved.query, &saved.options()).unwrap();
+            paths.sort();
+            paths
+        };
+
+        let stock = jade_tarball(
+            &tdir,
+            "Freemium_jade_global_20240101-100000.tar.gz",
+            &[(1, "Les antennes relais"), (2, "Les antennes de télévision")],
+            &[],
+        );
+        assert_eq!(
+            cycle(&[stock]),
+            ["jade/global/CETATEXT000000000001.xml", "jade/global/CETATEXT000000000002.xml"]
+        );
+        let delta = jade_tarball(
+            &tdir,
+            "JADE_20240108-210000.tar.gz",
+            &[(3, "Les antennes paraboliques")],
+            &[1],
+        );
+        assert_eq!(
+            cycle(&[delta]),
+            ["jade/global/CETATEXT000000000002.xml", "jade/global/CETATEXT000000000003.xml"]
+        );
+        assert_eq!(extracted(&edir), ["CETATEXT000000000002.xml", "CETATEXT000000000003.xml"]);
+        // a cycle without new tarballs leaves the mirror as it is
+        assert_eq!(
+            cycle(&[]),
+            ["jade/global/CETATEXT000000000002.xml", "jade/global/CETATEXT000000000003.xml"]
+        );
+    }
+
+    #[test]
+    fn test_corpus() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let mut corpus = Corpus::open(CorpusConfig::new(tmp.path())).unwrap();
+        assert!(corpus.index_dir().is_dir());
+        assert_eq!(corpus.search("requête", &SearchOptions::default()).unwrap().count, 0);
+
+        let dir = corpus.extracted_dir().join("jade/global/CETA");
+        std::fs::create_dir_all(&dir).unwrap();
+        for (i, text) in ["La requête est rejetée.", "La décision est annulée."].iter().enumerate() {
+            let id = format!("CETATEXT{:012}", i);
+            std::fs::write(
+                dir.join(format!("{}.xml", id)),
+                format!(
+                    "<TEXTE_JURI_ADMIN><META><META_COMMUN><ID>{}</ID></META_COMMUN></META>\
+                     <TEXTE><BLOC_TEXTUEL><CONTENU>{}</CONTENU></BLOC_TEXTUEL></TEXTE>\
+                     </TEXTE_JURI_ADMIN>",
+                    id, text
+                ),
+            )
+            .unwrap();
+        }
+        corpus.index().unwrap();
+
+        let results = corpus.search("requête", &SearchOptions::default()).unwrap();
+        assert_eq!(results.count, 1);
+        let document = corpus.document("CETATEXT000000000001").unwrap().unwrap();
+        assert_eq!(document.id(), "CETATEXT000000000001");
+        assert!(corpus.document("CETATEXT000000000002").unwrap().is_none());
+
+        let path = tmp.path().join("export.jsonl");
+        let mut writer = DocumentJsonlWriter::new(&path).unwrap();
+        let kinds = corpus.export("est", &SearchOptions::default(), &mut writer).unwrap();
+        assert_eq!(kinds.values().sum::<usize>(), 2);
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+    }
+}
@@ -0,0 +1,386 @@
+/// Comparison of two states of the corpus, to audit what an update
+/// changed: the documents added, removed (by the suppression lists of
+/// the delta tarballs) and modified, counted per fond, with the list of
+/// every change.
+///
+/// A state of the corpus ([`Snapshot`]) maps the path of every document
+/// (relative to the extraction directory) to the hash of its content.
+/// It is read either from an extraction directory, or from tarballs (the
+/// ones published up to a given day, or the ones of a manifest written by
+/// `dilarxiv freeze`), replaying the latest stock of every fond and the
+/// deltas that followed it.
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use log::{debug, warn};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::dumps::cas::content_hash;
+use crate::dumps::fonds::fond_of_extracted_path;
+use crate::dumps::manifest::{Manifest, sort_tarballs};
+use crate::dumps::tarballs::{
+    Tarball, TarballKind, is_suppression_list, sanitize_entry, suppressed_paths, walk_files,
+};
+
+/// The content hash of every document of a corpus, by path
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    documents: BTreeMap<String, String>,
+}
+
+impl Snapshot {
+    /// The documents of an extraction directory. The documents listed
+    /// in its suppression lists were removed upstream, and are left out.
+    pub fn from_dir(dir: &Path) -> Result<Snapshot> {
+        let (documents, lists) = walk_files(dir)
+            .par_bridge()
+            .try_fold(
+                || (BTreeMap::new(), vec![]),
+                |(mut documents, mut lists), file| {
+                    let file = file?;
+                    let relative = file.strip_prefix(dir).unwrap_or(&file).to_string_lossy().to_string();
+                    if is_suppression_list(&file) {
+                        lists.push(file);
+                    } else if file.extension().is_some_and(|ext| ext == "xml") {
+                        let content = std::fs::read(crate::dumps::winpath::extended_length(&file))
+                            .context(format!("Failed to read {}", file.display()))?;
+                        documents.insert(relative, content_hash(&content));
+                    }
+                    Ok::<_, anyhow::Error>((documents, lists))
+                },
+            )
+            .try_reduce(
+                || (BTreeMap::new(), vec![]),
+                |(mut documents, mut lists), (other, other_lists)| {
+                    documents.extend(other);
+                    lists.extend(other_lists);
+                    Ok((documents, lists))
+                },
+            )?;
+        let mut snapshot = Snapshot { documents };
+        for list in lists {
+            let content = std::fs::read_to_string(&list)
+                .context(format!("Failed to read suppression list {}", list.display()))?;
+            snapshot.remove_all(&content);
+        }
+        Ok(snapshot)
+    }
+
+    /// The documents published up to the day `until` (included), from
+    /// the tarballs of `dir`: for every fond, the latest stock of that
+    /// day or before, then the deltas that followed it, in order
+    pub fn from_tarballs(dir: &Path, until: NaiveDate) -> Result<Snapshot> {
+        let mut tarballs = vec![];
+        for entry in std::fs::read_dir(dir).context(format!("Failed to read {}", dir.display()))? {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            if !name.ends_with(".tar.gz") {
+                continue;
+            }
+            match Tarball::from_name(&name) {
+                Ok(tarball) if tarball.time <= until => tarballs.push(tarball),
+                Ok(_) => {}
+                Err(e) => warn!("Ignoring {}: {:#}", name, e),
+            }
+        }
+        Snapshot::from_tarball_list(dir, tarballs)
+    }
+
+    /// The documents of the tarballs of a manifest, stored in `dir`
+    pub fn from_manifest(manifest: &Manifest, dir: &Path) -> Result<Snapshot> {
+        Snapshot::from_tarball_list(dir, manifest.tarballs())
+    }
+
+    /// The documents of the given tarballs of `dir`: for every fond, the
+    /// latest stock, then the deltas that followed it
+    fn from_tarball_list(dir: &Path, tarballs: Vec<Tarball>) -> Result<Snapshot> {
+        let mut by_fond: BTreeMap<_, Vec<Tarball>> = BTreeMap::new();
+        for tarball in tarballs {
+            by_fond.entry(tarball.fond).or_default().push(tarball);
+        }
+        let snapshots = by_fond
+            .into_par_iter()
+            .map(|(_, mut tarballs)| {
+                sort_tarballs(&mut tarballs);
+                let start = tarballs
+                    .iter()
+                    .rposition(|t| t.kind == TarballKind::Stock)
+                    .unwrap_or(0);
+                let mut snapshot = Snapshot::default();
+                for tarball in &tarballs[start..] {
+                    snapshot.apply_tarball(&dir.join(tarball))?;
+                }
+                Ok(snapshot)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut snapshot = Snapshot::default();
+        for other in snapshots {
+            snapshot.documents.extend(other.documents);
+        }
+        Ok(snapshot)
+    }
+
+    /// Add the documents of a tarball, and remove the ones
+    /// listed in its suppression lists
+    fn apply_tarball(&mut self, tarball: &Path) -> Result<()> {
+        debug!("Reading {}", tarball.display());
+        let file = std::fs::File::open(tarball)
+            .context(format!("Failed to open tarball {}", tarball.display()))?;
+        let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(file));
+        let mut buffer = vec![];
+        for entry in tar.entries()? {
+            let mut entry = entry.context(format!("Corrupted entry in {}", tarball.display()))?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            // the rejected entries are not extracted either
+            let Ok(path) = sanitize_entry(&entry) else {
+                continue;
+            };
+            let path = path.to_string_lossy().to_string();
+            buffer.clear();
+            entry.read_to_end(&mut buffer)?;
+            if is_suppression_list(Path::new(&path)) {
+                self.remove_all(&String::from_utf8_lossy(&buffer));
+            } else if path.ends_with(".xml") {
+                self.documents.insert(path, content_hash(&buffer));
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the documents of a suppression list
+    fn remove_all(&mut self, list: &str) {
+        for path in suppressed_paths(list) {
+            self.documents.remove(&path);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+}
+
+/// Where a state of the corpus is read from: a day (`YYYY-MM-DD`,
+/// see [`Snapshot::from_tarballs`]), a manifest (a `.json` file) or an
+/// extraction directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotSource {
+    Date(NaiveDate),
+    Manifest(PathBuf),
+    Directory(PathBuf),
+}
+
+impl std::str::FromStr for SnapshotSource {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+            Ok(date) => SnapshotSource::Date(date),
+            Err(_) if s.ends_with(".json") => SnapshotSource::Manifest(PathBuf::from(s)),
+            Err(_) => SnapshotSource::Directory(PathBuf::from(s)),
+        })
+    }
+}
+
+impl SnapshotSource {
+    /// Read the state of the corpus, the dates and
+    /// manifests using the tarballs of `tarballs_dir`
+    pub fn snapshot(&self, tarballs_dir: &Path) -> Result<Snapshot> {
+        match self {
+            SnapshotSource::Date(date) => Snapshot::from_tarballs(tarballs_dir, *date),
+            SnapshotSource::Manifest(path) => {
+                Snapshot::from_manifest(&Manifest::from_file(path)?, tarballs_dir)
+            }
+            SnapshotSource::Directory(dir) => Snapshot::from_dir(dir),
+        }
+    }
+}
+
+/// How a document changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// A line of the detailed list of changes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change {
+    pub path: String,
+    pub fond: String,
+    pub change: ChangeKind,
+}
+
+/// Number of documents of a fond in each case
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FondChanges {
+    pub added: usize,
+    pub removed: usize,
+    pub modified: usize,
+    pub unchanged: usize,
+}
+
+/// The differences between two states of the corpus
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotDiff {
+    pub fonds: BTreeMap<String, FondChanges>,
+    /// Every added, removed or modified document, sorted by path
+    #[serde(skip)]
+    pub changes: Vec<Change>,
+}
+
+impl SnapshotDiff {
+    /// Total number of added, removed or modified documents
+    pub fn total(&self) -> usize {
+        self.changes.len()
+    }
+}
+
+/// Compare the states `old` and `new` of the corpus
+pub fn diff(old: &Snapshot, new: &Snapshot) -> SnapshotDiff {
+    let mut result = SnapshotDiff::default();
+    let mut record = |path: &str, change: Option<ChangeKind>| {
+        let fond = fond_of_extracted_path(Path::new(path));
+        let counts = result.fonds.entry(fond.clone()).or_default();
+        match change {
+            Some(ChangeKind::Added) => counts.added += 1,
+            Some(ChangeKind::Removed) => counts.removed += 1,
+            Some(ChangeKind::Modified) => counts.modified += 1,
+            None => counts.unchanged += 1,
+        }
+        if let Some(change) = change {
+            result.changes.push(Change {
+                path: path.to_string(),
+                fond,
+                change,
+            });
+        }
+    };
+    for (path, hash) in old.documents.iter() {
+        match new.documents.get(path) {
+            None => record(path, Some(ChangeKind::Removed)),
+            Some(other) if other != hash => record(path, Some(ChangeKind::Modified)),
+            Some(_) => record(path, None),
+        }
+    }
+    for path in new.documents.keys().filter(|p| !old.documents.contains_key(*p)) {
+        record(path, Some(ChangeKind::Added));
+    }
+    result.changes.sort_by(|a, b| a.path.cmp(&b.path));
+    result
+}
+
+/// Write the counts of changes per fond as JSON
+pub fn write_report(diff: &SnapshotDiff, path: &Path) -> Result<()> {
+    let file = std::fs::File::create(path)
+        .context(format!("Failed to create report {}", path.display()))?;
+    serde_json::to_writer_pretty(std::io::BufWriter::new(file), diff)
+        .context("Failed to serialize diff report")?;
+    Ok(())
+}
+
+/// Write every change as JSON lines
+pub fn write_changes(diff: &SnapshotDiff, path: &Path) -> Result<()> {
+    let file = std::fs::File::create(path)
+        .context(format!("Failed to create {}", path.display()))?;
+    let mut writer = std::io::BufWriter::new(file);
+    for change in diff.changes.iter() {
+        serde_json::to_writer(&mut writer, change)?;
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write a tarball with the given files
+    fn tarball(path: &Path, files: &[(&str, &str)]) {
+        let file = std::fs::File::create(path).unwrap();
+        let gzip = flate2::write::GzEncoder::new(file, flate2::Compression::fast());
+        let mut builder = tar::Builder::new(gzip);
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn test_diff() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        tarball(
+            &tmp.path().join("Freemium_jade_global_20240101-100000.tar.gz"),
+            &[("jade/global/A.xml", "<a/>"), ("jade/global/B.xml", "<b/>"), ("jade/global/C.xml", "<c/>")],
+        );
+        tarball(
+            &tmp.path().join("JADE_20240108-210000.tar.gz"),
+            &[
+                ("./jade/global/B.xml", "<b>modifié</b>"),
+                ("./jade/global/D.xml", "<d/>"),
+                ("./liste_suppression_jade.dat", "jade/global/C\n"),
+            ],
+        );
+        tarball(&tmp.path().join("CASS_20240108-210000.tar.gz"), &[("cass/global/E.xml", "<e/>")]);
+
+        let source: SnapshotSource = "2024-01-07".parse().unwrap();
+        let old = source.snapshot(tmp.path()).unwrap();
+        assert_eq!(old.len(), 3);
+        let new = Snapshot::from_tarballs(tmp.path(), NaiveDate::from_ymd_opt(2024, 1, 8).unwrap()).unwrap();
+        assert_eq!(new.len(), 4);
+
+        let result = diff(&old, &new);
+        let jade = &result.fonds["JADE"];
+        assert_eq!((jade.added, jade.removed, jade.modified, jade.unchanged), (1, 1, 1, 1));
+        assert_eq!(result.fonds["CASS"].added, 1);
+        let changes: Vec<_> = result.changes.iter().map(|c| (c.path.as_str(), c.change)).collect();
+        assert_eq!(
+            changes,
+            [
+                ("cass/global/E.xml", ChangeKind::Added),
+                ("jade/global/B.xml", ChangeKind::Modified),
+                ("jade/global/C.xml", ChangeKind::Removed),
+                ("jade/global/D.xml", ChangeKind::Added),
+            ]
+        );
+
+        // the same state, extracted
+        let extracted = tmp.path().join("extracted");
+        std::fs::create_dir_all(extracted.join("jade/global")).unwrap();
+        std::fs::write(extracted.join("jade/global/A.xml"), "<a/>").unwrap();
+        std::fs::write(extracted.join("jade/global/B.xml"), "<b>modifié</b>").unwrap();
+        std::fs::write(extracted.join("jade/global/C.xml"), "<c/>").unwrap();
+        std::fs::write(extracted.join("jade/global/D.xml"), "<d/>").unwrap();
+        std::fs::write(extracted.join("liste_suppression_jade.dat"), "jade/global/C\n").unwrap();
+        let source: SnapshotSource = extracted.to_string_lossy().parse().unwrap();
+        let from_dir = source.snapshot(tmp.path()).unwrap();
+        assert_eq!(diff(&from_dir, &new).total(), 1);
+
+        let manifest = Manifest::freeze(tmp.path(), &[], NaiveDate::from_ymd_opt(2024, 1, 7), Default::default())
+            .unwrap();
+        let path = tmp.path().join("corpus-manifest.json");
+        manifest.save(&path).unwrap();
+        let source: SnapshotSource = path.to_string_lossy().parse().unwrap();
+        assert_eq!(source.snapshot(tmp.path()).unwrap(), old);
+
+        let changes = tmp.path().join("changes.jsonl");
+        write_changes(&result, &changes).unwrap();
+        let first = std::fs::read_to_string(&changes).unwrap().lines().next().unwrap().to_string();
+        assert_eq!(first, r#"{"path":"cass/global/E.xml","fond":"CASS","change":"added"}"#);
+    }
+}
@@ -0,0 +1,310 @@
+/// Dense vectors of the documents, for hybrid semantic search.
+///
+/// The vectors are computed by an [`Embedder`], which can wrap any
+/// model (ONNX runtime, remote API, ...), and saved in a sidecar
+/// [`VectorStore`] next to the index, keyed by document id. A hybrid
+/// search first asks the index for the best candidates of a full text
+/// query, then re-ranks them by the cosine similarity of their vector
+/// with the vector of the query.
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use crate::dumps::extractor::split_article_path;
+use crate::dumps::pack::document_id;
+use crate::dumps::tarballs::{self, IndexFields, SearchOptions, SearchResults};
+
+/// Name of the vector store, in the directory of the index
+pub const VECTORS_FILE: &str = "vectors.jsonl";
+
+/// Computes the vector of a text. The vectors of the documents and
+/// of the queries must be computed by the same embedder.
+pub trait Embedder {
+    fn embed(&self, text: &str) -> Vec<f32>;
+}
+
+/// A baseline embedder without any model: the words of the text are
+/// hashed into a fixed number of dimensions. It only captures shared
+/// words, but makes it possible to try hybrid search before wiring
+/// an actual model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashingEmbedder {
+    pub dimension: usize,
+}
+
+impl Default for HashingEmbedder {
+    fn default() -> Self {
+        HashingEmbedder { dimension: 256 }
+    }
+}
+
+/// FNV-1a, stable across platforms and versions of Rust
+fn fnv1a(word: &str) -> u64 {
+    word.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl Embedder for HashingEmbedder {
+    fn embed(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; self.dimension.max(1)];
+        for word in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| w.chars().count() > 2)
+        {
+            let hash = fnv1a(&word.to_lowercase());
+            let slot = (hash % vector.len() as u64) as usize;
+            vector[slot] += if hash >> 63 == 0 { 1.0 } else { -1.0 };
+        }
+        vector
+    }
+}
+
+/// The cosine similarity of two vectors, 0 when one of
+/// them is null or when their dimensions differ
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
+
+/// The id under which the vector of an indexed document is stored:
+/// the id of the article for articles indexed separately, and the
+/// name of the file otherwise (e.g. `CETATEXT000049314894`)
+pub fn document_key(path: &str) -> String {
+    match split_article_path(path) {
+        (_, Some(article_id)) => article_id.to_string(),
+        (path, None) => document_id(path),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct VectorRecord {
+    id: String,
+    vector: Vec<f32>,
+}
+
+/// Vectors of documents, keyed by document id, saved as JSON lines
+/// (`{"id": "CETATEXT000049314894", "vector": [0.1, ...]}`)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VectorStore {
+    vectors: HashMap<String, Vec<f32>>,
+}
+
+impl VectorStore {
+    /// Read a store, empty when the file does not exist
+    pub fn open(path: &Path) -> Result<VectorStore> {
+        let mut store = VectorStore::default();
+        if !path.exists() {
+            return Ok(store);
+        }
+        let file =
+            std::fs::File::open(path).context(format!("Failed to open {}", path.display()))?;
+        for line in std::io::BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: VectorRecord = serde_json::from_str(&line)
+                .context(format!("Invalid vector in {}", path.display()))?;
+            store.vectors.insert(record.id, record.vector);
+        }
+        Ok(store)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file =
+            std::fs::File::create(path).context(format!("Failed to create {}", path.display()))?;
+        let mut writer = std::io::BufWriter::new(file);
+        let mut ids: Vec<&String> = self.vectors.keys().collect();
+        ids.sort();
+        for id in ids {
+            let record = VectorRecord {
+                id: id.clone(),
+                vector: self.vectors[id].clone(),
+            };
+            serde_json::to_writer(&mut writer, &record)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn insert(&mut self, id: String, vector: Vec<f32>) {
+        self.vectors.insert(id, vector);
+    }
+
+    pub fn get(&self, id: &str) -> Option<&[f32]> {
+        self.vectors.get(id).map(Vec::as_slice)
+    }
+
+    pub fn len(&self) -> usize {
+        self.vectors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vectors.is_empty()
+    }
+}
+
+/// Compute the vectors of the documents of an index that are not in
+/// the store yet, returning the number of vectors computed
+pub fn embed_index(
+    index: &tantivy::Index,
+    fields: &IndexFields,
+    embedder: &dyn Embedder,
+    store: &mut VectorStore,
+) -> Result<usize> {
+    let mut count = 0;
+    tarballs::for_each_document(index, fields, |path, body| {
+        let key = document_key(path);
+        if store.get(&key).is_none() {
+            store.insert(key, embedder.embed(body));
+            count += 1;
+        }
+        Ok(())
+    })?;
+    Ok(count)
+}
+
+/// Search the indices, then re-rank the `candidates` best results
+/// by the cosine similarity of their vector with the vector of the
+/// query. Results without a vector come last, in the order of the
+/// index. The offset and limit of `options` apply to the re-ranked
+/// results.
+pub fn hybrid_search(
+    indices: &[(&tantivy::Index, &IndexFields)],
+    query: &str,
+    options: &SearchOptions,
+    embedder: &dyn Embedder,
+    store: &VectorStore,
+    candidates: usize,
+) -> Result<SearchResults> {
+    let first_pass = SearchOptions {
+        offset: 0,
+        limit: candidates.max(options.window()?),
+        ..options.clone()
+    };
+    let mut results = tarballs::search_indices(indices, &None, query, &first_pass)?;
+    let target = embedder.embed(query);
+    let mut scored: Vec<_> = results
+        .hits
+        .into_iter()
+        .map(|hit| {
+            let similarity = store
+                .get(&document_key(&hit.0))
+                .map(|vector| cosine_similarity(vector, &target));
+            (similarity, hit)
+        })
+        .collect();
+    // stable, so that ties keep the order of the index
+    scored.sort_by(|(a, _), (b, _)| match (a, b) {
+        (Some(a), Some(b)) => b.total_cmp(a),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    });
+    results.hits = scored
+        .into_iter()
+        .skip(options.offset)
+        .take(options.limit)
+        .map(|(_, hit)| hit)
+        .collect();
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Embeds a text by counting two words
+    struct Words;
+
+    impl Embedder for Words {
+        fn embed(&self, text: &str) -> Vec<f32> {
+            let count = |word| text.matches(word).count() as f32;
+            vec![count("antenne"), count("permis")]
+        }
+    }
+
+    #[test]
+    fn test_hybrid_search() {
+        assert_eq!(
+            document_key("jade/a/CETATEXT000000000001.xml"),
+            "CETATEXT000000000001"
+        );
+        assert_eq!(
+            document_key("legi/a/LEGITEXT000000000001.xml#LEGIARTI000000000002"),
+            "LEGIARTI000000000002"
+        );
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]), 1.0);
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 0.0]), 0.0);
+
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let (index, fields) = tarballs::init_tantivy_ram().unwrap();
+        let mut writer: tantivy::IndexWriter = index.writer(15_000_000).unwrap();
+        for (id, text) in [
+            (
+                "CETATEXT000000000001",
+                "La requête. Le permis. Le permis de construire une antenne.",
+            ),
+            (
+                "CETATEXT000000000002",
+                "La requête. Une antenne relais, une antenne.",
+            ),
+            ("CETATEXT000000000003", "La requête. Rien."),
+        ] {
+            let file = tmp.path().join("jade").join(format!("{}.xml", id));
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            let body = format!(
+                "<TEXTE_JURI_ADMIN><CONTENU>{}</CONTENU></TEXTE_JURI_ADMIN>",
+                text
+            );
+            std::fs::write(&file, body).unwrap();
+            tarballs::reindex_file(&writer, &fields, tmp.path(), &file, false).unwrap();
+        }
+        writer.commit().unwrap();
+
+        let mut store = VectorStore::default();
+        assert_eq!(embed_index(&index, &fields, &Words, &mut store).unwrap(), 3);
+        // documents already in the store are skipped
+        assert_eq!(embed_index(&index, &fields, &Words, &mut store).unwrap(), 0);
+
+        let path = tmp.path().join(VECTORS_FILE);
+        store.save(&path).unwrap();
+        let mut store = VectorStore::open(&path).unwrap();
+        assert_eq!(store.len(), 3);
+        store.vectors.remove("CETATEXT000000000003");
+
+        let indices = [(&index, &fields)];
+        let paths = |query: &str| {
+            hybrid_search(
+                &indices,
+                query,
+                &SearchOptions::default(),
+                &Words,
+                &store,
+                10,
+            )
+            .unwrap()
+            .hits
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            paths("requête antenne"),
+            vec![
+                "jade/CETATEXT000000000002.xml",
+                "jade/CETATEXT000000000001.xml",
+                "jade/CETATEXT000000000003.xml"
+            ]
+        );
+        assert_eq!(paths("requête permis")[0], "jade/CETATEXT000000000001.xml");
+    }
+}
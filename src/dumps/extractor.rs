@@ -7,16 +7,47 @@
 /// only a few tags are actually extracted, and we assume
 /// that the XML files have a specific format that is not
 /// yet documented.
+use log::debug;
 use quick_xml::events::Event;
 use quick_xml::reader::Reader;
 
 use serde::{Deserialize, Serialize};
 
-use std::path::PathBuf;
+use std::io::BufRead;
+use std::path::Path;
+
+pub mod akn;
+pub mod anonymize;
+pub mod arrow;
+pub mod citation;
+pub mod cnil;
+pub mod ecli;
+pub mod encoding;
+pub mod entities;
+pub mod error;
+pub mod export;
+pub mod hf;
+pub mod identifiers;
+pub mod jorf;
+pub mod legi;
+pub mod markdown;
+pub mod parties;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod schema;
+pub mod segment;
+pub mod sqlite;
+
+use cnil::CnilDeliberation;
+pub use error::ExtractError;
+pub use export::{DocumentCsvWriter, DocumentJsonlWriter, DocumentWriter, ExportRecord};
+use jorf::JorfMetaData;
+use legi::{LegiArticle, LegiSection};
 
 pub mod law_extraction {
     use once_cell::sync::OnceCell;
     use regex::Regex;
+    use serde::{Deserialize, Serialize};
     use std::collections::HashMap;
 
     #[derive(Eq, Hash, PartialEq)]
@@ -42,46 +73,405 @@ pub mod law_extraction {
             count.entry(law).and_modify(|c| *c += 1).or_insert(1);
         }
     }
+
+    /// What a citation refers to
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    pub enum CitationKind {
+        /// An article of a code, e.g. "article R. 421-9 du code de l'urbanisme"
+        Article {
+            /// Number of the article, e.g. "R. 421-9"
+            number: String,
+            /// Name of the code, when it is given right after the article
+            code: Option<String>,
+        },
+        /// A text, e.g. "décret n° 2018-1123 du 10 décembre 2018"
+        Text {
+            /// Nature of the text ("décret", "loi", "ordonnance", "arrêté")
+            nature: String,
+            number: String,
+            /// Date of the text, as written (e.g. "10 décembre 2018")
+            date: Option<String>,
+        },
+        /// A prior decision, e.g. "n° 344646"
+        Decision { number: String },
+    }
+
+    /// A citation found in a text, with its position (in bytes)
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Citation {
+        #[serde(flatten)]
+        pub kind: CitationKind,
+        pub span: std::ops::Range<usize>,
+    }
+
+    fn article_regex() -> &'static Regex {
+        static INSTANCE: OnceCell<Regex> = OnceCell::new();
+        INSTANCE.get_or_init(|| {
+            Regex::new(r"\b(?:([LRDA])\.?\s*(\d+(?:-\d+)+)|(?i:articles?)\s+(\d+(?:-\d+)*))\b")
+                .expect("Unable to construct article regex")
+        })
+    }
+
+    fn code_regex() -> &'static Regex {
+        static INSTANCE: OnceCell<Regex> = OnceCell::new();
+        INSTANCE.get_or_init(|| {
+            Regex::new(r"^[^.;:()]{0,20}?\b(?:du|de\s+ce)\s+(code\b[^,.;:()\n]*)")
+                .expect("Unable to construct code regex")
+        })
+    }
+
+    fn text_regex() -> &'static Regex {
+        static INSTANCE: OnceCell<Regex> = OnceCell::new();
+        INSTANCE.get_or_init(|| {
+            Regex::new(
+                r"(?i)\b(décret|loi|ordonnance|arrêté)(?:\s+organique)?\s+n°\s*(\d+(?:-\d+)*)(?:\s+du\s+(\d{1,2}(?:er)?\s+\p{L}+\s+\d{4}))?",
+            )
+            .expect("Unable to construct text regex")
+        })
+    }
+
+    fn decision_regex() -> &'static Regex {
+        static INSTANCE: OnceCell<Regex> = OnceCell::new();
+        INSTANCE.get_or_init(|| {
+            Regex::new(r"(?i)\bn(?:°|os?\.?)\s*(\d{2,3}\s?\d{3})\b")
+                .expect("Unable to construct decision regex")
+        })
+    }
+
+    /// Words that end the name of a code
+    const CODE_STOP_WORDS: &[&str] = &[
+        "dans", "qui", "que", "selon", "issu", "issue", "en", "à", "au", "aux", "sont", "est",
+        "pour", "sur", "applicable", "applicables", "alors", "relatif", "relatives",
+    ];
+
+    fn code_name(after: &str) -> Option<String> {
+        let caps = code_regex().captures(after)?;
+        let words: Vec<&str> = caps[1]
+            .split_whitespace()
+            .take_while(|w| !CODE_STOP_WORDS.contains(&w.to_lowercase().as_str()))
+            .take(12)
+            .collect();
+        Some(words.join(" "))
+    }
+
+    /// Find the citations of articles, texts and decisions in `s`,
+    /// ordered by position
+    pub fn citations(s: &str) -> Vec<Citation> {
+        let mut found: Vec<Citation> = text_regex()
+            .captures_iter(s)
+            .map(|caps| Citation {
+                kind: CitationKind::Text {
+                    nature: caps[1].to_lowercase(),
+                    number: caps[2].to_string(),
+                    date: caps.get(3).map(|m| m.as_str().to_string()),
+                },
+                span: caps.get(0).unwrap().range(),
+            })
+            .collect();
+
+        for caps in article_regex().captures_iter(s) {
+            let m = caps.get(0).unwrap();
+            let number = match (caps.get(1), caps.get(2), caps.get(3)) {
+                (Some(prefix), Some(num), _) => format!("{}. {}", prefix.as_str(), num.as_str()),
+                (_, _, Some(num)) => num.as_str().to_string(),
+                _ => continue,
+            };
+            found.push(Citation {
+                kind: CitationKind::Article {
+                    number,
+                    code: code_name(&s[m.end()..]),
+                },
+                span: m.range(),
+            });
+        }
+
+        for caps in decision_regex().captures_iter(s) {
+            let span = caps.get(0).unwrap().range();
+            // "décret n° 2018-1123" is not a decision
+            if found.iter().any(|c| c.span.start < span.end && span.start < c.span.end) {
+                continue;
+            }
+            found.push(Citation {
+                kind: CitationKind::Decision {
+                    number: caps[1].split_whitespace().collect(),
+                },
+                span,
+            });
+        }
+
+        found.sort_by_key(|c| c.span.start);
+        found
+    }
 }
 
 /// This function takes a file that contains XML data
 /// and a mutable HashMap that will be filled with the
 /// count of each tag in the XML file.
 pub fn count_tags_in_file(
-    file: &PathBuf,
+    file: &Path,
     tag_count: &mut std::collections::HashMap<String, usize>,
-) {
-    use std::fs::File;
-    use std::io::Read;
+) -> Result<(), ExtractError> {
+    let mut reader = open_xml(file)?;
+    let mut buffer = Vec::new();
+    // the name is only copied the first time the tag is seen
+    let mut count = |name: &[u8]| {
+        let tag = String::from_utf8_lossy(name);
+        match tag_count.get_mut(tag.as_ref()) {
+            Some(count) => *count += 1,
+            None => {
+                tag_count.insert(tag.into_owned(), 1);
+            }
+        }
+    };
+    loop {
+        buffer.clear();
+        match reader.read_event_into(&mut buffer) {
+            Ok(Event::Start(e)) => count(e.name().as_ref()),
+            Ok(Event::End(e)) => count(e.name().as_ref()),
+            Ok(Event::Eof) => break,
+            Err(e) => return Err(ExtractError::xml(e, reader.buffer_position())),
+            _ => {}
+        }
+    }
+    Ok(())
+}
 
-    let mut file = File::open(file).unwrap();
-    let mut buffer = String::new();
-    file.read_to_string(&mut buffer).unwrap();
+pub mod text_cleanup {
+    use once_cell::sync::OnceCell;
+    use regex::{Captures, Regex};
 
-    let mut reader = Reader::from_str(&buffer);
-    loop {
-        match reader.read_event() {
-            Ok(Event::Start(e)) => {
-                let tag = String::from_utf8_lossy(e.name().as_ref()).to_string();
-                let e = tag_count.entry(tag).or_insert(0);
-                *e += 1;
+    fn line_break_regex() -> &'static Regex {
+        static INSTANCE: OnceCell<Regex> = OnceCell::new();
+        INSTANCE.get_or_init(|| {
+            Regex::new(r"(?i)<br\s*/?>|</p\s*>").expect("Unable to construct line break regex")
+        })
+    }
+
+    fn tag_regex() -> &'static Regex {
+        static INSTANCE: OnceCell<Regex> = OnceCell::new();
+        INSTANCE.get_or_init(|| Regex::new(r"<[^<>]*>").expect("Unable to construct tag regex"))
+    }
+
+    fn entity_regex() -> &'static Regex {
+        static INSTANCE: OnceCell<Regex> = OnceCell::new();
+        INSTANCE.get_or_init(|| {
+            Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);")
+                .expect("Unable to construct entity regex")
+        })
+    }
+
+    fn decode_entity(entity: &str) -> Option<char> {
+        if let Some(hex) = entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+            return char::from_u32(u32::from_str_radix(hex, 16).ok()?);
+        }
+        if let Some(dec) = entity.strip_prefix('#') {
+            return char::from_u32(dec.parse().ok()?);
+        }
+        match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => None,
+        }
+    }
+
+    /// Writes the entities matched by [`entity_regex`] decoded, in
+    /// the output of the replacement
+    struct EntityDecoder;
+
+    impl regex::Replacer for EntityDecoder {
+        fn replace_append(&mut self, caps: &Captures<'_>, dst: &mut String) {
+            match decode_entity(&caps[1]) {
+                Some(c) => dst.push(c),
+                None => dst.push_str(&caps[0]),
             }
-            Ok(Event::End(e)) => {
-                let tag = String::from_utf8_lossy(e.name().as_ref()).to_string();
-                let e = tag_count.entry(tag).or_insert(0);
-                *e += 1;
+        }
+    }
+
+    /// Normalize text extracted from a document: inline tags are
+    /// removed (`<br/>` and paragraphs become line breaks), entities
+    /// are decoded, and whitespace is collapsed. Paragraph breaks
+    /// (empty lines) are preserved.
+    pub fn clean_text(text: &str) -> String {
+        // entities are decoded first, since some documents contain
+        // escaped tags such as `&lt;br/&gt;`
+        let text = entity_regex().replace_all(text, EntityDecoder);
+        let text = line_break_regex().replace_all(&text, "\n");
+        let text = tag_regex().replace_all(&text, "");
+
+        // the words are copied once, to the cleaned text
+        let mut cleaned = String::with_capacity(text.len());
+        let mut blank_lines = 0;
+        for line in text.lines() {
+            let mut words = line.split_whitespace();
+            let Some(first) = words.next() else {
+                blank_lines += 1;
+                continue;
+            };
+            if !cleaned.is_empty() {
+                cleaned.push_str(if blank_lines > 0 { "\n\n" } else { "\n" });
+            }
+            blank_lines = 0;
+            cleaned.push_str(first);
+            for word in words {
+                cleaned.push(' ');
+                cleaned.push_str(word);
+            }
+        }
+        cleaned
+    }
+}
+
+/// A link from a document to a related text, as listed
+/// in the `LIENS` element of the document.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Link {
+    /// Type of the relation (e.g. "CITATION", "MODIFIE", "ABROGE")
+    pub relation: String,
+    /// Identifier of the linked text or article
+    pub target_id: String,
+    /// Title of the linked text, as written in the document
+    pub title: String,
+}
+
+/// The names of the currently open tags of a document, stored one
+/// after the other in a single buffer, so that opening a tag does
+/// not allocate once the buffer has grown to the depth of the document.
+#[derive(Debug, Default)]
+pub(crate) struct TagStack {
+    names: Vec<u8>,
+    starts: Vec<usize>,
+}
+
+impl TagStack {
+    pub(crate) fn push(&mut self, name: &[u8]) {
+        self.starts.push(self.names.len());
+        self.names.extend_from_slice(name);
+    }
+
+    pub(crate) fn pop(&mut self) {
+        if let Some(start) = self.starts.pop() {
+            self.names.truncate(start);
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    /// The innermost open tag
+    pub(crate) fn last(&self) -> Option<&[u8]> {
+        self.starts.last().map(|&start| &self.names[start..])
+    }
+
+    /// The open tags, from the root of the document
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        let ends = self.starts.iter().skip(1).copied().chain([self.names.len()]);
+        self.starts.iter().zip(ends).map(|(&start, end)| &self.names[start..end])
+    }
+
+    /// Whether a `name` tag is open
+    pub(crate) fn contains(&self, name: &[u8]) -> bool {
+        self.iter().any(|t| t == name)
+    }
+}
+
+/// Collects the `LIEN` elements of a document while it is being read.
+#[derive(Debug, Default)]
+pub(crate) struct LinkCollector {
+    links: Vec<Link>,
+    current: Option<Link>,
+}
+
+impl LinkCollector {
+    fn start_link(e: &quick_xml::events::BytesStart) -> Link {
+        let attr = |name: &str| {
+            e.try_get_attribute(name)
+                .ok()
+                .flatten()
+                .and_then(|a| a.unescape_value().ok())
+                .filter(|v| !v.is_empty())
+                .map(|v| v.into_owned())
+        };
+        Link {
+            relation: attr("typelien").unwrap_or_default(),
+            target_id: attr("id").or_else(|| attr("cidtexte")).unwrap_or_default(),
+            title: String::new(),
+        }
+    }
+
+    /// Feed the next event of the document to the collector
+    pub(crate) fn feed(&mut self, event: &Event) {
+        match event {
+            Event::Start(e) if e.name().as_ref() == b"LIEN" => {
+                self.current = Some(Self::start_link(e));
+            }
+            Event::Empty(e) if e.name().as_ref() == b"LIEN" => {
+                self.links.push(Self::start_link(e));
+            }
+            Event::Text(t) => {
+                if let Some(link) = self.current.as_mut() {
+                    link.title.push_str(t.unescape().unwrap_or_default().trim());
+                }
+            }
+            Event::End(e) if e.name().as_ref() == b"LIEN" => {
+                if let Some(link) = self.current.take() {
+                    self.links.push(link);
+                }
             }
-            Ok(Event::Eof) => break,
-            Err(_) => break,
             _ => {}
         }
     }
+
+    pub(crate) fn finish(self) -> Vec<Link> {
+        self.links
+    }
+}
+
+/// An entry of the summary (`SOMMAIRE`) of a decision: either a
+/// classification heading (`SCT`) or an analysis (`ANA`).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Abstract {
+    /// Classification code of the entry (e.g. "51-02-01")
+    pub code: Option<String>,
+    /// "SCT" for headings and "ANA" for analyses
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Text of the entry, without its classification code
+    pub text: String,
+}
+
+impl Abstract {
+    fn new(kind: &str, content: &str) -> Abstract {
+        static INSTANCE: once_cell::sync::OnceCell<regex::Regex> = once_cell::sync::OnceCell::new();
+        let re = INSTANCE.get_or_init(|| {
+            regex::Regex::new(r"(?s)^\s*(\d{2}(?:-\d{2,3})*)\s+(.*?)\s*$")
+                .expect("Unable to construct classification code regex")
+        });
+        let (code, text) = match re.captures(content) {
+            Some(caps) => (Some(caps[1].to_string()), caps[2].to_string()),
+            None => (None, content.trim().to_string()),
+        };
+        Abstract {
+            code,
+            kind: kind.to_string(),
+            text,
+        }
+    }
 }
 
 /// PreDilaText is a struct that contains the metadata and text
 /// of a decision from the DILA database. The inner metadata is
 /// not parsed and normalized yet.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PreDilaText {
     /// The unique identifier of the decision in the DILA database
     pub id: String,
@@ -104,11 +494,12 @@ pub struct PreDilaText {
     pub juri_code: Option<String>,
     /// "Demandeur" is the party that made the request
     pub requester: Option<String>,
-    /// President is the judge that made the decision
+    /// President is the judge that made the decision, without honorific
     pub president: Option<String>,
-    /// "Avocat" is the lawyer that made the request
-    pub lawyers: Option<String>,
-    /// "Rapporteur" is the judge that made the report
+    /// "Avocats" are the lawyers or law firms of the parties
+    #[serde(default)]
+    pub lawyers: Vec<String>,
+    /// "Rapporteur" is the judge that made the report, without honorific
     pub rapporteur: Option<String>,
     /// "Commissaire gouvernement" is the government commissioner
     pub government_commissioner: Option<String>,
@@ -116,28 +507,18 @@ pub struct PreDilaText {
     pub ecli_code: Option<String>,
     /// The full text of the decision (contains <br/> tags in addition to linebreaks)
     pub text: String,
+    /// Texts cited by the decision
+    #[serde(default)]
+    pub links: Vec<Link>,
+    /// Classification headings and analyses of the decision
+    #[serde(default)]
+    pub abstracts: Vec<Abstract>,
 }
 
-impl Default for PreDilaText {
-    fn default() -> Self {
-        PreDilaText {
-            id: String::new(),
-            oldid: String::new(),
-            origin: String::new(),
-            url: String::new(),
-            nature: String::new(),
-            title: None,
-            decision_date: None,
-            jurisdiction: None,
-            juri_code: None,
-            requester: None,
-            president: None,
-            lawyers: None,
-            rapporteur: None,
-            government_commissioner: None,
-            ecli_code: None,
-            text: String::new(),
-        }
+impl PreDilaText {
+    /// The ECLI of the decision, when it is present and valid
+    pub fn ecli(&self) -> Option<ecli::Ecli> {
+        self.ecli_code.as_deref().and_then(|s| ecli::Ecli::parse(s).ok())
     }
 }
 
@@ -148,7 +529,7 @@ enum ReadingState {
     ID,
     OldID,
     Origin,
-    URL,
+    Url,
     Nature,
     Title,
     DecisionDate,
@@ -171,18 +552,18 @@ fn update_pre_dila(pre_dila: &mut PreDilaText, reading_state: &Option<ReadingSta
             ReadingState::ID => pre_dila.id = text.to_string(),
             ReadingState::OldID => pre_dila.oldid = text.to_string(),
             ReadingState::Origin => pre_dila.origin = text.to_string(),
-            ReadingState::URL => pre_dila.url = text.to_string(),
+            ReadingState::Url => pre_dila.url = text.to_string(),
             ReadingState::Nature => pre_dila.nature = text.to_string(),
             ReadingState::Title => pre_dila.title = Some(text.to_string()),
             ReadingState::DecisionDate => pre_dila.decision_date = Some(text.to_string()),
             ReadingState::Jurisdiction => pre_dila.jurisdiction = Some(text.to_string()),
             ReadingState::JuriCode => pre_dila.juri_code = Some(text.to_string()),
             ReadingState::Requester => pre_dila.requester = Some(text.to_string()),
-            ReadingState::President => pre_dila.president = Some(text.to_string()),
-            ReadingState::Lawyers => pre_dila.lawyers = Some(text.to_string()),
-            ReadingState::Rapporteur => pre_dila.rapporteur = Some(text.to_string()),
+            ReadingState::President => pre_dila.president = parties::person(text),
+            ReadingState::Lawyers => pre_dila.lawyers = parties::lawyers(text),
+            ReadingState::Rapporteur => pre_dila.rapporteur = parties::person(text),
             ReadingState::GovernmentCommissioner => {
-                pre_dila.government_commissioner = Some(text.to_string())
+                pre_dila.government_commissioner = parties::person(text)
             }
             ReadingState::ECLICode => pre_dila.ecli_code = Some(text.to_string()),
             ReadingState::Text => pre_dila.text.push_str(text),
@@ -195,7 +576,7 @@ fn event_to_reading_state(e: &[u8]) -> Option<ReadingState> {
         b"ID" => Some(ReadingState::ID),
         b"ANCIEN_ID" => Some(ReadingState::OldID),
         b"ORIGINE" => Some(ReadingState::Origin),
-        b"URL" => Some(ReadingState::URL),
+        b"URL" => Some(ReadingState::Url),
         b"NATURE" => Some(ReadingState::Nature),
         b"TITRE" => Some(ReadingState::Title),
         b"DATE_DEC" => Some(ReadingState::DecisionDate),
@@ -213,69 +594,424 @@ fn event_to_reading_state(e: &[u8]) -> Option<ReadingState> {
 }
 
 /// This function reads an XML file and returns a PreDilaText struct
-/// with the metadata and text of the decision.
-fn reader_to_pre_dila(r: &mut Reader<&[u8]>) -> PreDilaText {
+/// with the metadata and text of the decision. The document is read
+/// event by event, so only the extracted fields are kept in memory.
+fn reader_to_pre_dila<R: BufRead>(r: &mut Reader<R>) -> Result<PreDilaText, ExtractError> {
     let mut pre_dila = PreDilaText::default();
+    let mut buf = Vec::new();
 
     let mut reading_state = None;
+    let mut links = LinkCollector::default();
+    // the summary entry being read, with its text so far
+    let mut current_abstract: Option<(&'static str, String)> = None;
 
     loop {
-        let event = r.read_event();
+        buf.clear();
+        let event = r
+            .read_event_into(&mut buf)
+            .map_err(|e| ExtractError::xml(e, r.buffer_position()))?;
+        links.feed(&event);
         match &event {
-            Err(e) => {
-                eprintln!("Error at position {}: {:?}", r.buffer_position(), e);
-                break;
+            Event::Start(e) if e.name().as_ref() == b"SCT" => {
+                current_abstract = Some(("SCT", String::new()));
             }
-            Ok(Event::Eof) => break,
-            Ok(Event::Start(e)) => {
+            Event::Start(e) if e.name().as_ref() == b"ANA" => {
+                current_abstract = Some(("ANA", String::new()));
+            }
+            Event::End(e) if matches!(e.name().as_ref(), b"SCT" | b"ANA") => {
+                if let Some((kind, content)) = current_abstract.take() {
+                    pre_dila.abstracts.push(Abstract::new(kind, &content));
+                }
+            }
+            _ => {}
+        }
+        match &event {
+            Event::Eof => break,
+            Event::Start(e) => {
                 let e = e.name();
                 if let Some(s) = event_to_reading_state(e.as_ref()) {
                     reading_state = Some(s);
                 }
             }
-            Ok(Event::End(e)) => {
+            Event::End(e) => {
                 let e = e.name();
                 let s = event_to_reading_state(e.as_ref());
                 if s == reading_state {
                     reading_state = None;
                 }
             }
-            Ok(Event::Text(t)) => {
-                let txt = t.unescape().unwrap();
+            Event::Text(t) => {
+                let txt = t
+                    .unescape()
+                    .map_err(|e| ExtractError::xml(e, r.buffer_position()))?;
+                if let Some((_, content)) = current_abstract.as_mut() {
+                    content.push_str(&txt);
+                }
                 update_pre_dila(&mut pre_dila, &reading_state, &txt);
             }
             _ => {}
         }
     }
 
-    pre_dila
+    pre_dila.links = links.finish();
+    Ok(pre_dila)
 }
 
-/// This function reads from a io::Read and returns a PreDilaText struct
+/// Open an XML file for streaming parsing, transcoding
+/// it to UTF-8 if needed (see [`encoding`])
+fn open_xml(file: &Path) -> Result<Reader<Box<dyn BufRead>>, ExtractError> {
+    let f = encoding::open_utf8(file).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => ExtractError::NotFound(file.to_path_buf()),
+        _ => ExtractError::Io(file.to_path_buf(), e),
+    })?;
+    Ok(Reader::from_reader(f))
+}
+
+/// This function reads an XML file and returns a PreDilaText struct
 /// with the metadata and text of the decision.
-pub fn parse_file<T>(file: T, buf: &mut String) -> PreDilaText 
-where 
+pub fn parse_file<T>(file: T) -> Result<PreDilaText, ExtractError>
+where
     T: AsRef<std::path::Path>
 {
-    use std::fs::File;
-    use std::io::Read;
-
     let file = file.as_ref();
-    if !file.exists() {
-        panic!("File does not exist: {}", file.display());
+    let long = crate::dumps::winpath::extended_length(file);
+    if !long.exists() {
+        return Err(ExtractError::NotFound(file.to_path_buf()));
     }
-    if !file.is_file() {
-        panic!("Path is not a file: {}", file.display());
+    if !long.is_file() {
+        return Err(ExtractError::NotAFile(file.to_path_buf()));
     }
     if file.extension().unwrap_or_default() != "xml" {
-        panic!("File is not an XML file: {}", file.display());
+        return Err(ExtractError::NotXml(file.to_path_buf()));
     }
-    let mut file = File::open(file).unwrap();
-    file.read_to_string(buf).unwrap();
-    let mut reader = Reader::from_str(&buf);
+    reader_to_pre_dila(&mut open_xml(file)?)
+}
+
+/// Read an XML file and parse it according to its root tag,
+/// see [`parse_document`]. The file is streamed twice: once to
+/// find the root tag, and once to parse it.
+pub fn parse_document_file<T>(file: T) -> Result<Document, ExtractError>
+where
+    T: AsRef<std::path::Path>,
+{
+    let file = file.as_ref();
+    let _open = crate::open_files::acquire(1);
+    let kind = DocumentKind::from_root_tag(root_tag_reader(&mut open_xml(file)?).as_deref());
+    parse_document_reader(kind, &mut open_xml(file)?)
+}
+
+/// Parse the content of an XML document already loaded in memory
+/// (for instance read back from a packed corpus).
+pub fn parse_str(content: &str) -> Result<PreDilaText, ExtractError> {
+    let mut reader = Reader::from_str(content);
     reader_to_pre_dila(&mut reader)
 }
 
+/// The name of the root element of an XML document
+/// (e.g. "TEXTE_JURI_ADMIN" or "TEXTE_JORF").
+pub fn root_tag(content: &str) -> Option<String> {
+    root_tag_reader(&mut Reader::from_str(content))
+}
+
+fn root_tag_reader<R: BufRead>(reader: &mut Reader<R>) -> Option<String> {
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) => {
+                return Some(String::from_utf8_lossy(e.name().as_ref()).to_string());
+            }
+            Ok(Event::Eof) | Err(_) => return None,
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
+/// The layouts of documents found in the DILA archives,
+/// each one handled by its own parser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum DocumentKind {
+    /// Court decisions (TEXTE_JURI_ADMIN, TEXTE_JURI_JUDI,
+    /// TEXTE_JURI_CONSTIT, ...)
+    Decision,
+    /// Texts of the Journal Officiel (TEXTE_JORF, TEXTE_VERSION)
+    /// and metadata of consolidated texts (TEXTELR)
+    Jorf,
+    /// Articles of consolidated texts (ARTICLE)
+    LegiArticle,
+    /// Sections of consolidated texts (SECTION_TA)
+    LegiSection,
+    /// Deliberations of the CNIL (TEXTECNIL)
+    Cnil,
+}
+
+impl DocumentKind {
+    /// The kind of documents whose root element is `tag`.
+    /// Unknown layouts are parsed as decisions, which
+    /// only rely on the common metadata.
+    pub fn from_root_tag(tag: Option<&str>) -> DocumentKind {
+        match tag {
+            Some("TEXTE_JORF") | Some("TEXTE_VERSION") | Some("TEXTELR") => DocumentKind::Jorf,
+            Some("ARTICLE") => DocumentKind::LegiArticle,
+            Some("SECTION_TA") => DocumentKind::LegiSection,
+            Some("TEXTECNIL") => DocumentKind::Cnil,
+            Some(t) if t.starts_with("TEXTE_JURI") => DocumentKind::Decision,
+            other => {
+                debug!("Unknown root tag {:?}, parsing as a decision", other);
+                DocumentKind::Decision
+            }
+        }
+    }
+
+    /// Short name of the kind, used to name the
+    /// CSV files documents are exported to
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DocumentKind::Decision => "decision",
+            DocumentKind::Jorf => "jorf",
+            DocumentKind::LegiArticle => "article",
+            DocumentKind::LegiSection => "section",
+            DocumentKind::Cnil => "cnil",
+        }
+    }
+}
+
+impl std::fmt::Display for DocumentKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Guess the kind of a document from its root element, so that
+/// documents of several fonds can be processed together.
+pub fn detect_document_kind(content: &str) -> DocumentKind {
+    DocumentKind::from_root_tag(root_tag(content).as_deref())
+}
+
+/// A document from the DILA archive, parsed according
+/// to the layout given by its root tag.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Document {
+    /// Court decisions (TEXTE_JURI_ADMIN, TEXTE_JURI_JUDI, ...)
+    Decision(PreDilaText),
+    /// Texts published in the Journal Officiel, and
+    /// metadata of consolidated texts (TEXTELR)
+    Jorf(JorfMetaData),
+    /// Articles of consolidated texts
+    LegiArticle(LegiArticle),
+    /// Sections of consolidated texts
+    LegiSection(LegiSection),
+    /// Deliberations of the CNIL
+    Cnil(CnilDeliberation),
+}
+
+impl Document {
+    /// The layout the document was parsed with
+    pub fn document_kind(&self) -> DocumentKind {
+        match self {
+            Document::Decision(_) => DocumentKind::Decision,
+            Document::Jorf(_) => DocumentKind::Jorf,
+            Document::LegiArticle(_) => DocumentKind::LegiArticle,
+            Document::LegiSection(_) => DocumentKind::LegiSection,
+            Document::Cnil(_) => DocumentKind::Cnil,
+        }
+    }
+
+    /// Short name of the kind of document, used to name
+    /// the CSV files they are exported to
+    pub fn kind(&self) -> &'static str {
+        self.document_kind().as_str()
+    }
+
+    /// Identifier of the document
+    pub fn id(&self) -> &str {
+        match self {
+            Document::Decision(d) => &d.id,
+            Document::Jorf(j) => &j.id,
+            Document::LegiArticle(a) => &a.id,
+            Document::LegiSection(s) => &s.id,
+            Document::Cnil(c) => &c.id,
+        }
+    }
+
+    /// Textual content of the document, for the kinds
+    /// of documents that have one
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            Document::Decision(d) => Some(&d.text),
+            Document::LegiArticle(a) => Some(&a.contenu),
+            Document::Cnil(c) => Some(&c.text),
+            Document::Jorf(_) | Document::LegiSection(_) => None,
+        }
+    }
+
+    /// Whether the document is a placeholder (a suppressed document or
+    /// a stub): without text, for the kinds of documents that have one,
+    /// or without title, for the texts of the Journal Officiel and the
+    /// sections without articles. Placeholders are neither indexed nor
+    /// exported.
+    pub fn is_empty(&self) -> bool {
+        let blank = |s: Option<&str>| s.is_none_or(|s| text_cleanup::clean_text(s).is_empty());
+        match self {
+            Document::Jorf(j) => blank(j.title.as_deref()),
+            Document::LegiSection(s) => blank(s.title.as_deref()) && s.articles.trim().is_empty(),
+            _ => blank(self.text()),
+        }
+    }
+
+    /// Main date of the document: the date of decisions and CNIL
+    /// deliberations, the date of texts of the Journal Officiel (or of
+    /// their publication), and the first day of application of articles
+    pub fn date(&self) -> Option<chrono::NaiveDate> {
+        let date = match self {
+            Document::Decision(d) => d.decision_date.as_deref(),
+            Document::Jorf(j) => j.text_date.as_deref().or(j.publication_date.as_deref()),
+            Document::LegiArticle(a) => a.date_debut.as_deref(),
+            Document::LegiSection(_) => None,
+            Document::Cnil(c) => c.date.as_deref(),
+        }?;
+        chrono::NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok()
+    }
+
+    /// The links of the document to related texts
+    pub fn links(&self) -> &[Link] {
+        match self {
+            Document::Decision(d) => &d.links,
+            Document::Jorf(j) => &j.links,
+            Document::LegiArticle(a) => &a.links,
+            Document::LegiSection(_) => &[],
+            Document::Cnil(c) => &c.links,
+        }
+    }
+
+    /// Identifiers of the document and of the documents it refers
+    /// to, gathered from its metadata, its links and its text
+    pub fn identifiers(&self) -> identifiers::Identifiers {
+        let mut ids = identifiers::Identifiers::default();
+        let (own_id, text, links) = match self {
+            Document::Decision(d) => {
+                ids.add_old_id(d.oldid.clone());
+                (&d.id, Some(&d.text), &d.links[..])
+            }
+            Document::Jorf(j) => {
+                if let Some(nor) = &j.nor {
+                    ids.add_nor(nor.clone());
+                }
+                if let Some(old_id) = &j.old_id {
+                    ids.add_old_id(old_id.clone());
+                }
+                if let Some(cid) = j.cid.as_ref().filter(|cid| **cid != j.id) {
+                    ids.add_reference(cid.clone());
+                }
+                (&j.id, None, &j.links[..])
+            }
+            Document::LegiArticle(a) => {
+                if let Some(parent) = &a.parent_text {
+                    ids.add_reference(parent.clone());
+                }
+                (&a.id, Some(&a.contenu), &a.links[..])
+            }
+            Document::LegiSection(s) => {
+                if let Some(parent) = &s.parent_text {
+                    ids.add_reference(parent.clone());
+                }
+                for article in s.articles.split(';') {
+                    ids.add_reference(article);
+                }
+                (&s.id, None, &[][..])
+            }
+            Document::Cnil(c) => {
+                if let Some(old_id) = &c.old_id {
+                    ids.add_old_id(old_id.clone());
+                }
+                (&c.id, Some(&c.text), &c.links[..])
+            }
+        };
+        for link in links {
+            if link.target_id != *own_id {
+                ids.add_reference(link.target_id.clone());
+            }
+        }
+        if let Some(text) = text {
+            ids.scan(text, own_id);
+        }
+        ids
+    }
+
+    /// Normalize the textual content of the document,
+    /// see [`text_cleanup::clean_text`]
+    pub fn clean(&mut self) {
+        use text_cleanup::clean_text;
+        match self {
+            Document::Decision(d) => d.text = clean_text(&d.text),
+            Document::LegiArticle(a) => a.contenu = clean_text(&a.contenu),
+            Document::Cnil(c) => c.text = clean_text(&c.text),
+            Document::Jorf(_) | Document::LegiSection(_) => {}
+        }
+    }
+}
+
+/// Parse a document, choosing the parser from its root tag
+/// (see [`detect_document_kind`]).
+pub fn parse_document(content: &str) -> Result<Document, ExtractError> {
+    let kind = detect_document_kind(content);
+    parse_document_reader(kind, &mut Reader::from_str(content))
+}
+
+/// Parse a document, with one record per article for texts that
+/// embed their articles (see [`legi::split_articles`]). Other
+/// documents give a single record, as with [`parse_document`].
+pub fn parse_articles(content: &str) -> Result<Vec<Document>, ExtractError> {
+    let articles = legi::split_articles(content)?;
+    if articles.is_empty() {
+        return Ok(vec![parse_document(content)?]);
+    }
+    Ok(articles.into_iter().map(Document::LegiArticle).collect())
+}
+
+/// Read an XML file and parse it at article granularity,
+/// see [`parse_articles`].
+pub fn parse_articles_file<T>(file: T) -> Result<Vec<Document>, ExtractError>
+where
+    T: AsRef<std::path::Path>,
+{
+    let file = file.as_ref();
+    let articles = legi::split_articles_reader(&mut open_xml(file)?)?;
+    if articles.is_empty() {
+        return Ok(vec![parse_document_file(file)?]);
+    }
+    Ok(articles.into_iter().map(Document::LegiArticle).collect())
+}
+
+/// Name under which an article embedded in the document
+/// at `path` is indexed: `path#id`
+pub fn article_path(path: &str, id: &str) -> String {
+    format!("{}#{}", path, id)
+}
+
+/// Split a path produced by [`article_path`] into the path
+/// of the document and the identifier of the article, if any
+pub fn split_article_path(path: &str) -> (&str, Option<&str>) {
+    match path.trim().split_once('#') {
+        Some((path, id)) => (path, Some(id)),
+        None => (path.trim(), None),
+    }
+}
+
+/// Parse a document with the parser of the given kind
+fn parse_document_reader<R: BufRead>(
+    kind: DocumentKind,
+    reader: &mut Reader<R>,
+) -> Result<Document, ExtractError> {
+    Ok(match kind {
+        DocumentKind::Jorf => Document::Jorf(jorf::parse_jorf_reader(reader)?),
+        DocumentKind::LegiArticle => Document::LegiArticle(legi::parse_article_reader(reader)?),
+        DocumentKind::LegiSection => Document::LegiSection(legi::parse_section_reader(reader)?),
+        DocumentKind::Cnil => Document::Cnil(cnil::parse_cnil_reader(reader)?),
+        DocumentKind::Decision => Document::Decision(reader_to_pre_dila(reader)?),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -343,7 +1079,7 @@ mod tests {
     #[test]
     fn test_pre_dila_metadata_parser() {
         let mut reader = Reader::from_reader(EXAMPLE_XML.as_bytes());
-        let pre_dila = reader_to_pre_dila(&mut reader);
+        let pre_dila = reader_to_pre_dila(&mut reader).unwrap();
 
         assert_eq!(pre_dila.id, "CETATEXT000049314894");
         assert_eq!(pre_dila.oldid, "JG_L_2024_03_000000490536");
@@ -362,18 +1098,260 @@ mod tests {
         assert_eq!(pre_dila.juri_code, Some("490536".to_string()));
         assert_eq!(pre_dila.requester, None);
         assert_eq!(pre_dila.president, None);
-        assert_eq!(pre_dila.lawyers, Some("SCP BAUER-VIOLAS - FESCHOTTE-DESBOIS - SEBAGH ; SCP MARLANGE, DE LA BURGADE ; SCP SPINOSI".to_string()));
+        assert_eq!(
+            pre_dila.lawyers,
+            vec![
+                "SCP BAUER-VIOLAS - FESCHOTTE-DESBOIS - SEBAGH",
+                "SCP MARLANGE, DE LA BURGADE",
+                "SCP SPINOSI"
+            ]
+        );
         assert_eq!(
             pre_dila.rapporteur,
-            Some("M. Alexandre Trémolière".to_string())
+            Some("Alexandre Trémolière".to_string())
         );
         assert_eq!(
             pre_dila.government_commissioner,
-            Some("M. Clément Malverti".to_string())
+            Some("Clément Malverti".to_string())
         );
         assert_eq!(
             pre_dila.ecli_code,
             Some("ECLI:FR:CECHR:2024:490536.20240321".to_string())
         );
+        assert_eq!(pre_dila.ecli().map(|e| e.year), Some(2024));
+    }
+
+    #[test]
+    fn test_tag_stack() {
+        let mut stack = TagStack::default();
+        assert_eq!(stack.last(), None);
+        stack.push(b"ARTICLE");
+        stack.push(b"BLOC_TEXTUEL");
+        stack.push(b"CONTENU");
+        assert_eq!(stack.len(), 3);
+        assert_eq!(stack.last(), Some(&b"CONTENU"[..]));
+        assert!(stack.contains(b"BLOC_TEXTUEL"));
+        assert!(!stack.contains(b"BLOC"));
+        stack.pop();
+        stack.pop();
+        stack.push(b"ID");
+        assert_eq!(stack.iter().collect::<Vec<_>>(), [&b"ARTICLE"[..], &b"ID"[..]]);
+        stack.pop();
+        stack.pop();
+        stack.pop();
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn test_clean_text() {
+        use text_cleanup::clean_text;
+        assert_eq!(
+            clean_text("  Premier   alinéa<br/>suite\t du texte.<br/>\n<br/>\nSecond &amp; dernier&#160;alinéa &lt;p&gt; "),
+            "Premier alinéa\nsuite du texte.\n\nSecond & dernier alinéa"
+        );
+        assert_eq!(clean_text("<p>a</p><p>b</p>"), "a\nb");
+        assert_eq!(clean_text("&unknown; entity"), "&unknown; entity");
+    }
+
+    #[test]
+    fn test_citations() {
+        use law_extraction::{CitationKind, citations};
+
+        let s = "Aux termes de l'article R. 421-9 du code de l'urbanisme, dans sa rédaction issue du \
+                 décret n° 2018-1123 du 10 décembre 2018 relatif à l'extension, et de l'article L. 761-1 \
+                 du code de justice administrative. Comp. CE, 20 juin 2012, M. Richard et autres, n° 344646.";
+        let found: Vec<CitationKind> = citations(s).into_iter().map(|c| c.kind).collect();
+        assert_eq!(
+            found,
+            vec![
+                CitationKind::Article {
+                    number: "R. 421-9".to_string(),
+                    code: Some("code de l'urbanisme".to_string()),
+                },
+                CitationKind::Text {
+                    nature: "décret".to_string(),
+                    number: "2018-1123".to_string(),
+                    date: Some("10 décembre 2018".to_string()),
+                },
+                CitationKind::Article {
+                    number: "L. 761-1".to_string(),
+                    code: Some("code de justice administrative".to_string()),
+                },
+                CitationKind::Decision {
+                    number: "344646".to_string(),
+                },
+            ]
+        );
+
+        let spans = citations("article L. 110-1 du code de l'entrée et du séjour des étrangers et du droit d'asile");
+        assert_eq!(spans[0].span, 8..16);
+        assert_eq!(
+            spans[0].kind,
+            CitationKind::Article {
+                number: "L. 110-1".to_string(),
+                code: Some("code de l'entrée et du séjour des étrangers et du droit d'asile".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_document_file() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let path = tmp.path().join("CETATEXT000049314894.xml");
+        std::fs::write(&path, EXAMPLE_XML).unwrap();
+        assert_eq!(parse_document_file(&path).unwrap(), parse_document(EXAMPLE_XML).unwrap());
+        assert_eq!(parse_file(&path).unwrap(), parse_str(EXAMPLE_XML).unwrap());
+    }
+
+    #[test]
+    fn test_mixed_encodings() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let utf8 = tmp.path().join("utf8.xml");
+        std::fs::write(&utf8, EXAMPLE_XML).unwrap();
+        let latin1 = tmp.path().join("latin1.xml");
+        let declared = EXAMPLE_XML.replace("encoding=\"UTF-8\"", "encoding=\"ISO-8859-1\"");
+        let (content, _, unmappable) = encoding_rs::WINDOWS_1252.encode(&declared);
+        assert!(!unmappable);
+        std::fs::write(&latin1, &content).unwrap();
+
+        let from_utf8 = parse_document_file(&utf8).unwrap();
+        let from_latin1 = parse_document_file(&latin1).unwrap();
+        assert_eq!(from_utf8, from_latin1);
+        match from_latin1 {
+            Document::Decision(d) => assert_eq!(d.jurisdiction.as_deref(), Some("Conseil d'État")),
+            other => panic!("unexpected document {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_extract_errors() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        assert!(matches!(
+            parse_file(tmp.path().join("missing.xml")),
+            Err(ExtractError::NotFound(_))
+        ));
+        assert!(matches!(parse_file(tmp.path()), Err(ExtractError::NotAFile(_))));
+
+        let txt = tmp.path().join("notes.txt");
+        std::fs::write(&txt, EXAMPLE_XML).unwrap();
+        assert!(matches!(parse_file(&txt), Err(ExtractError::NotXml(_))));
+
+        // files without declaration would be read as Windows-1252
+        let invalid = tmp.path().join("invalid.xml");
+        let content = b"<?xml version=\"1.0\" encoding=\"UTF-8\"?><TEXTE_JURI_ADMIN><ID>\xff\xfe</ID></TEXTE_JURI_ADMIN>";
+        std::fs::write(&invalid, content).unwrap();
+        assert!(matches!(
+            parse_document_file(&invalid),
+            Err(ExtractError::InvalidUtf8 { .. })
+        ));
+
+        let truncated = "<TEXTE_JURI_ADMIN><META></TEXTE_JURI_ADMIN>";
+        assert!(matches!(parse_str(truncated), Err(ExtractError::Xml { .. })));
+    }
+
+    #[test]
+    fn test_abstracts() {
+        let pre_dila = parse_str(EXAMPLE_XML).unwrap();
+        let kinds: Vec<&str> = pre_dila.abstracts.iter().map(|a| a.kind.as_str()).collect();
+        assert_eq!(kinds, vec!["SCT", "SCT", "SCT", "ANA", "ANA", "ANA"]);
+        assert_eq!(pre_dila.abstracts[0].code.as_deref(), Some("51-02-01"));
+        assert!(pre_dila.abstracts[0].text.starts_with("POSTES ET COMMUNICATIONS"));
+        assert_eq!(pre_dila.abstracts[2].code.as_deref(), Some("68-04-045"));
+        assert_eq!(pre_dila.abstracts[4].code.as_deref(), Some("68-03-01-02"));
+        assert!(pre_dila.abstracts[4].text.starts_with("1) a) Les c et j"));
+        // the summary does not leak into the text of the decision
+        assert!(!pre_dila.text.contains("POSTES ET COMMUNICATIONS"));
+    }
+
+    #[test]
+    fn test_parse_articles() {
+        assert_eq!(
+            parse_articles(EXAMPLE_XML).unwrap(),
+            vec![parse_document(EXAMPLE_XML).unwrap()]
+        );
+        let text = "<TEXTE_VERSION><META><ID>JORFTEXT000049314000</ID></META>\
+                    <ARTICLE id=\"JORFARTI000049314001\" num=\"1\"><CONTENU>Un.</CONTENU></ARTICLE>\
+                    <ARTICLE id=\"JORFARTI000049314002\" num=\"2\"><CONTENU>Deux.</CONTENU></ARTICLE>\
+                    </TEXTE_VERSION>";
+        let docs = parse_articles(text).unwrap();
+        assert_eq!(docs.len(), 2);
+        assert!(docs.iter().all(|d| d.kind() == "article"));
+
+        let path = article_path("texte/JORFTEXT000049314000.xml", "JORFARTI000049314002");
+        assert_eq!(
+            split_article_path(&format!("{}\n", path)),
+            ("texte/JORFTEXT000049314000.xml", Some("JORFARTI000049314002"))
+        );
+        assert_eq!(split_article_path("a.xml"), ("a.xml", None));
+    }
+
+    #[test]
+    fn test_parse_document_routing() {
+        assert_eq!(root_tag(EXAMPLE_XML).as_deref(), Some("TEXTE_JURI_ADMIN"));
+        assert_eq!(detect_document_kind(EXAMPLE_XML), DocumentKind::Decision);
+        assert_eq!(detect_document_kind("<TEXTELR/>"), DocumentKind::Jorf);
+        assert_eq!(detect_document_kind("<SECTION_TA/>"), DocumentKind::LegiSection);
+        assert_eq!(detect_document_kind("<TEXTECNIL/>"), DocumentKind::Cnil);
+        assert_eq!(parse_document(EXAMPLE_XML).unwrap().kind(), "decision");
+        let jorf = "<?xml version=\"1.0\"?><TEXTE_JORF><META><NOR>ECOX2400123D</NOR></META></TEXTE_JORF>";
+        match parse_document(jorf).unwrap() {
+            Document::Jorf(meta) => assert_eq!(meta.nor.as_deref(), Some("ECOX2400123D")),
+            other => panic!("unexpected document {:?}", other),
+        }
+        let article = "<ARTICLE><META><META_COMMUN><ID>LEGIARTI000006335064</ID></META_COMMUN></META></ARTICLE>";
+        assert_eq!(parse_document(article).unwrap().kind(), "article");
+    }
+
+    #[test]
+    fn test_empty_documents() {
+        assert!(!parse_document(EXAMPLE_XML).unwrap().is_empty());
+        // suppressed decision: metadata only
+        let stub = "<TEXTE_JURI_ADMIN><META><META_COMMUN><ID>CETATEXT000000000001</ID></META_COMMUN></META>\
+                    <TEXTE><BLOC_TEXTUEL><CONTENU> <br/> </CONTENU></BLOC_TEXTUEL></TEXTE></TEXTE_JURI_ADMIN>";
+        assert!(parse_document(stub).unwrap().is_empty());
+        let article = "<ARTICLE><META><META_COMMUN><ID>LEGIARTI000006335064</ID></META_COMMUN></META></ARTICLE>";
+        assert!(parse_document(article).unwrap().is_empty());
+        let no_id = "<TEXTE_JURI_ADMIN><TEXTE><BLOC_TEXTUEL><CONTENU>Rejet.</CONTENU></BLOC_TEXTUEL></TEXTE></TEXTE_JURI_ADMIN>";
+        assert!(!parse_document(no_id).unwrap().is_empty());
+        let section = Document::LegiSection(legi::LegiSection {
+            id: "LEGISCTA000006147745".to_string(),
+            articles: "LEGIARTI000006335064".to_string(),
+            ..Default::default()
+        });
+        assert!(!section.is_empty());
+    }
+
+    #[test]
+    fn test_links() {
+        let xml = r#"<TEXTE_JURI_ADMIN><ID>CETATEXT000049314894</ID><LIENS>
+<LIEN cidtexte="LEGITEXT000006070933" id="LEGIARTI000006449326" naturetexte="CODE" sens="source" typelien="CITATION">Code de justice administrative - article L761-1</LIEN>
+<LIEN cidtexte="JORFTEXT000000886460" id="" sens="source" typelien="CITATION"/>
+</LIENS></TEXTE_JURI_ADMIN>"#;
+        let pre_dila = parse_str(xml).unwrap();
+        assert_eq!(
+            pre_dila.links,
+            vec![
+                Link {
+                    relation: "CITATION".to_string(),
+                    target_id: "LEGIARTI000006449326".to_string(),
+                    title: "Code de justice administrative - article L761-1".to_string(),
+                },
+                Link {
+                    relation: "CITATION".to_string(),
+                    target_id: "JORFTEXT000000886460".to_string(),
+                    title: String::new(),
+                },
+            ]
+        );
+
+        let document = Document::Decision(pre_dila);
+        let record = ExportRecord::try_from(&document).unwrap();
+        let links = record.get("links").unwrap();
+        assert!(links.starts_with(r#"[{"relation":"CITATION","target_id":"LEGIARTI000006449326""#));
+        assert_eq!(record.columns[0], ("id".to_string(), "CETATEXT000049314894".to_string()));
+
+        let ids = document.identifiers();
+        assert_eq!(ids.referenced_ids, vec!["LEGIARTI000006449326", "JORFTEXT000000886460"]);
+        assert!(ids.old_ids.is_empty());
     }
 }
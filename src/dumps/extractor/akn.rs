@@ -0,0 +1,208 @@
+/// Conversion of documents to Akoma Ntoso 3.0, the OASIS standard of
+/// legal documents used by several EU pipelines. Decisions and CNIL
+/// deliberations are `judgment` documents, texts of the Journal Officiel,
+/// codes articles and sections are `act` documents. Only the subset
+/// needed by the extracted metadata is written: the FRBR identification
+/// of the document, its title, and its text, one `p` per line (the text
+/// is not split into the parts of a judgment or the divisions of an act).
+use quick_xml::escape::escape;
+
+use super::identifiers::permalink;
+use super::markdown::{jurisdiction, title};
+use super::Document;
+
+/// Namespace of Akoma Ntoso 3.0
+pub const AKN_NAMESPACE: &str = "http://docs.oasis-open.org/legaldocml/ns/akn/3.0";
+
+/// Identifier usable in the URIs and `eId` of Akoma Ntoso,
+/// e.g. `conseil-d-etat` for "Conseil d'État"
+fn slug(value: &str) -> String {
+    let mut slug = String::new();
+    for c in value.chars().flat_map(char::to_lowercase) {
+        let c = match c {
+            'à' | 'â' | 'ä' => 'a',
+            'é' | 'è' | 'ê' | 'ë' => 'e',
+            'î' | 'ï' => 'i',
+            'ô' | 'ö' => 'o',
+            'ù' | 'û' | 'ü' => 'u',
+            'ç' => 'c',
+            c if c.is_ascii_alphanumeric() => c,
+            _ => '-',
+        };
+        if c != '-' || !(slug.is_empty() || slug.ends_with('-')) {
+            slug.push(c);
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// The type of Akoma Ntoso document and its `name` attribute
+fn document_type(document: &Document) -> (&'static str, &'static str) {
+    match document {
+        Document::Decision(_) => ("judgment", "decision"),
+        Document::Cnil(_) => ("judgment", "deliberation"),
+        Document::Jorf(_) => ("act", "jorf"),
+        Document::LegiArticle(_) => ("act", "article"),
+        Document::LegiSection(_) => ("act", "section"),
+    }
+}
+
+/// The `meta` element: FRBR identification of the work, its French
+/// expression and this XML manifestation, and the organizations
+/// they refer to
+fn meta(document: &Document, doc_type: &str) -> String {
+    let id = escape(document.id());
+    let date = document.date().map(|d| d.to_string());
+    let author = jurisdiction(document);
+    let author_ref = author.as_ref().map(|a| slug(a)).filter(|a| !a.is_empty());
+    let work = format!(
+        "/akn/fr/{}/{}/{}",
+        doc_type,
+        date.as_deref().unwrap_or("unknown"),
+        id
+    );
+    let frbr_date = |name: &str| {
+        date.as_ref()
+            .map(|d| format!("<FRBRdate date=\"{}\" name=\"{}\"/>", d, name))
+            .unwrap_or_default()
+    };
+
+    let mut meta = String::from("<meta><identification source=\"#dila\">");
+    meta.push_str(&format!(
+        "<FRBRWork><FRBRthis value=\"{work}/!main\"/><FRBRuri value=\"{work}\"/>"
+    ));
+    if let Document::Decision(d) = document
+        && let Some(ecli) = d.ecli()
+    {
+        meta.push_str(&format!("<FRBRalias value=\"{}\" name=\"ECLI\"/>", escape(ecli.to_string())));
+    }
+    if let Some(url) = permalink(document.id()) {
+        meta.push_str(&format!("<FRBRalias value=\"{}\" name=\"legifrance\"/>", escape(url.as_str())));
+    }
+    meta.push_str(&frbr_date(if doc_type == "judgment" { "decision" } else { "signature" }));
+    meta.push_str(&format!(
+        "<FRBRauthor href=\"#{}\"/><FRBRcountry value=\"fr\"/></FRBRWork>",
+        author_ref.as_deref().unwrap_or("dila")
+    ));
+    meta.push_str(&format!(
+        "<FRBRExpression><FRBRthis value=\"{work}/fra@/!main\"/><FRBRuri value=\"{work}/fra@\"/>{}\
+         <FRBRauthor href=\"#dila\"/><FRBRlanguage language=\"fra\"/></FRBRExpression>",
+        frbr_date("expression")
+    ));
+    meta.push_str(&format!(
+        "<FRBRManifestation><FRBRthis value=\"{work}/fra@/main.xml\"/><FRBRuri value=\"{work}/fra@.akn\"/>{}\
+         <FRBRauthor href=\"#dila\"/></FRBRManifestation>",
+        frbr_date("manifestation")
+    ));
+    meta.push_str("</identification><references source=\"#dila\">");
+    meta.push_str(
+        "<TLCOrganization eId=\"dila\" href=\"/ontology/organization/fr/dila\" \
+         showAs=\"Direction de l'information légale et administrative\"/>",
+    );
+    if let (Some(author), Some(author_ref)) = (author.as_ref(), author_ref.as_ref()) {
+        meta.push_str(&format!(
+            "<TLCOrganization eId=\"{0}\" href=\"/ontology/organization/fr/{0}\" showAs=\"{1}\"/>",
+            author_ref,
+            escape(author.trim())
+        ));
+    }
+    meta.push_str("</references></meta>");
+    meta
+}
+
+/// The lines of the text of the document as `p` elements
+fn paragraphs(document: &Document) -> String {
+    let lines: Vec<String> = document
+        .text()
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| format!("<p>{}</p>", escape(line)))
+        .collect();
+    if lines.is_empty() { "<p/>".to_string() } else { lines.concat() }
+}
+
+/// The document as an Akoma Ntoso XML document
+pub fn to_akoma_ntoso(document: &Document) -> String {
+    let (doc_type, name) = document_type(document);
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!("<akomaNtoso xmlns=\"{}\">", AKN_NAMESPACE));
+    xml.push_str(&format!("<{} name=\"{}\">", doc_type, name));
+    xml.push_str(&meta(document, doc_type));
+    if let Some(title) = title(document).filter(|t| !t.trim().is_empty()) {
+        xml.push_str(&format!("<preface><p><docTitle>{}</docTitle></p></preface>", escape(title.trim())));
+    }
+    match document {
+        Document::Decision(_) | Document::Cnil(_) => {
+            xml.push_str(&format!("<judgmentBody><decision>{}</decision></judgmentBody>", paragraphs(document)));
+        }
+        Document::LegiArticle(a) => {
+            let num = a.num.as_deref().map(str::trim).unwrap_or_default();
+            xml.push_str(&format!(
+                "<body><article eId=\"art_{}\"><num>{}</num><content>{}</content></article></body>",
+                slug(num),
+                escape(num),
+                paragraphs(document)
+            ));
+        }
+        Document::Jorf(_) | Document::LegiSection(_) => {
+            xml.push_str(&format!(
+                "<body><hcontainer name=\"text\"><content>{}</content></hcontainer></body>",
+                paragraphs(document)
+            ));
+        }
+    }
+    xml.push_str(&format!("</{}></akomaNtoso>\n", doc_type));
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dumps::extractor::PreDilaText;
+    use crate::dumps::extractor::legi::LegiArticle;
+
+    #[test]
+    fn test_akoma_ntoso() {
+        assert_eq!(slug("Conseil d'État"), "conseil-d-etat");
+
+        let decision = Document::Decision(PreDilaText {
+            id: "CETATEXT000049314894".to_string(),
+            title: Some("Conseil d'État, 21/03/2024, <antennes>".to_string()),
+            decision_date: Some("2024-03-21".to_string()),
+            jurisdiction: Some("Conseil d'État".to_string()),
+            ecli_code: Some("ECLI:FR:CECHR:2024:490536.20240321".to_string()),
+            text: "Vu la procédure suivante :\n\nLa requête est rejetée.".to_string(),
+            ..Default::default()
+        });
+        let xml = to_akoma_ntoso(&decision);
+        assert!(xml.contains("<judgment name=\"decision\">"));
+        assert!(xml.contains("<FRBRuri value=\"/akn/fr/judgment/2024-03-21/CETATEXT000049314894\"/>"));
+        assert!(xml.contains("<FRBRalias value=\"ECLI:FR:CECHR:2024:490536.20240321\" name=\"ECLI\"/>"));
+        assert!(xml.contains("<FRBRauthor href=\"#conseil-d-etat\"/>"));
+        assert!(xml.contains("<docTitle>Conseil d&apos;État, 21/03/2024, &lt;antennes&gt;</docTitle>"));
+        assert!(xml.contains("<decision><p>Vu la procédure suivante :</p><p>La requête est rejetée.</p></decision>"));
+
+        let article = Document::LegiArticle(LegiArticle {
+            id: "LEGIARTI000006335064".to_string(),
+            num: Some("L110-1".to_string()),
+            contenu: "Le territoire français est le patrimoine commun de la nation.".to_string(),
+            ..Default::default()
+        });
+        let xml = to_akoma_ntoso(&article);
+        assert!(xml.contains("<act name=\"article\">"));
+        assert!(xml.contains("<article eId=\"art_l110-1\"><num>L110-1</num>"));
+
+        // the output is well formed
+        for xml in [to_akoma_ntoso(&decision), xml] {
+            let mut reader = quick_xml::Reader::from_str(&xml);
+            loop {
+                match reader.read_event().unwrap() {
+                    quick_xml::events::Event::Eof => break,
+                    _ => continue,
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,261 @@
+/// Export of a training corpus for NLP where the names of the people in
+/// the text of the decisions are masked (or removed). The names come
+/// from the metadata of the decision (requester, judges, lawyers) and
+/// from a pattern matching the capitalized words that follow an
+/// honorific ("M. Dupont", "Mme Anne Martin", "Maître Durand"). The
+/// already pseudonymized names of the dumps ("M. X...") are left as is.
+/// This is a best effort pass, not a guarantee that no name remains.
+///
+/// Every line of the JSON lines file has the same schema:
+///
+/// ```json
+/// {"id":"CETATEXT000049314894","text":"... M. [NOM] ...","anonymized":true,"masked":3,
+///  "metadata":{"kind":"decision","date":"2024-03-21","jurisdiction":"Conseil d'État",...}}
+/// ```
+///
+/// where `anonymized` tells whether the text went through the pass (only
+/// decisions do, the texts of the Journal Officiel and of the codes are
+/// written unchanged) and `masked` is the number of names replaced.
+use once_cell::sync::OnceCell;
+use regex::Regex;
+use serde::Serialize;
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::export::{sibling_path, write_line};
+use super::hf::HfMetadata;
+use super::parties::person;
+use super::sqlite::parties;
+use super::{Document, DocumentWriter, ExtractError};
+
+/// What replaces a masked name
+pub const NAME_MASK: &str = "[NOM]";
+
+/// How the names are removed from the text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Anonymization {
+    /// Replace the names by [`NAME_MASK`], keeping the honorifics
+    Mask,
+    /// Remove the names and their honorifics
+    Strip,
+}
+
+fn honorific_regex() -> &'static Regex {
+    static INSTANCE: OnceCell<Regex> = OnceCell::new();
+    INSTANCE.get_or_init(|| {
+        // a name is a run of capitalized words of two letters or more
+        // after the honorific, possibly preceded by initials ("J.-P."),
+        // so that the pseudonyms "X..." do not match
+        let word = r"\p{Lu}[\p{L}'’-]+";
+        let initials = r"\p{Lu}\.(?:-\p{Lu}\.)?";
+        Regex::new(&format!(
+            r"\b(?:Monsieur|Madame|Mademoiselle|Maître|Mlle|Mme|MM\.|Me|M\.)[ \u{{a0}}]+((?:{initials}[ \u{{a0}}]+)*{word}(?:[ \u{{a0}}]+{word})*)"
+        ))
+        .expect("Unable to construct honorific regex")
+    })
+}
+
+/// The text of a document with the names masked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnonymizedText {
+    pub text: String,
+    /// Whether the text went through the anonymization
+    pub anonymized: bool,
+    /// Number of names masked or removed
+    pub masked: usize,
+}
+
+/// Mask the `spans` (byte offsets, possibly overlapping) of `text`
+fn mask_spans(text: &str, mut spans: Vec<(usize, usize)>, mode: Anonymization) -> (String, usize) {
+    spans.sort();
+    let mut merged: Vec<(usize, usize)> = vec![];
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    let mut result = String::with_capacity(text.len());
+    let mut position = 0;
+    for (start, end) in merged.iter() {
+        result.push_str(&text[position..*start]);
+        match mode {
+            Anonymization::Mask => result.push_str(NAME_MASK),
+            // do not leave a space before the next space or punctuation
+            Anonymization::Strip
+                if result.ends_with(' ')
+                    && text[*end..].starts_with([' ', ',', '.', ';', ':', ')']) =>
+            {
+                result.pop();
+            }
+            Anonymization::Strip => {}
+        }
+        position = *end;
+    }
+    result.push_str(&text[position..]);
+    (result, merged.len())
+}
+
+/// The text of `document` with the names of people masked,
+/// for decisions, and unchanged for the other documents
+pub fn anonymize(document: &Document, mode: Anonymization) -> AnonymizedText {
+    let text = document.text().unwrap_or_default();
+    if !matches!(document, Document::Decision(_)) {
+        return AnonymizedText {
+            text: text.to_string(),
+            anonymized: false,
+            masked: 0,
+        };
+    }
+    let mut spans = vec![];
+    for captures in honorific_regex().captures_iter(text) {
+        let name = captures.get(1).expect("the name is not optional");
+        let start = match mode {
+            Anonymization::Mask => name.start(),
+            Anonymization::Strip => captures.get(0).expect("match").start(),
+        };
+        spans.push((start, name.end()));
+    }
+    let names: Vec<String> = parties(document)
+        .into_iter()
+        .filter_map(|(_, name)| person(name))
+        .filter(|name| name.chars().count() > 2)
+        .map(|name| regex::escape(&name))
+        .collect();
+    if !names.is_empty() {
+        let names = Regex::new(&format!(r"(?i)\b(?:{})\b", names.join("|")))
+            .expect("names are escaped");
+        spans.extend(names.find_iter(text).map(|m| (m.start(), m.end())));
+    }
+    let (text, masked) = mask_spans(text, spans, mode);
+    AnonymizedText {
+        text,
+        anonymized: true,
+        masked,
+    }
+}
+
+/// A line of the training corpus
+#[derive(Debug, Clone, Serialize)]
+struct TrainingRecord<'a> {
+    id: &'a str,
+    text: String,
+    anonymized: bool,
+    masked: usize,
+    metadata: HfMetadata,
+}
+
+/// Writes the documents with their names masked as JSON lines. Files
+/// that could not be parsed are listed in a sibling `errors` file
+/// (e.g. `results.anonymized.errors.jsonl`).
+pub struct DocumentAnonymizedWriter {
+    path: PathBuf,
+    mode: Anonymization,
+    writer: std::io::BufWriter<std::fs::File>,
+    errors: Option<std::io::BufWriter<std::fs::File>>,
+}
+
+impl DocumentAnonymizedWriter {
+    pub fn new<T: AsRef<Path>>(path: T, mode: Anonymization) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let writer = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        Ok(DocumentAnonymizedWriter {
+            path,
+            mode,
+            writer,
+            errors: None,
+        })
+    }
+}
+
+impl DocumentWriter for DocumentAnonymizedWriter {
+    fn write(&mut self, document: &Document) -> anyhow::Result<()> {
+        let anonymized = anonymize(document, self.mode);
+        let record = TrainingRecord {
+            id: document.id(),
+            text: anonymized.text,
+            anonymized: anonymized.anonymized,
+            masked: anonymized.masked,
+            metadata: HfMetadata::from(document),
+        };
+        write_line(&mut self.writer, &record)
+    }
+
+    fn write_error(&mut self, path: &str, error: &ExtractError) -> anyhow::Result<()> {
+        if self.errors.is_none() {
+            let file = std::fs::File::create(sibling_path(&self.path, "errors", "jsonl"))?;
+            self.errors = Some(std::io::BufWriter::new(file));
+        }
+        let errors = self.errors.as_mut().expect("writer was just created");
+        write_line(
+            errors,
+            &serde_json::json!({ "path": path, "error": error.to_string() }),
+        )
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        if let Some(errors) = self.errors.as_mut() {
+            errors.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dumps::extractor::PreDilaText;
+    use crate::dumps::extractor::legi::LegiArticle;
+
+    #[test]
+    fn test_anonymize() {
+        let decision = Document::Decision(PreDilaText {
+            id: "CETATEXT000049314894".to_string(),
+            president: Some("M. Alexandre Trémolière".to_string()),
+            lawyers: vec!["SCP Piwnica, Molinié".to_string()],
+            text: "Vu la requête présentée par Mme Anne-Sophie Le Gall, représentée par la \
+                   SCP Piwnica, Molinié ; M. X... demande l'annulation. Entendu le rapport de \
+                   M. J.-P. Durand. Délibéré par Alexandre Trémolière, président."
+                .to_string(),
+            ..Default::default()
+        });
+        let masked = anonymize(&decision, Anonymization::Mask);
+        assert!(masked.anonymized);
+        assert_eq!(masked.masked, 4);
+        assert_eq!(
+            masked.text,
+            "Vu la requête présentée par Mme [NOM], représentée par la [NOM] ; M. X... demande \
+             l'annulation. Entendu le rapport de M. [NOM]. Délibéré par [NOM], président."
+        );
+        let stripped = anonymize(&decision, Anonymization::Strip);
+        assert!(stripped.text.starts_with("Vu la requête présentée par, représentée par la ;"));
+        assert!(stripped.text.contains("le rapport de. Délibéré par, président."));
+
+        let article = Document::LegiArticle(LegiArticle {
+            id: "LEGIARTI000006335064".to_string(),
+            contenu: "Le ministre M. Dupont est chargé de l'exécution.".to_string(),
+            ..Default::default()
+        });
+        let unchanged = anonymize(&article, Anonymization::Mask);
+        assert!(!unchanged.anonymized);
+        assert_eq!(unchanged.text, "Le ministre M. Dupont est chargé de l'exécution.");
+
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let path = tmp.path().join("results.anonymized.jsonl");
+        let mut writer = DocumentAnonymizedWriter::new(&path, Anonymization::Mask).unwrap();
+        writer.write(&decision).unwrap();
+        writer.write(&article).unwrap();
+        writer.flush().unwrap();
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines[0]["anonymized"], true);
+        assert_eq!(lines[0]["metadata"]["kind"], "decision");
+        assert!(!lines[0]["text"].as_str().unwrap().contains("Trémolière"));
+        assert_eq!(lines[1]["anonymized"], false);
+    }
+}
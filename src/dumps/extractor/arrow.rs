@@ -0,0 +1,246 @@
+/// Export of documents as Arrow record batches, for in-process
+/// analytics: [`record_batch`] converts documents for the callers of
+/// the library, and [`DocumentArrowWriter`] streams them in the Arrow
+/// IPC streaming format, read in Python with
+/// `pyarrow.ipc.open_stream(path).read_pandas()`.
+use arrow_array::{ArrayRef, Date32Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use arrow_ipc::writer::StreamWriter;
+use chrono::NaiveDate;
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::export::{ExportRecord, sibling_path, write_line};
+use super::markdown::{jurisdiction, title};
+use super::{Document, DocumentWriter, ExtractError};
+
+/// Number of documents written at once in a record batch
+pub const ARROW_BATCH_SIZE: usize = 1024;
+
+/// The schema of the record batches: the `id`, `kind`, `date`,
+/// `jurisdiction`, `title` and `text` of the documents, and the
+/// other columns of [`ExportRecord`] as a JSON object in `metadata`
+pub fn document_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("id", DataType::Utf8, false),
+        Field::new("kind", DataType::Utf8, false),
+        Field::new("date", DataType::Date32, true),
+        Field::new("jurisdiction", DataType::Utf8, true),
+        Field::new("title", DataType::Utf8, true),
+        Field::new("text", DataType::Utf8, true),
+        Field::new("metadata", DataType::Utf8, false),
+    ]))
+}
+
+/// A document as a row of [`document_schema`]
+struct Row {
+    id: String,
+    kind: &'static str,
+    date: Option<NaiveDate>,
+    jurisdiction: Option<String>,
+    title: Option<String>,
+    text: Option<String>,
+    metadata: String,
+}
+
+impl TryFrom<&Document> for Row {
+    type Error = anyhow::Error;
+
+    fn try_from(document: &Document) -> anyhow::Result<Self> {
+        let record = ExportRecord::try_from(document)?;
+        let metadata: serde_json::Map<String, serde_json::Value> = record
+            .metadata()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(column, value)| (column.to_string(), value.into()))
+            .collect();
+        Ok(Row {
+            id: document.id().to_string(),
+            kind: document.kind(),
+            date: document.date(),
+            jurisdiction: jurisdiction(document),
+            title: title(document),
+            text: document.text().map(String::from),
+            metadata: serde_json::to_string(&metadata)?,
+        })
+    }
+}
+
+/// Days since the UNIX epoch, the unit of `Date32`
+fn days_since_epoch(date: NaiveDate) -> i32 {
+    (date - NaiveDate::default()).num_days() as i32
+}
+
+fn rows_to_batch(rows: &[Row]) -> anyhow::Result<RecordBatch> {
+    let strings = |value: fn(&Row) -> Option<&str>| {
+        Arc::new(rows.iter().map(value).collect::<StringArray>()) as ArrayRef
+    };
+    let dates: Date32Array = rows.iter().map(|r| r.date.map(days_since_epoch)).collect();
+    let columns = vec![
+        strings(|r| Some(&r.id)),
+        strings(|r| Some(r.kind)),
+        Arc::new(dates) as ArrayRef,
+        strings(|r| r.jurisdiction.as_deref()),
+        strings(|r| r.title.as_deref()),
+        strings(|r| r.text.as_deref()),
+        strings(|r| Some(&r.metadata)),
+    ];
+    Ok(RecordBatch::try_new(document_schema(), columns)?)
+}
+
+/// The documents as a record batch of [`document_schema`]
+pub fn record_batch(documents: &[Document]) -> anyhow::Result<RecordBatch> {
+    let rows = documents
+        .iter()
+        .map(Row::try_from)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    rows_to_batch(&rows)
+}
+
+/// Writes the documents to `out` in the Arrow IPC streaming format, by
+/// record batches of [`ARROW_BATCH_SIZE`] documents. Files that could
+/// not be parsed are listed in a sibling `errors` file (e.g.
+/// `results.errors.jsonl`) when writing to a file.
+pub struct DocumentArrowWriter<W: Write> {
+    writer: StreamWriter<W>,
+    pending: Vec<Row>,
+    errors_path: Option<PathBuf>,
+    errors: Option<std::io::BufWriter<std::fs::File>>,
+}
+
+impl DocumentArrowWriter<std::io::BufWriter<std::fs::File>> {
+    /// Write the documents to the file at `path`
+    pub fn create<T: AsRef<Path>>(path: T) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let mut writer = DocumentArrowWriter::new(file)?;
+        writer.errors_path = Some(sibling_path(path, "errors", "jsonl"));
+        Ok(writer)
+    }
+}
+
+impl<W: Write> DocumentArrowWriter<W> {
+    pub fn new(out: W) -> anyhow::Result<Self> {
+        Ok(DocumentArrowWriter {
+            writer: StreamWriter::try_new(out, &document_schema())?,
+            pending: vec![],
+            errors_path: None,
+            errors: None,
+        })
+    }
+
+    fn write_pending(&mut self) -> anyhow::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let batch = rows_to_batch(&self.pending)?;
+        self.writer.write(&batch)?;
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Write the last documents and the end of the stream
+    pub fn finish(mut self) -> anyhow::Result<W> {
+        self.flush()?;
+        self.writer.finish()?;
+        Ok(self.writer.into_inner()?)
+    }
+}
+
+impl<W: Write> DocumentWriter for DocumentArrowWriter<W> {
+    fn write(&mut self, document: &Document) -> anyhow::Result<()> {
+        self.pending.push(Row::try_from(document)?);
+        if self.pending.len() >= ARROW_BATCH_SIZE {
+            self.write_pending()?;
+        }
+        Ok(())
+    }
+
+    fn write_error(&mut self, path: &str, error: &ExtractError) -> anyhow::Result<()> {
+        let Some(errors_path) = self.errors_path.as_ref() else {
+            return Ok(());
+        };
+        if self.errors.is_none() {
+            let file = std::fs::File::create(errors_path)?;
+            self.errors = Some(std::io::BufWriter::new(file));
+        }
+        let errors = self.errors.as_mut().expect("writer was just created");
+        write_line(
+            errors,
+            &serde_json::json!({ "path": path, "error": error.to_string() }),
+        )
+    }
+
+    /// Write the pending documents as a record batch. Readers of the
+    /// stream accept it without its end marker, written by
+    /// [`DocumentArrowWriter::finish`].
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.write_pending()?;
+        self.writer.flush()?;
+        if let Some(errors) = self.errors.as_mut() {
+            errors.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dumps::extractor::PreDilaText;
+    use crate::dumps::extractor::legi::LegiSection;
+    use arrow_array::Array;
+    use arrow_ipc::reader::StreamReader;
+
+    #[test]
+    fn test_arrow_writer() {
+        let documents = [
+            Document::Decision(PreDilaText {
+                id: "CETATEXT000049314894".to_string(),
+                decision_date: Some("2024-03-21".to_string()),
+                jurisdiction: Some("Conseil d'État".to_string()),
+                text: "La requête est rejetée.".to_string(),
+                ..Default::default()
+            }),
+            Document::LegiSection(LegiSection {
+                id: "LEGISCTA000006147745".to_string(),
+                title: Some("Livre Ier".to_string()),
+                ..Default::default()
+            }),
+        ];
+        let batch = record_batch(&documents).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        let dates = batch.column(2).as_any().downcast_ref::<Date32Array>().unwrap();
+        assert_eq!(dates.value_as_date(0), NaiveDate::from_ymd_opt(2024, 3, 21));
+        assert!(dates.is_null(1));
+
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let path = tmp.path().join("results.arrows");
+        let mut writer = DocumentArrowWriter::create(&path).unwrap();
+        for document in documents.iter() {
+            writer.write(document).unwrap();
+        }
+        writer
+            .write_error("missing.xml", &ExtractError::NotFound("missing.xml".into()))
+            .unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        assert!(tmp.path().join("results.errors.jsonl").exists());
+
+        // the stream is readable without its end marker
+        let file = std::fs::File::open(&path).unwrap();
+        let reader = StreamReader::try_new(file, None).unwrap();
+        let batches: Vec<RecordBatch> = reader.map(Result::unwrap).collect();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].schema(), document_schema());
+        let titles = batches[0].column(4).as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(titles.value(1), "Livre Ier");
+
+        let mut writer = DocumentArrowWriter::new(vec![]).unwrap();
+        writer.write(&documents[0]).unwrap();
+        let bytes = writer.finish().unwrap();
+        let reader = StreamReader::try_new(std::io::Cursor::new(bytes), None).unwrap();
+        assert_eq!(reader.map(|b| b.unwrap().num_rows()).sum::<usize>(), 1);
+    }
+}
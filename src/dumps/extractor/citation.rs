@@ -0,0 +1,276 @@
+/// Citations of the exported documents, to cite the decisions found
+/// by a search from Zotero or LaTeX: CSL-JSON items (`legal_case` for
+/// decisions and deliberations, `legislation` for texts and articles),
+/// or biblatex entries (`@jurisdiction` and `@legislation`), built from
+/// the jurisdiction, date, number and ECLI of the documents.
+use chrono::NaiveDate;
+use serde_json::{Value, json};
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::export::{sibling_path, write_line};
+use super::identifiers::permalink;
+use super::markdown::{jurisdiction, title};
+use super::{Document, DocumentWriter, ExtractError};
+
+/// Format of the citations written by [`DocumentCitationWriter`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum CitationFormat {
+    /// CSL-JSON, imported by Zotero and read by pandoc
+    Csl,
+    /// biblatex
+    Bibtex,
+}
+
+impl CitationFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CitationFormat::Csl => "csl.json",
+            CitationFormat::Bibtex => "bib",
+        }
+    }
+}
+
+/// What is cited, as needed by the citation formats
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Citation {
+    /// Key of the citation, the identifier of the document
+    pub key: String,
+    /// Whether the document is a decision (or a deliberation)
+    /// rather than a text
+    pub case: bool,
+    pub title: String,
+    /// The court or authority that issued the document
+    pub authority: Option<String>,
+    pub date: Option<NaiveDate>,
+    /// Number of the decision or text in its jurisdiction
+    pub number: Option<String>,
+    pub ecli: Option<String>,
+    pub url: Option<String>,
+}
+
+fn non_empty(value: Option<&String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+impl From<&Document> for Citation {
+    fn from(document: &Document) -> Self {
+        let (case, number, ecli) = match document {
+            Document::Decision(d) => (
+                true,
+                non_empty(d.juri_code.as_ref()),
+                d.ecli().map(|e| e.to_string()),
+            ),
+            Document::Cnil(c) => (true, non_empty(c.number.as_ref()), None),
+            Document::Jorf(j) => (false, non_empty(j.num.as_ref()), None),
+            Document::LegiArticle(a) => (false, non_empty(a.num.as_ref()), None),
+            Document::LegiSection(_) => (false, None, None),
+        };
+        let authority = jurisdiction(document);
+        let date = document.date();
+        // decisions without title are named after their court and date
+        let title = non_empty(title(document).as_ref()).unwrap_or_else(|| {
+            let parts: Vec<String> = [
+                authority.clone(),
+                date.map(|d| d.format("%d/%m/%Y").to_string()),
+                number.as_ref().map(|n| format!("n° {}", n)),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            if parts.is_empty() { document.id().to_string() } else { parts.join(", ") }
+        });
+        Citation {
+            key: document.id().to_string(),
+            case,
+            title,
+            authority,
+            date,
+            number,
+            ecli,
+            url: permalink(document.id()).map(String::from),
+        }
+    }
+}
+
+/// The citation as a CSL-JSON item
+pub fn to_csl(citation: &Citation) -> Value {
+    use chrono::Datelike;
+
+    let mut item = json!({
+        "id": citation.key,
+        "type": if citation.case { "legal_case" } else { "legislation" },
+        "title": citation.title,
+    });
+    let mut set = |field: &str, value: Option<&String>| {
+        if let Some(value) = value {
+            item[field] = Value::from(value.as_str());
+        }
+    };
+    set("authority", citation.authority.as_ref());
+    set("number", citation.number.as_ref());
+    set("URL", citation.url.as_ref());
+    set("note", citation.ecli.as_ref());
+    if let Some(date) = citation.date {
+        item["issued"] = json!({ "date-parts": [[date.year(), date.month(), date.day()]] });
+    }
+    item
+}
+
+/// Escape the characters of `value` that are special in LaTeX
+fn escape_latex(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '{' | '}' | '&' | '%' | '$' | '#' | '_' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The citation as a biblatex entry
+pub fn to_bibtex(citation: &Citation) -> String {
+    let kind = if citation.case { "jurisdiction" } else { "legislation" };
+    let mut fields = vec![("title", escape_latex(&citation.title))];
+    if let Some(authority) = &citation.authority {
+        fields.push(("institution", escape_latex(authority)));
+    }
+    if let Some(date) = citation.date {
+        fields.push(("date", date.to_string()));
+    }
+    if let Some(number) = &citation.number {
+        fields.push(("number", escape_latex(number)));
+    }
+    if let Some(ecli) = &citation.ecli {
+        fields.push(("note", escape_latex(ecli)));
+    }
+    if let Some(url) = &citation.url {
+        // urls are verbatim fields
+        fields.push(("url", url.clone()));
+    }
+    let mut entry = format!("@{}{{{},\n", kind, citation.key);
+    for (name, value) in fields {
+        entry.push_str(&format!("  {} = {{{}}},\n", name, value));
+    }
+    entry.push_str("}\n");
+    entry
+}
+
+/// Writes the citations of the documents to a single file, rewritten
+/// by every [`DocumentWriter::flush`] (a CSL-JSON file is an array of
+/// items). Files that could not be parsed are listed in a sibling
+/// `errors` file (e.g. `results.errors.jsonl`).
+pub struct DocumentCitationWriter {
+    path: PathBuf,
+    format: CitationFormat,
+    citations: Vec<Citation>,
+    errors: Option<std::io::BufWriter<std::fs::File>>,
+}
+
+impl DocumentCitationWriter {
+    pub fn new<T: AsRef<Path>>(path: T, format: CitationFormat) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        // fail early when the file cannot be written
+        std::fs::File::create(&path)?;
+        Ok(DocumentCitationWriter {
+            path,
+            format,
+            citations: vec![],
+            errors: None,
+        })
+    }
+}
+
+impl DocumentWriter for DocumentCitationWriter {
+    fn write(&mut self, document: &Document) -> anyhow::Result<()> {
+        self.citations.push(Citation::from(document));
+        Ok(())
+    }
+
+    fn write_error(&mut self, path: &str, error: &ExtractError) -> anyhow::Result<()> {
+        if self.errors.is_none() {
+            let file = std::fs::File::create(sibling_path(&self.path, "errors", "jsonl"))?;
+            self.errors = Some(std::io::BufWriter::new(file));
+        }
+        let errors = self.errors.as_mut().expect("writer was just created");
+        write_line(errors, &json!({ "path": path, "error": error.to_string() }))
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        let mut out = std::io::BufWriter::new(std::fs::File::create(&self.path)?);
+        match self.format {
+            CitationFormat::Csl => {
+                let items: Vec<Value> = self.citations.iter().map(to_csl).collect();
+                serde_json::to_writer_pretty(&mut out, &items)?;
+                out.write_all(b"\n")?;
+            }
+            CitationFormat::Bibtex => {
+                for citation in self.citations.iter() {
+                    writeln!(out, "{}", to_bibtex(citation))?;
+                }
+            }
+        }
+        out.flush()?;
+        if let Some(errors) = self.errors.as_mut() {
+            errors.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dumps::extractor::PreDilaText;
+
+    #[test]
+    fn test_citations() {
+        let document = Document::Decision(PreDilaText {
+            id: "CETATEXT000049314894".to_string(),
+            decision_date: Some("2024-03-21".to_string()),
+            jurisdiction: Some("Conseil d'État".to_string()),
+            juri_code: Some("490536".to_string()),
+            ecli_code: Some("ECLI:FR:CECHR:2024:490536.20240321".to_string()),
+            ..Default::default()
+        });
+        let citation = Citation::from(&document);
+        assert_eq!(citation.title, "Conseil d'État, 21/03/2024, n° 490536");
+
+        let csl = to_csl(&citation);
+        assert_eq!(csl["type"], "legal_case");
+        assert_eq!(csl["authority"], "Conseil d'État");
+        assert_eq!(csl["issued"]["date-parts"], json!([[2024, 3, 21]]));
+        assert_eq!(csl["note"], "ECLI:FR:CECHR:2024:490536.20240321");
+
+        assert_eq!(
+            to_bibtex(&citation),
+            "@jurisdiction{CETATEXT000049314894,\n\
+             \x20 title = {Conseil d'État, 21/03/2024, n° 490536},\n\
+             \x20 institution = {Conseil d'État},\n\
+             \x20 date = {2024-03-21},\n\
+             \x20 number = {490536},\n\
+             \x20 note = {ECLI:FR:CECHR:2024:490536.20240321},\n\
+             \x20 url = {https://www.legifrance.gouv.fr/ceta/id/CETATEXT000049314894},\n\
+             }\n"
+        );
+        assert_eq!(escape_latex("50 % & n°_1"), "50 \\% \\& n°\\_1");
+
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let path = tmp.path().join("results.csl.json");
+        let mut writer = DocumentCitationWriter::new(&path, CitationFormat::Csl).unwrap();
+        writer.write(&document).unwrap();
+        writer.write(&document).unwrap();
+        writer.flush().unwrap();
+        let items: Vec<Value> =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(items.len(), 2);
+    }
+}
@@ -0,0 +1,208 @@
+/// Deliberations of the Commission nationale de l'informatique
+/// et des libertés (TEXTECNIL documents of the CNIL dumps).
+use once_cell::sync::OnceCell;
+use quick_xml::events::Event;
+use quick_xml::reader::Reader;
+use regex::Regex;
+
+use std::io::BufRead;
+
+use serde::{Deserialize, Serialize};
+
+use super::{ExtractError, Link, LinkCollector, TagStack};
+
+/// Metadata and text of a CNIL deliberation.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CnilDeliberation {
+    /// The unique identifier of the deliberation (e.g. "CNILTEXT000017651912")
+    pub id: String,
+    /// The identifier of the deliberation before the 2015 reform
+    pub old_id: Option<String>,
+    /// Dataset origin of the deliberation (e.g. "CNIL")
+    pub origin: String,
+    /// The relative path to the XML file in the DILA archive
+    pub url: String,
+    /// The type of text (e.g. "DELIBERATION")
+    pub nature: String,
+    /// Title of the deliberation
+    pub title: Option<String>,
+    /// Number of the deliberation (e.g. "SAN-2022-021")
+    pub number: Option<String>,
+    /// Type of deliberation (e.g. "Sanction", "Avis", "Autorisation")
+    pub deliberation_type: Option<String>,
+    /// Date of the deliberation in the format YYYY-MM-DD
+    pub date: Option<String>,
+    /// Date of publication in the format YYYY-MM-DD
+    pub publication_date: Option<String>,
+    /// Legal state of the deliberation (e.g. "VIGUEUR")
+    pub legal_state: Option<String>,
+    /// Amount of the financial penalty in euros, if any
+    pub sanction_amount: Option<u64>,
+    /// The entity concerned by the deliberation, as named in its title
+    pub entity: Option<String>,
+    /// The full text of the deliberation
+    pub text: String,
+    /// Texts cited by the deliberation
+    #[serde(default)]
+    pub links: Vec<Link>,
+}
+
+fn sanction_regex() -> &'static Regex {
+    static INSTANCE: OnceCell<Regex> = OnceCell::new();
+    INSTANCE.get_or_init(|| {
+        Regex::new(r"(?i)(?:amende|sanction pécuniaire)[^.€]*?(\d{1,3}(?:[  .]?\d{3})*)\s*(?:€|euros)")
+            .expect("Unable to construct sanction regex")
+    })
+}
+
+fn entity_regex() -> &'static Regex {
+    static INSTANCE: OnceCell<Regex> = OnceCell::new();
+    INSTANCE.get_or_init(|| {
+        Regex::new(r"(?i)(?:à l['’]encontre\s+(?:de\s+|d['’])|concernant\s+)(.+?)\s*$")
+            .expect("Unable to construct entity regex")
+    })
+}
+
+/// Amount in euros of the first financial penalty mentioned in `text`
+pub fn sanction_amount(text: &str) -> Option<u64> {
+    let caps = sanction_regex().captures(text)?;
+    caps[1]
+        .chars()
+        .filter(|c| c.is_ascii_digit())
+        .collect::<String>()
+        .parse()
+        .ok()
+}
+
+/// The entity a deliberation is about, guessed from its title
+/// (e.g. "... à l'encontre de la société X")
+pub fn entity_from_title(title: &str) -> Option<String> {
+    let caps = entity_regex().captures(title)?;
+    Some(caps[1].trim_end_matches('.').to_string())
+}
+
+fn update_cnil(delib: &mut CnilDeliberation, tag: &[u8], text: &str) {
+    fn set(field: &mut Option<String>, text: &str) {
+        if field.is_none() {
+            *field = Some(text.to_string());
+        }
+    }
+    match tag {
+        b"ID" if delib.id.is_empty() => delib.id = text.to_string(),
+        b"ORIGINE" if delib.origin.is_empty() => delib.origin = text.to_string(),
+        b"URL" if delib.url.is_empty() => delib.url = text.to_string(),
+        b"NATURE" if delib.nature.is_empty() => delib.nature = text.to_string(),
+        // the full title names the entity concerned, prefer it
+        b"ANCIEN_ID" => set(&mut delib.old_id, text),
+        b"TITREFULL" => delib.title = Some(text.to_string()),
+        b"TITRE" => set(&mut delib.title, text),
+        b"NUMERO" => set(&mut delib.number, text),
+        b"NATURE_DELIB" => set(&mut delib.deliberation_type, text),
+        b"DATE_TEXTE" => set(&mut delib.date, text),
+        b"DATE_PUBLI" => set(&mut delib.publication_date, text),
+        b"ETAT_JURIDIQUE" => set(&mut delib.legal_state, text),
+        _ => {}
+    }
+}
+
+/// Parse a CNIL deliberation.
+pub fn parse_cnil(content: &str) -> Result<CnilDeliberation, ExtractError> {
+    parse_cnil_reader(&mut Reader::from_str(content))
+}
+
+/// Parse a CNIL deliberation from a buffered reader.
+pub fn parse_cnil_reader<R: BufRead>(
+    reader: &mut Reader<R>,
+) -> Result<CnilDeliberation, ExtractError> {
+    let mut buf = Vec::new();
+    let mut delib = CnilDeliberation::default();
+    // names of the currently open tags
+    let mut stack = TagStack::default();
+    let mut links = LinkCollector::default();
+
+    loop {
+        buf.clear();
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|e| ExtractError::xml(e, reader.buffer_position()))?;
+        links.feed(&event);
+        match event {
+            Event::Eof => break,
+            Event::Start(e) => stack.push(e.name().as_ref()),
+            Event::End(_) => {
+                stack.pop();
+            }
+            Event::Text(t) => {
+                let txt = t
+                    .unescape()
+                    .map_err(|e| ExtractError::xml(e, reader.buffer_position()))?;
+                if stack.contains(b"CONTENU") {
+                    delib.text.push_str(&txt);
+                } else if let Some(tag) = stack.last() {
+                    let txt = txt.trim();
+                    if !txt.is_empty() {
+                        update_cnil(&mut delib, tag, txt);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    delib.sanction_amount = sanction_amount(&delib.text);
+    delib.entity = delib.title.as_deref().and_then(entity_from_title);
+    delib.links = links.finish();
+    Ok(delib)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<TEXTECNIL>
+<META>
+<META_COMMUN>
+<ID>CNILTEXT000046650123</ID>
+<ANCIEN_ID/>
+<ORIGINE>CNIL</ORIGINE>
+<URL>cnil/CNIL/TEXT/00/00/46/65/01/CNILTEXT000046650123.xml</URL>
+<NATURE>DELIBERATION</NATURE>
+</META_COMMUN>
+<META_SPEC>
+<META_CNIL>
+<TITRE>Délibération SAN-2022-021 du 24 novembre 2022</TITRE>
+<TITREFULL>Délibération de la formation restreinte n°SAN-2022-021 du 24 novembre 2022 concernant la société EXEMPLE SAS</TITREFULL>
+<NUMERO>SAN-2022-021</NUMERO>
+<NATURE_DELIB>Sanction</NATURE_DELIB>
+<DATE_TEXTE>2022-11-24</DATE_TEXTE>
+<DATE_PUBLI>2022-12-01</DATE_PUBLI>
+<ETAT_JURIDIQUE>VIGUEUR</ETAT_JURIDIQUE>
+</META_CNIL>
+</META_SPEC>
+</META>
+<TEXTE>
+<BLOC_TEXTUEL>
+<CONTENU>La formation restreinte décide de prononcer à l'encontre de la société une amende administrative d'un montant de 8 000 000 euros.</CONTENU>
+</BLOC_TEXTUEL>
+</TEXTE>
+</TEXTECNIL>"#;
+
+    #[test]
+    fn test_cnil_parser() {
+        let delib = parse_cnil(EXAMPLE_XML).unwrap();
+        assert_eq!(delib.id, "CNILTEXT000046650123");
+        assert_eq!(delib.number.as_deref(), Some("SAN-2022-021"));
+        assert_eq!(delib.deliberation_type.as_deref(), Some("Sanction"));
+        assert_eq!(delib.date.as_deref(), Some("2022-11-24"));
+        assert_eq!(delib.sanction_amount, Some(8_000_000));
+        assert_eq!(delib.entity.as_deref(), Some("la société EXEMPLE SAS"));
+    }
+
+    #[test]
+    fn test_sanction_amount() {
+        assert_eq!(sanction_amount("une sanction pécuniaire de 150 000 €"), Some(150_000));
+        assert_eq!(sanction_amount("une amende de 1.500 euros"), Some(1_500));
+        assert_eq!(sanction_amount("un avertissement public"), None);
+    }
+}
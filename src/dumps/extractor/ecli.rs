@@ -0,0 +1,113 @@
+/// European Case Law Identifiers (ECLI), as found in the `ECLI`
+/// element of the decisions, e.g. `ECLI:FR:CECHR:2024:490536.20240321`.
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Result;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A validated ECLI, split into its components.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Ecli {
+    /// Two letter country code (e.g. "FR", or "EU" for the European courts)
+    pub country: String,
+    /// Code of the court (e.g. "CECHR" for the Conseil d'État, chambres réunies)
+    pub court: String,
+    /// Year of the decision
+    pub year: u16,
+    /// Identifier of the decision within the court and year
+    /// (e.g. "490536.20240321")
+    pub ordinal: String,
+}
+
+impl Ecli {
+    /// Parse an ECLI, checking the format of every component.
+    /// Surrounding whitespace is ignored and the prefix is case-insensitive.
+    pub fn parse(s: &str) -> Result<Ecli> {
+        let s = s.trim();
+        let parts: Vec<&str> = s.split(':').collect();
+        let [prefix, country, court, year, ordinal] = parts[..] else {
+            return Err(anyhow::anyhow!(
+                "Invalid ECLI {}: expected 5 components separated by ':'",
+                s
+            ));
+        };
+        if !prefix.eq_ignore_ascii_case("ECLI") {
+            return Err(anyhow::anyhow!("Invalid ECLI {}: missing ECLI prefix", s));
+        }
+        if country.len() != 2 || !country.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(anyhow::anyhow!("Invalid ECLI {}: bad country code {}", s, country));
+        }
+        if court.is_empty() || court.len() > 7 || !court.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(anyhow::anyhow!("Invalid ECLI {}: bad court code {}", s, court));
+        }
+        if year.len() != 4 {
+            return Err(anyhow::anyhow!("Invalid ECLI {}: bad year {}", s, year));
+        }
+        let year: u16 = year
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid ECLI {}: bad year {}", s, year))?;
+        if ordinal.is_empty()
+            || ordinal.len() > 25
+            || !ordinal.chars().all(|c| c.is_ascii_alphanumeric() || c == '.')
+        {
+            return Err(anyhow::anyhow!("Invalid ECLI {}: bad ordinal {}", s, ordinal));
+        }
+        Ok(Ecli {
+            country: country.to_ascii_uppercase(),
+            court: court.to_ascii_uppercase(),
+            year,
+            ordinal: ordinal.to_string(),
+        })
+    }
+}
+
+impl FromStr for Ecli {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ecli::parse(s)
+    }
+}
+
+impl fmt::Display for Ecli {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ECLI:{}:{}:{}:{}", self.country, self.court, self.year, self.ordinal)
+    }
+}
+
+// ECLIs are exchanged as strings, so that they can be joined
+// directly with other case-law databases
+impl Serialize for Ecli {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Ecli {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ecli::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ecli_parsing() {
+        let ecli: Ecli = "ECLI:FR:CECHR:2024:490536.20240321".parse().unwrap();
+        assert_eq!(ecli.country, "FR");
+        assert_eq!(ecli.court, "CECHR");
+        assert_eq!(ecli.year, 2024);
+        assert_eq!(ecli.ordinal, "490536.20240321");
+        assert_eq!(ecli.to_string(), "ECLI:FR:CECHR:2024:490536.20240321");
+
+        assert!(Ecli::parse("ECLI:FR:CECHR:2024").is_err());
+        assert!(Ecli::parse("ELCI:FR:CECHR:2024:490536").is_err());
+        assert!(Ecli::parse("ECLI:FRA:CECHR:2024:490536").is_err());
+        assert!(Ecli::parse("ECLI:FR:CECHR:24:490536").is_err());
+        assert!(Ecli::parse("ECLI:FR:CECHR:2024:490/536").is_err());
+    }
+}
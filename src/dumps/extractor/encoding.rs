@@ -0,0 +1,103 @@
+/// Character encodings of the XML files. Most documents are UTF-8,
+/// but some older dumps are ISO-8859-1: they are transcoded to UTF-8
+/// before parsing, using the encoding of the XML declaration, or
+/// guessed from the content when there is no declaration.
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+
+use std::borrow::Cow;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+/// How many bytes are looked at to find the XML declaration
+/// and guess the encoding
+const SNIFF_LEN: usize = 8192;
+
+/// The value of the `encoding` attribute of the XML declaration
+fn declared_encoding(head: &[u8]) -> Option<&'static Encoding> {
+    let head = head.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(head);
+    let start = head.iter().position(|b| !b.is_ascii_whitespace())?;
+    let head = &head[start..];
+    if !head.starts_with(b"<?xml") {
+        return None;
+    }
+    let end = head.iter().position(|&b| b == b'>')?;
+    let decl = &head[..end];
+    let pos = decl.windows(8).position(|w| w == b"encoding")?;
+    let rest = &decl[pos + 8..];
+    let quote = rest.iter().position(|&b| b == b'"' || b == b'\'')?;
+    let delim = rest[quote];
+    let value = &rest[quote + 1..];
+    let len = value.iter().position(|&b| b == delim)?;
+    Encoding::for_label(&value[..len])
+}
+
+/// Whether `head` is valid UTF-8, ignoring a character
+/// cut at the end of the buffer
+fn looks_like_utf8(head: &[u8]) -> bool {
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+/// Encoding of a document, from the first bytes of the document:
+/// the byte order mark, then the XML declaration, and finally
+/// Windows-1252 (a superset of ISO-8859-1) if the content is not UTF-8.
+pub fn detect_encoding(head: &[u8]) -> &'static Encoding {
+    let head = &head[..head.len().min(SNIFF_LEN)];
+    if let Some((encoding, _)) = Encoding::for_bom(head) {
+        return encoding;
+    }
+    match declared_encoding(head) {
+        // quick-xml only reads ASCII compatible encodings
+        Some(encoding) if encoding.is_ascii_compatible() => encoding,
+        _ if looks_like_utf8(head) => UTF_8,
+        _ => WINDOWS_1252,
+    }
+}
+
+/// Decode a whole document to UTF-8. Documents that
+/// already are UTF-8 are borrowed, and invalid UTF-8 is
+/// replaced with U+FFFD.
+pub fn decode(content: &[u8]) -> Cow<'_, str> {
+    let (text, _, _) = detect_encoding(content).decode(content);
+    text
+}
+
+/// Open a file for reading as UTF-8. UTF-8 files are streamed,
+/// files in other encodings are transcoded in memory. Invalid UTF-8
+/// in UTF-8 files is left for the XML parser to report.
+pub(crate) fn open_utf8(path: &Path) -> std::io::Result<Box<dyn BufRead>> {
+    let path = crate::dumps::winpath::extended_length(path);
+    let mut reader = BufReader::with_capacity(SNIFF_LEN, std::fs::File::open(path)?);
+    let encoding = detect_encoding(reader.fill_buf()?);
+    if encoding == UTF_8 {
+        return Ok(Box::new(reader));
+    }
+    let mut content = Vec::new();
+    reader.read_to_end(&mut content)?;
+    let (text, _, _) = encoding.decode(&content);
+    Ok(Box::new(std::io::Cursor::new(text.into_owned().into_bytes())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_encoding() {
+        let latin1 = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><TITRE>Conseil d'\xC9tat</TITRE>";
+        assert_eq!(detect_encoding(latin1), WINDOWS_1252);
+        assert_eq!(decode(latin1), "<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><TITRE>Conseil d'État</TITRE>");
+
+        let utf8 = "<?xml version='1.0' encoding='UTF-8'?><TITRE>Conseil d'État</TITRE>";
+        assert_eq!(detect_encoding(utf8.as_bytes()), UTF_8);
+        assert!(matches!(decode(utf8.as_bytes()), Cow::Borrowed(_)));
+
+        // no declaration: sniffed from the content
+        assert_eq!(detect_encoding(b"<TITRE>Conseil d'\xC9tat</TITRE>"), WINDOWS_1252);
+        assert_eq!(detect_encoding("<TITRE>Conseil d'État</TITRE>".as_bytes()), UTF_8);
+        // a character cut by the end of the sniffed bytes
+        assert_eq!(detect_encoding(&"<TITRE>É".as_bytes()[..8]), UTF_8);
+    }
+}
@@ -0,0 +1,213 @@
+/// Regex based pre-extraction of entities from the text of decisions:
+/// monetary amounts ("1 500 euros"), dates ("21 mars 2024") and case
+/// numbers ("n° 490536", "pourvoi n° 21-12.345"), as typed spans with
+/// a normalized value. This is meant for quantitative research, not
+/// as a replacement for a full NLP pipeline.
+use chrono::NaiveDate;
+use once_cell::sync::OnceCell;
+use regex::{Captures, Regex};
+
+use serde::{Deserialize, Serialize};
+
+fn amount_regex() -> &'static Regex {
+    static INSTANCE: OnceCell<Regex> = OnceCell::new();
+    INSTANCE.get_or_init(|| {
+        // thousands are separated by spaces (possibly non breaking) or dots
+        Regex::new(
+            r"(?i)\b(\d{1,3}(?:[ \u{a0}\u{202f}.]\d{3})+|\d+)(?:,(\d{1,2}))?[ \u{a0}\u{202f}]?(euros?\b|€|eur\b|francs?\b|frs?\b)",
+        )
+        .expect("Unable to construct amount regex")
+    })
+}
+
+const MONTHS: &[(&str, u32)] = &[
+    ("janvier", 1),
+    ("février", 2),
+    ("fevrier", 2),
+    ("mars", 3),
+    ("avril", 4),
+    ("mai", 5),
+    ("juin", 6),
+    ("juillet", 7),
+    ("août", 8),
+    ("aout", 8),
+    ("septembre", 9),
+    ("octobre", 10),
+    ("novembre", 11),
+    ("décembre", 12),
+    ("decembre", 12),
+];
+
+fn date_regex() -> &'static Regex {
+    static INSTANCE: OnceCell<Regex> = OnceCell::new();
+    INSTANCE.get_or_init(|| {
+        let months = MONTHS.iter().map(|(m, _)| *m).collect::<Vec<_>>().join("|");
+        Regex::new(&format!(
+            r"(?i)\b(?:(1er|\d{{1,2}})\s+({})\s+(\d{{4}})|(\d{{1,2}})/(\d{{1,2}})/(\d{{4}}))\b",
+            months
+        ))
+        .expect("Unable to construct date regex")
+    })
+}
+
+fn case_number_regex() -> &'static Regex {
+    static INSTANCE: OnceCell<Regex> = OnceCell::new();
+    INSTANCE.get_or_init(|| {
+        // Cour de cassation ("21-12.345", possibly preceded by the
+        // letter of the chamber), courts of appeal ("21/01234"), and
+        // administrative courts ("490536", "23PA01234")
+        Regex::new(
+            r"(?:\b[Nn]o|[Nn]°)\s*(?:[A-Z]\s+)?(\d{2}-\d{2}\.\d{3}|\d{2}/\d{5}|\d{2}[A-Z]{2}\d{5}|\d{5,7})\b",
+        )
+        .expect("Unable to construct case number regex")
+    })
+}
+
+/// The type of an entity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityKind {
+    Amount,
+    Date,
+    CaseNumber,
+}
+
+/// An entity found in a text. Offsets are counted in characters
+/// (not bytes) from the start of the text, the end being excluded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entity {
+    pub kind: EntityKind,
+    pub start: usize,
+    pub end: usize,
+    /// The entity as written in the text
+    pub text: String,
+    /// Normalized value: "1500.00 EUR" for amounts, YYYY-MM-DD
+    /// for dates, and the bare number for case numbers
+    pub value: String,
+}
+
+/// Normalize an amount, e.g. "1 500,5 euros" becomes "1500.50 EUR"
+fn amount_value(caps: &Captures) -> Option<String> {
+    let units: String = caps[1].chars().filter(char::is_ascii_digit).collect();
+    let cents = caps.get(2).map(|c| format!("{:0<2}", c.as_str()));
+    let currency = match caps[3].to_lowercase().as_str() {
+        "€" | "eur" | "euro" | "euros" => "EUR",
+        _ => "FRF",
+    };
+    let units = match units.trim_start_matches('0') {
+        "" => "0",
+        units => units,
+    };
+    Some(format!("{}.{} {}", units, cents.as_deref().unwrap_or("00"), currency))
+}
+
+/// Normalize a date to YYYY-MM-DD, `None` for impossible dates
+fn date_value(caps: &Captures) -> Option<String> {
+    let (day, month, year) = if let Some(month) = caps.get(2) {
+        let month = month.as_str().to_lowercase();
+        let (_, month) = MONTHS.iter().find(|(m, _)| *m == month)?;
+        let day = if caps[1].eq_ignore_ascii_case("1er") {
+            1
+        } else {
+            caps[1].parse().ok()?
+        };
+        (day, *month, caps[3].parse().ok()?)
+    } else {
+        (caps[4].parse().ok()?, caps[5].parse().ok()?, caps[6].parse().ok()?)
+    };
+    NaiveDate::from_ymd_opt(year, month, day).map(|d| d.format("%Y-%m-%d").to_string())
+}
+
+/// The entities of `text`, ordered by position
+pub fn extract_entities(text: &str) -> Vec<Entity> {
+    type Normalize = fn(&Captures) -> Option<String>;
+    let extractors: [(EntityKind, &Regex, Normalize); 3] = [
+        (EntityKind::Amount, amount_regex(), amount_value),
+        (EntityKind::Date, date_regex(), date_value),
+        (EntityKind::CaseNumber, case_number_regex(), |caps| {
+            Some(caps[1].to_string())
+        }),
+    ];
+    let mut spans = vec![];
+    for (kind, regex, normalize) in extractors {
+        for caps in regex.captures_iter(text) {
+            let m = caps.get(0).expect("the whole match is always captured");
+            if let Some(value) = normalize(&caps) {
+                spans.push((m.start(), m.end(), kind, value));
+            }
+        }
+    }
+    spans.sort_by_key(|(start, end, _, _)| (*start, *end));
+
+    // convert the byte offsets to character offsets
+    let (mut counted, mut chars) = (0, 0);
+    let mut char_offset = |byte: usize| {
+        if byte >= counted {
+            chars += text[counted..byte].chars().count();
+        } else {
+            chars -= text[byte..counted].chars().count();
+        }
+        counted = byte;
+        chars
+    };
+    spans
+        .into_iter()
+        .map(|(start, end, kind, value)| Entity {
+            kind,
+            start: char_offset(start),
+            end: char_offset(end),
+            text: text[start..end].to_string(),
+            value,
+        })
+        .collect()
+}
+
+/// The distinct values of the entities of a kind, separated
+/// by ";" as the other list columns of the CSV exports
+pub fn join_values(entities: &[Entity], kind: EntityKind) -> String {
+    let mut values: Vec<&str> = vec![];
+    for entity in entities.iter().filter(|e| e.kind == kind) {
+        if !values.contains(&entity.value.as_str()) {
+            values.push(&entity.value);
+        }
+    }
+    values.join(";")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_entities() {
+        let text = "Par une décision n° 490536 du 21 mars 2024, l'État est condamné \
+                    à verser 1 500,5 euros et 3000 € ; vu le pourvoi n° A 21-12.345 \
+                    et la loi n° 2024-12 du 1er février 2024 (10/02/2024, 31/02/2024).";
+        let entities = extract_entities(text);
+        let values: Vec<(EntityKind, &str)> =
+            entities.iter().map(|e| (e.kind, e.value.as_str())).collect();
+        assert_eq!(
+            values,
+            vec![
+                (EntityKind::CaseNumber, "490536"),
+                (EntityKind::Date, "2024-03-21"),
+                (EntityKind::Amount, "1500.50 EUR"),
+                (EntityKind::Amount, "3000.00 EUR"),
+                (EntityKind::CaseNumber, "21-12.345"),
+                (EntityKind::Date, "2024-02-01"),
+                (EntityKind::Date, "2024-02-10"),
+            ]
+        );
+
+        // offsets are in characters
+        let chars: Vec<char> = text.chars().collect();
+        for e in entities.iter() {
+            assert_eq!(chars[e.start..e.end].iter().collect::<String>(), e.text);
+        }
+        assert_eq!(entities[2].text, "1 500,5 euros");
+        assert_eq!(
+            join_values(&entities, EntityKind::Date),
+            "2024-03-21;2024-02-01;2024-02-10"
+        );
+    }
+}
@@ -0,0 +1,58 @@
+/// Errors raised while extracting documents from the XML files.
+use std::fmt;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum ExtractError {
+    /// The file does not exist
+    NotFound(PathBuf),
+    /// The path exists but is not a regular file
+    NotAFile(PathBuf),
+    /// The file does not have the `.xml` extension
+    NotXml(PathBuf),
+    /// The file could not be read
+    Io(PathBuf, std::io::Error),
+    /// The document contains invalid UTF-8 at the given byte position
+    InvalidUtf8 { position: u64 },
+    /// The document is not well-formed XML
+    Xml { position: u64, message: String },
+}
+
+impl ExtractError {
+    /// Wrap an error of the XML reader, raised at `position`
+    pub(crate) fn xml(error: quick_xml::Error, position: u64) -> ExtractError {
+        match error {
+            quick_xml::Error::Encoding(_) => ExtractError::InvalidUtf8 { position },
+            e => ExtractError::Xml {
+                position,
+                message: e.to_string(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtractError::NotFound(path) => write!(f, "File does not exist: {}", path.display()),
+            ExtractError::NotAFile(path) => write!(f, "Path is not a file: {}", path.display()),
+            ExtractError::NotXml(path) => write!(f, "File is not an XML file: {}", path.display()),
+            ExtractError::Io(path, e) => write!(f, "Could not read {}: {}", path.display(), e),
+            ExtractError::InvalidUtf8 { position } => {
+                write!(f, "Invalid UTF-8 at position {}", position)
+            }
+            ExtractError::Xml { position, message } => {
+                write!(f, "Invalid XML at position {}: {}", position, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExtractError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExtractError::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
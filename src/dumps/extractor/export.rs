@@ -0,0 +1,761 @@
+/// Export of parsed documents. Every output (CSV files, JSON lines,
+/// the metadata stored in the index) goes through [`ExportRecord`],
+/// the flattened view of a document, so that a field added to one of
+/// the document types reaches all of them.
+///
+/// The CSV and JSON lines files are written under a temporary name
+/// (see [`partial_path`]) and renamed once the export is complete, so
+/// that a crash never leaves a truncated file under the final name.
+/// Every `flush_every` documents, the files are synced to the disk and
+/// the progress of the export is saved next to them (see
+/// [`ExportCheckpoint`]): an export interrupted by a crash continues
+/// after the last saved document when it is run again.
+use anyhow::Context;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::entities::{self, EntityKind};
+use super::identifiers;
+use super::{Document, DocumentKind, ExtractError};
+
+/// Columns holding the content of the documents rather than metadata
+pub const TEXT_COLUMNS: &[&str] = &["text", "contenu"];
+
+/// Flatten a document into a list of (column, value), in the order
+/// of the fields of its type. Nested values such as the list of links
+/// are written as JSON.
+fn flatten<T: Serialize>(document: &T) -> anyhow::Result<Vec<(String, String)>> {
+    let Value::Object(fields) = serde_json::to_value(document)? else {
+        return Err(anyhow::anyhow!("Documents must serialize to a map"));
+    };
+    fields
+        .into_iter()
+        .map(|(column, value)| {
+            let cell = match value {
+                Value::Null => String::new(),
+                Value::String(s) => s,
+                Value::Bool(_) | Value::Number(_) => value.to_string(),
+                Value::Array(_) | Value::Object(_) => serde_json::to_string(&value)?,
+            };
+            Ok((column, cell))
+        })
+        .collect()
+}
+
+/// A document flattened into named columns: the fields of the document,
+/// followed by the identifiers it refers to (`nor_numbers`, `old_ids`
+/// and `referenced_ids`, separated by ";") and by its `permalink` on
+/// legifrance.gouv.fr, when it has one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportRecord {
+    pub kind: DocumentKind,
+    pub columns: Vec<(String, String)>,
+}
+
+impl TryFrom<&Document> for ExportRecord {
+    type Error = anyhow::Error;
+
+    fn try_from(document: &Document) -> anyhow::Result<Self> {
+        let mut columns = match document {
+            Document::Decision(d) => flatten(d)?,
+            Document::Jorf(j) => flatten(j)?,
+            Document::LegiArticle(a) => flatten(a)?,
+            Document::LegiSection(s) => flatten(s)?,
+            Document::Cnil(c) => flatten(c)?,
+        };
+        // flat columns to join documents across fonds
+        let ids = document.identifiers();
+        columns.push(("nor_numbers".to_string(), ids.nor.join(";")));
+        columns.push(("old_ids".to_string(), ids.old_ids.join(";")));
+        columns.push(("referenced_ids".to_string(), ids.referenced_ids.join(";")));
+        let permalink = identifiers::permalink(document.id());
+        columns.push(("permalink".to_string(), permalink.map(String::from).unwrap_or_default()));
+        Ok(ExportRecord {
+            kind: document.document_kind(),
+            columns,
+        })
+    }
+}
+
+impl ExportRecord {
+    /// Add the `amounts`, `dates` and `case_numbers` columns, listing
+    /// the entities found in the text of the document (see
+    /// [`entities::extract_entities`])
+    pub fn add_entities(&mut self, document: &Document) {
+        let found = document.text().map(entities::extract_entities).unwrap_or_default();
+        for (column, kind) in [
+            ("amounts", EntityKind::Amount),
+            ("dates", EntityKind::Date),
+            ("case_numbers", EntityKind::CaseNumber),
+        ] {
+            self.columns
+                .push((column.to_string(), entities::join_values(&found, kind)));
+        }
+    }
+
+    /// Value of a column
+    pub fn get(&self, column: &str) -> Option<&str> {
+        self.columns
+            .iter()
+            .find(|(c, _)| c == column)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The columns that are not the content of the document
+    pub fn metadata(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.columns
+            .iter()
+            .filter(|(c, _)| !TEXT_COLUMNS.contains(&c.as_str()))
+            .map(|(c, v)| (c.as_str(), v.as_str()))
+    }
+
+    /// The record as a JSON object, with a leading `kind` field.
+    /// Empty values are written as `null`.
+    pub fn to_json(&self) -> Map<String, Value> {
+        let mut object = Map::new();
+        object.insert("kind".to_string(), Value::from(self.kind.as_str()));
+        for (column, value) in self.columns.iter() {
+            let value = if value.is_empty() {
+                Value::Null
+            } else {
+                Value::from(value.as_str())
+            };
+            object.insert(column.clone(), value);
+        }
+        object
+    }
+}
+
+/// Where parsed documents, and the files that could not be
+/// parsed, are written.
+pub trait DocumentWriter {
+    fn write(&mut self, document: &Document) -> anyhow::Result<()>;
+
+    /// Record that the file at `path` could not be parsed
+    fn write_error(&mut self, path: &str, error: &ExtractError) -> anyhow::Result<()>;
+
+    fn flush(&mut self) -> anyhow::Result<()>;
+}
+
+/// Documents written between two checkpoints of an export
+pub const DEFAULT_FLUSH_EVERY: usize = 1000;
+
+/// Path under which `path` is written until the export is complete,
+/// e.g. `results.csv.partial`
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".partial");
+    path.with_file_name(name)
+}
+
+/// Name of the partial file of `path`, as recorded in the checkpoints
+fn partial_name(path: &Path) -> String {
+    partial_path(path).file_name().unwrap_or_default().to_string_lossy().to_string()
+}
+
+/// Path of the checkpoint of the export to `path`,
+/// e.g. `results.csv.checkpoint`
+pub fn checkpoint_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".checkpoint");
+    path.with_file_name(name)
+}
+
+/// The progress of an export, saved every `flush_every` documents
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportCheckpoint {
+    /// Number of documents written
+    pub documents: u64,
+    /// Identifier of the last document written
+    pub last_id: String,
+    /// Length of the partial files, by file name, when the
+    /// checkpoint was saved
+    pub lengths: BTreeMap<String, u64>,
+}
+
+impl ExportCheckpoint {
+    /// The checkpoint of the export to `path`, if it was interrupted
+    pub fn load(path: &Path) -> anyhow::Result<Option<ExportCheckpoint>> {
+        let checkpoint = checkpoint_path(path);
+        if !checkpoint.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&checkpoint)
+            .context(format!("Failed to read {}", checkpoint.display()))?;
+        let checkpoint = serde_json::from_str(&content)
+            .context(format!("Invalid checkpoint {}", checkpoint.display()))?;
+        Ok(Some(checkpoint))
+    }
+
+    /// Save the checkpoint of the export to `path`, replacing the
+    /// previous one at once
+    fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let checkpoint = checkpoint_path(path);
+        let tmp = checkpoint.with_extension("checkpoint.tmp");
+        std::fs::write(&tmp, serde_json::to_vec(self)?)
+            .context(format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &checkpoint)
+            .context(format!("Failed to write {}", checkpoint.display()))?;
+        Ok(())
+    }
+}
+
+/// A file written under its partial path, appended to when resuming
+/// an export
+fn create_partial(path: &Path, resumed: Option<&ExportCheckpoint>) -> anyhow::Result<std::fs::File> {
+    let partial = partial_path(path);
+    let file = match resumed {
+        // the lines written after the checkpoint are written again
+        Some(checkpoint) if partial.exists() => {
+            let file = std::fs::OpenOptions::new().append(true).open(&partial)?;
+            file.set_len(checkpoint.lengths.get(&partial_name(path)).copied().unwrap_or(0))?;
+            file
+        }
+        _ => std::fs::File::create(&partial)?,
+    };
+    Ok(file)
+}
+
+/// Sync the partial file `file` of `path` to the disk, returning its
+/// name and length for the checkpoint
+fn sync_partial(path: &Path, file: &std::fs::File) -> anyhow::Result<(String, u64)> {
+    file.sync_data()?;
+    Ok((partial_name(path), file.metadata()?.len()))
+}
+
+/// Rename the complete partial file of `path` to `path`
+fn finish_partial(path: &Path, file: std::fs::File) -> anyhow::Result<()> {
+    file.sync_data()?;
+    drop(file);
+    std::fs::rename(partial_path(path), path).context(format!("Failed to write {}", path.display()))
+}
+
+/// The checkpoints of an export: how many documents were written, and
+/// the documents of an interrupted run that are skipped when resuming
+#[derive(Debug)]
+struct ExportProgress {
+    /// The main file of the export
+    path: PathBuf,
+    flush_every: usize,
+    written: ExportCheckpoint,
+    /// The checkpoint of the interrupted run, until its documents are
+    /// skipped
+    resumed: Option<ExportCheckpoint>,
+    skipped: u64,
+    /// Whether the export continues an interrupted one
+    resuming: bool,
+}
+
+impl ExportProgress {
+    /// The progress of the export to `path`, continuing an interrupted
+    /// export with `resume`
+    fn new(path: &Path, resume: bool) -> anyhow::Result<Self> {
+        let resumed = if resume {
+            ExportCheckpoint::load(path)?
+                .filter(|checkpoint| checkpoint.documents > 0 && partial_path(path).exists())
+        } else {
+            None
+        };
+        match resumed.as_ref() {
+            Some(checkpoint) => info!(
+                "Resuming the export to {} after {} documents",
+                path.display(),
+                checkpoint.documents
+            ),
+            None => {
+                let _ = std::fs::remove_file(checkpoint_path(path));
+            }
+        }
+        Ok(ExportProgress {
+            path: path.to_path_buf(),
+            flush_every: DEFAULT_FLUSH_EVERY,
+            written: ExportCheckpoint::default(),
+            resuming: resumed.is_some(),
+            resumed,
+            skipped: 0,
+        })
+    }
+
+    /// The last checkpoint, giving the lengths of the partial files
+    /// kept when they are opened, if the export continues an
+    /// interrupted one
+    fn checkpoint(&self) -> Option<&ExportCheckpoint> {
+        match self.resumed.as_ref() {
+            Some(resumed) => Some(resumed),
+            None => self.resuming.then_some(&self.written),
+        }
+    }
+
+    /// Whether the documents are still the ones written by the
+    /// interrupted run
+    fn skipping(&self) -> bool {
+        self.resumed.is_some()
+    }
+
+    /// Whether `document` was written by the interrupted run. The
+    /// documents must come in the same order as in that run.
+    fn skip(&mut self, document: &Document) -> anyhow::Result<bool> {
+        let Some(checkpoint) = self.resumed.as_ref() else {
+            return Ok(false);
+        };
+        self.skipped += 1;
+        if self.skipped == checkpoint.documents {
+            if document.id() != checkpoint.last_id {
+                return Err(anyhow::anyhow!(
+                    "The export to {} cannot be resumed, its documents changed: remove {}",
+                    self.path.display(),
+                    checkpoint_path(&self.path).display()
+                ));
+            }
+            self.written = self.resumed.take().expect("resumed checkpoint");
+        }
+        Ok(true)
+    }
+
+    /// Record that `document` was written, returning whether a
+    /// checkpoint is due
+    fn written(&mut self, document: &Document) -> bool {
+        self.written.documents += 1;
+        self.written.last_id = document.id().to_string();
+        self.flush_every > 0 && self.written.documents.is_multiple_of(self.flush_every as u64)
+    }
+
+    /// Save a checkpoint, with the lengths of the synced partial files
+    /// (the files not opened since the previous one keep their length)
+    fn save(&mut self, lengths: BTreeMap<String, u64>) -> anyhow::Result<()> {
+        self.written.lengths.extend(lengths);
+        self.written.save(&self.path)
+    }
+
+    /// Remove the checkpoint of the complete export
+    fn finish(&self) -> anyhow::Result<()> {
+        if let Some(checkpoint) = self.resumed.as_ref() {
+            warn!(
+                "The export to {} had {} documents before being interrupted, and only {} now",
+                self.path.display(),
+                checkpoint.documents,
+                self.skipped
+            );
+        }
+        match std::fs::remove_file(checkpoint_path(&self.path)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Path of a sibling of `path` suffixed by `kind`,
+/// e.g. `results.jorf.csv` for `results.csv`
+pub(crate) fn sibling_path(path: &Path, kind: &str, extension: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}.{}.{}", stem, kind, extension))
+}
+
+/// A CSV file together with whether its header was already written
+struct CsvFile {
+    path: PathBuf,
+    writer: csv::Writer<std::fs::File>,
+    has_header: bool,
+}
+
+impl CsvFile {
+    fn create(path: &Path, resumed: Option<&ExportCheckpoint>) -> anyhow::Result<Self> {
+        let file = create_partial(path, resumed)?;
+        let has_header = file.metadata()?.len() > 0;
+        Ok(CsvFile {
+            path: path.to_path_buf(),
+            writer: csv::WriterBuilder::new().has_headers(false).from_writer(file),
+            has_header,
+        })
+    }
+
+    fn sync(&mut self) -> anyhow::Result<(String, u64)> {
+        self.writer.flush()?;
+        sync_partial(&self.path, self.writer.get_ref())
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        let file = self.writer.into_inner().map_err(|e| e.into_error())?;
+        finish_partial(&self.path, file)
+    }
+
+    fn write_record(&mut self, record: &[(String, String)]) -> anyhow::Result<()> {
+        if !self.has_header {
+            self.writer.write_record(record.iter().map(|(c, _)| c))?;
+            self.has_header = true;
+        }
+        self.writer.write_record(record.iter().map(|(_, v)| v))?;
+        Ok(())
+    }
+}
+
+/// Writes documents to CSV files, one file per kind of document.
+/// Decisions are written to the given path, other kinds of documents
+/// to a sibling file suffixed by their kind (e.g. `results.jorf.csv`).
+/// The decisions file is always created, the other ones only when
+/// a document of their kind is written. Files that could not be parsed
+/// are listed in the `errors` file. The files get their names when the
+/// writer is flushed, at the end of the export.
+pub struct DocumentCsvWriter {
+    path: PathBuf,
+    writers: BTreeMap<&'static str, CsvFile>,
+    entities: bool,
+    progress: ExportProgress,
+}
+
+impl DocumentCsvWriter {
+    pub fn new<T: AsRef<Path>>(path: T) -> anyhow::Result<Self> {
+        Self::open(path.as_ref(), false)
+    }
+
+    /// Continue the export to `path` if it was interrupted, skipping
+    /// the documents written before its last checkpoint, which must
+    /// come first and in the same order
+    pub fn resume<T: AsRef<Path>>(path: T) -> anyhow::Result<Self> {
+        Self::open(path.as_ref(), true)
+    }
+
+    fn open(path: &Path, resume: bool) -> anyhow::Result<Self> {
+        let progress = ExportProgress::new(path, resume)?;
+        let mut writers = BTreeMap::new();
+        writers.insert("decision", CsvFile::create(path, progress.checkpoint())?);
+        let mut writer = DocumentCsvWriter {
+            path: path.to_path_buf(),
+            writers,
+            entities: false,
+            progress,
+        };
+        // the files of the interrupted export are completed too, even
+        // if no document of their kind comes after the checkpoint
+        if let Some(checkpoint) = writer.progress.checkpoint() {
+            let kinds = [
+                DocumentKind::Decision,
+                DocumentKind::Jorf,
+                DocumentKind::LegiArticle,
+                DocumentKind::LegiSection,
+                DocumentKind::Cnil,
+            ];
+            let resumed: Vec<&'static str> = kinds
+                .iter()
+                .map(DocumentKind::as_str)
+                .chain(["errors"])
+                .filter(|kind| checkpoint.lengths.contains_key(&partial_name(&writer.path_for(kind))))
+                .collect();
+            for kind in resumed {
+                writer.file(kind)?;
+            }
+        }
+        Ok(writer)
+    }
+
+    /// Sync the files and save a checkpoint every `documents` documents,
+    /// 0 to only write the files at the end
+    pub fn with_flush_every(mut self, documents: usize) -> Self {
+        self.progress.flush_every = documents;
+        self
+    }
+
+    /// Also write the entities found in the text of the
+    /// documents (see [`ExportRecord::add_entities`])
+    pub fn with_entities(mut self, entities: bool) -> Self {
+        self.entities = entities;
+        self
+    }
+
+    /// Path of the CSV file where documents of the given kind are written
+    pub fn path_for(&self, kind: &str) -> PathBuf {
+        if kind == "decision" {
+            return self.path.clone();
+        }
+        sibling_path(&self.path, kind, "csv")
+    }
+
+    fn file(&mut self, kind: &'static str) -> anyhow::Result<&mut CsvFile> {
+        if !self.writers.contains_key(kind) {
+            let file = CsvFile::create(&self.path_for(kind), self.progress.checkpoint())?;
+            self.writers.insert(kind, file);
+        }
+        Ok(self.writers.get_mut(kind).expect("writer was just inserted"))
+    }
+}
+
+impl DocumentWriter for DocumentCsvWriter {
+    fn write(&mut self, document: &Document) -> anyhow::Result<()> {
+        if self.progress.skip(document)? {
+            return Ok(());
+        }
+        let mut record = ExportRecord::try_from(document)?;
+        if self.entities {
+            record.add_entities(document);
+        }
+        self.file(record.kind.as_str())?.write_record(&record.columns)?;
+        if self.progress.written(document) {
+            let lengths = self.writers.values_mut().map(CsvFile::sync).collect::<anyhow::Result<_>>()?;
+            self.progress.save(lengths)?;
+        }
+        Ok(())
+    }
+
+    fn write_error(&mut self, path: &str, error: &ExtractError) -> anyhow::Result<()> {
+        if self.progress.skipping() {
+            return Ok(());
+        }
+        self.file("errors")?.write_record(&[
+            ("path".to_string(), path.to_string()),
+            ("error".to_string(), error.to_string()),
+        ])
+    }
+
+    /// Complete the export: the files get their names
+    fn flush(&mut self) -> anyhow::Result<()> {
+        for (_, file) in std::mem::take(&mut self.writers) {
+            file.finish()?;
+        }
+        self.progress.finish()
+    }
+}
+
+/// Writes documents as JSON lines, all kinds of documents in the same
+/// file (see [`ExportRecord::to_json`]). Files that could not be parsed
+/// are listed in a sibling `errors` file (e.g. `results.errors.jsonl`).
+/// The files get their names when the writer is flushed, at the end of
+/// the export.
+pub struct DocumentJsonlWriter {
+    path: PathBuf,
+    writer: Option<std::io::BufWriter<std::fs::File>>,
+    errors: Option<std::io::BufWriter<std::fs::File>>,
+    entities: bool,
+    progress: ExportProgress,
+}
+
+impl DocumentJsonlWriter {
+    pub fn new<T: AsRef<Path>>(path: T) -> anyhow::Result<Self> {
+        Self::open(path.as_ref(), false)
+    }
+
+    /// Continue the export to `path` if it was interrupted (see
+    /// [`DocumentCsvWriter::resume`])
+    pub fn resume<T: AsRef<Path>>(path: T) -> anyhow::Result<Self> {
+        Self::open(path.as_ref(), true)
+    }
+
+    fn open(path: &Path, resume: bool) -> anyhow::Result<Self> {
+        let progress = ExportProgress::new(path, resume)?;
+        let writer = std::io::BufWriter::new(create_partial(path, progress.checkpoint())?);
+        let mut writer = DocumentJsonlWriter {
+            path: path.to_path_buf(),
+            writer: Some(writer),
+            errors: None,
+            entities: false,
+            progress,
+        };
+        // the errors of the interrupted export are completed too
+        let errors_path = writer.errors_path();
+        if let Some(checkpoint) = writer.progress.checkpoint()
+            && checkpoint.lengths.contains_key(&partial_name(&errors_path))
+        {
+            let file = create_partial(&errors_path, Some(checkpoint))?;
+            writer.errors = Some(std::io::BufWriter::new(file));
+        }
+        Ok(writer)
+    }
+
+    /// Sync the files and save a checkpoint every `documents` documents,
+    /// 0 to only write the files at the end
+    pub fn with_flush_every(mut self, documents: usize) -> Self {
+        self.progress.flush_every = documents;
+        self
+    }
+
+    fn errors_path(&self) -> PathBuf {
+        sibling_path(&self.path, "errors", "jsonl")
+    }
+
+    fn files(&mut self) -> Vec<(PathBuf, &mut std::io::BufWriter<std::fs::File>)> {
+        let errors_path = self.errors_path();
+        let mut files = vec![];
+        if let Some(writer) = self.writer.as_mut() {
+            files.push((self.path.clone(), writer));
+        }
+        if let Some(errors) = self.errors.as_mut() {
+            files.push((errors_path, errors));
+        }
+        files
+    }
+
+    /// Also write the entities found in the text of the
+    /// documents (see [`ExportRecord::add_entities`])
+    pub fn with_entities(mut self, entities: bool) -> Self {
+        self.entities = entities;
+        self
+    }
+}
+
+/// Write `value` followed by a newline
+pub(crate) fn write_line(writer: &mut impl Write, value: &impl Serialize) -> anyhow::Result<()> {
+    serde_json::to_writer(&mut *writer, value)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+impl DocumentWriter for DocumentJsonlWriter {
+    fn write(&mut self, document: &Document) -> anyhow::Result<()> {
+        if self.progress.skip(document)? {
+            return Ok(());
+        }
+        let mut record = ExportRecord::try_from(document)?;
+        if self.entities {
+            record.add_entities(document);
+        }
+        let writer = self.writer.as_mut().ok_or_else(|| anyhow::anyhow!("The export is complete"))?;
+        write_line(writer, &record.to_json())?;
+        if self.progress.written(document) {
+            let mut lengths = BTreeMap::new();
+            for (path, file) in self.files() {
+                file.flush()?;
+                let (name, length) = sync_partial(&path, file.get_ref())?;
+                lengths.insert(name, length);
+            }
+            self.progress.save(lengths)?;
+        }
+        Ok(())
+    }
+
+    fn write_error(&mut self, path: &str, error: &ExtractError) -> anyhow::Result<()> {
+        if self.progress.skipping() {
+            return Ok(());
+        }
+        if self.errors.is_none() {
+            let file = create_partial(&self.errors_path(), self.progress.checkpoint())?;
+            self.errors = Some(std::io::BufWriter::new(file));
+        }
+        let errors = self.errors.as_mut().expect("writer was just created");
+        write_line(
+            errors,
+            &serde_json::json!({ "path": path, "error": error.to_string() }),
+        )
+    }
+
+    /// Complete the export: the files get their names
+    fn flush(&mut self) -> anyhow::Result<()> {
+        let errors_path = self.errors_path();
+        for (path, writer) in [(self.path.clone(), self.writer.take()), (errors_path, self.errors.take())] {
+            if let Some(writer) = writer {
+                finish_partial(&path, writer.into_inner().map_err(|e| e.into_error())?)?;
+            }
+        }
+        self.progress.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dumps::extractor::legi::LegiSection;
+
+    #[test]
+    fn test_writers_agree() {
+        let document = Document::LegiSection(LegiSection {
+            id: "LEGISCTA000006147745".to_string(),
+            title: Some("Livre Ier".to_string()),
+            articles: "LEGIARTI000006335064".to_string(),
+            ..Default::default()
+        });
+        let record = ExportRecord::try_from(&document).unwrap();
+        assert_eq!(record.get("title"), Some("Livre Ier"));
+        assert_eq!(record.get("referenced_ids"), Some("LEGIARTI000006335064"));
+        // sections have no page of their own
+        assert_eq!(record.get("permalink"), Some(""));
+
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let csv_path = tmp.path().join("results.csv");
+        let jsonl_path = tmp.path().join("results.jsonl");
+        let mut csv = DocumentCsvWriter::new(&csv_path).unwrap();
+        let mut jsonl = DocumentJsonlWriter::new(&jsonl_path).unwrap();
+        for writer in [&mut csv as &mut dyn DocumentWriter, &mut jsonl] {
+            writer.write(&document).unwrap();
+            writer.write_error("missing.xml", &ExtractError::NotFound("missing.xml".into())).unwrap();
+            writer.flush().unwrap();
+        }
+
+        // the same columns, in the same order
+        let mut reader = csv::Reader::from_path(tmp.path().join("results.section.csv")).unwrap();
+        let header: Vec<String> = reader.headers().unwrap().iter().map(String::from).collect();
+        let line = std::fs::read_to_string(&jsonl_path).unwrap();
+        let json: Map<String, Value> = serde_json::from_str(line.trim()).unwrap();
+        assert_eq!(json["kind"], "section");
+        assert_eq!(json.keys().skip(1).cloned().collect::<Vec<_>>(), header);
+        assert!(json["parent_text"].is_null());
+
+        assert!(tmp.path().join("results.errors.csv").exists());
+        assert!(tmp.path().join("results.errors.jsonl").exists());
+    }
+
+    #[test]
+    fn test_resume_export() {
+        let section = |i: usize| {
+            Document::LegiSection(LegiSection {
+                id: format!("LEGISCTA00000614774{}", i),
+                title: Some(format!("Livre {}", i)),
+                ..Default::default()
+            })
+        };
+        let documents: Vec<Document> = (0..5).map(section).collect();
+        let error = ExtractError::NotFound("missing.xml".into());
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let csv_path = tmp.path().join("results.csv");
+        let jsonl_path = tmp.path().join("results.jsonl");
+
+        // a crash after the third document: the checkpoint is after the
+        // second one, the partial files have more
+        let csv = DocumentCsvWriter::resume(&csv_path).unwrap().with_flush_every(2);
+        let jsonl = DocumentJsonlWriter::resume(&jsonl_path).unwrap().with_flush_every(2);
+        for mut writer in [Box::new(csv) as Box<dyn DocumentWriter>, Box::new(jsonl)] {
+            writer.write(&documents[0]).unwrap();
+            writer.write_error("missing.xml", &error).unwrap();
+            for document in &documents[1..3] {
+                writer.write(document).unwrap();
+            }
+        }
+        assert!(!jsonl_path.exists());
+        let checkpoint = ExportCheckpoint::load(&jsonl_path).unwrap().unwrap();
+        assert_eq!(checkpoint.documents, 2);
+        assert_eq!(checkpoint.last_id, "LEGISCTA000006147741");
+        assert_eq!(checkpoint.lengths.len(), 2);
+
+        let csv = DocumentCsvWriter::resume(&csv_path).unwrap();
+        let jsonl = DocumentJsonlWriter::resume(&jsonl_path).unwrap();
+        for mut writer in [Box::new(csv) as Box<dyn DocumentWriter>, Box::new(jsonl)] {
+            writer.write(&documents[0]).unwrap();
+            writer.write_error("missing.xml", &error).unwrap();
+            for document in &documents[1..] {
+                writer.write(document).unwrap();
+            }
+            writer.flush().unwrap();
+        }
+        let lines: Vec<String> = std::fs::read_to_string(&jsonl_path).unwrap().lines().map(String::from).collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[2].contains("LEGISCTA000006147742"));
+        let errors = std::fs::read_to_string(tmp.path().join("results.errors.jsonl")).unwrap();
+        assert_eq!(errors.lines().count(), 1);
+        let mut reader = csv::Reader::from_path(tmp.path().join("results.section.csv")).unwrap();
+        assert_eq!(reader.records().count(), 5);
+        assert!(!partial_path(&jsonl_path).exists());
+        assert!(!checkpoint_path(&csv_path).exists());
+
+        // other documents than the ones of the interrupted export
+        let mut jsonl = DocumentJsonlWriter::resume(&jsonl_path).unwrap().with_flush_every(1);
+        jsonl.write(&documents[0]).unwrap();
+        drop(jsonl);
+        let mut jsonl = DocumentJsonlWriter::resume(&jsonl_path).unwrap();
+        assert!(jsonl.write(&documents[1]).is_err());
+    }
+}
@@ -0,0 +1,273 @@
+/// Export of documents as a dataset of the HuggingFace Hub: gzipped
+/// JSON lines shards in `data/` (`train-00000.jsonl.gz`, ...), with a
+/// `dataset_infos.json` describing their features, so that the
+/// directory can be pushed to the Hub as is and loaded with
+/// `datasets.load_dataset`. Every line has the same schema:
+///
+/// ```json
+/// {"id":"CETATEXT000049314894","text":"...","metadata":{"kind":"decision","date":"2024-03-21",
+///  "jurisdiction":"Conseil d'État","title":"...","referenced_ids":["LEGIARTI000006335064"],
+///  "permalink":"https://www.legifrance.gouv.fr/ceta/id/CETATEXT000049314894"}}
+/// ```
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde::Serialize;
+use serde_json::json;
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::export::write_line;
+use super::identifiers::permalink;
+use super::markdown::{jurisdiction, title};
+use super::{Document, DocumentWriter, ExtractError};
+
+/// Default number of documents per shard
+pub const HF_SHARD_SIZE: usize = 100_000;
+
+/// Name of the description of the features and splits of the dataset
+pub const DATASET_INFOS_FILE: &str = "dataset_infos.json";
+
+/// The metadata of a document, with the same keys for all the
+/// kinds of documents (missing values are `null`)
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct HfMetadata {
+    kind: &'static str,
+    date: Option<String>,
+    jurisdiction: Option<String>,
+    title: Option<String>,
+    referenced_ids: Vec<String>,
+    permalink: Option<String>,
+}
+
+impl From<&Document> for HfMetadata {
+    fn from(document: &Document) -> Self {
+        HfMetadata {
+            kind: document.kind(),
+            date: document.date().map(|d| d.to_string()),
+            jurisdiction: jurisdiction(document),
+            title: title(document),
+            referenced_ids: document.identifiers().referenced_ids,
+            permalink: permalink(document.id()).map(String::from),
+        }
+    }
+}
+
+/// A line of the dataset
+#[derive(Debug, Clone, Serialize)]
+struct HfRecord<'a> {
+    id: &'a str,
+    text: &'a str,
+    metadata: HfMetadata,
+}
+
+impl<'a> From<&'a Document> for HfRecord<'a> {
+    fn from(document: &'a Document) -> Self {
+        HfRecord {
+            id: document.id(),
+            text: document.text().unwrap_or_default(),
+            metadata: HfMetadata::from(document),
+        }
+    }
+}
+
+/// The `datasets` features of [`HfRecord`]
+fn features() -> serde_json::Value {
+    let string = json!({ "dtype": "string", "_type": "Value" });
+    json!({
+        "id": string,
+        "text": string,
+        "metadata": {
+            "kind": string,
+            "date": string,
+            "jurisdiction": string,
+            "title": string,
+            "referenced_ids": { "feature": string, "_type": "Sequence" },
+            "permalink": string,
+        },
+    })
+}
+
+/// The shard being written
+struct Shard {
+    writer: GzEncoder<std::io::BufWriter<std::fs::File>>,
+    documents: usize,
+}
+
+/// Writes the documents to the shards of a dataset in a directory,
+/// [`HF_SHARD_SIZE`] documents per shard by default. Files that could
+/// not be parsed are listed in `errors.jsonl`, next to `data/`.
+pub struct DocumentHfWriter {
+    dir: PathBuf,
+    shard_size: usize,
+    shard: Option<Shard>,
+    shards: usize,
+    documents: usize,
+    /// Size of the JSON lines, before compression
+    bytes: u64,
+    errors: Option<std::io::BufWriter<std::fs::File>>,
+}
+
+impl DocumentHfWriter {
+    pub fn new<T: AsRef<Path>>(dir: T) -> anyhow::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(dir.join("data"))?;
+        Ok(DocumentHfWriter {
+            dir,
+            shard_size: HF_SHARD_SIZE,
+            shard: None,
+            shards: 0,
+            documents: 0,
+            bytes: 0,
+            errors: None,
+        })
+    }
+
+    /// Number of documents per shard
+    pub fn with_shard_size(mut self, shard_size: usize) -> Self {
+        self.shard_size = shard_size.max(1);
+        self
+    }
+
+    /// Path of the shard number `shard`
+    pub fn shard_path(&self, shard: usize) -> PathBuf {
+        self.dir.join("data").join(format!("train-{:05}.jsonl.gz", shard))
+    }
+
+    fn finish_shard(&mut self) -> anyhow::Result<()> {
+        if let Some(shard) = self.shard.take() {
+            shard.writer.finish()?.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Size of the shards written, compressed
+    fn download_size(&self) -> anyhow::Result<u64> {
+        let mut size = 0;
+        for shard in 0..self.shards {
+            size += std::fs::metadata(self.shard_path(shard))?.len();
+        }
+        Ok(size)
+    }
+
+    fn write_infos(&self) -> anyhow::Result<()> {
+        let download_size = self.download_size()?;
+        let infos = json!({
+            "default": {
+                "description": "Documents of the DILA open data (Légifrance)",
+                "homepage": "https://echanges.dila.gouv.fr/OPENDATA/",
+                "license": "Licence Ouverte / Open Licence 2.0",
+                "features": features(),
+                "config_name": "default",
+                "splits": {
+                    "train": {
+                        "name": "train",
+                        "num_bytes": self.bytes,
+                        "num_examples": self.documents,
+                    },
+                },
+                "download_size": download_size,
+                "dataset_size": self.bytes,
+                "size_in_bytes": download_size + self.bytes,
+            }
+        });
+        let file = std::fs::File::create(self.dir.join(DATASET_INFOS_FILE))?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), &infos)?;
+        Ok(())
+    }
+}
+
+impl DocumentWriter for DocumentHfWriter {
+    fn write(&mut self, document: &Document) -> anyhow::Result<()> {
+        if self.shard.as_ref().is_some_and(|s| s.documents >= self.shard_size) {
+            self.finish_shard()?;
+        }
+        if self.shard.is_none() {
+            let file = std::fs::File::create(self.shard_path(self.shards))?;
+            self.shards += 1;
+            self.shard = Some(Shard {
+                writer: GzEncoder::new(std::io::BufWriter::new(file), Compression::default()),
+                documents: 0,
+            });
+        }
+        let mut line = serde_json::to_vec(&HfRecord::from(document))?;
+        line.push(b'\n');
+        let shard = self.shard.as_mut().expect("shard was just created");
+        shard.writer.write_all(&line)?;
+        shard.documents += 1;
+        self.documents += 1;
+        self.bytes += line.len() as u64;
+        Ok(())
+    }
+
+    fn write_error(&mut self, path: &str, error: &ExtractError) -> anyhow::Result<()> {
+        if self.errors.is_none() {
+            let file = std::fs::File::create(self.dir.join("errors.jsonl"))?;
+            self.errors = Some(std::io::BufWriter::new(file));
+        }
+        let errors = self.errors.as_mut().expect("writer was just created");
+        write_line(errors, &json!({ "path": path, "error": error.to_string() }))
+    }
+
+    /// Close the current shard (the next documents go to a new one)
+    /// and describe the dataset written so far
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.finish_shard()?;
+        self.write_infos()?;
+        if let Some(errors) = self.errors.as_mut() {
+            errors.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dumps::extractor::PreDilaText;
+    use crate::dumps::extractor::legi::LegiSection;
+    use std::io::Read;
+
+    #[test]
+    fn test_hf_writer() {
+        let decision = Document::Decision(PreDilaText {
+            id: "CETATEXT000049314894".to_string(),
+            decision_date: Some("2024-03-21".to_string()),
+            text: "Vu le code LEGIARTI000006335064.".to_string(),
+            ..Default::default()
+        });
+        let section = Document::LegiSection(LegiSection {
+            id: "LEGISCTA000006147745".to_string(),
+            ..Default::default()
+        });
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let mut writer = DocumentHfWriter::new(tmp.path()).unwrap().with_shard_size(2);
+        for document in [&decision, &section, &decision] {
+            writer.write(document).unwrap();
+        }
+        writer.flush().unwrap();
+
+        let read = |shard: usize| {
+            let mut content = String::new();
+            let file = std::fs::File::open(writer.shard_path(shard)).unwrap();
+            flate2::read::GzDecoder::new(file).read_to_string(&mut content).unwrap();
+            content
+        };
+        assert_eq!(read(0).lines().count(), 2);
+        assert_eq!(read(1).lines().count(), 1);
+        assert!(!writer.shard_path(2).exists());
+        // the same keys for all the kinds of documents
+        let lines: Vec<serde_json::Value> =
+            read(0).lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        let keys = |v: &serde_json::Value| v["metadata"].as_object().unwrap().keys().cloned().collect::<Vec<_>>();
+        assert_eq!(keys(&lines[0]), keys(&lines[1]));
+        assert_eq!(lines[0]["metadata"]["referenced_ids"][0], "LEGIARTI000006335064");
+        assert_eq!(lines[1]["text"], "");
+
+        let infos: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(tmp.path().join(DATASET_INFOS_FILE)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(infos["default"]["splits"]["train"]["num_examples"], 3);
+    }
+}
@@ -0,0 +1,143 @@
+/// Identifiers that allow to join documents across fonds: NOR
+/// numbers of the texts published in the Journal Officiel, and DILA
+/// identifiers such as JORFTEXT, LEGITEXT or LEGIARTI ids.
+use once_cell::sync::OnceCell;
+use regex::Regex;
+use reqwest::Url;
+
+use serde::{Deserialize, Serialize};
+
+fn nor_regex() -> &'static Regex {
+    static INSTANCE: OnceCell<Regex> = OnceCell::new();
+    INSTANCE.get_or_init(|| {
+        // three letters for the ministry, one for the service, two
+        // digits for the year, five for the order number, one letter
+        // for the type of text
+        Regex::new(r"\b[A-Z]{4}\d{7}[A-Z]\b").expect("Unable to construct NOR regex")
+    })
+}
+
+fn dila_id_regex() -> &'static Regex {
+    static INSTANCE: OnceCell<Regex> = OnceCell::new();
+    INSTANCE.get_or_init(|| {
+        Regex::new(r"\b[A-Z]{4}(?:TEXT|ARTI|SCTA|CONT)\d{12}\b")
+            .expect("Unable to construct DILA identifier regex")
+    })
+}
+
+/// NOR numbers (e.g. "ECOX2400123D") found in `s`
+pub fn nor_numbers(s: &str) -> Vec<String> {
+    nor_regex().find_iter(s).map(|m| m.as_str().to_string()).collect()
+}
+
+/// DILA identifiers (e.g. "JORFTEXT000049314000", "LEGIARTI000006335064")
+/// found in `s`
+pub fn dila_ids(s: &str) -> Vec<String> {
+    dila_id_regex().find_iter(s).map(|m| m.as_str().to_string()).collect()
+}
+
+/// Base URL of the public website of the documents
+pub const LEGIFRANCE_URL: &str = "https://www.legifrance.gouv.fr";
+
+/// The page of the document `id` on legifrance.gouv.fr, for the kinds of
+/// identifiers whose page only depends on the identifier (e.g.
+/// `https://www.legifrance.gouv.fr/ceta/id/CETATEXT000049314894`).
+/// Sections of codes, whose page also depends on their code, have none.
+pub fn permalink(id: &str) -> Option<Url> {
+    let id = id.trim();
+    if dila_id_regex().find(id).is_none_or(|m| m.as_str() != id) {
+        return None;
+    }
+    let path = match &id[..8] {
+        "CETATEXT" => "ceta/id",
+        "JURITEXT" => "juri/id",
+        "CONSTEXT" => "cons/id",
+        "JORFTEXT" => "jorf/id",
+        "JORFARTI" => "jorf/article_jo",
+        "LEGITEXT" => "loda/id",
+        "LEGIARTI" => "codes/article_lc",
+        "CNILTEXT" => "cnil/id",
+        "KALITEXT" => "conv_coll/id",
+        _ => return None,
+    };
+    Url::parse(&format!("{}/{}/{}", LEGIFRANCE_URL, path, id)).ok()
+}
+
+/// Identifiers of a document and of the documents it refers to.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Identifiers {
+    /// NOR numbers of the document and of the texts it cites
+    pub nor: Vec<String>,
+    /// Identifiers of the document before the 2015 reform
+    pub old_ids: Vec<String>,
+    /// DILA identifiers of the texts and articles the document refers to
+    pub referenced_ids: Vec<String>,
+}
+
+impl Identifiers {
+    fn push(list: &mut Vec<String>, value: String) {
+        if !value.is_empty() && !list.contains(&value) {
+            list.push(value);
+        }
+    }
+
+    pub fn add_nor(&mut self, nor: impl Into<String>) {
+        Self::push(&mut self.nor, nor.into());
+    }
+
+    pub fn add_old_id(&mut self, id: impl Into<String>) {
+        Self::push(&mut self.old_ids, id.into());
+    }
+
+    pub fn add_reference(&mut self, id: impl Into<String>) {
+        Self::push(&mut self.referenced_ids, id.into());
+    }
+
+    /// Record the NOR numbers and DILA identifiers mentioned in `text`,
+    /// except `own_id`
+    pub fn scan(&mut self, text: &str, own_id: &str) {
+        for nor in nor_numbers(text) {
+            self.add_nor(nor);
+        }
+        for id in dila_ids(text) {
+            if id != own_id {
+                self.add_reference(id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identifiers() {
+        let text = "Vu le décret n° 2024-223 (NOR : ECOX2400123D), publié sous \
+                    JORFTEXT000049314000, et l'article LEGIARTI000006335064 ; \
+                    ECOX2400123D";
+        assert_eq!(nor_numbers(text), vec!["ECOX2400123D", "ECOX2400123D"]);
+
+        let mut ids = Identifiers::default();
+        ids.scan(text, "JORFTEXT000049314000");
+        assert_eq!(ids.nor, vec!["ECOX2400123D"]);
+        assert_eq!(ids.referenced_ids, vec!["LEGIARTI000006335064"]);
+    }
+
+    #[test]
+    fn test_permalink() {
+        let url = |id| permalink(id).map(|u| u.to_string());
+        assert_eq!(
+            url("CETATEXT000049314894").as_deref(),
+            Some("https://www.legifrance.gouv.fr/ceta/id/CETATEXT000049314894")
+        );
+        assert_eq!(
+            url("LEGIARTI000006335064").as_deref(),
+            Some("https://www.legifrance.gouv.fr/codes/article_lc/LEGIARTI000006335064")
+        );
+        assert!(url("JURITEXT000007053021").unwrap().ends_with("/juri/id/JURITEXT000007053021"));
+        assert_eq!(url("LEGISCTA000006147745"), None);
+        assert_eq!(url("CETATEXT0000493"), None);
+        assert_eq!(url("../CETATEXT000049314894"), None);
+    }
+}
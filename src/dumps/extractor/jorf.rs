@@ -0,0 +1,215 @@
+/// Metadata of the texts published in the Journal Officiel
+/// (TEXTE_JORF and TEXTE_VERSION documents of the JORF dumps).
+use quick_xml::events::Event;
+use quick_xml::reader::Reader;
+
+use std::io::BufRead;
+
+use serde::{Deserialize, Serialize};
+
+use super::{ExtractError, Link, LinkCollector, TagStack};
+
+/// Metadata of a text published in the Journal Officiel.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JorfMetaData {
+    /// The unique identifier of the text (e.g. "JORFTEXT000049314894")
+    pub id: String,
+    /// The identifier of the text before the 2015 reform
+    pub old_id: Option<String>,
+    /// The identifier shared by all versions of the text
+    pub cid: Option<String>,
+    /// Dataset origin of the text (e.g. "JORF")
+    pub origin: String,
+    /// The relative path to the XML file in the DILA archive
+    pub url: String,
+    /// The type of text (e.g. "DECRET", "ARRETE", "LOI")
+    pub nature: String,
+    /// Number of the text (e.g. "2024-123")
+    pub num: Option<String>,
+    /// NOR identifier, of the form "ECOX2400123D"
+    pub nor: Option<String>,
+    /// Title of the text
+    pub title: Option<String>,
+    /// Ministry that issued the text
+    pub ministry: Option<String>,
+    /// Authority that issued the text, when it is not a ministry
+    pub authority: Option<String>,
+    /// Signatories of the text, separated by "; "
+    pub signatories: Option<String>,
+    /// Date of the text in the format YYYY-MM-DD
+    pub text_date: Option<String>,
+    /// Date of publication in the Journal Officiel in the format YYYY-MM-DD
+    pub publication_date: Option<String>,
+    /// Number of the issue of the Journal Officiel
+    pub publication_number: Option<String>,
+    /// First page of the text in the Journal Officiel
+    pub page_start: Option<String>,
+    /// Last page of the text in the Journal Officiel
+    pub page_end: Option<String>,
+    /// Texts cited, modified or abrogated by the text
+    #[serde(default)]
+    pub links: Vec<Link>,
+}
+
+/// Store `text` in the field of `meta` corresponding to `tag`.
+/// Tags that appear several times (e.g. `TITRE`) keep their
+/// first value.
+fn update_jorf(meta: &mut JorfMetaData, tag: &[u8], text: &str) {
+    fn set(field: &mut Option<String>, text: &str) {
+        if field.is_none() {
+            *field = Some(text.to_string());
+        }
+    }
+    match tag {
+        b"ID" if meta.id.is_empty() => meta.id = text.to_string(),
+        b"ORIGINE" if meta.origin.is_empty() => meta.origin = text.to_string(),
+        b"URL" if meta.url.is_empty() => meta.url = text.to_string(),
+        b"NATURE" if meta.nature.is_empty() => meta.nature = text.to_string(),
+        b"ANCIEN_ID" => set(&mut meta.old_id, text),
+        b"CID" => set(&mut meta.cid, text),
+        b"NUM" => set(&mut meta.num, text),
+        b"NOR" => set(&mut meta.nor, text),
+        b"TITRE" | b"TITREFULL" => set(&mut meta.title, text),
+        b"MINISTERE" => set(&mut meta.ministry, text),
+        b"AUTORITE" => set(&mut meta.authority, text),
+        b"DATE_TEXTE" => set(&mut meta.text_date, text),
+        b"DATE_PUBLI" => set(&mut meta.publication_date, text),
+        b"NUM_PARUTION" => set(&mut meta.publication_number, text),
+        b"PAGE_DEB_PUBLI" => set(&mut meta.page_start, text),
+        b"PAGE_FIN_PUBLI" => set(&mut meta.page_end, text),
+        _ => {}
+    }
+}
+
+/// Parse the metadata of a JORF text.
+pub fn parse_jorf(content: &str) -> Result<JorfMetaData, ExtractError> {
+    parse_jorf_reader(&mut Reader::from_str(content))
+}
+
+/// Parse the metadata of a JORF text from a buffered reader,
+/// without loading the whole document in memory.
+pub fn parse_jorf_reader<R: BufRead>(reader: &mut Reader<R>) -> Result<JorfMetaData, ExtractError> {
+    let mut buf = Vec::new();
+    let mut meta = JorfMetaData::default();
+    // names of the currently open tags
+    let mut stack = TagStack::default();
+    // the signatories are stored as free text, separated by "; "
+    let mut signatories = String::new();
+    let mut in_signatories = false;
+    let mut links = LinkCollector::default();
+
+    loop {
+        buf.clear();
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|e| ExtractError::xml(e, reader.buffer_position()))?;
+        links.feed(&event);
+        match event {
+            Event::Eof => break,
+            Event::Start(e) => {
+                if e.name().as_ref() == b"SIGNATAIRES" {
+                    in_signatories = true;
+                }
+                stack.push(e.name().as_ref());
+            }
+            Event::End(e) => {
+                if e.name().as_ref() == b"SIGNATAIRES" {
+                    in_signatories = false;
+                }
+                stack.pop();
+            }
+            Event::Text(t) => {
+                let txt = t
+                    .unescape()
+                    .map_err(|e| ExtractError::xml(e, reader.buffer_position()))?;
+                let txt = txt.trim();
+                if txt.is_empty() {
+                    continue;
+                }
+                if in_signatories {
+                    if !signatories.is_empty() {
+                        signatories.push_str("; ");
+                    }
+                    signatories.push_str(txt);
+                } else if let Some(tag) = stack.last() {
+                    update_jorf(&mut meta, tag, txt);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if !signatories.is_empty() {
+        meta.signatories = Some(signatories);
+    }
+    meta.links = links.finish();
+    Ok(meta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<TEXTE_VERSION>
+<META>
+<META_COMMUN>
+<ID>JORFTEXT000049314000</ID>
+<ANCIEN_ID/>
+<ORIGINE>JORF</ORIGINE>
+<URL>texte/version/JORF/TEXT/00/00/49/31/40/JORFTEXT000049314000.xml</URL>
+<NATURE>DECRET</NATURE>
+</META_COMMUN>
+<META_SPEC>
+<META_TEXTE_CHRONICLE>
+<CID>JORFTEXT000049314000</CID>
+<NUM>2024-223</NUM>
+<NOR>ECOX2400123D</NOR>
+<DATE_PUBLI>2024-03-16</DATE_PUBLI>
+<DATE_TEXTE>2024-03-14</DATE_TEXTE>
+<NUM_PARUTION>0064</NUM_PARUTION>
+<PAGE_DEB_PUBLI>12</PAGE_DEB_PUBLI>
+<PAGE_FIN_PUBLI>14</PAGE_FIN_PUBLI>
+</META_TEXTE_CHRONICLE>
+<META_TEXTE_VERSION>
+<TITRE>Décret n° 2024-223 du 14 mars 2024 relatif aux antennes-relais</TITRE>
+<TITREFULL>Décret n° 2024-223 du 14 mars 2024 relatif aux antennes-relais (complet)</TITREFULL>
+<MINISTERE>Ministère de l'économie, des finances et de la souveraineté</MINISTERE>
+</META_TEXTE_VERSION>
+</META_SPEC>
+</META>
+<SIGNATAIRES>
+<CONTENU>
+<p>Par le Premier ministre :</p><br/>
+<p>Gabriel Attal</p>
+<p>Le ministre de l'économie, Bruno Le Maire</p>
+</CONTENU>
+</SIGNATAIRES>
+</TEXTE_VERSION>"#;
+
+    #[test]
+    fn test_jorf_metadata_parser() {
+        let meta = parse_jorf(EXAMPLE_XML).unwrap();
+        assert_eq!(meta.id, "JORFTEXT000049314000");
+        assert_eq!(meta.origin, "JORF");
+        assert_eq!(meta.nature, "DECRET");
+        assert_eq!(meta.num.as_deref(), Some("2024-223"));
+        assert_eq!(meta.nor.as_deref(), Some("ECOX2400123D"));
+        assert_eq!(
+            meta.title.as_deref(),
+            Some("Décret n° 2024-223 du 14 mars 2024 relatif aux antennes-relais")
+        );
+        assert_eq!(
+            meta.ministry.as_deref(),
+            Some("Ministère de l'économie, des finances et de la souveraineté")
+        );
+        assert_eq!(meta.publication_date.as_deref(), Some("2024-03-16"));
+        assert_eq!(meta.publication_number.as_deref(), Some("0064"));
+        assert_eq!(meta.page_start.as_deref(), Some("12"));
+        assert_eq!(meta.page_end.as_deref(), Some("14"));
+        assert_eq!(
+            meta.signatories.as_deref(),
+            Some("Par le Premier ministre :; Gabriel Attal; Le ministre de l'économie, Bruno Le Maire")
+        );
+    }
+}
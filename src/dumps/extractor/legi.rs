@@ -0,0 +1,466 @@
+/// Consolidated legislation (LEGI dumps). Codes and texts are split in
+/// ARTICLE documents, organised by SECTION_TA documents, and TEXTELR
+/// documents describe the text itself (see [`super::jorf`] for the
+/// metadata of the latter).
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::Reader;
+
+use std::io::BufRead;
+
+use serde::{Deserialize, Serialize};
+
+use super::{ExtractError, Link, LinkCollector, TagStack};
+
+/// A version of an article of a consolidated text.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LegiArticle {
+    /// The unique identifier of the article version (e.g. "LEGIARTI000006335064")
+    pub id: String,
+    /// The relative path to the XML file in the DILA archive
+    pub url: String,
+    /// Number of the article in its text (e.g. "L110-1")
+    pub num: Option<String>,
+    /// Legal state of this version (e.g. "VIGUEUR", "ABROGE", "MODIFIE")
+    pub etat: Option<String>,
+    /// First day this version applies, in the format YYYY-MM-DD
+    pub date_debut: Option<String>,
+    /// Day this version stops applying, in the format YYYY-MM-DD
+    /// ("2999-01-01" for articles currently in force)
+    pub date_fin: Option<String>,
+    /// Identifier of the text containing the article (e.g. "LEGITEXT000006070158")
+    pub parent_text: Option<String>,
+    /// Title of the text containing the article
+    pub parent_title: Option<String>,
+    /// Titles of the enclosing sections, separated by " > "
+    pub section_path: Option<String>,
+    /// Content of the article, one paragraph per line
+    pub contenu: String,
+    /// Texts cited, modified or abrogated by the article
+    #[serde(default)]
+    pub links: Vec<Link>,
+}
+
+/// A section of a consolidated text, listing its articles.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LegiSection {
+    /// The unique identifier of the section (e.g. "LEGISCTA000006147745")
+    pub id: String,
+    /// Title of the section (e.g. "Chapitre Ier : Dispositions générales")
+    pub title: Option<String>,
+    /// Identifier of the text containing the section
+    pub parent_text: Option<String>,
+    /// Title of the text containing the section
+    pub parent_title: Option<String>,
+    /// Titles of the enclosing sections, separated by " > "
+    pub section_path: Option<String>,
+    /// Identifiers of the articles of the section, separated by ";"
+    pub articles: String,
+}
+
+fn attribute(e: &BytesStart, name: &str) -> Option<String> {
+    e.try_get_attribute(name)
+        .ok()
+        .flatten()
+        .and_then(|a| a.unescape_value().ok())
+        .map(|v| v.into_owned())
+}
+
+/// What the `CONTEXTE` element of articles and sections tells
+/// about their position in the text.
+#[derive(Default)]
+struct Context {
+    parent_text: Option<String>,
+    parent_title: Option<String>,
+    sections: Vec<String>,
+    links: Vec<Link>,
+}
+
+impl Context {
+    fn section_path(&self) -> Option<String> {
+        if self.sections.is_empty() {
+            None
+        } else {
+            Some(self.sections.join(" > "))
+        }
+    }
+}
+
+/// Common traversal of ARTICLE and SECTION_TA documents: `on_text` is
+/// called with the currently open tags for every text outside of the
+/// `CONTEXTE` element, and `on_tag` for every opening tag.
+fn walk_legi<R: BufRead>(
+    reader: &mut Reader<R>,
+    mut on_tag: impl FnMut(&BytesStart),
+    mut on_text: impl FnMut(&TagStack, &str),
+) -> Result<Context, ExtractError> {
+    let mut buf = Vec::new();
+    let mut ctx = Context::default();
+    let mut stack = TagStack::default();
+    let mut links = LinkCollector::default();
+
+    loop {
+        buf.clear();
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|e| ExtractError::xml(e, reader.buffer_position()))?;
+        links.feed(&event);
+        match event {
+            Event::Eof => break,
+            Event::Start(e) => {
+                if e.name().as_ref() == b"TEXTE" && stack.contains(b"CONTEXTE") {
+                    ctx.parent_text = attribute(&e, "cid");
+                }
+                on_tag(&e);
+                stack.push(e.name().as_ref());
+            }
+            Event::Empty(e) => {
+                on_tag(&e);
+            }
+            Event::End(_) => {
+                stack.pop();
+            }
+            Event::Text(t) => {
+                let txt = t
+                    .unescape()
+                    .map_err(|e| ExtractError::xml(e, reader.buffer_position()))?;
+                let txt = txt.trim();
+                if txt.is_empty() {
+                    continue;
+                }
+                if stack.contains(b"CONTEXTE") {
+                    match stack.last() {
+                        Some(b"TITRE_TXT") if ctx.parent_title.is_none() => {
+                            ctx.parent_title = Some(txt.to_string())
+                        }
+                        Some(b"TITRE_TM") => ctx.sections.push(txt.to_string()),
+                        _ => {}
+                    }
+                } else {
+                    on_text(&stack, txt);
+                }
+            }
+            _ => {}
+        }
+    }
+    ctx.links = links.finish();
+    Ok(ctx)
+}
+
+/// Parse an ARTICLE document of the LEGI dumps.
+pub fn parse_article(content: &str) -> Result<LegiArticle, ExtractError> {
+    parse_article_reader(&mut Reader::from_str(content))
+}
+
+/// Parse an ARTICLE document from a buffered reader.
+pub fn parse_article_reader<R: BufRead>(
+    reader: &mut Reader<R>,
+) -> Result<LegiArticle, ExtractError> {
+    let mut article = LegiArticle::default();
+
+    let ctx = walk_legi(
+        reader,
+        |_| {},
+        |stack, txt| {
+            if stack.contains(b"BLOC_TEXTUEL") {
+                // one paragraph per line
+                if !article.contenu.is_empty() {
+                    article.contenu.push('\n');
+                }
+                article.contenu.push_str(txt);
+                return;
+            }
+            let set = |field: &mut Option<String>| {
+                if field.is_none() {
+                    *field = Some(txt.to_string());
+                }
+            };
+            match stack.last() {
+                Some(b"ID") if article.id.is_empty() => article.id = txt.to_string(),
+                Some(b"URL") if article.url.is_empty() => article.url = txt.to_string(),
+                Some(b"NUM") => set(&mut article.num),
+                Some(b"ETAT") => set(&mut article.etat),
+                Some(b"DATE_DEBUT") => set(&mut article.date_debut),
+                Some(b"DATE_FIN") => set(&mut article.date_fin),
+                _ => {}
+            }
+        },
+    )?;
+
+    article.section_path = ctx.section_path();
+    article.parent_text = ctx.parent_text;
+    article.parent_title = ctx.parent_title;
+    article.links = ctx.links;
+    Ok(article)
+}
+
+/// Parse a SECTION_TA document of the LEGI dumps.
+pub fn parse_section(content: &str) -> Result<LegiSection, ExtractError> {
+    parse_section_reader(&mut Reader::from_str(content))
+}
+
+/// Parse a SECTION_TA document from a buffered reader.
+pub fn parse_section_reader<R: BufRead>(
+    reader: &mut Reader<R>,
+) -> Result<LegiSection, ExtractError> {
+    let mut section = LegiSection::default();
+    let mut articles: Vec<String> = vec![];
+
+    let ctx = walk_legi(
+        reader,
+        |e| {
+            if e.name().as_ref() == b"LIEN_ART"
+                && let Some(id) = attribute(e, "id")
+            {
+                articles.push(id);
+            }
+        },
+        |stack, txt| match stack.last() {
+            Some(b"ID") if section.id.is_empty() => section.id = txt.to_string(),
+            Some(b"TITRE_TA") if section.title.is_none() => section.title = Some(txt.to_string()),
+            _ => {}
+        },
+    )?;
+
+    section.section_path = ctx.section_path();
+    section.parent_text = ctx.parent_text;
+    section.parent_title = ctx.parent_title;
+    section.articles = articles.join(";");
+    Ok(section)
+}
+
+/// Store `txt`, found in the `tag` element of an embedded
+/// ARTICLE element, in the corresponding field of `article`
+fn update_embedded_article(article: &mut LegiArticle, tag: &[u8], txt: &str) {
+    let set = |field: &mut Option<String>| {
+        if field.is_none() {
+            *field = Some(txt.to_string());
+        }
+    };
+    match tag {
+        b"ID" if article.id.is_empty() => article.id = txt.to_string(),
+        b"NUM" => set(&mut article.num),
+        b"ETAT" => set(&mut article.etat),
+        b"DATE_DEBUT" => set(&mut article.date_debut),
+        b"DATE_FIN" => set(&mut article.date_fin),
+        _ => {}
+    }
+}
+
+/// Split a text into its articles: one record per ARTICLE element
+/// nested in the document, with its own identifier, number and
+/// applicability dates, read either from the attributes of the
+/// element (`id`, `num`, `etat`, `debut`, `fin`) or from its children.
+/// The root element is never split, so an ARTICLE document
+/// or a document without embedded articles gives an empty list.
+pub fn split_articles(content: &str) -> Result<Vec<LegiArticle>, ExtractError> {
+    split_articles_reader(&mut Reader::from_str(content))
+}
+
+/// Split a text read from a buffered reader into its articles,
+/// see [`split_articles`].
+pub fn split_articles_reader<R: BufRead>(
+    reader: &mut Reader<R>,
+) -> Result<Vec<LegiArticle>, ExtractError> {
+    let mut buf = Vec::new();
+    let mut stack = TagStack::default();
+    let mut articles = vec![];
+    // the text containing the articles
+    let mut parent_id: Option<String> = None;
+    let mut parent_title: Option<String> = None;
+    let mut url: Option<String> = None;
+    // the article being read, and its depth in the document
+    let mut current: Option<(LegiArticle, usize)> = None;
+
+    loop {
+        buf.clear();
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|e| ExtractError::xml(e, reader.buffer_position()))?;
+        match event {
+            Event::Eof => break,
+            Event::Start(e) => {
+                if e.name().as_ref() == b"ARTICLE" && !stack.is_empty() && current.is_none() {
+                    let article = LegiArticle {
+                        id: attribute(&e, "id").unwrap_or_default(),
+                        num: attribute(&e, "num"),
+                        etat: attribute(&e, "etat"),
+                        date_debut: attribute(&e, "debut"),
+                        date_fin: attribute(&e, "fin"),
+                        ..Default::default()
+                    };
+                    current = Some((article, stack.len()));
+                }
+                stack.push(e.name().as_ref());
+            }
+            Event::End(_) => {
+                stack.pop();
+                if let Some((_, depth)) = &current
+                    && stack.len() == *depth
+                {
+                    let (article, _) = current.take().expect("article is open");
+                    articles.push(article);
+                }
+            }
+            Event::Text(t) => {
+                let txt = t
+                    .unescape()
+                    .map_err(|e| ExtractError::xml(e, reader.buffer_position()))?;
+                let txt = txt.trim();
+                if txt.is_empty() {
+                    continue;
+                }
+                let tag = stack.last().unwrap_or_default();
+                match current.as_mut() {
+                    Some((article, depth))
+                        if stack
+                            .iter()
+                            .skip(*depth)
+                            .any(|t| t == b"BLOC_TEXTUEL" || t == b"CONTENU") =>
+                    {
+                        // one paragraph per line
+                        if !article.contenu.is_empty() {
+                            article.contenu.push('\n');
+                        }
+                        article.contenu.push_str(txt);
+                    }
+                    Some((article, _)) => update_embedded_article(article, tag, txt),
+                    None => match tag {
+                        b"ID" if parent_id.is_none() => parent_id = Some(txt.to_string()),
+                        b"URL" if url.is_none() => url = Some(txt.to_string()),
+                        b"TITRE" | b"TITREFULL" | b"TITRE_TXT" if parent_title.is_none() => {
+                            parent_title = Some(txt.to_string())
+                        }
+                        _ => {}
+                    },
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for article in articles.iter_mut() {
+        article.parent_text = parent_id.clone();
+        article.parent_title = parent_title.clone();
+        article.url = url.clone().unwrap_or_default();
+    }
+    Ok(articles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE_ARTICLE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ARTICLE>
+<META>
+<META_COMMUN>
+<ID>LEGIARTI000006335064</ID>
+<ANCIEN_ID/>
+<ORIGINE>LEGI</ORIGINE>
+<URL>article/LEGI/ARTI/00/00/06/33/50/LEGIARTI000006335064.xml</URL>
+<NATURE>Article</NATURE>
+</META_COMMUN>
+<META_SPEC>
+<META_ARTICLE>
+<NUM>L110-1</NUM>
+<ETAT>VIGUEUR</ETAT>
+<DATE_DEBUT>2005-03-01</DATE_DEBUT>
+<DATE_FIN>2999-01-01</DATE_FIN>
+<TYPE>AUTONOME</TYPE>
+</META_ARTICLE>
+</META_SPEC>
+</META>
+<CONTEXTE>
+<TEXTE autorite="" cid="LEGITEXT000006070158" nature="CODE">
+<TITRE_TXT c_titre_court="CESEDA" id_txt="LEGITEXT000006070158">Code de l'entrée et du séjour des étrangers et du droit d'asile</TITRE_TXT>
+<TM><TITRE_TM id="LEGISCTA000006118123">Partie législative</TITRE_TM>
+<TM><TITRE_TM id="LEGISCTA000006147745">Livre Ier : Dispositions générales</TITRE_TM></TM>
+</TM>
+</TEXTE>
+</CONTEXTE>
+<VERSIONS/>
+<NOTA><CONTENU/></NOTA>
+<BLOC_TEXTUEL>
+<CONTENU>
+<p>Le présent code régit l'entrée et le séjour des étrangers en France.</p><br/>
+<p>Il régit également l'exercice du droit d'asile.</p>
+</CONTENU>
+</BLOC_TEXTUEL>
+</ARTICLE>"#;
+
+    const EXAMPLE_SECTION: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<SECTION_TA>
+<ID>LEGISCTA000006147745</ID>
+<TITRE_TA>Livre Ier : Dispositions générales</TITRE_TA>
+<COMMENTAIRE/>
+<CONTEXTE>
+<TEXTE cid="LEGITEXT000006070158">
+<TITRE_TXT>Code de l'entrée et du séjour des étrangers et du droit d'asile</TITRE_TXT>
+<TM><TITRE_TM id="LEGISCTA000006118123">Partie législative</TITRE_TM></TM>
+</TEXTE>
+</CONTEXTE>
+<STRUCTURE_TA>
+<LIEN_ART debut="2005-03-01" etat="VIGUEUR" fin="2999-01-01" id="LEGIARTI000006335064" num="L110-1" origine="LEGI"/>
+<LIEN_ART debut="2005-03-01" etat="VIGUEUR" fin="2999-01-01" id="LEGIARTI000006335065" num="L110-2" origine="LEGI"/>
+</STRUCTURE_TA>
+</SECTION_TA>"#;
+
+    #[test]
+    fn test_legi_article_parser() {
+        let article = parse_article(EXAMPLE_ARTICLE).unwrap();
+        assert_eq!(article.id, "LEGIARTI000006335064");
+        assert_eq!(article.num.as_deref(), Some("L110-1"));
+        assert_eq!(article.etat.as_deref(), Some("VIGUEUR"));
+        assert_eq!(article.date_debut.as_deref(), Some("2005-03-01"));
+        assert_eq!(article.date_fin.as_deref(), Some("2999-01-01"));
+        assert_eq!(article.parent_text.as_deref(), Some("LEGITEXT000006070158"));
+        assert_eq!(
+            article.section_path.as_deref(),
+            Some("Partie législative > Livre Ier : Dispositions générales")
+        );
+        assert_eq!(
+            article.contenu,
+            "Le présent code régit l'entrée et le séjour des étrangers en France.\nIl régit également l'exercice du droit d'asile."
+        );
+    }
+
+    #[test]
+    fn test_split_articles() {
+        let text = r#"<TEXTE_VERSION>
+<META><META_COMMUN><ID>LEGITEXT000006070158</ID><URL>texte/version/LEGITEXT000006070158.xml</URL></META_COMMUN>
+<META_SPEC><META_TEXTE_VERSION><TITRE>Code de l'entrée et du séjour</TITRE></META_TEXTE_VERSION></META_SPEC></META>
+<ARTICLE id="LEGIARTI000006335064" num="L110-1" etat="VIGUEUR" debut="2005-03-01" fin="2999-01-01">
+<BLOC_TEXTUEL><CONTENU><p>Premier alinéa.</p><p>Second alinéa.</p></CONTENU></BLOC_TEXTUEL>
+</ARTICLE>
+<ARTICLE>
+<META><ID>LEGIARTI000006335065</ID><NUM>L110-2</NUM><DATE_DEBUT>2006-01-01</DATE_DEBUT></META>
+<BLOC_TEXTUEL><CONTENU>Texte de l'article.</CONTENU></BLOC_TEXTUEL>
+</ARTICLE>
+</TEXTE_VERSION>"#;
+        let articles = split_articles(text).unwrap();
+        assert_eq!(articles.len(), 2);
+        assert_eq!(articles[0].id, "LEGIARTI000006335064");
+        assert_eq!(articles[0].num.as_deref(), Some("L110-1"));
+        assert_eq!(articles[0].date_fin.as_deref(), Some("2999-01-01"));
+        assert_eq!(articles[0].contenu, "Premier alinéa.\nSecond alinéa.");
+        assert_eq!(articles[1].id, "LEGIARTI000006335065");
+        assert_eq!(articles[1].num.as_deref(), Some("L110-2"));
+        assert_eq!(articles[1].date_debut.as_deref(), Some("2006-01-01"));
+        assert_eq!(articles[1].contenu, "Texte de l'article.");
+        assert_eq!(articles[1].parent_text.as_deref(), Some("LEGITEXT000006070158"));
+        assert_eq!(articles[1].parent_title.as_deref(), Some("Code de l'entrée et du séjour"));
+
+        // articles are not split further
+        assert!(split_articles(EXAMPLE_ARTICLE).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_legi_section_parser() {
+        let section = parse_section(EXAMPLE_SECTION).unwrap();
+        assert_eq!(section.id, "LEGISCTA000006147745");
+        assert_eq!(section.title.as_deref(), Some("Livre Ier : Dispositions générales"));
+        assert_eq!(section.parent_text.as_deref(), Some("LEGITEXT000006070158"));
+        assert_eq!(section.section_path.as_deref(), Some("Partie législative"));
+        assert_eq!(section.articles, "LEGIARTI000006335064;LEGIARTI000006335065");
+    }
+}
@@ -0,0 +1,234 @@
+/// Export of documents as one file per document, for static site
+/// generators and LLM ingestion: Markdown files starting with a YAML
+/// front matter (id, date, jurisdiction and title of the document),
+/// plain text files holding only the text, or Akoma Ntoso XML files
+/// (see [`super::akn`]).
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::export::write_line;
+use super::{Document, DocumentWriter, ExtractError};
+
+/// Name of the list of files that could not be parsed,
+/// in the directory of the exported files
+pub const ERRORS_FILE: &str = "errors.jsonl";
+
+/// Format of the files written by [`DocumentFileWriter`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TextFormat {
+    /// Markdown with a YAML front matter
+    Md,
+    /// Plain text
+    Txt,
+    /// Akoma Ntoso XML
+    Akn,
+}
+
+impl TextFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            TextFormat::Md => "md",
+            TextFormat::Txt => "txt",
+            TextFormat::Akn => "xml",
+        }
+    }
+}
+
+/// Title of the document: the title of decisions, texts and
+/// deliberations, and the number of articles
+pub(crate) fn title(document: &Document) -> Option<String> {
+    match document {
+        Document::Decision(d) => d.title.clone(),
+        Document::Jorf(j) => j.title.clone(),
+        Document::LegiArticle(a) => a.num.as_ref().map(|num| format!("Article {}", num)),
+        Document::LegiSection(s) => s.title.clone(),
+        Document::Cnil(c) => c.title.clone(),
+    }
+}
+
+/// Who issued the document: the court of decisions, the
+/// ministry or authority of texts of the Journal Officiel
+pub(crate) fn jurisdiction(document: &Document) -> Option<String> {
+    match document {
+        Document::Decision(d) => d.jurisdiction.clone(),
+        Document::Jorf(j) => j.ministry.clone().or(j.authority.clone()),
+        Document::Cnil(_) => Some("CNIL".to_string()),
+        Document::LegiArticle(_) | Document::LegiSection(_) => None,
+    }
+}
+
+/// The YAML front matter of a document, between `---` lines. Values
+/// are written as double quoted strings (JSON strings are valid YAML),
+/// missing values as `null`.
+pub fn front_matter(document: &Document) -> String {
+    let quote = |value: Option<String>| match value {
+        Some(value) if !value.trim().is_empty() => {
+            serde_json::Value::from(value.trim()).to_string()
+        }
+        _ => "null".to_string(),
+    };
+    let mut yaml = String::from("---\n");
+    yaml.push_str(&format!("id: {}\n", quote(Some(document.id().to_string()))));
+    yaml.push_str(&format!("kind: {}\n", document.kind()));
+    yaml.push_str(&format!("date: {}\n", quote(document.date().map(|d| d.to_string()))));
+    yaml.push_str(&format!("jurisdiction: {}\n", quote(jurisdiction(document))));
+    yaml.push_str(&format!("title: {}\n", quote(title(document))));
+    yaml.push_str("---\n");
+    yaml
+}
+
+/// Escape the characters that would make a line of text start a
+/// heading, a quote, a list or a table in Markdown, such as the
+/// numbers of considérants ("1. Considérant que ...")
+fn escape_line(line: &str) -> String {
+    if line.starts_with(['#', '>', '-', '+', '*', '|', '=']) {
+        return format!("\\{}", line);
+    }
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits > 0 && line[digits..].starts_with(['.', ')']) {
+        return format!("{}\\{}", &line[..digits], &line[digits..]);
+    }
+    line.to_string()
+}
+
+/// The document as Markdown: its front matter, followed by the lines
+/// of its text as paragraphs
+pub fn to_markdown(document: &Document) -> String {
+    let mut markdown = front_matter(document);
+    let paragraphs: Vec<String> = document
+        .text()
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(escape_line)
+        .collect();
+    if !paragraphs.is_empty() {
+        markdown.push('\n');
+        markdown.push_str(&paragraphs.join("\n\n"));
+        markdown.push('\n');
+    }
+    markdown
+}
+
+/// The document as plain text: only its text
+pub fn to_text(document: &Document) -> String {
+    let mut text = document.text().unwrap_or_default().trim().to_string();
+    text.push('\n');
+    text
+}
+
+/// Name of the file of a document, from its identifier
+fn file_stem(document: &Document) -> String {
+    let stem: String = document
+        .id()
+        .trim()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    if stem.is_empty() { "document".to_string() } else { stem }
+}
+
+/// Writes every document to its own file (e.g. `CETATEXT000049314894.md`)
+/// in a directory. Files that could not be parsed are listed in
+/// [`ERRORS_FILE`], in the same directory.
+pub struct DocumentFileWriter {
+    dir: PathBuf,
+    format: TextFormat,
+    errors: Option<std::io::BufWriter<std::fs::File>>,
+}
+
+impl DocumentFileWriter {
+    pub fn new<T: AsRef<Path>>(dir: T, format: TextFormat) -> anyhow::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+        Ok(DocumentFileWriter {
+            dir,
+            format,
+            errors: None,
+        })
+    }
+
+    /// Path of the file of a document
+    pub fn path_for(&self, document: &Document) -> PathBuf {
+        self.dir
+            .join(format!("{}.{}", file_stem(document), self.format.extension()))
+    }
+}
+
+impl DocumentWriter for DocumentFileWriter {
+    fn write(&mut self, document: &Document) -> anyhow::Result<()> {
+        let content = match self.format {
+            TextFormat::Md => to_markdown(document),
+            TextFormat::Txt => to_text(document),
+            TextFormat::Akn => super::akn::to_akoma_ntoso(document),
+        };
+        std::fs::write(self.path_for(document), content)?;
+        Ok(())
+    }
+
+    fn write_error(&mut self, path: &str, error: &ExtractError) -> anyhow::Result<()> {
+        if self.errors.is_none() {
+            let file = std::fs::File::create(self.dir.join(ERRORS_FILE))?;
+            self.errors = Some(std::io::BufWriter::new(file));
+        }
+        let errors = self.errors.as_mut().expect("writer was just created");
+        write_line(
+            errors,
+            &serde_json::json!({ "path": path, "error": error.to_string() }),
+        )
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        if let Some(errors) = self.errors.as_mut() {
+            errors.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dumps::extractor::PreDilaText;
+
+    #[test]
+    fn test_markdown() {
+        let document = Document::Decision(PreDilaText {
+            id: "CETATEXT000049314894".to_string(),
+            title: Some("Conseil d'État, 21/03/2024, \"antennes\"".to_string()),
+            decision_date: Some("2024-03-21".to_string()),
+            jurisdiction: Some("Conseil d'État".to_string()),
+            text: "Vu la procédure suivante :\n\n1. Considérant que\n- la commune".to_string(),
+            ..Default::default()
+        });
+        assert_eq!(
+            to_markdown(&document),
+            "---\n\
+             id: \"CETATEXT000049314894\"\n\
+             kind: decision\n\
+             date: \"2024-03-21\"\n\
+             jurisdiction: \"Conseil d'État\"\n\
+             title: \"Conseil d'État, 21/03/2024, \\\"antennes\\\"\"\n\
+             ---\n\
+             \n\
+             Vu la procédure suivante :\n\
+             \n\
+             1\\. Considérant que\n\
+             \n\
+             \\- la commune\n"
+        );
+
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let mut writer = DocumentFileWriter::new(tmp.path().join("out"), TextFormat::Txt).unwrap();
+        writer.write(&document).unwrap();
+        writer
+            .write_error("missing.xml", &ExtractError::NotFound("missing.xml".into()))
+            .unwrap();
+        writer.flush().unwrap();
+        let text =
+            std::fs::read_to_string(tmp.path().join("out/CETATEXT000049314894.txt")).unwrap();
+        assert!(text.starts_with("Vu la procédure"));
+        assert!(tmp.path().join("out").join(ERRORS_FILE).exists());
+    }
+}
@@ -0,0 +1,75 @@
+//! Normalization of the people and firms named in the metadata of
+//! decisions: the lawyers ("SCP X ; SCP Y") are split into a list,
+//! and the honorifics of the judges ("M.", "Mme", ...) are removed,
+//! so that the same party is always written the same way.
+
+/// Honorifics removed in front of names, longest first
+const HONORIFICS: &[&str] = &[
+    "Monsieur", "Madame", "Mademoiselle", "Maître", "Mlle.", "Mlle", "Mme.", "Mme", "MM.", "Me.",
+    "Me", "M.",
+];
+
+/// Collapse runs of whitespace into a single space
+fn collapse_whitespace(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Remove a leading honorific from a name, e.g.
+/// "M. Alexandre Trémolière" becomes "Alexandre Trémolière"
+pub fn strip_honorific(name: &str) -> String {
+    let name = collapse_whitespace(name);
+    for honorific in HONORIFICS {
+        if let Some(rest) = name.strip_prefix(honorific)
+            && (rest.starts_with(' ') || honorific.ends_with('.'))
+            && !rest.trim().is_empty()
+        {
+            return rest.trim().to_string();
+        }
+    }
+    name
+}
+
+/// Normalized name of a judge, `None` when empty
+pub fn person(name: &str) -> Option<String> {
+    Some(strip_honorific(name)).filter(|n| !n.is_empty())
+}
+
+/// Split the lawyers of a decision, separated by ";", into a
+/// list of firms or names without duplicates, in order of appearance
+pub fn lawyers(s: &str) -> Vec<String> {
+    let mut lawyers: Vec<String> = vec![];
+    for lawyer in s.split(';').map(strip_honorific) {
+        let lawyer = lawyer.trim_end_matches([',', '.']).trim().to_string();
+        if !lawyer.is_empty() && !lawyers.contains(&lawyer) {
+            lawyers.push(lawyer);
+        }
+    }
+    lawyers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parties() {
+        assert_eq!(
+            lawyers("SCP BAUER-VIOLAS - FESCHOTTE-DESBOIS - SEBAGH ; SCP MARLANGE,  DE LA BURGADE ;SCP SPINOSI;"),
+            vec![
+                "SCP BAUER-VIOLAS - FESCHOTTE-DESBOIS - SEBAGH",
+                "SCP MARLANGE, DE LA BURGADE",
+                "SCP SPINOSI"
+            ]
+        );
+        assert_eq!(lawyers("Me Dupont ; Me Dupont"), vec!["Dupont"]);
+        assert!(lawyers(" ; ").is_empty());
+
+        assert_eq!(strip_honorific("M. Alexandre Trémolière"), "Alexandre Trémolière");
+        assert_eq!(strip_honorific("Mme  Anne Martin"), "Anne Martin");
+        assert_eq!(strip_honorific("M.Durand"), "Durand");
+        // not an honorific
+        assert_eq!(strip_honorific("Mercier"), "Mercier");
+        assert_eq!(strip_honorific("Meyer"), "Meyer");
+        assert_eq!(person("  "), None);
+    }
+}
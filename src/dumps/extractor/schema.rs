@@ -0,0 +1,230 @@
+/// Schema discovery: infer the layout of the documents of a corpus
+/// from the documents themselves, to add support for new fonds.
+///
+/// For every fond, the schema records the paths of the tags
+/// (e.g. `/TEXTE_JURI_ADMIN/META/META_COMMUN/ID`) with how often
+/// they appear, in how many documents, whether they hold text and
+/// which attributes they carry, together with the number of documents
+/// in which two tags appear together.
+use log::debug;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::reader::Reader;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::BufRead;
+use std::path::Path;
+
+use super::{ExtractError, open_xml};
+use crate::dumps::fonds::fond_of_extracted_path;
+use crate::dumps::tarballs::walk_files;
+
+/// Usage of a tag path.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagStats {
+    /// Number of occurrences of the path
+    pub count: usize,
+    /// Number of documents in which the path appears. A path
+    /// appearing in all the documents of a fond is likely required.
+    pub documents: usize,
+    /// Number of occurrences holding some text
+    pub with_text: usize,
+    /// Number of occurrences carrying each attribute
+    pub attributes: BTreeMap<String, usize>,
+}
+
+impl TagStats {
+    fn merge(&mut self, other: TagStats) {
+        self.count += other.count;
+        self.documents += other.documents;
+        self.with_text += other.with_text;
+        for (name, count) in other.attributes {
+            *self.attributes.entry(name).or_insert(0) += count;
+        }
+    }
+}
+
+/// The inferred schema of the documents of a fond.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FondSchema {
+    /// Number of documents scanned
+    pub documents: usize,
+    /// Number of files that are not well-formed XML
+    pub errors: usize,
+    /// Usage of each tag path
+    pub paths: BTreeMap<String, TagStats>,
+    /// For two tag names `a < b`, the number of documents
+    /// in which both appear
+    pub cooccurrence: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+/// The tags seen in a single document
+#[derive(Default)]
+struct DocumentTags {
+    paths: BTreeMap<String, TagStats>,
+    names: BTreeSet<String>,
+}
+
+impl DocumentTags {
+    /// Record an opening tag under `parent`, found at `position`,
+    /// returning its path
+    fn open(
+        &mut self,
+        e: &BytesStart,
+        parent: Option<&String>,
+        position: u64,
+    ) -> Result<String, ExtractError> {
+        let name = String::from_utf8_lossy(e.name().as_ref()).to_string();
+        let path = format!("{}/{}", parent.map(String::as_str).unwrap_or(""), name);
+        let stats = self.paths.entry(path.clone()).or_default();
+        stats.count += 1;
+        for attr in e.attributes() {
+            let attr = attr.map_err(|e| ExtractError::Xml {
+                position,
+                message: e.to_string(),
+            })?;
+            let key = String::from_utf8_lossy(attr.key.as_ref()).to_string();
+            *stats.attributes.entry(key).or_insert(0) += 1;
+        }
+        self.names.insert(name);
+        Ok(path)
+    }
+}
+
+impl FondSchema {
+    /// Scan a document and add its tags to the schema. The
+    /// schema is left untouched if the document is not well-formed.
+    pub fn scan<R: BufRead>(&mut self, reader: &mut Reader<R>) -> Result<(), ExtractError> {
+        let mut buf = Vec::new();
+        let mut stack: Vec<String> = vec![];
+        let mut tags = DocumentTags::default();
+
+        loop {
+            buf.clear();
+            let event = reader
+                .read_event_into(&mut buf)
+                .map_err(|e| ExtractError::xml(e, reader.buffer_position()))?;
+            match event {
+                Event::Eof => break,
+                Event::Start(e) => {
+                    let path = tags.open(&e, stack.last(), reader.buffer_position())?;
+                    stack.push(path);
+                }
+                Event::Empty(e) => {
+                    tags.open(&e, stack.last(), reader.buffer_position())?;
+                }
+                Event::End(_) => {
+                    stack.pop();
+                }
+                Event::Text(t) if !t.iter().all(|b| b.is_ascii_whitespace()) => {
+                    if let Some(path) = stack.last()
+                        && let Some(stats) = tags.paths.get_mut(path)
+                    {
+                        stats.with_text += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.documents += 1;
+        for (path, mut stats) in tags.paths {
+            stats.documents = 1;
+            self.paths.entry(path).or_default().merge(stats);
+        }
+        let names: Vec<String> = tags.names.into_iter().collect();
+        for (i, a) in names.iter().enumerate() {
+            let row = self.cooccurrence.entry(a.clone()).or_default();
+            for b in &names[i + 1..] {
+                *row.entry(b.clone()).or_insert(0) += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, other: FondSchema) {
+        self.documents += other.documents;
+        self.errors += other.errors;
+        for (path, stats) in other.paths {
+            self.paths.entry(path).or_default().merge(stats);
+        }
+        for (a, row) in other.cooccurrence {
+            let target = self.cooccurrence.entry(a).or_default();
+            for (b, count) in row {
+                *target.entry(b).or_insert(0) += count;
+            }
+        }
+    }
+}
+
+/// The inferred schema of a corpus, per fond.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CorpusSchema {
+    pub fonds: BTreeMap<String, FondSchema>,
+}
+
+impl CorpusSchema {
+    fn merge(mut self, other: CorpusSchema) -> CorpusSchema {
+        for (fond, schema) in other.fonds {
+            self.fonds.entry(fond).or_default().merge(schema);
+        }
+        self
+    }
+}
+
+/// Infer the schema of all the XML documents of an extracted
+/// directory, in parallel. Documents are grouped by fond using
+/// the first directory of their path.
+pub fn discover_schema(dir: &Path) -> anyhow::Result<CorpusSchema> {
+    walk_files(dir)
+        .par_bridge()
+        .try_fold(CorpusSchema::default, |mut schema, file| {
+            let file = file?;
+            if file.extension().is_none_or(|ext| ext != "xml") {
+                return Ok(schema);
+            }
+            let relative = file.strip_prefix(dir).unwrap_or(&file);
+            let fond = schema.fonds.entry(fond_of_extracted_path(relative)).or_default();
+            if let Err(e) = open_xml(&file).and_then(|mut reader| fond.scan(&mut reader)) {
+                debug!("Could not scan {}: {}", file.display(), e);
+                fond.errors += 1;
+            }
+            Ok::<_, anyhow::Error>(schema)
+        })
+        .try_reduce(CorpusSchema::default, |a, b| Ok(a.merge(b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fond_schema() {
+        let mut schema = FondSchema::default();
+        let docs = [
+            r#"<TEXTE><META><ID>1</ID><LIEN id="A" typelien="CITATION"/></META><CONTENU>Texte</CONTENU></TEXTE>"#,
+            r#"<TEXTE><META><ID>2</ID></META><CONTENU/></TEXTE>"#,
+        ];
+        for doc in docs {
+            schema.scan(&mut Reader::from_str(doc)).unwrap();
+        }
+        assert_eq!(schema.documents, 2);
+
+        let id = &schema.paths["/TEXTE/META/ID"];
+        assert_eq!((id.count, id.documents, id.with_text), (2, 2, 2));
+        let lien = &schema.paths["/TEXTE/META/LIEN"];
+        assert_eq!(lien.documents, 1);
+        assert_eq!(lien.attributes.get("typelien"), Some(&1));
+        assert_eq!(schema.paths["/TEXTE/CONTENU"].with_text, 1);
+
+        assert_eq!(schema.cooccurrence["ID"]["META"], 2);
+        assert_eq!(schema.cooccurrence["ID"]["LIEN"], 1);
+        assert_eq!(schema.cooccurrence["ID"]["TEXTE"], 2);
+
+        // malformed documents do not change the schema
+        let before = schema.clone();
+        assert!(schema.scan(&mut Reader::from_str("<TEXTE><META></TEXTE>")).is_err());
+        assert_eq!(schema, before);
+    }
+}
@@ -0,0 +1,276 @@
+//! Segmentation of the text of documents into paragraphs and
+//! sentences, for NLP pipelines. The rules are tuned for French legal
+//! prose: abbreviations such as "art.", "M." or "L. 761-1" and the
+//! numbers of considérants ("1. Considérant que ...") do not end
+//! a sentence.
+use serde::{Deserialize, Serialize};
+
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use super::export::{sibling_path, write_line};
+use super::{Document, DocumentWriter, ExtractError};
+
+/// Abbreviations after which a period does not end a sentence,
+/// in lowercase and without the period. Single letters (initials,
+/// "L." or "R." articles of codes) are handled separately.
+const ABBREVIATIONS: &[&str] = &[
+    "al", "art", "arts", "ass", "av", "bd", "c", "cass", "cf", "ch", "chap", "civ", "cons",
+    "crim", "déc", "dr", "ex", "mm", "mme", "mmes", "mlle", "me", "no", "op", "ord", "p", "pp",
+    "préc", "req", "sect", "soc", "spéc", "st", "ste", "vol",
+];
+
+/// A sentence of a text. Offsets are counted in characters (not
+/// bytes) from the start of the text, the end being excluded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sentence {
+    /// Index of the paragraph of the sentence in the text
+    pub paragraph: usize,
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// Byte ranges of the paragraphs of a text: its non empty lines
+/// (as produced by [`super::text_cleanup::clean_text`]), trimmed.
+fn paragraphs(text: &str) -> Vec<Range<usize>> {
+    let mut paragraphs = vec![];
+    let mut offset = 0;
+    for line in text.split('\n') {
+        let trimmed = line.trim_start();
+        let start = offset + (line.len() - trimmed.len());
+        let end = start + trimmed.trim_end().len();
+        if end > start {
+            paragraphs.push(start..end);
+        }
+        offset += line.len() + 1;
+    }
+    paragraphs
+}
+
+/// Whether a word is the number of a considérant or of a
+/// section (e.g. "1", "12", "IV")
+fn is_numbering(word: &str) -> bool {
+    !word.is_empty()
+        && (word.chars().all(|c| c.is_ascii_digit())
+            || word.chars().all(|c| matches!(c, 'I' | 'V' | 'X' | 'L' | 'C')))
+}
+
+/// Whether a period at the end of `before` ends a sentence
+fn period_ends_sentence(before: &str) -> bool {
+    let mut words = before.split_whitespace().rev();
+    let Some(word) = words.next() else {
+        return false;
+    };
+    let word = word.trim_start_matches(['(', '«', '"', '[']);
+    // "l'art."
+    let word = word.rsplit(['\'', '’']).next().unwrap_or(word);
+    let mut chars = word.chars();
+    if let (Some(c), None) = (chars.next(), chars.next())
+        && c.is_alphabetic()
+    {
+        return false;
+    }
+    if ABBREVIATIONS.contains(&word.to_lowercase().as_str()) {
+        return false;
+    }
+    // "1. Considérant que", or a list in a sentence: "... ; 2. Le"
+    let previous = words.next();
+    !(is_numbering(word) && previous.is_none_or(|p| p.ends_with([';', ':'])))
+}
+
+/// Byte ranges of the sentences of a paragraph
+fn sentences(paragraph: &str) -> Vec<Range<usize>> {
+    let chars: Vec<(usize, char)> = paragraph.char_indices().collect();
+    let mut sentences = vec![];
+    let mut start = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let (pos, c) = chars[i];
+        i += 1;
+        if !matches!(c, '.' | '!' | '?' | '…') {
+            continue;
+        }
+        // closing punctuation belongs to the sentence
+        let mut j = i;
+        loop {
+            while j < chars.len()
+                && matches!(chars[j].1, '.' | '!' | '?' | '…' | '»' | '"' | ')' | '”')
+            {
+                j += 1;
+            }
+            // French typography puts a space before the closing quote
+            let mut k = j;
+            while k < chars.len() && chars[k].1.is_whitespace() {
+                k += 1;
+            }
+            if k == j || chars.get(k).is_none_or(|&(_, c)| c != '»') {
+                break;
+            }
+            j = k + 1;
+        }
+        if j == chars.len() || !chars[j].1.is_whitespace() {
+            continue;
+        }
+        let mut k = j;
+        while k < chars.len() && chars[k].1.is_whitespace() {
+            k += 1;
+        }
+        let Some(&(next_pos, next)) = chars.get(k) else {
+            continue;
+        };
+        let starts_sentence = next.is_uppercase()
+            || next.is_ascii_digit()
+            || matches!(next, '«' | '"' | '(' | '-' | '–' | '—');
+        if !starts_sentence || (c == '.' && !period_ends_sentence(&paragraph[start..pos])) {
+            continue;
+        }
+        sentences.push(start..chars[j].0);
+        start = next_pos;
+        i = k;
+    }
+    if start < paragraph.len() {
+        sentences.push(start..paragraph.len());
+    }
+    sentences
+}
+
+/// Split a text into sentences, grouped by paragraph
+pub fn segment(text: &str) -> Vec<Sentence> {
+    let mut result = vec![];
+    // character offset of the byte `counted`
+    let (mut counted, mut chars) = (0, 0);
+    let mut char_offset = |byte: usize| {
+        chars += text[counted..byte].chars().count();
+        counted = byte;
+        chars
+    };
+    for (paragraph, range) in paragraphs(text).into_iter().enumerate() {
+        for sentence in sentences(&text[range.clone()]) {
+            let (start, end) = (range.start + sentence.start, range.start + sentence.end);
+            result.push(Sentence {
+                paragraph,
+                start: char_offset(start),
+                end: char_offset(end),
+                text: text[start..end].to_string(),
+            });
+        }
+    }
+    result
+}
+
+/// A sentence of a document, as written by [`DocumentSegmentWriter`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SegmentRecord {
+    pub id: String,
+    pub kind: String,
+    /// Index of the sentence in the document
+    pub sentence: usize,
+    #[serde(flatten)]
+    pub inner: Sentence,
+}
+
+/// Writes the sentences of the documents as JSON lines, one line per
+/// sentence with the id of its document and its offsets in the text.
+/// Documents without text (sections, texts of the Journal Officiel)
+/// are skipped. Files that could not be parsed are listed in a sibling
+/// `errors` file (e.g. `results.errors.jsonl`).
+pub struct DocumentSegmentWriter {
+    path: PathBuf,
+    writer: std::io::BufWriter<std::fs::File>,
+    errors: Option<std::io::BufWriter<std::fs::File>>,
+}
+
+impl DocumentSegmentWriter {
+    pub fn new<T: AsRef<Path>>(path: T) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let writer = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        Ok(DocumentSegmentWriter {
+            path,
+            writer,
+            errors: None,
+        })
+    }
+}
+
+impl DocumentWriter for DocumentSegmentWriter {
+    fn write(&mut self, document: &Document) -> anyhow::Result<()> {
+        let Some(text) = document.text() else {
+            return Ok(());
+        };
+        for (sentence, inner) in segment(text).into_iter().enumerate() {
+            let record = SegmentRecord {
+                id: document.id().to_string(),
+                kind: document.kind().to_string(),
+                sentence,
+                inner,
+            };
+            write_line(&mut self.writer, &record)?;
+        }
+        Ok(())
+    }
+
+    fn write_error(&mut self, path: &str, error: &ExtractError) -> anyhow::Result<()> {
+        if self.errors.is_none() {
+            let file = std::fs::File::create(sibling_path(&self.path, "errors", "jsonl"))?;
+            self.errors = Some(std::io::BufWriter::new(file));
+        }
+        let errors = self.errors.as_mut().expect("writer was just created");
+        write_line(
+            errors,
+            &serde_json::json!({ "path": path, "error": error.to_string() }),
+        )
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        if let Some(errors) = self.errors.as_mut() {
+            errors.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(text: &str) -> Vec<String> {
+        segment(text).into_iter().map(|s| s.text).collect()
+    }
+
+    #[test]
+    fn test_segment() {
+        let text = "1. Considérant que M. Dupont a saisi le tribunal. Il demande l'annulation \
+                    de la décision en application de l'art. 4 et de l'article L. 761-1 du code.\n\
+                    \n\
+                    2. Aux termes de la loi n° 2024-12 : « Nul ne peut être privé de ses droits. » \
+                    Le recours est rejeté !";
+        assert_eq!(
+            texts(text),
+            vec![
+                "1. Considérant que M. Dupont a saisi le tribunal.",
+                "Il demande l'annulation de la décision en application de l'art. 4 \
+                 et de l'article L. 761-1 du code.",
+                "2. Aux termes de la loi n° 2024-12 : « Nul ne peut être privé de ses droits. »",
+                "Le recours est rejeté !",
+            ]
+        );
+
+        let sentences = segment(text);
+        assert_eq!(sentences.iter().map(|s| s.paragraph).collect::<Vec<_>>(), vec![0, 0, 1, 1]);
+        // offsets are in characters
+        let chars: Vec<char> = text.chars().collect();
+        for s in sentences {
+            assert_eq!(chars[s.start..s.end].iter().collect::<String>(), s.text);
+        }
+
+        // numbered lists inside a sentence, and lowercase after a period
+        assert_eq!(
+            texts("Il invoque : 1. La loi ; 2. Le décret. Pour ces motifs, etc. décide."),
+            vec!["Il invoque : 1. La loi ; 2. Le décret.", "Pour ces motifs, etc. décide."]
+        );
+        assert!(segment("  \n\n ").is_empty());
+    }
+}
@@ -0,0 +1,209 @@
+/// Export of documents to a SQLite database, for R and Python users.
+/// The database holds one table per entity rather than one wide table:
+///
+/// - `documents`: one row per document (id, kind, date, jurisdiction,
+///   title, text, and the other columns of [`ExportRecord`] as JSON);
+/// - `links`: the texts cited by the documents;
+/// - `parties`: the judges and lawyers of the decisions;
+/// - `errors`: the files that could not be parsed.
+use rusqlite::{Connection, params};
+
+use std::path::Path;
+
+use super::export::ExportRecord;
+use super::markdown::{jurisdiction, title};
+use super::{Document, DocumentWriter, ExtractError};
+
+const SCHEMA: &str = "
+CREATE TABLE documents (
+    id TEXT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    date TEXT,
+    jurisdiction TEXT,
+    title TEXT,
+    text TEXT,
+    metadata TEXT NOT NULL
+);
+CREATE INDEX documents_date ON documents (date);
+CREATE INDEX documents_jurisdiction ON documents (jurisdiction);
+
+CREATE TABLE links (
+    document_id TEXT NOT NULL REFERENCES documents (id),
+    relation TEXT NOT NULL,
+    target_id TEXT NOT NULL,
+    title TEXT NOT NULL
+);
+CREATE INDEX links_document_id ON links (document_id);
+CREATE INDEX links_target_id ON links (target_id);
+
+CREATE TABLE parties (
+    document_id TEXT NOT NULL REFERENCES documents (id),
+    role TEXT NOT NULL,
+    name TEXT NOT NULL
+);
+CREATE INDEX parties_document_id ON parties (document_id);
+CREATE INDEX parties_name ON parties (name);
+
+CREATE TABLE errors (
+    path TEXT NOT NULL,
+    error TEXT NOT NULL
+);
+";
+
+/// The judges and lawyers of a document, with their role
+/// (`president`, `rapporteur`, `lawyer`, ...)
+pub(crate) fn parties(document: &Document) -> Vec<(&'static str, &str)> {
+    let Document::Decision(d) = document else {
+        return vec![];
+    };
+    let mut parties = vec![];
+    for (role, name) in [
+        ("requester", &d.requester),
+        ("president", &d.president),
+        ("rapporteur", &d.rapporteur),
+        ("government_commissioner", &d.government_commissioner),
+    ] {
+        if let Some(name) = name.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+            parties.push((role, name));
+        }
+    }
+    parties.extend(d.lawyers.iter().map(|lawyer| ("lawyer", lawyer.as_str())));
+    parties
+}
+
+/// Writes the documents to a new SQLite database (an existing file is
+/// replaced). The rows are written in a single transaction, committed
+/// by [`DocumentWriter::flush`].
+pub struct DocumentSqliteWriter {
+    connection: Connection,
+}
+
+impl DocumentSqliteWriter {
+    pub fn new<T: AsRef<Path>>(path: T) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+        connection.execute_batch("BEGIN")?;
+        Ok(DocumentSqliteWriter { connection })
+    }
+}
+
+impl DocumentWriter for DocumentSqliteWriter {
+    fn write(&mut self, document: &Document) -> anyhow::Result<()> {
+        let record = ExportRecord::try_from(document)?;
+        let metadata: serde_json::Map<String, serde_json::Value> = record
+            .metadata()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(column, value)| (column.to_string(), value.into()))
+            .collect();
+        let id = document.id();
+        // a document exported twice replaces its previous rows
+        self.connection
+            .execute("DELETE FROM links WHERE document_id = ?1", params![id])?;
+        self.connection
+            .execute("DELETE FROM parties WHERE document_id = ?1", params![id])?;
+        self.connection.execute(
+            "INSERT OR REPLACE INTO documents (id, kind, date, jurisdiction, title, text, metadata)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                id,
+                document.kind(),
+                document.date().map(|d| d.to_string()),
+                jurisdiction(document),
+                title(document),
+                document.text(),
+                serde_json::to_string(&metadata)?,
+            ],
+        )?;
+        let mut insert_link = self.connection.prepare_cached(
+            "INSERT INTO links (document_id, relation, target_id, title) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for link in document.links() {
+            insert_link.execute(params![id, link.relation, link.target_id, link.title])?;
+        }
+        let mut insert_party = self
+            .connection
+            .prepare_cached("INSERT INTO parties (document_id, role, name) VALUES (?1, ?2, ?3)")?;
+        for (role, name) in parties(document) {
+            insert_party.execute(params![id, role, name])?;
+        }
+        Ok(())
+    }
+
+    fn write_error(&mut self, path: &str, error: &ExtractError) -> anyhow::Result<()> {
+        self.connection.execute(
+            "INSERT INTO errors (path, error) VALUES (?1, ?2)",
+            params![path, error.to_string()],
+        )?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        self.connection.execute_batch("COMMIT; BEGIN")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dumps::extractor::{Link, PreDilaText};
+
+    #[test]
+    fn test_sqlite_writer() {
+        let document = Document::Decision(PreDilaText {
+            id: "CETATEXT000049314894".to_string(),
+            decision_date: Some("2024-03-21".to_string()),
+            jurisdiction: Some("Conseil d'État".to_string()),
+            president: Some("Alexandre Trémolière".to_string()),
+            lawyers: vec!["SCP Piwnica".to_string(), "SARL Cabinet Briard".to_string()],
+            text: "La requête est rejetée.".to_string(),
+            links: vec![Link {
+                relation: "CITATION".to_string(),
+                target_id: "LEGIARTI000006335064".to_string(),
+                title: "Code de l'urbanisme - article L600-1".to_string(),
+            }],
+            ..Default::default()
+        });
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let path = tmp.path().join("results.sqlite");
+        let mut writer = DocumentSqliteWriter::new(&path).unwrap();
+        writer.write(&document).unwrap();
+        writer.write(&document).unwrap();
+        writer
+            .write_error("missing.xml", &ExtractError::NotFound("missing.xml".into()))
+            .unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+
+        let connection = Connection::open(&path).unwrap();
+        let count = |table: &str| -> i64 {
+            connection
+                .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(count("documents"), 1);
+        assert_eq!(count("links"), 1);
+        assert_eq!(count("parties"), 3);
+        assert_eq!(count("errors"), 1);
+        let (date, jurisdiction): (String, String) = connection
+            .query_row("SELECT date, jurisdiction FROM documents", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(date, "2024-03-21");
+        assert_eq!(jurisdiction, "Conseil d'État");
+        let cited: String = connection
+            .query_row(
+                "SELECT d.id FROM documents d JOIN links l ON l.document_id = d.id
+                 WHERE l.target_id = 'LEGIARTI000006335064'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(cited, "CETATEXT000049314894");
+    }
+}
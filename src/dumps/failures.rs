@@ -0,0 +1,182 @@
+/// Files that could not be processed during a run (downloaded,
+/// extracted, indexed or exported), collected instead of only being
+/// logged, so that they can be listed at the end of the run in
+/// `errors.jsonl`, one JSON object per file:
+///
+/// ```json
+/// {"path":"juri/.../JURITEXT000049314894.xml","stage":"index","error":"..."}
+/// ```
+use anyhow::{Context, Result};
+use clap::Args;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Name of the report of the failures of a run
+pub const ERRORS_FILE: &str = "errors.jsonl";
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct FailureConfig {
+    /// Exit with an error when more than this fraction of the files
+    /// failed (e.g. `0.01`), the failures are listed in `errors.jsonl`
+    #[arg(long, value_name = "RATE", env = "LEGIFRANCE_MAX_FAILURE_RATE", global = true)]
+    pub max_failure_rate: Option<f64>,
+}
+
+/// The step of a run where a file failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    Download,
+    Extract,
+    Index,
+    Export,
+}
+
+/// A file that could not be processed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Failure {
+    pub path: String,
+    pub stage: Stage,
+    pub error: String,
+}
+
+#[derive(Debug, Default)]
+struct Failures {
+    failures: Vec<Failure>,
+    processed: usize,
+}
+
+/// The failures of a run, shared by the threads of the run
+/// (clones record to the same list)
+#[derive(Debug, Clone, Default)]
+pub struct FailureLog {
+    inner: Arc<Mutex<Failures>>,
+}
+
+impl FailureLog {
+    pub fn new() -> Self {
+        FailureLog::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Failures> {
+        // the list stays valid when a thread panicked while recording
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record that `path` failed at `stage`
+    pub fn record(&self, path: impl Into<String>, stage: Stage, error: impl std::fmt::Display) {
+        self.lock().failures.push(Failure {
+            path: path.into(),
+            stage,
+            error: format!("{:#}", error),
+        });
+    }
+
+    /// Count `count` files processed, successfully or not
+    pub fn processed(&self, count: usize) {
+        self.lock().processed += count;
+    }
+
+    pub fn failures(&self) -> Vec<Failure> {
+        self.lock().failures.clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().failures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().failures.is_empty()
+    }
+
+    /// Whether nothing was processed nor failed
+    pub fn is_unused(&self) -> bool {
+        let failures = self.lock();
+        failures.processed == 0 && failures.failures.is_empty()
+    }
+
+    /// Fraction of the processed files that failed
+    pub fn failure_rate(&self) -> f64 {
+        let failures = self.lock();
+        match failures.processed {
+            0 if failures.failures.is_empty() => 0.0,
+            0 => 1.0,
+            processed => (failures.failures.len() as f64 / processed as f64).min(1.0),
+        }
+    }
+
+    /// Write the failures as JSON lines to `path`
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let file =
+            std::fs::File::create(path).context(format!("Failed to create {}", path.display()))?;
+        let mut writer = std::io::BufWriter::new(file);
+        for failure in self.lock().failures.iter() {
+            serde_json::to_writer(&mut writer, failure)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// At the end of a run that processed files, write the failures
+    /// to `path`, and fail when their rate is above `max_rate`
+    pub fn report(&self, path: &Path, max_rate: Option<f64>) -> Result<()> {
+        if self.is_unused() {
+            return Ok(());
+        }
+        self.write(path)?;
+        if !self.is_empty() {
+            warn!("{} files failed, listed in {}", self.len(), path.display());
+        }
+        match max_rate {
+            Some(max_rate) if self.failure_rate() > max_rate => Err(anyhow::anyhow!(
+                "{:.2}% of the files failed, more than the maximum of {:.2}%",
+                100.0 * self.failure_rate(),
+                100.0 * max_rate
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failure_log() {
+        let log = FailureLog::new();
+        assert!(log.is_unused());
+        assert_eq!(log.failure_rate(), 0.0);
+
+        let shared = log.clone();
+        std::thread::spawn(move || {
+            shared.processed(3);
+            shared.record("jade/a.xml", Stage::Index, "malformed XML");
+        })
+        .join()
+        .unwrap();
+        log.processed(1);
+        assert_eq!(log.len(), 1);
+        assert_eq!(log.failure_rate(), 0.25);
+
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let path = tmp.path().join(ERRORS_FILE);
+        log.write(&path).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            content,
+            "{\"path\":\"jade/a.xml\",\"stage\":\"index\",\"error\":\"malformed XML\"}\n"
+        );
+        let failure: Failure = serde_json::from_str(content.trim()).unwrap();
+        assert_eq!(failure, log.failures()[0]);
+
+        assert!(log.report(&path, None).is_ok());
+        assert!(log.report(&path, Some(0.5)).is_ok());
+        assert!(log.report(&path, Some(0.1)).is_err());
+    }
+}
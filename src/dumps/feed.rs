@@ -0,0 +1,202 @@
+/// Atom feeds of the new documents matching the saved queries, kept
+/// by `dilarxiv watch` (one `{query}.atom` file per saved query) so
+/// that new texts of the Journal Officiel or new decisions can be
+/// followed in any feed reader. Every update adds the new documents at
+/// the top of the feed, which keeps the last [`FEED_LENGTH`] entries.
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use std::io::Write;
+use std::path::Path;
+
+use crate::dumps::extractor::identifiers::permalink;
+use crate::dumps::notify::NewMatch;
+
+/// Namespace of Atom 1.0
+pub const ATOM_NAMESPACE: &str = "http://www.w3.org/2005/Atom";
+
+/// Number of entries kept in a feed
+pub const FEED_LENGTH: usize = 100;
+
+fn atom_namespace() -> String {
+    ATOM_NAMESPACE.to_string()
+}
+
+/// `link` element of a feed or entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AtomLink {
+    #[serde(rename = "@href")]
+    pub href: String,
+}
+
+/// `author` element of a feed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AtomAuthor {
+    pub name: String,
+}
+
+/// A document of the feed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AtomEntry {
+    /// `urn:legifrance:` followed by the identifier of the document
+    pub id: String,
+    pub title: String,
+    /// Permalink of the document on legifrance.gouv.fr
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link: Option<AtomLink>,
+    /// When the document started matching the query
+    pub updated: DateTime<Utc>,
+    /// Date of the document
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published: Option<DateTime<Utc>>,
+    /// An excerpt of the text of the document
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+}
+
+impl From<(&NewMatch, DateTime<Utc>)> for AtomEntry {
+    fn from((m, found): (&NewMatch, DateTime<Utc>)) -> Self {
+        AtomEntry {
+            id: format!("urn:legifrance:{}", m.id),
+            title: m.title.clone().unwrap_or_else(|| m.id.clone()),
+            link: permalink(&m.id).map(|url| AtomLink { href: url.into() }),
+            updated: found,
+            published: m.date.and_then(|d| d.and_hms_opt(0, 0, 0)).map(|d| d.and_utc()),
+            summary: m.summary.clone(),
+        }
+    }
+}
+
+/// The feed of a saved query
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename = "feed")]
+pub struct AtomFeed {
+    #[serde(rename = "@xmlns", default = "atom_namespace")]
+    xmlns: String,
+    pub id: String,
+    pub title: String,
+    pub updated: DateTime<Utc>,
+    pub author: AtomAuthor,
+    /// The newest entries first
+    #[serde(rename = "entry", default)]
+    pub entries: Vec<AtomEntry>,
+}
+
+impl AtomFeed {
+    /// An empty feed for the saved query `query`
+    pub fn new(query: &str, now: DateTime<Utc>) -> Self {
+        AtomFeed {
+            xmlns: atom_namespace(),
+            id: format!("urn:legifrance:query:{}", query),
+            title: format!("Légifrance: {}", query),
+            updated: now,
+            author: AtomAuthor {
+                name: "dilarxiv".to_string(),
+            },
+            entries: vec![],
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<AtomFeed> {
+        let content =
+            std::fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+        quick_xml::de::from_str(&content).context(format!("Invalid feed in {}", path.display()))
+    }
+
+    /// Write the feed, replacing the file at `path` once written
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let tmp_path = path.with_extension("atom.tmp");
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+        xml.push_str(&quick_xml::se::to_string(self)?);
+        xml.push('\n');
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&tmp_path)?);
+        writer.write_all(xml.as_bytes())?;
+        writer.flush()?;
+        drop(writer);
+        std::fs::rename(&tmp_path, path).context(format!("Failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Add the documents of `matches` that are not in the feed yet,
+    /// returning the number of entries added
+    pub fn add(&mut self, matches: &[NewMatch], now: DateTime<Utc>) -> usize {
+        let mut added: Vec<AtomEntry> = matches
+            .iter()
+            .map(|m| AtomEntry::from((m, now)))
+            .filter(|entry| !self.entries.iter().any(|e| e.id == entry.id))
+            .collect();
+        let count = added.len();
+        if count > 0 {
+            added.append(&mut self.entries);
+            added.truncate(FEED_LENGTH);
+            self.entries = added;
+            self.updated = now;
+        }
+        count
+    }
+}
+
+/// Add the new documents matching the saved query `query` to its feed
+/// at `path`, created if it does not exist
+pub fn update_feed(path: &Path, query: &str, matches: &[NewMatch], now: DateTime<Utc>) -> Result<()> {
+    let mut feed = if path.exists() {
+        AtomFeed::from_file(path)?
+    } else {
+        AtomFeed::new(query, now)
+    };
+    if feed.add(matches, now) > 0 || !path.exists() {
+        feed.save(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_update_feed() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let path = tmp.path().join("antennes-relais.atom");
+        let first = DateTime::parse_from_rfc3339("2024-03-22T06:00:00Z").unwrap().to_utc();
+        update_feed(&path, "antennes-relais", &[], first).unwrap();
+        assert!(AtomFeed::from_file(&path).unwrap().entries.is_empty());
+
+        let decision = NewMatch {
+            id: "CETATEXT000049314894".to_string(),
+            path: "jade/CETATEXT000049314894.xml".to_string(),
+            title: Some("Conseil d'État, 21/03/2024 & <autres>".to_string()),
+            date: NaiveDate::from_ymd_opt(2024, 3, 21),
+            summary: Some("…l'installation d'une antenne relais…".to_string()),
+        };
+        let untitled = NewMatch {
+            id: "JORFTEXT000049300000".to_string(),
+            path: "jorf/JORFTEXT000049300000.xml".to_string(),
+            title: None,
+            date: None,
+            summary: None,
+        };
+        let second = first + chrono::Duration::days(1);
+        update_feed(&path, "antennes-relais", std::slice::from_ref(&decision), second).unwrap();
+        let third = second + chrono::Duration::days(1);
+        update_feed(&path, "antennes-relais", &[untitled, decision], third).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains("<feed xmlns=\"http://www.w3.org/2005/Atom\">"));
+        assert!(content.contains(
+            "<link href=\"https://www.legifrance.gouv.fr/ceta/id/CETATEXT000049314894\"/>"
+        ));
+        let feed = AtomFeed::from_file(&path).unwrap();
+        assert_eq!(feed.updated, third);
+        let ids: Vec<&str> = feed.entries.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, ["urn:legifrance:JORFTEXT000049300000", "urn:legifrance:CETATEXT000049314894"]);
+        let entry = &feed.entries[1];
+        assert_eq!(entry.title, "Conseil d'État, 21/03/2024 & <autres>");
+        assert_eq!(entry.updated, second);
+        assert_eq!(entry.published.unwrap().to_rfc3339(), "2024-03-21T00:00:00+00:00");
+        assert_eq!(feed.entries[0].title, "JORFTEXT000049300000");
+        assert_eq!(feed.entries[0].published, None);
+    }
+}
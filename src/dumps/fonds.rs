@@ -17,7 +17,7 @@ pub enum Fond {
 use clap::ValueEnum;
 impl ValueEnum for Fond {
     fn value_variants<'a>() -> &'a [Self] {
-        &FONDS
+        FONDS
     }
 
     fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
@@ -74,6 +74,18 @@ pub const FONDS: &[Fond] = &[
     Fond::CAPP,
 ];
 
+/// The fond of an extracted document, from the first directory of its
+/// path relative to the extraction directory (e.g. `jade/global/...`).
+/// Files directly in the extraction directory belong to "UNKNOWN".
+pub fn fond_of_extracted_path(path: &std::path::Path) -> String {
+    match path.components().next() {
+        Some(c) if path.components().count() > 1 => {
+            c.as_os_str().to_string_lossy().to_uppercase()
+        }
+        _ => "UNKNOWN".to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
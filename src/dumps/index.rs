@@ -0,0 +1,2735 @@
+/// Full text index of the extracted files, using tantivy: creation
+/// and update of the index (one per fond or a single one), and queries
+/// on it. Only built with the `index` feature.
+use anyhow::{Context, Result};
+
+use indicatif::ProgressStyle;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use log::{debug, warn};
+use serde::{Serialize, Deserialize};
+
+use chrono::{Datelike, NaiveDate};
+
+use crate::dumps::extractor::ExportRecord;
+use crate::dumps::failures::{FailureLog, Stage};
+use crate::dumps::fonds::fond_of_extracted_path;
+use crate::dumps::ledger::LedgerEntry;
+use crate::dumps::tarballs::{parse_tarball_name, suppressed_paths, walk_files};
+use crate::logging::progress_bar;
+use crate::shutdown;
+
+// STEP 3 create the index
+// using tantivy
+
+pub mod file_collector {
+
+    use std::fs::OpenOptions;
+    use std::io::BufWriter;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    use tantivy::collector::Collector;
+    use tantivy::collector::SegmentCollector;
+    use tantivy::index::SegmentReader;
+    use tantivy::schema::Field;
+    use tantivy::schema::{OwnedValue, TantivyDocument};
+    use tantivy::store::StoreReader;
+    use tantivy::{DocId, Result, Score, SegmentOrdinal};
+
+    /// In order to write the list of all matches into
+    /// a file we will create our own Collector / SegmentCollector
+    /// that will buffer-write the results to a file
+    ///
+    /// FileList collector is an empty struct used to implement the traits
+    pub struct FileListCollector {
+        path_field: Field,
+        bufwriter: FileCollectorFruit,
+    }
+
+    impl FileListCollector {
+        pub fn new(path_field: Field, file_path: &PathBuf) -> FileListCollector {
+            let file = OpenOptions::new()
+                .create(true)
+                .read(true)
+                .append(true)
+                .open(file_path)
+                .expect("could not open file");
+            let bufwriter = Arc::new(Mutex::new(BufWriter::new(file)));
+            FileListCollector {
+                path_field,
+                bufwriter,
+            }
+        }
+    }
+
+    // this will be shared between all segments
+    // so we need to make sure that it is thread-safe
+    // and we need to use a Mutex to protect it
+    type FileCollectorFruit = Arc<Mutex<std::io::BufWriter<std::fs::File>>>;
+
+    pub struct FileListSegmentCollector {
+        path_field: Field,
+        bufwriter: FileCollectorFruit,
+        store_reader: StoreReader,
+    }
+
+    impl SegmentCollector for FileListSegmentCollector {
+        type Fruit = ();
+
+        fn collect(&mut self, doc: DocId, _: Score) {
+            let doc: TantivyDocument = self
+                .store_reader
+                .get(doc)
+                .unwrap_or_else(|_| panic!("Could not get document {doc}"));
+
+            if let Some(OwnedValue::Str(s)) = doc.get_first(self.path_field) {
+                let mut lock = self.bufwriter.lock().expect("Unable to acquire lock");
+                writeln!(lock, "{}", s).expect("Unable to write to buffer");
+            }
+        }
+
+        fn harvest(self) {
+            let mut lock = self.bufwriter.lock().expect("Unable to acquire lock");
+            lock.flush().expect("unable to flush buffer");
+        }
+    }
+
+    impl Collector for FileListCollector {
+        type Fruit = ();
+        type Child = FileListSegmentCollector;
+
+        fn requires_scoring(&self) -> bool {
+            false
+        }
+
+        fn for_segment(
+            &self,
+            _: SegmentOrdinal,
+            segment_reader: &SegmentReader,
+        ) -> Result<Self::Child> {
+            let store = segment_reader.get_store_reader(100)?;
+            Ok(FileListSegmentCollector {
+                path_field: self.path_field,
+                bufwriter: self.bufwriter.clone(),
+                store_reader: store,
+            })
+        }
+
+        fn merge_fruits(&self, _: Vec<()>) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// The matching documents of a segment, one bit per document
+    pub struct Matches {
+        words: Vec<u64>,
+    }
+
+    impl Matches {
+        pub fn contains(&self, doc: DocId) -> bool {
+            self.words
+                .get(doc as usize / 64)
+                .is_some_and(|word| word & (1 << (doc % 64)) != 0)
+        }
+    }
+
+    /// Collects the matching documents of every segment,
+    /// in the order of the segments of the searcher
+    pub struct MatchCollector;
+
+    pub struct MatchSegmentCollector {
+        segment: SegmentOrdinal,
+        matches: Matches,
+    }
+
+    impl SegmentCollector for MatchSegmentCollector {
+        type Fruit = (SegmentOrdinal, Matches);
+
+        fn collect(&mut self, doc: DocId, _: Score) {
+            self.matches.words[doc as usize / 64] |= 1 << (doc % 64);
+        }
+
+        fn harvest(self) -> Self::Fruit {
+            (self.segment, self.matches)
+        }
+    }
+
+    impl Collector for MatchCollector {
+        type Fruit = Vec<Matches>;
+        type Child = MatchSegmentCollector;
+
+        fn requires_scoring(&self) -> bool {
+            false
+        }
+
+        fn for_segment(
+            &self,
+            segment: SegmentOrdinal,
+            segment_reader: &SegmentReader,
+        ) -> Result<Self::Child> {
+            let words = (segment_reader.max_doc() as usize).div_ceil(64);
+            Ok(MatchSegmentCollector {
+                segment,
+                matches: Matches { words: vec![0; words] },
+            })
+        }
+
+        fn merge_fruits(&self, mut fruits: Vec<(SegmentOrdinal, Matches)>) -> Result<Vec<Matches>> {
+            fruits.sort_by_key(|(segment, _)| *segment);
+            Ok(fruits.into_iter().map(|(_, matches)| matches).collect())
+        }
+    }
+}
+
+/// Counts of the results of a search per year and per jurisdiction,
+/// read from the fast fields of the index
+pub mod facet_collector {
+    use std::collections::BTreeMap;
+
+    use chrono::Datelike;
+    use serde::{Deserialize, Serialize};
+    use tantivy::collector::{Collector, SegmentCollector};
+    use tantivy::fastfield::Column;
+    use tantivy::index::SegmentReader;
+    use tantivy::{DateTime, DocId, Result, Score, SegmentOrdinal};
+
+    /// Number of results per year and per jurisdiction. Results
+    /// without a date or a jurisdiction are not counted.
+    #[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct Facets {
+        pub years: BTreeMap<i32, usize>,
+        pub jurisdictions: BTreeMap<String, usize>,
+    }
+
+    impl Facets {
+        /// Add the counts of `other`, e.g. the results of another index
+        pub fn merge(&mut self, other: Facets) {
+            for (year, count) in other.years {
+                *self.years.entry(year).or_insert(0) += count;
+            }
+            for (jurisdiction, count) in other.jurisdictions {
+                *self.jurisdictions.entry(jurisdiction).or_insert(0) += count;
+            }
+        }
+    }
+
+    /// Collects the [`Facets`] of the results, given the names of the
+    /// date field and of the jurisdiction field (absent in old indices)
+    pub struct FacetCollector {
+        date_field: String,
+        jurisdiction_field: Option<String>,
+    }
+
+    impl FacetCollector {
+        pub fn new(date_field: &str, jurisdiction_field: Option<&str>) -> FacetCollector {
+            FacetCollector {
+                date_field: date_field.to_string(),
+                jurisdiction_field: jurisdiction_field.map(str::to_string),
+            }
+        }
+    }
+
+    /// The jurisdictions of a segment: the term ordinal of
+    /// each document, and the names of the ordinals
+    struct Jurisdictions {
+        ordinal: Box<dyn Fn(DocId) -> Option<u64> + Send>,
+        names: Vec<String>,
+        counts: Vec<usize>,
+    }
+
+    pub struct FacetSegmentCollector {
+        dates: Column<DateTime>,
+        jurisdictions: Option<Jurisdictions>,
+        years: BTreeMap<i32, usize>,
+    }
+
+    impl SegmentCollector for FacetSegmentCollector {
+        type Fruit = Facets;
+
+        fn collect(&mut self, doc: DocId, _: Score) {
+            if let Some(date) = self.dates.first(doc).and_then(super::from_tantivy_date) {
+                *self.years.entry(date.year()).or_insert(0) += 1;
+            }
+            if let Some(jurisdictions) = self.jurisdictions.as_mut()
+                && let Some(ordinal) = (jurisdictions.ordinal)(doc)
+            {
+                jurisdictions.counts[ordinal as usize] += 1;
+            }
+        }
+
+        fn harvest(self) -> Facets {
+            let jurisdictions = self
+                .jurisdictions
+                .map(|j| {
+                    j.names
+                        .into_iter()
+                        .zip(j.counts)
+                        .filter(|(_, count)| *count > 0)
+                        .collect()
+                })
+                .unwrap_or_default();
+            Facets {
+                years: self.years,
+                jurisdictions,
+            }
+        }
+    }
+
+    impl Collector for FacetCollector {
+        type Fruit = Facets;
+        type Child = FacetSegmentCollector;
+
+        fn requires_scoring(&self) -> bool {
+            false
+        }
+
+        fn for_segment(
+            &self,
+            _: SegmentOrdinal,
+            segment_reader: &SegmentReader,
+        ) -> Result<Self::Child> {
+            let fast_fields = segment_reader.fast_fields();
+            let dates = fast_fields.date(&self.date_field)?;
+            let column = match &self.jurisdiction_field {
+                Some(field) => fast_fields.str(field)?,
+                None => None,
+            };
+            let jurisdictions = match column {
+                Some(column) => {
+                    let mut names = Vec::with_capacity(column.num_terms());
+                    for ordinal in 0..column.num_terms() as u64 {
+                        let mut name = String::new();
+                        column.ord_to_str(ordinal, &mut name)?;
+                        names.push(name);
+                    }
+                    Some(Jurisdictions {
+                        counts: vec![0; names.len()],
+                        names,
+                        ordinal: Box::new(move |doc| column.term_ords(doc).next()),
+                    })
+                }
+                None => None,
+            };
+            Ok(FacetSegmentCollector {
+                dates,
+                jurisdictions,
+                years: BTreeMap::new(),
+            })
+        }
+
+        fn merge_fruits(&self, fruits: Vec<Facets>) -> Result<Facets> {
+            let mut facets = Facets::default();
+            for fruit in fruits {
+                facets.merge(fruit);
+            }
+            Ok(facets)
+        }
+    }
+
+    /// Counts the results per year (`2024`) or per month (`2024-03`),
+    /// given the name of the date field. Results without a date
+    /// are not counted.
+    pub struct HistogramCollector {
+        date_field: String,
+        bucket: super::HistogramBucket,
+    }
+
+    impl HistogramCollector {
+        pub fn new(date_field: &str, bucket: super::HistogramBucket) -> HistogramCollector {
+            HistogramCollector {
+                date_field: date_field.to_string(),
+                bucket,
+            }
+        }
+    }
+
+    pub struct HistogramSegmentCollector {
+        dates: Column<DateTime>,
+        bucket: super::HistogramBucket,
+        counts: BTreeMap<String, usize>,
+    }
+
+    impl SegmentCollector for HistogramSegmentCollector {
+        type Fruit = BTreeMap<String, usize>;
+
+        fn collect(&mut self, doc: DocId, _: Score) {
+            if let Some(date) = self.dates.first(doc).and_then(super::from_tantivy_date) {
+                *self.counts.entry(self.bucket.key(date)).or_insert(0) += 1;
+            }
+        }
+
+        fn harvest(self) -> Self::Fruit {
+            self.counts
+        }
+    }
+
+    impl Collector for HistogramCollector {
+        type Fruit = BTreeMap<String, usize>;
+        type Child = HistogramSegmentCollector;
+
+        fn requires_scoring(&self) -> bool {
+            false
+        }
+
+        fn for_segment(
+            &self,
+            _: SegmentOrdinal,
+            segment_reader: &SegmentReader,
+        ) -> Result<Self::Child> {
+            Ok(HistogramSegmentCollector {
+                dates: segment_reader.fast_fields().date(&self.date_field)?,
+                bucket: self.bucket,
+                counts: BTreeMap::new(),
+            })
+        }
+
+        fn merge_fruits(&self, fruits: Vec<Self::Fruit>) -> Result<Self::Fruit> {
+            let mut counts = BTreeMap::new();
+            for fruit in fruits {
+                for (key, count) in fruit {
+                    *counts.entry(key).or_insert(0) += count;
+                }
+            }
+            Ok(counts)
+        }
+    }
+}
+
+pub struct IndexFields {
+    path: tantivy::schema::Field,
+    body: tantivy::schema::Field,
+    /// Whether the text is stored besides being indexed
+    /// (see [`AnalyzerConfig::store_body`])
+    body_stored: bool,
+    /// Date of the document (see [`crate::dumps::extractor::Document::date`])
+    date: tantivy::schema::Field,
+    /// Year of the date, to be queried as `year:[2020 TO 2024]`
+    year: Option<tantivy::schema::Field>,
+    /// Metadata of the parsed document (see [`ExportRecord`]),
+    /// missing in indices created by older versions
+    metadata: Option<tantivy::schema::Field>,
+    /// Identifier of the document (e.g. "CETATEXT000049314000")
+    id: Option<tantivy::schema::Field>,
+    /// Jurisdiction of decisions (e.g. "Conseil d'État")
+    jurisdiction: Option<tantivy::schema::Field>,
+    /// Nature of the document (e.g. "Arrêt", "DECRET")
+    nature: Option<tantivy::schema::Field>,
+    /// Fond of the document, from its path (e.g. "JADE")
+    fond: Option<tantivy::schema::Field>,
+    /// Path of the file the document was read from, shared by
+    /// the articles of a text (see [`remove_file`])
+    file: Option<tantivy::schema::Field>,
+    /// Name of the tarball the document was extracted from
+    /// (see [`crate::dumps::provenance`] and [`remove_tarball`])
+    tarball: Option<tantivy::schema::Field>,
+    /// Date of the tarball the document was extracted from
+    tarball_date: Option<tantivy::schema::Field>,
+}
+
+impl IndexFields {
+    /// Look up the fields by name, so that indices created
+    /// before a field was added can still be opened
+    fn from_schema(schema: &tantivy::schema::Schema) -> Result<IndexFields> {
+        Ok(IndexFields {
+            path: schema.get_field("path")?,
+            body: schema.get_field("body")?,
+            body_stored: schema.get_field_entry(schema.get_field("body")?).is_stored(),
+            date: schema.get_field("date").context(
+                "The index was created by an older version, delete it and index again",
+            )?,
+            year: schema.get_field("year").ok(),
+            metadata: schema.get_field("metadata").ok(),
+            id: schema.get_field("id").ok(),
+            jurisdiction: schema.get_field("jurisdiction").ok(),
+            nature: schema.get_field("nature").ok(),
+            fond: schema.get_field("fond").ok(),
+            file: schema.get_field("file").ok(),
+            tarball: schema.get_field("tarball").ok(),
+            tarball_date: schema.get_field("tarball_date").ok(),
+        })
+    }
+
+    /// Fail when the text of the documents is not stored in the index
+    fn require_body(&self) -> Result<()> {
+        if !self.body_stored {
+            anyhow::bail!(
+                "The index does not store the text of the documents, index again with the text stored"
+            );
+        }
+        Ok(())
+    }
+
+    /// The fields searched by queries that do not name a field:
+    /// the text, and the exact values of the dedicated metadata fields
+    /// (so that `CETATEXT000049314000` finds that decision). Other
+    /// fields are queried as `jurisdiction:"Conseil d'État"`.
+    fn default_search_fields(&self) -> Vec<tantivy::schema::Field> {
+        std::iter::once(self.body)
+            .chain(self.id)
+            .chain(self.jurisdiction)
+            .chain(self.nature)
+            .chain(self.fond)
+            .collect()
+    }
+
+    /// The tantivy document of an indexed file
+    fn document(&self, file: FondXMLFile) -> tantivy::TantivyDocument {
+        use tantivy::schema::OwnedValue;
+
+        let mut doc = tantivy::TantivyDocument::default();
+        let (path, _) = crate::dumps::extractor::split_article_path(&file.path);
+        if let Some(field) = self.fond {
+            doc.add_text(field, fond_of_extracted_path(Path::new(path)));
+        }
+        if let Some(field) = self.file {
+            doc.add_text(field, path);
+        }
+        if let Some(record) = file.metadata.as_ref() {
+            for (field, column) in [
+                (self.id, "id"),
+                (self.jurisdiction, "jurisdiction"),
+                (self.nature, "nature"),
+            ] {
+                if let (Some(field), Some(value)) =
+                    (field, record.get(column).filter(|v| !v.is_empty()))
+                {
+                    doc.add_text(field, value);
+                }
+            }
+        }
+        if let Some(tarball) = file.tarball.as_deref() {
+            if let Some(field) = self.tarball {
+                doc.add_text(field, tarball);
+            }
+            if let (Some(field), Ok(parsed)) =
+                (self.tarball_date, parse_tarball_name(tarball))
+            {
+                doc.add_date(field, to_tantivy_date(parsed.date));
+            }
+        }
+        // the strings are moved, `add_text` would copy them
+        doc.add_field_value(self.path, OwnedValue::Str(file.path));
+        doc.add_field_value(self.body, OwnedValue::Str(file.body));
+        if let Some(date) = file.date {
+            doc.add_date(self.date, to_tantivy_date(date));
+            if let Some(field) = self.year {
+                doc.add_i64(field, date.year() as i64);
+            }
+        }
+        if let (Some(field), Some(record)) = (self.metadata, file.metadata) {
+            let object = record
+                .metadata()
+                .filter(|(_, v)| !v.is_empty())
+                .map(|(c, v)| (c.to_string(), OwnedValue::from(v)))
+                .collect();
+            doc.add_object(field, object);
+        }
+        doc
+    }
+}
+
+/// Name of the file recording the [`AnalyzerConfig`] of an index,
+/// in the directory of the index
+pub const ANALYZER_FILE: &str = "analyzer.toml";
+
+/// Settings of the analyzer of the text of the documents. They are
+/// chosen when an index is created and saved in its directory
+/// (see [`ANALYZER_FILE`]), so that queries go through the same
+/// pipeline as the indexed documents. They are written in TOML,
+/// every setting being optional:
+///
+/// ```toml
+/// stemming = true
+/// stopwords = ["considérant", "requérant"]
+/// max_token_length = 40
+/// ascii_folding = true
+/// store_body = true
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalyzerConfig {
+    /// Reduce words to their stem with the French snowball
+    /// stemmer, so that "constructions" matches "construction"
+    pub stemming: bool,
+    /// Words ignored besides the French stopwords, compared
+    /// to the words of the text before removing accents
+    pub stopwords: Vec<String>,
+    /// Words of this length (in bytes) or longer are ignored
+    pub max_token_length: usize,
+    /// Remove the accents, so that "decision" matches "décision"
+    pub ascii_folding: bool,
+    /// Store the text in the index besides indexing it. Without it the
+    /// index is about half the size, the text being read from the XML
+    /// files, but [`similar_documents`] and [`for_each_document`]
+    /// are not available.
+    pub store_body: bool,
+}
+
+impl Default for AnalyzerConfig {
+    fn default() -> Self {
+        AnalyzerConfig {
+            stemming: false,
+            stopwords: vec![],
+            max_token_length: 40,
+            ascii_folding: true,
+            store_body: true,
+        }
+    }
+}
+
+impl AnalyzerConfig {
+    /// Read settings from a TOML file
+    pub fn from_file(path: &Path) -> Result<AnalyzerConfig> {
+        let content = std::fs::read_to_string(path)
+            .context(format!("Failed to read {}", path.display()))?;
+        toml::from_str(&content).context(format!("Invalid analyzer settings {}", path.display()))
+    }
+
+    /// The settings of the index at `index_path`. Indices created
+    /// before the settings were saved use the default ones.
+    pub fn load(index_path: &Path) -> Result<AnalyzerConfig> {
+        let path = index_path.join(ANALYZER_FILE);
+        if !path.exists() {
+            return Ok(AnalyzerConfig::default());
+        }
+        AnalyzerConfig::from_file(&path)
+    }
+
+    fn save(&self, index_path: &Path) -> Result<()> {
+        let path = index_path.join(ANALYZER_FILE);
+        std::fs::write(&path, toml::to_string(self)?)
+            .context(format!("Failed to write {}", path.display()))
+    }
+
+    fn tokenizer(&self) -> tantivy::tokenizer::TextAnalyzer {
+        use tantivy::tokenizer::*;
+
+        let mut builder = TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(RemoveLongFilter::limit(self.max_token_length))
+            .filter(LowerCaser)
+            .dynamic();
+        if !self.stopwords.is_empty() {
+            let words = self.stopwords.iter().map(|w| w.to_lowercase());
+            builder = builder.filter_dynamic(StopWordFilter::remove(words));
+        }
+        if self.ascii_folding {
+            builder = builder.filter_dynamic(AsciiFoldingFilter);
+        }
+        builder = builder.filter_dynamic(StopWordFilter::new(Language::French).unwrap());
+        if self.stemming {
+            builder = builder.filter_dynamic(Stemmer::new(Language::French));
+        }
+        builder.build()
+    }
+}
+
+#[inline(always)]
+fn build_schema(store_body: bool) -> tantivy::schema::Schema {
+    use tantivy::schema::*;
+
+    let idx_fr = TextFieldIndexing::default()
+        .set_tokenizer("custom_fr")
+        .set_index_option(IndexRecordOption::WithFreqsAndPositions);
+
+    let mut opts_fr = TextOptions::default().set_indexing_options(idx_fr);
+    if store_body {
+        opts_fr = opts_fr.set_stored();
+    }
+
+    let mut schema_builder = Schema::builder();
+    schema_builder.add_text_field("path", STRING | STORED);
+    schema_builder.add_text_field("body", opts_fr);
+    schema_builder.add_date_field("date", FAST | INDEXED | STORED);
+    schema_builder.add_i64_field("year", INDEXED);
+    schema_builder.add_json_field("metadata", STRING | STORED);
+    // exact values, fast to filter and aggregate on
+    schema_builder.add_text_field("id", STRING | STORED);
+    schema_builder.add_text_field("jurisdiction", STRING | STORED | FAST);
+    schema_builder.add_text_field("nature", STRING | STORED | FAST);
+    schema_builder.add_text_field("fond", STRING | STORED | FAST);
+    schema_builder.add_text_field("file", STRING);
+    schema_builder.add_text_field("tarball", STRING | STORED);
+    schema_builder.add_date_field("tarball_date", INDEXED | STORED);
+    schema_builder.build()
+}
+
+pub fn init_tantivy(index_path: &PathBuf) -> Result<(tantivy::Index, IndexFields)> {
+    init_tantivy_with(index_path, &AnalyzerConfig::default())
+}
+
+/// Open the index at `index_path`, or create it with the given
+/// analyzer settings. Existing indices keep the settings they
+/// were created with. A damaged index is an error, as it may be read
+/// by other processes: see [`repair_damaged_index`].
+pub fn init_tantivy_with(
+    index_path: &PathBuf,
+    analyzer: &AnalyzerConfig,
+) -> Result<(tantivy::Index, IndexFields)> {
+    use tantivy::Index;
+
+    // If the index does not exist, create it
+    // otherwise open it
+    let (index, analyzer) = match open_index(index_path) {
+        Ok(index) => {
+            let stored = AnalyzerConfig::load(index_path)?;
+            if stored != *analyzer && *analyzer != AnalyzerConfig::default() {
+                warn!(
+                    "The index at {} was created with other analyzer settings, keeping them",
+                    index_path.display()
+                );
+            }
+            (index, stored)
+        }
+        Err(e) if index_path.join(META_FILE).exists() => {
+            return Err(anyhow::anyhow!(
+                "The index at {} is damaged ({:#}), repair it with `--repair-index` \
+                 or rebuild it with `--rebuild-index`",
+                index_path.display(),
+                e
+            ));
+        }
+        Err(_) if has_segment_files(index_path) => {
+            return Err(anyhow::anyhow!(
+                "The index at {} has lost its {}, rebuild it with `dilarxiv index` or `--rebuild-index`",
+                index_path.display(),
+                META_FILE
+            ));
+        }
+        Err(_) => {
+            // Create the index
+            let index = Index::create_in_dir(index_path, build_schema(analyzer.store_body))?;
+            analyzer.save(index_path)?;
+            (index, analyzer.clone())
+        }
+    };
+
+    index.tokenizers().register("custom_fr", analyzer.tokenizer());
+    let fields = IndexFields::from_schema(&index.schema())?;
+
+    Ok((index, fields))
+}
+
+/// The list of the segments of an index, which tantivy writes last
+const META_FILE: &str = "meta.json";
+
+/// Open the index at `index_path`, checking that the files of its
+/// segments exist
+fn open_index(index_path: &Path) -> Result<tantivy::Index> {
+    let index = tantivy::Index::open_in_dir(index_path)?;
+    let metas = index.load_metas()?;
+    let missing: Vec<PathBuf> = metas
+        .segments
+        .iter()
+        .flat_map(segment_files)
+        .filter(|file| !index_path.join(file).exists())
+        .collect();
+    if !missing.is_empty() {
+        return Err(anyhow::anyhow!(
+            "missing segment files {}",
+            missing.iter().map(|f| f.display().to_string()).collect::<Vec<_>>().join(", ")
+        ));
+    }
+    Ok(index)
+}
+
+/// The files that a segment of an index needs (unlike
+/// `SegmentMeta::list_files`, which also lists the files it may have)
+fn segment_files(segment: &tantivy::SegmentMeta) -> Vec<PathBuf> {
+    use tantivy::SegmentComponent;
+
+    SegmentComponent::iterator()
+        .filter(|component| match component {
+            SegmentComponent::TempStore => false,
+            SegmentComponent::Delete => segment.has_deletes(),
+            _ => true,
+        })
+        .map(|component| segment.relative_path(*component))
+        .collect()
+}
+
+/// Whether the directory `index_path` has files of tantivy segments
+fn has_segment_files(index_path: &Path) -> bool {
+    std::fs::read_dir(index_path).is_ok_and(|entries| {
+        entries.flatten().any(|entry| {
+            let path = entry.path();
+            matches!(path.extension().and_then(|e| e.to_str()), Some("idx" | "store" | "term"))
+        })
+    })
+}
+
+/// What [`repair_index`] did to an index
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexRepair {
+    /// Segments dropped, as some of their files were missing or corrupted
+    pub dropped_segments: usize,
+    /// Documents of the dropped segments, missing until they are indexed again
+    pub lost_documents: u64,
+    /// Files of no segment (e.g. of an interrupted commit or merge) removed
+    pub removed_files: usize,
+}
+
+impl IndexRepair {
+    fn drop_segments(&mut self, segments: Vec<tantivy::SegmentMeta>) {
+        for segment in segments {
+            warn!("Dropping segment {} of the index", segment.id().uuid_string());
+            self.dropped_segments += 1;
+            self.lost_documents += segment.num_docs() as u64;
+        }
+    }
+}
+
+/// Repair the index at `index_path`, e.g. after the process was
+/// killed while writing it: the segments whose files are missing or
+/// corrupted are dropped from its `meta.json` (the previous one is kept
+/// as `meta.json.damaged`), and the files of no segment are removed.
+/// The documents of the dropped segments are lost until the index is
+/// rebuilt. Fails if `meta.json` itself cannot be read.
+pub fn repair_index(index_path: &Path) -> Result<IndexRepair> {
+    let mut repair = IndexRepair::default();
+    let index = tantivy::Index::open_in_dir(index_path)?;
+    let mut metas = index.load_metas()?;
+    let backup = index_path.join(format!("{}.damaged", META_FILE));
+    std::fs::copy(index_path.join(META_FILE), &backup)
+        .context(format!("Failed to back up {}", backup.display()))?;
+
+    let missing = |segment: &tantivy::SegmentMeta| {
+        segment_files(segment).iter().any(|file| !index_path.join(file).exists())
+    };
+    let (dropped, kept) = metas.segments.into_iter().partition(missing);
+    metas.segments = kept;
+    repair.drop_segments(dropped);
+    save_metas(index_path, &metas)?;
+
+    // the files that are left are checked against their checksum
+    let index = tantivy::Index::open_in_dir(index_path)?;
+    let corrupted = index.validate_checksum()?;
+    if !corrupted.is_empty() {
+        let (dropped, kept) = metas
+            .segments
+            .into_iter()
+            .partition(|segment: &tantivy::SegmentMeta| {
+                segment_files(segment).iter().any(|file| corrupted.contains(file))
+            });
+        metas.segments = kept;
+        repair.drop_segments(dropped);
+        save_metas(index_path, &metas)?;
+    }
+
+    let index = tantivy::Index::open_in_dir(index_path)?;
+    let writer: tantivy::IndexWriter = index.writer_with_num_threads(1, 15_000_000)?;
+    let collected = writer.garbage_collect_files().wait()?;
+    repair.removed_files = collected.deleted_files.len();
+    Ok(repair)
+}
+
+/// Repair the index at `index_path` if it exists but cannot be opened
+/// (see [`repair_index`]), returning what was done. The index must not
+/// be used by another process meanwhile, i.e. the data directory must be
+/// locked (see [`crate::dumps::lock::DataLock`]).
+pub fn repair_damaged_index(index_path: &Path) -> Result<Option<IndexRepair>> {
+    if !index_path.join(META_FILE).exists() {
+        return Ok(None);
+    }
+    let Err(e) = open_index(index_path) else {
+        return Ok(None);
+    };
+    warn!("The index at {} is damaged ({:#}), repairing it", index_path.display(), e);
+    let repair = repair_index(index_path)
+        .and_then(|repair| open_index(index_path).map(|_| repair))
+        .context(format!(
+            "The index at {} could not be repaired, rebuild it with `dilarxiv index` or `--rebuild-index`",
+            index_path.display()
+        ))?;
+    warn!(
+        "Repaired the index at {}: {} segments ({} documents) dropped, {} files removed",
+        index_path.display(),
+        repair.dropped_segments,
+        repair.lost_documents,
+        repair.removed_files
+    );
+    Ok(Some(repair))
+}
+
+/// Replace the `meta.json` of the index at `index_path`, atomically
+fn save_metas(index_path: &Path, metas: &tantivy::IndexMeta) -> Result<()> {
+    let path = index_path.join(META_FILE);
+    let tmp = index_path.join(format!("{}.tmp", META_FILE));
+    let mut buffer = serde_json::to_vec_pretty(metas)?;
+    buffer.push(b'\n');
+    std::fs::write(&tmp, buffer).context(format!("Failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, &path).context(format!("Failed to replace {}", path.display()))
+}
+
+pub fn init_tantivy_ram() -> Result<(tantivy::Index, IndexFields)> {
+    init_tantivy_ram_with(&AnalyzerConfig::default())
+}
+
+/// Create an index in RAM with the given analyzer settings
+pub fn init_tantivy_ram_with(analyzer: &AnalyzerConfig) -> Result<(tantivy::Index, IndexFields)> {
+    use tantivy::Index;
+
+    // Create the index in RAM
+    let index = Index::create_in_ram(build_schema(analyzer.store_body));
+    index.tokenizers().register("custom_fr", analyzer.tokenizer());
+    let fields = IndexFields::from_schema(&index.schema())?;
+
+    Ok((index, fields))
+}
+
+/// The date tags of the metadata, by priority: the date of the decision,
+/// of publication in the Journal Officiel, of signature of the text and
+/// of the first day of application of an article
+const DATE_TAGS: [&str; 4] = ["DATE_DEC", "DATE_PUBLI", "DATE_TEXTE", "DATE_DEBUT"];
+
+/// The date of a document whose metadata could not be parsed (or has
+/// no date), read from the date tags of its `META` element. The text
+/// is left out, as it cites many older dates (e.g. the dates of the
+/// attacked decision or of the laws applied), and the first date tag
+/// matching `re` is only used when none of [`DATE_TAGS`] is found.
+fn get_date_juri(doc: &str, re: &regex::Regex) -> Option<NaiveDate> {
+    let metadata = doc.find("</META>").map_or(doc, |end| &doc[..end]);
+    let parse = |date: &str| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok();
+    DATE_TAGS
+        .iter()
+        .find_map(|tag| {
+            let open = format!("<{}>", tag);
+            let start = metadata.find(&open)? + open.len();
+            parse(metadata.get(start..start + 10)?)
+        })
+        .or_else(|| re.captures_iter(metadata).find_map(|cap| parse(&cap["date"])))
+}
+
+/// Dates are indexed at midnight UTC
+fn to_tantivy_date(date: NaiveDate) -> tantivy::DateTime {
+    tantivy::DateTime::from_timestamp_secs(date.and_time(chrono::NaiveTime::MIN).and_utc().timestamp())
+}
+
+fn from_tantivy_date(date: tantivy::DateTime) -> Option<NaiveDate> {
+    chrono::DateTime::from_timestamp(date.into_timestamp_secs(), 0).map(|d| d.date_naive())
+}
+
+#[derive(Debug, Clone)]
+struct FondXMLFile {
+    path: String,
+    body: String,
+    date: Option<NaiveDate>,
+    metadata: Option<ExportRecord>,
+    /// Name of the tarball the file was extracted from, if known
+    tarball: Option<String>,
+    /// Whether the document is a placeholder, which is not indexed
+    /// (see [`crate::dumps::extractor::Document::is_empty`])
+    empty: bool,
+}
+
+fn parse_file(
+    dir: &Path,
+    file: &Path,
+    re: &regex::Regex,
+    per_article: bool,
+) -> Result<Vec<FondXMLFile>> {
+    let path = file
+        .strip_prefix(dir)
+        .map_err(|_| anyhow::anyhow!("Failed to strip prefix from {}", file.display()))?
+        .to_string_lossy()
+        .to_string();
+    parse_file_as(path, file, re, per_article)
+}
+
+/// Parse `file`, recording it in the index under the name `path`
+fn parse_file_as(
+    path: String,
+    file: &Path,
+    re: &regex::Regex,
+    per_article: bool,
+) -> Result<Vec<FondXMLFile>> {
+    use std::io::Read;
+
+    READ_BUFFER.with_borrow_mut(|buffer| {
+        buffer.clear();
+        {
+            let _open = crate::open_files::acquire(1);
+            std::fs::File::open(crate::dumps::winpath::extended_length(file))
+                .and_then(|mut f| f.read_to_end(buffer))
+                .context("Could not open file")?;
+        }
+        // UTF-8 files are parsed in place
+        let body = crate::dumps::extractor::encoding::decode(buffer);
+        let parsed = parse_body_as(path, &body, re, per_article);
+        drop(body);
+        if buffer.capacity() > MAX_READ_BUFFER {
+            *buffer = Vec::new();
+        }
+        parsed
+    })
+}
+
+/// Largest buffer kept by a parsing thread for the next file, the
+/// few larger files get a buffer of their own
+const MAX_READ_BUFFER: usize = 16 << 20;
+
+thread_local! {
+    /// The content of the file being parsed, whose allocation is
+    /// reused for the next files parsed by the same thread
+    static READ_BUFFER: std::cell::RefCell<Vec<u8>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+/// Parse the content of a document. With `per_article`, texts
+/// embedding their articles give one entry per article, recorded
+/// under the name `path#id` (see [`crate::dumps::extractor::article_path`]).
+fn parse_body_as(
+    path: String,
+    body: &str,
+    re: &regex::Regex,
+    per_article: bool,
+) -> Result<Vec<FondXMLFile>> {
+    use crate::dumps::extractor::{self, Document, text_cleanup::clean_text};
+
+    // metadata is a bonus, documents that cannot be parsed are still searchable
+    let record = |document: &Document| match ExportRecord::try_from(document) {
+        Ok(record) => Some(record),
+        Err(e) => {
+            debug!("Could not export the metadata of {}: {}", path, e);
+            None
+        }
+    };
+
+    if per_article {
+        let articles = extractor::legi::split_articles(body)?;
+        if !articles.is_empty() {
+            return Ok(articles
+                .into_iter()
+                .map(|article| {
+                    let text = clean_text(&article.contenu);
+                    let article_path = extractor::article_path(&path, &article.id);
+                    let document = Document::LegiArticle(article);
+                    FondXMLFile {
+                        path: article_path,
+                        body: text,
+                        // an article is dated by its first day of application
+                        date: document.date().or_else(|| get_date_juri(body, re)),
+                        metadata: record(&document),
+                        tarball: None,
+                        empty: document.is_empty(),
+                    }
+                })
+                .collect());
+        }
+    }
+    let (date, metadata, empty) = match extractor::parse_document(body) {
+        Ok(document) => (document.date(), record(&document), document.is_empty()),
+        Err(e) => {
+            debug!("Could not parse {}: {}", path, e);
+            (None, None, false)
+        }
+    };
+    let date = date.or_else(|| get_date_juri(body, re));
+    // tags and entities are noise for the full text search
+    let body = clean_text(body);
+    Ok(vec![FondXMLFile {
+        path,
+        body,
+        date,
+        metadata,
+        tarball: None,
+        empty,
+    }])
+}
+
+/// Index a file in the tantivy index
+fn index_file(
+    index_writer: &tantivy::IndexWriter,
+    fields: &IndexFields,
+    file: FondXMLFile,
+) -> Result<()> {
+    index_writer.add_document(fields.document(file))?;
+    Ok(())
+}
+
+/// Remove from the index the documents read from the file at `path`
+/// (relative to the extraction directory), including its articles when
+/// it was indexed with `per_article`. As all the changes of the writer,
+/// the removal is visible after the next commit.
+pub fn remove_file(index_writer: &tantivy::IndexWriter, fields: &IndexFields, path: &str) {
+    use tantivy::Term;
+
+    index_writer.delete_term(Term::from_field_text(fields.path, path));
+    if let Some(field) = fields.file {
+        index_writer.delete_term(Term::from_field_text(field, path));
+    }
+}
+
+/// Remove from the index the documents extracted from the tarball
+/// named `tarball` (e.g. before processing it again). As all the
+/// changes of the writer, the removal is visible after the next commit.
+pub fn remove_tarball(
+    index_writer: &tantivy::IndexWriter,
+    fields: &IndexFields,
+    tarball: &str,
+) -> Result<()> {
+    let field = fields.tarball.context(
+        "The index was created without the provenance of documents, delete it and index again",
+    )?;
+    index_writer.delete_term(tantivy::Term::from_field_text(field, tarball));
+    Ok(())
+}
+
+/// Index the entries parsed from a document, replacing
+/// the entries previously indexed for the same file (placeholders
+/// only remove them)
+fn index_files(
+    index_writer: &tantivy::IndexWriter,
+    fields: &IndexFields,
+    files: Vec<FondXMLFile>,
+) {
+    if let Some(first) = files.first() {
+        let (path, _) = crate::dumps::extractor::split_article_path(&first.path);
+        remove_file(index_writer, fields, path);
+    }
+    for file in files {
+        let path = file.path.clone();
+        if file.empty {
+            debug!("Skipping the empty document {}", path);
+            continue;
+        }
+        if let Err(e) = index_file(index_writer, fields, file) {
+            warn!("Failed to index {}: {}", path, e);
+        }
+    }
+}
+
+/// Index `file`, found in the extraction directory `dir`, replacing
+/// the documents previously indexed for it
+pub fn reindex_file(
+    index_writer: &tantivy::IndexWriter,
+    fields: &IndexFields,
+    dir: &Path,
+    file: &Path,
+    per_article: bool,
+) -> Result<()> {
+    let re = regex::Regex::new(r"(?<date>\d{4}-\d{2}-\d{2})</DATE").unwrap();
+    let docs = parse_file(dir, file, &re, per_article)?;
+    index_files(index_writer, fields, docs);
+    Ok(())
+}
+
+/// Remove from the index the documents listed in a suppression list
+/// of a delta tarball (see [`suppressed_paths`]). Returns the number
+/// of paths listed.
+pub fn apply_suppression_list(
+    index_writer: &tantivy::IndexWriter,
+    fields: &IndexFields,
+    list: &Path,
+) -> Result<usize> {
+    let content = std::fs::read_to_string(list)
+        .context(format!("Failed to read suppression list {}", list.display()))?;
+    let paths = suppressed_paths(&content);
+    for path in paths.iter() {
+        // the documents are indexed under their path in the archives
+        // when read from a pack, and under the path they were extracted
+        // to otherwise, which differ for the files renamed on Windows
+        remove_file(index_writer, fields, path);
+        if cfg!(windows) {
+            let portable = crate::dumps::winpath::portable_path(Path::new(path));
+            if portable != Path::new(path) {
+                remove_file(index_writer, fields, &portable.to_string_lossy());
+            }
+        }
+    }
+    Ok(paths.len())
+}
+
+/// Knobs of the indexing of a directory
+#[derive(Debug, Clone)]
+pub struct IndexingOptions {
+    /// Number of threads parsing the files, 0 for one per core
+    pub threads: usize,
+    /// Commit the index every `batch_size` documents, so that an
+    /// interrupted indexing keeps most of its work. 0 to only commit
+    /// at the end.
+    pub batch_size: usize,
+    /// Where the files that cannot be parsed are recorded
+    pub failures: FailureLog,
+}
+
+impl Default for IndexingOptions {
+    fn default() -> Self {
+        IndexingOptions {
+            threads: 0,
+            batch_size: 100_000,
+            failures: FailureLog::new(),
+        }
+    }
+}
+
+/// Number of parsed files waiting to be added to the index,
+/// bounding the memory used when parsing is faster than indexing
+const PARSED_QUEUE_SIZE: usize = 1024;
+
+/// Index all files in a directory using tantivy,
+/// recursively. Files are parsed in parallel.
+/// With `per_article`, the articles of texts are indexed separately.
+pub fn index_files_in_dir(
+    index_writer: &mut tantivy::IndexWriter,
+    fields: &IndexFields,
+    dir: &Path,
+    per_article: bool,
+    options: &IndexingOptions,
+) -> Result<()> {
+    index_files_under(index_writer, fields, dir, dir, per_article, options)
+}
+
+/// Index the files of `dir`, a subdirectory of the extraction
+/// directory `root`, recording them under their path relative to `root`.
+/// Files are parsed on a pool of threads, and the parsed documents are
+/// sent through a bounded channel to the index writer (which is
+/// multi-threaded itself).
+fn index_files_under(
+    index_writer: &mut tantivy::IndexWriter,
+    fields: &IndexFields,
+    root: &Path,
+    dir: &Path,
+    per_article: bool,
+    options: &IndexingOptions,
+) -> Result<()> {
+    // create a progress bar
+    let pb = progress_bar(0);
+    let re = regex::Regex::new(r"(?<date>\d{4}-\d{2}-\d{2})</DATE").unwrap();
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{msg} [{wide_bar}] {pos}/{len} ({eta})")
+            .context("Failed to create progress bar template")?
+            .progress_chars("##-"),
+    );
+    pb.set_message(format!("Indexing {}", dir.display()));
+    let provenance = crate::dumps::provenance::Provenance::load(root)?;
+    let provenance = &provenance;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.threads)
+        .build()
+        .context("Failed to create the parsing threads")?;
+    let (sender, receiver) = crossbeam_channel::bounded(PARSED_QUEUE_SIZE);
+    std::thread::scope(|scope| {
+        let parser_pb = pb.clone();
+        let re = &re;
+        scope.spawn(move || {
+            // the directories are read on the global pool: the parsing
+            // threads are all busy waiting for files
+            let files = walk_files(dir);
+            pool.install(|| {
+                files
+                    .filter_map(|file| match file {
+                        Ok(file) => Some(file),
+                        Err(e) => {
+                            warn!("{:#}", e);
+                            None
+                        }
+                    })
+                    .filter(|p| p.extension().is_some_and(|ext| ext == "xml"))
+                    // the files already parsed are still indexed and committed
+                    .take_while(|_| !shutdown::is_interrupted())
+                    .inspect(|_| parser_pb.inc_length(1))
+                    .par_bridge()
+                    .for_each_with(sender, |sender, file| {
+                        options.failures.processed(1);
+                        match parse_file(root, &file, re, per_article) {
+                            // the receiver only stops early on errors
+                            Ok(mut docs) => {
+                                if let Some(first) = docs.first() {
+                                    let (path, _) =
+                                        crate::dumps::extractor::split_article_path(&first.path);
+                                    let tarball = provenance.get(path).map(String::from);
+                                    for doc in docs.iter_mut() {
+                                        doc.tarball.clone_from(&tarball);
+                                    }
+                                }
+                                let _ = sender.send(docs);
+                            }
+                            Err(e) => {
+                                warn!("Failed to parse {}", file.display());
+                                let path = file.strip_prefix(root).unwrap_or(&file);
+                                options.failures.record(
+                                    path.to_string_lossy(),
+                                    Stage::Index,
+                                    e,
+                                );
+                                parser_pb.inc(1);
+                            }
+                        }
+                    })
+            })
+        });
+
+        let mut uncommitted = 0;
+        for docs in receiver {
+            uncommitted += docs.len();
+            index_files(index_writer, fields, docs);
+            pb.inc(1);
+            if options.batch_size > 0 && uncommitted >= options.batch_size {
+                index_writer.commit()?;
+                uncommitted = 0;
+            }
+        }
+        Ok::<_, anyhow::Error>(())
+    })?;
+    pb.finish();
+    index_writer.commit()?;
+    Ok(())
+}
+
+/// Path of a sibling of the index directory, e.g. `index.previous`
+fn index_sibling(index_path: &Path, suffix: &str) -> PathBuf {
+    let name = index_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "index".to_string());
+    index_path.with_file_name(format!("{}.{}", name, suffix))
+}
+
+/// Rebuild the index at `index_path` from scratch: `build` creates
+/// the new index in a temporary sibling directory (`index.building`),
+/// which replaces the index only once `build` succeeded, so that a
+/// crash never leaves a half-built index in place. An interrupted
+/// `build` (see [`shutdown`]) does not replace the index either.
+/// The replaced index is kept as `index.previous` (see [`rollback_index`]).
+pub fn rebuild_index<T, F>(index_path: &Path, build: F) -> Result<T>
+where
+    F: FnOnce(&Path) -> Result<T>,
+{
+    let building = index_sibling(index_path, "building");
+    // leftovers of an interrupted rebuild
+    if building.exists() {
+        std::fs::remove_dir_all(&building)
+            .context(format!("Failed to remove {}", building.display()))?;
+    }
+    std::fs::create_dir_all(&building)
+        .context(format!("Failed to create {}", building.display()))?;
+    let result = match build(&building) {
+        Ok(_) if shutdown::is_interrupted() => {
+            let _ = std::fs::remove_dir_all(&building);
+            return Err(anyhow::anyhow!(
+                "Interrupted, {} was not replaced",
+                index_path.display()
+            ));
+        }
+        Ok(result) => result,
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&building);
+            return Err(e);
+        }
+    };
+
+    let previous = index_sibling(index_path, "previous");
+    let is_empty = |path: &Path| std::fs::read_dir(path).is_ok_and(|mut d| d.next().is_none());
+    if is_empty(index_path) {
+        std::fs::remove_dir(index_path)?;
+    } else if index_path.exists() {
+        if previous.exists() {
+            std::fs::remove_dir_all(&previous)
+                .context(format!("Failed to remove {}", previous.display()))?;
+        }
+        std::fs::rename(index_path, &previous)
+            .context(format!("Failed to move {} aside", index_path.display()))?;
+    }
+    std::fs::rename(&building, index_path)
+        .context(format!("Failed to move the new index to {}", index_path.display()))?;
+    Ok(result)
+}
+
+/// Put back the index replaced by the last [`rebuild_index`],
+/// keeping the current one as the previous index
+pub fn rollback_index(index_path: &Path) -> Result<()> {
+    let previous = index_sibling(index_path, "previous");
+    if !previous.exists() {
+        return Err(anyhow::anyhow!("There is no previous index at {}", previous.display()));
+    }
+    let current = index_sibling(index_path, "rollback");
+    if index_path.exists() {
+        std::fs::rename(index_path, &current)
+            .context(format!("Failed to move {} aside", index_path.display()))?;
+    }
+    std::fs::rename(&previous, index_path)
+        .context(format!("Failed to restore {}", previous.display()))?;
+    if current.exists() {
+        std::fs::rename(&current, &previous)
+            .context(format!("Failed to keep {}", index_path.display()))?;
+    }
+    Ok(())
+}
+
+/// Path of the index of a fond in the index directory
+pub fn fond_index_path(index_dir: &Path, fond: &str) -> PathBuf {
+    index_dir.join(fond.to_uppercase())
+}
+
+/// The fonds that have their own index in the index directory
+pub fn list_fond_indices(index_dir: &Path) -> Result<Vec<String>> {
+    let mut fonds = vec![];
+    for entry in std::fs::read_dir(index_dir)? {
+        let path = entry?.path();
+        if path.join("meta.json").is_file()
+            && let Some(name) = path.file_name().and_then(|n| n.to_str())
+        {
+            fonds.push(name.to_string());
+        }
+    }
+    fonds.sort();
+    Ok(fonds)
+}
+
+/// Open (or create, with the given analyzer settings) the index of each
+/// of the `fonds`, in a subdirectory of the index directory named after the fond
+pub fn open_fond_indices(
+    index_dir: &Path,
+    fonds: &[String],
+    analyzer: &AnalyzerConfig,
+) -> Result<Vec<(tantivy::Index, IndexFields)>> {
+    fonds
+        .iter()
+        .map(|fond| {
+            let path = fond_index_path(index_dir, fond);
+            std::fs::create_dir_all(&path)
+                .context(format!("Failed to create index directory {}", path.display()))?;
+            init_tantivy_with(&path, analyzer)
+        })
+        .collect()
+}
+
+/// Index the extracted documents with one index per fond, so that
+/// the index of a fond can be rebuilt alone. Every directory of the
+/// extraction directory is a fond (e.g. `jade` is indexed in
+/// `JADE`). Returns the fonds that were indexed.
+pub fn index_fonds_in_dir(
+    index_dir: &Path,
+    dir: &Path,
+    writer_memory: usize,
+    per_article: bool,
+    analyzer: &AnalyzerConfig,
+    options: &IndexingOptions,
+) -> Result<Vec<String>> {
+    let mut fonds = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let fond_dir = entry?.path();
+        if !fond_dir.is_dir() {
+            continue;
+        }
+        let fond = fond_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_uppercase())
+            .unwrap_or_default();
+        let path = fond_index_path(index_dir, &fond);
+        std::fs::create_dir_all(&path)
+            .context(format!("Failed to create index directory {}", path.display()))?;
+        let (index, fields) = init_tantivy_with(&path, analyzer)?;
+        let mut writer = index.writer(writer_memory)?;
+        index_files_under(&mut writer, &fields, dir, &fond_dir, per_article, options)?;
+        fonds.push(fond);
+    }
+    fonds.sort();
+    Ok(fonds)
+}
+
+/// Index all documents of a content addressable store.
+/// Documents are recorded under their path in the original archive.
+pub fn index_content_store(
+    index_writer: &mut tantivy::IndexWriter,
+    fields: &IndexFields,
+    store: &crate::dumps::cas::ContentStore,
+    per_article: bool,
+) -> Result<()> {
+    let pb = progress_bar(store.len() as u64);
+    let re = regex::Regex::new(r"(?<date>\d{4}-\d{2}-\d{2})</DATE").unwrap();
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{msg} [{wide_bar}] {pos}/{len} ({eta})")
+            .context("Failed to create progress bar template")?
+            .progress_chars("##-"),
+    );
+    pb.set_message(format!("Indexing {} documents", store.len()));
+
+    for (path, blob) in store.documents() {
+        if !path.ends_with(".xml") {
+            pb.inc(1);
+            continue;
+        }
+        match parse_file_as(path.to_string(), &blob, &re, per_article) {
+            Ok(docs) => index_files(index_writer, fields, docs),
+            Err(_) => warn!("Failed to parse {}", path),
+        }
+        pb.inc(1);
+    }
+    index_writer.commit()?;
+    Ok(())
+}
+
+/// Index all documents of a packed corpus.
+pub fn index_pack(
+    index_writer: &mut tantivy::IndexWriter,
+    fields: &IndexFields,
+    pack: &crate::dumps::pack::PackReader,
+    per_article: bool,
+) -> Result<()> {
+    let pb = progress_bar(pack.len() as u64);
+    let re = regex::Regex::new(r"(?<date>\d{4}-\d{2}-\d{2})</DATE").unwrap();
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{msg} [{wide_bar}] {pos}/{len} ({eta})")
+            .context("Failed to create progress bar template")?
+            .progress_chars("##-"),
+    );
+    pb.set_message(format!("Indexing {} documents", pack.len()));
+
+    for entry in pack.entries() {
+        match pack
+            .read(entry)
+            .and_then(|body| parse_body_as(entry.path.clone(), &body, &re, per_article))
+        {
+            Ok(docs) => index_files(index_writer, fields, docs),
+            Err(e) => warn!("Failed to parse {}: {}", entry.path, e),
+        }
+        pb.inc(1);
+    }
+    index_writer.commit()?;
+    Ok(())
+}
+
+/// How the results of a search are ordered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SortBy {
+    /// Most relevant first
+    #[default]
+    Score,
+    /// Newest first, undated documents last
+    Date,
+}
+
+/// Width of the buckets of a histogram of search results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum HistogramBucket {
+    Year,
+    Month,
+}
+
+impl HistogramBucket {
+    /// The bucket of a date, e.g. `2024` or `2024-03`
+    pub fn key(&self, date: NaiveDate) -> String {
+        match self {
+            HistogramBucket::Year => date.format("%Y").to_string(),
+            HistogramBucket::Month => date.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+/// Restrictions of a search besides the query itself
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    /// Only documents dated on or after this day
+    pub from: Option<NaiveDate>,
+    /// Only documents dated on or before this day
+    pub to: Option<NaiveDate>,
+    /// Count the results per year and per jurisdiction
+    pub facets: bool,
+    /// Number of results returned
+    pub limit: usize,
+    /// Number of best results skipped, to show the next pages
+    pub offset: usize,
+    /// Order of the results
+    pub sort: SortBy,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            from: None,
+            to: None,
+            facets: false,
+            limit: 10,
+            offset: 0,
+            sort: SortBy::Score,
+        }
+    }
+}
+
+impl SearchOptions {
+    /// Most results a search can skip and return (`offset + limit`), since
+    /// tantivy reserves room for all of them before searching. All the
+    /// matches can still be listed with [`export_matches`].
+    pub const MAX_WINDOW: usize = 100_000;
+
+    /// Number of best results to collect: the skipped ones and the page
+    pub fn window(&self) -> Result<usize> {
+        self.offset
+            .checked_add(self.limit)
+            .filter(|window| *window <= Self::MAX_WINDOW)
+            .with_context(|| {
+                format!(
+                    "Cannot skip {} results and return {}: offset and limit add up to more than {}",
+                    self.offset,
+                    self.limit,
+                    Self::MAX_WINDOW
+                )
+            })
+    }
+}
+
+/// A search result: the path of the document and its date
+pub type SearchHit = (String, Option<NaiveDate>);
+
+/// The results of a search
+#[derive(Debug, Clone, Default)]
+pub struct SearchResults {
+    /// Number of matching documents
+    pub count: usize,
+    /// The first matching documents
+    pub hits: Vec<SearchHit>,
+    /// Counts of all the matching documents, when
+    /// requested with [`SearchOptions::facets`]
+    pub facets: Option<facet_collector::Facets>,
+}
+
+/// search all files in the index
+pub fn search_index(
+    index: &tantivy::Index,
+    fields: &IndexFields,
+    save: &Option<String>,
+    query: &str,
+    options: &SearchOptions,
+) -> Result<SearchResults> {
+    search_indices(&[(index, fields)], save, query, options)
+}
+
+/// Search several indices (e.g. the indices of several fonds, see
+/// [`open_fond_indices`]) and merge their results. The first results
+/// are the best scoring documents of all the indices, although scores
+/// of different indices are only roughly comparable.
+pub fn search_indices(
+    indices: &[(&tantivy::Index, &IndexFields)],
+    save: &Option<String>,
+    query: &str,
+    options: &SearchOptions,
+) -> Result<SearchResults> {
+    let mut results = SearchResults {
+        facets: options.facets.then(facet_collector::Facets::default),
+        ..Default::default()
+    };
+    let mut scored = vec![];
+    for (index, fields) in indices {
+        let (found, scores) = search_scored(index, fields, save, query, options)?;
+        results.count += found.count;
+        scored.extend(scores.into_iter().zip(found.hits));
+        if let (Some(all), Some(facets)) = (results.facets.as_mut(), found.facets) {
+            all.merge(facets);
+        }
+    }
+    scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    results.hits = scored
+        .into_iter()
+        .skip(options.offset)
+        .take(options.limit)
+        .map(|(_, hit)| hit)
+        .collect();
+    Ok(results)
+}
+
+/// Write the paths of all the documents matching the query in several
+/// indices to `out`, one per line, sorted by path and without
+/// duplicates. Returns the number of paths written.
+///
+/// The paths are written as they are found, see [`for_each_matching_path`].
+pub fn export_matches(
+    indices: &[(&tantivy::Index, &IndexFields)],
+    query: &str,
+    options: &SearchOptions,
+    out: &mut dyn std::io::Write,
+) -> Result<usize> {
+    for_each_matching_path(indices, query, options, |path| {
+        out.write_all(path)?;
+        out.write_all(b"\n")?;
+        Ok(())
+    })
+}
+
+/// The paths of all the documents matching the query
+/// in several indices, sorted and without duplicates
+pub fn matching_paths(
+    indices: &[(&tantivy::Index, &IndexFields)],
+    query: &str,
+    options: &SearchOptions,
+) -> Result<Vec<String>> {
+    let mut paths = vec![];
+    for_each_matching_path(indices, query, options, |path| {
+        paths.push(String::from_utf8_lossy(path).into_owned());
+        Ok(())
+    })?;
+    Ok(paths)
+}
+
+/// The paths of the matching documents of a segment, read in
+/// order from the term dictionary of the path field
+struct PathRun<'a> {
+    inverted: &'a tantivy::InvertedIndexReader,
+    terms: tantivy::termdict::TermStreamer<'a>,
+    matches: &'a file_collector::Matches,
+}
+
+impl PathRun<'_> {
+    /// Move to the next path of a matching document, returning
+    /// false once all the paths of the segment have been read
+    fn next_match(&mut self) -> Result<bool> {
+        use tantivy::schema::IndexRecordOption;
+        use tantivy::{DocSet, TERMINATED};
+
+        while self.terms.advance() {
+            let mut postings = self
+                .inverted
+                .read_postings_from_terminfo(self.terms.value(), IndexRecordOption::Basic)?;
+            // a path has several documents when replaced documents are not merged yet
+            while postings.doc() != TERMINATED {
+                if self.matches.contains(postings.doc()) {
+                    return Ok(true);
+                }
+                postings.advance();
+            }
+        }
+        Ok(false)
+    }
+
+    fn path(&self) -> &[u8] {
+        self.terms.key()
+    }
+}
+
+/// Call `f` with the path of every document matching the query in
+/// several indices, in order and once per path, returning the number
+/// of paths. The paths are not gathered in memory: the matching
+/// documents of each segment are marked in a bitset, and the paths are
+/// read in order from the term dictionaries of the segments, which
+/// are merged as they are read.
+pub fn for_each_matching_path<F>(
+    indices: &[(&tantivy::Index, &IndexFields)],
+    query: &str,
+    options: &SearchOptions,
+    mut f: F,
+) -> Result<usize>
+where
+    F: FnMut(&[u8]) -> Result<()>,
+{
+    let mut segments = vec![];
+    for (index, fields) in indices {
+        let searcher = index.reader()?.searcher();
+        let query = build_query(index, fields, query, options)?;
+        let matches = searcher.search(&query, &file_collector::MatchCollector)?;
+        for (reader, matches) in searcher.segment_readers().iter().zip(matches) {
+            segments.push((reader.inverted_index(fields.path)?, matches));
+        }
+    }
+
+    let mut runs = vec![];
+    for (inverted, matches) in segments.iter() {
+        let mut run = PathRun {
+            inverted,
+            terms: inverted.terms().stream()?,
+            matches,
+        };
+        if run.next_match()? {
+            runs.push(run);
+        }
+    }
+
+    let mut path = vec![];
+    let mut count = 0;
+    while let Some(first) = runs.iter().map(PathRun::path).min() {
+        path.clear();
+        path.extend_from_slice(first);
+        f(&path)?;
+        count += 1;
+        // the runs on this path move on, so that it is written once
+        let mut i = 0;
+        while i < runs.len() {
+            if runs[i].path() == path.as_slice() && !runs[i].next_match()? {
+                runs.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+    Ok(count)
+}
+
+/// The documents matching the query in several indices, with the
+/// tarball they were extracted from and their year, sorted by path
+/// and without duplicates
+pub fn matching_documents(
+    indices: &[(&tantivy::Index, &IndexFields)],
+    query: &str,
+    options: &SearchOptions,
+) -> Result<Vec<LedgerEntry>> {
+    use tantivy::schema::Value;
+
+    let mut documents = vec![];
+    for (index, fields) in indices {
+        let searcher = index.reader()?.searcher();
+        let query = build_query(index, fields, query, options)?;
+        for address in searcher.search(&query, &tantivy::collector::DocSetCollector)? {
+            let doc: tantivy::TantivyDocument = searcher.doc(address)?;
+            let (path, date) = stored_hit(&doc, fields);
+            let tarball = fields
+                .tarball
+                .and_then(|field| doc.get_first(field))
+                .and_then(|v| v.as_str())
+                .map(String::from);
+            documents.push(LedgerEntry {
+                path,
+                tarball,
+                year: date.map(|date| date.year()),
+            });
+        }
+    }
+    documents.sort_by(|a, b| a.path.cmp(&b.path));
+    documents.dedup_by(|a, b| a.path == b.path);
+    Ok(documents)
+}
+
+/// Number of documents matching the query in several indices,
+/// without retrieving any of them
+pub fn count_matches(
+    indices: &[(&tantivy::Index, &IndexFields)],
+    query: &str,
+    options: &SearchOptions,
+) -> Result<usize> {
+    let mut count = 0;
+    for (index, fields) in indices {
+        let searcher = index.reader()?.searcher();
+        let query = build_query(index, fields, query, options)?;
+        count += searcher.search(&query, &tantivy::collector::Count)?;
+    }
+    Ok(count)
+}
+
+/// Number of documents matching the query in several indices per
+/// year or per month, read from the date fast field. Undated
+/// documents are not counted.
+pub fn date_histogram(
+    indices: &[(&tantivy::Index, &IndexFields)],
+    query: &str,
+    options: &SearchOptions,
+    bucket: HistogramBucket,
+) -> Result<std::collections::BTreeMap<String, usize>> {
+    let mut counts = std::collections::BTreeMap::new();
+    for (index, fields) in indices {
+        let searcher = index.reader()?.searcher();
+        let query = build_query(index, fields, query, options)?;
+        let collector = facet_collector::HistogramCollector::new(
+            index.schema().get_field_name(fields.date),
+            bucket,
+        );
+        for (key, count) in searcher.search(&query, &collector)? {
+            *counts.entry(key).or_insert(0) += count;
+        }
+    }
+    Ok(counts)
+}
+
+/// Parse a query, restricted to the dates of the options
+fn build_query(
+    index: &tantivy::Index,
+    fields: &IndexFields,
+    query: &str,
+    options: &SearchOptions,
+) -> Result<Box<dyn tantivy::query::Query>> {
+    use std::ops::Bound;
+    use tantivy::query::{BooleanQuery, Occur, Query, RangeQuery};
+
+    let query_parser =
+        tantivy::query::QueryParser::for_index(index, fields.default_search_fields());
+    let query = query_parser.parse_query(query)?;
+    if options.from.is_none() && options.to.is_none() {
+        return Ok(query);
+    }
+    let bound = |date: Option<NaiveDate>| match date {
+        Some(date) => Bound::Included(to_tantivy_date(date)),
+        None => Bound::Unbounded,
+    };
+    let range: Box<dyn Query> = Box::new(RangeQuery::new_date_bounds(
+        index.schema().get_field_name(fields.date).to_string(),
+        bound(options.from),
+        bound(options.to),
+    ));
+    Ok(Box::new(BooleanQuery::new(vec![(Occur::Must, query), (Occur::Must, range)])))
+}
+
+/// A document as stored in the index
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StoredDocument {
+    /// Path relative to the extraction directory, followed by
+    /// `#article_id` for the articles indexed separately
+    pub path: String,
+    pub date: Option<NaiveDate>,
+    /// The metadata of the document (see [`ExportRecord::metadata`]),
+    /// empty for documents indexed by older versions
+    pub metadata: serde_json::Map<String, serde_json::Value>,
+    /// Name of the tarball the document was extracted from, if known
+    pub tarball: Option<String>,
+}
+
+/// The stored document whose `field` is exactly `value`
+fn find_document(
+    searcher: &tantivy::Searcher,
+    field: tantivy::schema::Field,
+    value: &str,
+) -> Result<Option<tantivy::TantivyDocument>> {
+    let query = tantivy::query::TermQuery::new(
+        tantivy::Term::from_field_text(field, value),
+        tantivy::schema::IndexRecordOption::Basic,
+    );
+    let top_docs = searcher.search(&query, &tantivy::collector::TopDocs::with_limit(1))?;
+    match top_docs.into_iter().next() {
+        Some((_, doc_address)) => Ok(Some(searcher.doc(doc_address)?)),
+        None => Ok(None),
+    }
+}
+
+/// The path and the date of a stored document
+fn stored_hit(doc: &tantivy::TantivyDocument, fields: &IndexFields) -> SearchHit {
+    use tantivy::schema::Value;
+
+    let path = doc
+        .get_first(fields.path)
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string();
+    let date = doc
+        .get_first(fields.date)
+        .and_then(|v| v.as_datetime())
+        .and_then(from_tantivy_date);
+    (path, date)
+}
+
+/// The documents whose text is the most similar to the text of the
+/// document identified by `key` (its identifier, or its path), found
+/// with a "more like this" query on the rarest frequent words of its
+/// text. The documents of all the indices are ranked together, and
+/// the document itself is left out. Returns `None` when the document
+/// is in none of the indices.
+pub fn similar_documents(
+    indices: &[(&tantivy::Index, &IndexFields)],
+    key: &str,
+    limit: usize,
+) -> Result<Option<Vec<SearchHit>>> {
+    use tantivy::query::MoreLikeThisQuery;
+    use tantivy::schema::{OwnedValue, Value};
+
+    let mut source = None;
+    for (index, fields) in indices {
+        let searcher = index.reader()?.searcher();
+        for field in fields.id.into_iter().chain([fields.path]) {
+            if let Some(doc) = find_document(&searcher, field, key)? {
+                fields.require_body()?;
+                let body = doc.get_first(fields.body).and_then(|v| v.as_str()).map(String::from);
+                source = Some((stored_hit(&doc, fields).0, body.unwrap_or_default()));
+                break;
+            }
+        }
+        if source.is_some() {
+            break;
+        }
+    }
+    let Some((path, body)) = source else {
+        return Ok(None);
+    };
+
+    let mut scored = vec![];
+    for (index, fields) in indices {
+        let searcher = index.reader()?.searcher();
+        let query = MoreLikeThisQuery::builder()
+            // words found in no other document do not help
+            .with_min_doc_frequency(2)
+            .with_document_fields(vec![(fields.body, vec![OwnedValue::from(body.as_str())])]);
+        let top_docs =
+            searcher.search(&query, &tantivy::collector::TopDocs::with_limit(limit + 1))?;
+        for (score, doc_address) in top_docs {
+            let hit = stored_hit(&searcher.doc(doc_address)?, fields);
+            if hit.0 != path {
+                scored.push((score, hit));
+            }
+        }
+    }
+    scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    Ok(Some(scored.into_iter().take(limit).map(|(_, hit)| hit).collect()))
+}
+
+/// Call `f` with the path and the text of every document of the
+/// index, returning the number of documents
+pub fn for_each_document<F>(index: &tantivy::Index, fields: &IndexFields, mut f: F) -> Result<usize>
+where
+    F: FnMut(&str, &str) -> Result<()>,
+{
+    use tantivy::schema::Value;
+
+    fields.require_body()?;
+    let searcher = index.reader()?.searcher();
+    let mut count = 0;
+    for (segment, reader) in searcher.segment_readers().iter().enumerate() {
+        for doc_id in reader.doc_ids_alive() {
+            let address = tantivy::DocAddress::new(segment as u32, doc_id);
+            let doc: tantivy::TantivyDocument = searcher.doc(address)?;
+            let (path, _) = stored_hit(&doc, fields);
+            let body = doc.get_first(fields.body).and_then(|v| v.as_str()).unwrap_or_default();
+            f(&path, body)?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Look up a document by its identifier (e.g. "CETATEXT000049314894")
+pub fn get_by_id(
+    index: &tantivy::Index,
+    fields: &IndexFields,
+    id: &str,
+) -> Result<Option<StoredDocument>> {
+    use tantivy::schema::{OwnedValue, Value};
+
+    let id_field = fields.id.context(
+        "The index was created without identifiers, delete it and index again",
+    )?;
+    let searcher = index.reader()?.searcher();
+    let Some(doc) = find_document(&searcher, id_field, id)? else {
+        return Ok(None);
+    };
+    let (path, date) = stored_hit(&doc, fields);
+    let metadata = match fields.metadata.and_then(|field| doc.get_first(field)) {
+        Some(OwnedValue::Object(object)) => object
+            .iter()
+            .filter_map(|(k, v)| Some((k.clone(), serde_json::Value::from(v.as_str()?))))
+            .collect(),
+        _ => serde_json::Map::new(),
+    };
+    let tarball = fields
+        .tarball
+        .and_then(|field| doc.get_first(field))
+        .and_then(|v| v.as_str())
+        .map(String::from);
+    Ok(Some(StoredDocument {
+        path,
+        date,
+        metadata,
+        tarball,
+    }))
+}
+
+/// Search an index, returning the results and the keys the
+/// hits are sorted by (their score, or their date)
+fn search_scored(
+    index: &tantivy::Index,
+    fields: &IndexFields,
+    save: &Option<String>,
+    query: &str,
+    options: &SearchOptions,
+) -> Result<(SearchResults, Vec<f64>)> {
+    use tantivy::schema::document::Value;
+
+    let reader = index
+        .reader_builder()
+        .reload_policy(tantivy::ReloadPolicy::OnCommitWithDelay)
+        .try_into()?;
+    let searcher = reader.searcher();
+    let query = build_query(index, fields, query, options)?;
+
+    let schema = index.schema();
+    let fcol = save
+        .as_ref()
+        .map(|savepath| file_collector::FileListCollector::new(fields.path, &PathBuf::from(savepath)));
+    let facets = options.facets.then(|| {
+        facet_collector::FacetCollector::new(
+            schema.get_field_name(fields.date),
+            fields.jurisdiction.map(|f| schema.get_field_name(f)),
+        )
+    });
+    // the results of several indices are merged before skipping, and
+    // tantivy needs at least one result even for an empty page
+    let top = tantivy::collector::TopDocs::with_limit(options.window()?.max(1));
+    let (doc_count, top_docs, _, facets) = match options.sort {
+        SortBy::Score => {
+            let (count, top_docs, files, facets) =
+                searcher.search(&query, &(tantivy::collector::Count, top, fcol, facets))?;
+            let top_docs: Vec<_> = top_docs.into_iter().map(|(s, d)| (s as f64, d)).collect();
+            (count, top_docs, files, facets)
+        }
+        SortBy::Date => {
+            let top = top.order_by_fast_field::<tantivy::DateTime>(
+                schema.get_field_name(fields.date),
+                tantivy::Order::Desc,
+            );
+            let (count, top_docs, files, facets) =
+                searcher.search(&query, &(tantivy::collector::Count, top, fcol, facets))?;
+            let top_docs: Vec<_> = top_docs
+                .into_iter()
+                .map(|(date, d)| (date.into_timestamp_secs() as f64, d))
+                .collect();
+            (count, top_docs, files, facets)
+        }
+    };
+
+    let mut results = Vec::new();
+    let mut scores = Vec::new();
+    for (score, doc_address) in top_docs {
+        let doc: tantivy::TantivyDocument = searcher.doc(doc_address)?;
+        let title = doc
+            .get_first(fields.path)
+            .ok_or_else(|| anyhow::anyhow!("Failed to get path"))?
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Path is not a string"))?;
+        let date = doc
+            .get_first(fields.date)
+            .and_then(|d| d.as_datetime())
+            .and_then(from_tantivy_date);
+
+        results.push((title.to_owned(), date));
+        scores.push(score);
+    }
+    let results = SearchResults {
+        count: doc_count,
+        hits: results,
+        facets,
+    };
+    Ok((results, scores))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dumps::tarballs::is_suppression_list;
+
+    #[test]
+    fn test_get_date_juri() {
+        let re = regex::Regex::new(r"(?<date>\d{4}-\d{2}-\d{2})</DATE").unwrap();
+        let doc = r#"<DATE_FIN>2999-13-01</DATE_FIN><DATE_JURI>2023-01-01</DATE_JURI>"#;
+        assert_eq!(get_date_juri(doc, &re), NaiveDate::from_ymd_opt(2023, 1, 1));
+        // the date of the attacked decision and the dates cited by
+        // the text are not the date of the decision
+        let doc = "<TEXTE_JURI_JUDI><META><META_JURI_JUDI><DATE_DEC_ATT>2019-05-12</DATE_DEC_ATT>\
+                   </META_JURI_JUDI><META_JURI><DATE_DEC>2021-03-10</DATE_DEC></META_JURI></META>\
+                   <TEXTE><CONTENU><DATE>2001-01-01</DATE> vu l'arrêt du 2019-05-12";
+        assert_eq!(get_date_juri(doc, &re), NaiveDate::from_ymd_opt(2021, 3, 10));
+        let doc = "<TEXTE_JURI_JUDI><META><ID>JURITEXT000000000001</ID></META>\
+                   <TEXTE><CONTENU><DATE>2001-01-01</DATE></CONTENU></TEXTE>";
+        assert_eq!(get_date_juri(doc, &re), None);
+
+        // the parsed metadata comes first
+        let body = "<TEXTE_JURI_JUDI><META><META_COMMUN><ID>JURITEXT000000000001</ID></META_COMMUN>\
+                    <META_SPEC><META_JURI><TITRE>Cour de cassation, 10 mars 2021, 2018-06-30</TITRE>\
+                    <DATE_DEC>2021-03-10</DATE_DEC></META_JURI><META_JURI_JUDI>\
+                    <DATE_DEC_ATT>2018-06-30</DATE_DEC_ATT></META_JURI_JUDI></META_SPEC></META>\
+                    <TEXTE><BLOC_TEXTUEL><CONTENU>Vu l'arrêt de la cour d'appel du 30 juin 2018 \
+                    et la décision du 1999-01-01</CONTENU></BLOC_TEXTUEL></TEXTE></TEXTE_JURI_JUDI>";
+        let files = parse_body_as("cass/a.xml".to_string(), body, &re, false).unwrap();
+        assert_eq!(files[0].date, NaiveDate::from_ymd_opt(2021, 3, 10));
+        let date = NaiveDate::from_ymd_opt(2024, 3, 21).unwrap();
+        assert_eq!(from_tantivy_date(to_tantivy_date(date)), Some(date));
+    }
+
+    #[test]
+    fn test_index_metadata() {
+        use tantivy::schema::document::Value;
+
+        let (index, fields) = init_tantivy_ram().unwrap();
+        let mut writer: tantivy::IndexWriter = index.writer(15_000_000).unwrap();
+        let re = regex::Regex::new(r"(?<date>\d{4}-\d{2}-\d{2})</DATE").unwrap();
+        let body = "<TEXTE_JURI_ADMIN><META><META_COMMUN><ID>CETATEXT000000000001</ID></META_COMMUN>\
+                    <META_SPEC><META_JURI><DATE_DEC>2024-03-21</DATE_DEC>\
+                    <JURIDICTION>Conseil d'État</JURIDICTION></META_JURI></META_SPEC></META>\
+                    <TEXTE><BLOC_TEXTUEL><CONTENU>Le Conseil rejette la requête.</CONTENU></BLOC_TEXTUEL></TEXTE>\
+                    </TEXTE_JURI_ADMIN>";
+        let files = parse_body_as("a.xml".to_string(), body, &re, false).unwrap();
+        index_files(&writer, &fields, files);
+        writer.commit().unwrap();
+
+        let searcher = index.reader().unwrap().searcher();
+        let doc: tantivy::TantivyDocument = searcher.doc(tantivy::DocAddress::new(0, 0)).unwrap();
+        let metadata = doc.get_first(fields.metadata.unwrap()).unwrap();
+        let jurisdiction = metadata
+            .as_object()
+            .unwrap()
+            .find(|(k, _)| *k == "jurisdiction")
+            .and_then(|(_, v)| v.as_str());
+        assert_eq!(jurisdiction, Some("Conseil d'État"));
+        // the text is only in the body
+        assert!(metadata.as_object().unwrap().all(|(k, _)| k != "text"));
+    }
+
+    #[test]
+    fn test_search_metadata_fields() {
+        let (index, fields) = init_tantivy_ram().unwrap();
+        let mut writer: tantivy::IndexWriter = index.writer(15_000_000).unwrap();
+        let re = regex::Regex::new(r"(?<date>\d{4}-\d{2}-\d{2})</DATE").unwrap();
+        for (path, id, jurisdiction) in [
+            ("jade/global/a.xml", "CETATEXT000000000001", "Conseil d'État"),
+            ("capp/global/b.xml", "JURITEXT000000000002", "Cour d'appel de Paris"),
+        ] {
+            let body = format!(
+                "<TEXTE_JURI_ADMIN><META><META_COMMUN><ID>{}</ID><NATURE>Arrêt</NATURE></META_COMMUN>\
+                 <META_SPEC><META_JURI><DATE_DEC>2024-03-21</DATE_DEC>\
+                 <JURIDICTION>{}</JURIDICTION></META_JURI></META_SPEC></META>\
+                 <TEXTE><BLOC_TEXTUEL><CONTENU>La requête est rejetée.</CONTENU></BLOC_TEXTUEL></TEXTE>\
+                 </TEXTE_JURI_ADMIN>",
+                id, jurisdiction
+            );
+            let files = parse_body_as(path.to_string(), &body, &re, false).unwrap();
+            index_files(&writer, &fields, files);
+        }
+        writer.commit().unwrap();
+
+        let paths = |query: &str| {
+            let results =
+                search_index(&index, &fields, &None, query, &SearchOptions::default()).unwrap();
+            results.hits.into_iter().map(|(path, _)| path).collect::<Vec<_>>()
+        };
+        assert_eq!(paths(r#"requête AND jurisdiction:"Conseil d'État""#), vec!["jade/global/a.xml"]);
+        assert_eq!(paths("fond:CAPP"), vec!["capp/global/b.xml"]);
+        assert_eq!(paths("nature:Arrêt").len(), 2);
+        // identifiers are searched without naming the field
+        assert_eq!(paths("JURITEXT000000000002"), vec!["capp/global/b.xml"]);
+        assert_eq!(
+            paths(r#"jurisdiction:"Conseil d'État" AND year:[2020 TO 2024] AND body:requête"#),
+            vec!["jade/global/a.xml"]
+        );
+        assert!(paths("year:[2020 TO 2023]").is_empty());
+        // other metadata are queried in the metadata field
+        assert_eq!(paths("metadata.id:CETATEXT000000000001"), vec!["jade/global/a.xml"]);
+
+        let doc = get_by_id(&index, &fields, "CETATEXT000000000001").unwrap().unwrap();
+        assert_eq!(doc.path, "jade/global/a.xml");
+        assert_eq!(doc.date, NaiveDate::from_ymd_opt(2024, 3, 21));
+        assert_eq!(doc.metadata["jurisdiction"], "Conseil d'État");
+        assert_eq!(get_by_id(&index, &fields, "CETATEXT000000000003").unwrap(), None);
+        // only whole identifiers are looked up, not paths or prefixes
+        assert_eq!(get_by_id(&index, &fields, "JURITEXT000000000002").unwrap().unwrap().path, "capp/global/b.xml");
+        assert_eq!(get_by_id(&index, &fields, "CETATEXT").unwrap(), None);
+        assert_eq!(get_by_id(&index, &fields, "jade/global/a.xml").unwrap(), None);
+
+        let options = SearchOptions {
+            facets: true,
+            ..Default::default()
+        };
+        let facets = search_index(&index, &fields, &None, "requête", &options)
+            .unwrap()
+            .facets
+            .unwrap();
+        assert_eq!(facets.years, [(2024, 2)].into());
+        assert_eq!(
+            facets.jurisdictions,
+            [("Conseil d'État".to_string(), 1), ("Cour d'appel de Paris".to_string(), 1)].into()
+        );
+        // only the documents matching the dates are counted
+        let options = SearchOptions {
+            from: NaiveDate::from_ymd_opt(2025, 1, 1),
+            facets: true,
+            ..Default::default()
+        };
+        let later = search_index(&index, &fields, &None, "requête", &options).unwrap();
+        assert_eq!(later.facets, Some(Default::default()));
+        let results = search_index(&index, &fields, &None, "requête", &SearchOptions::default());
+        assert_eq!(results.unwrap().facets, None);
+    }
+
+    #[test]
+    fn test_stemming() {
+        let re = regex::Regex::new(r"(?<date>\d{4}-\d{2}-\d{2})</DATE").unwrap();
+        let body = "<TEXTE_JURI_ADMIN><CONTENU>Le permis autorise les constructions.</CONTENU></TEXTE_JURI_ADMIN>";
+        let count = |index: &tantivy::Index, fields: &IndexFields| {
+            search_index(index, fields, &None, "construction", &SearchOptions::default())
+                .unwrap()
+                .count
+        };
+
+        let (index, fields) = init_tantivy_ram().unwrap();
+        let mut writer: tantivy::IndexWriter = index.writer(15_000_000).unwrap();
+        index_files(&writer, &fields, parse_body_as("a.xml".to_string(), body, &re, false).unwrap());
+        writer.commit().unwrap();
+        assert_eq!(count(&index, &fields), 0);
+
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let path = tmp.path().to_path_buf();
+        let analyzer = AnalyzerConfig {
+            stemming: true,
+            ..Default::default()
+        };
+        {
+            let (index, fields) = init_tantivy_with(&path, &analyzer).unwrap();
+            let mut writer: tantivy::IndexWriter = index.writer(15_000_000).unwrap();
+            index_files(&writer, &fields, parse_body_as("a.xml".to_string(), body, &re, false).unwrap());
+            writer.commit().unwrap();
+            assert_eq!(count(&index, &fields), 1);
+        }
+        // reopening the index uses the settings it was created with
+        assert_eq!(AnalyzerConfig::load(&path).unwrap(), analyzer);
+        let (index, fields) = init_tantivy(&path).unwrap();
+        assert_eq!(count(&index, &fields), 1);
+    }
+
+    #[test]
+    fn test_analyzer_config() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let file = tmp.path().join("analyzer.toml");
+        std::fs::write(&file, "stopwords = [\"Permis\"]\nascii_folding = false\nmax_token_length = 9\n").unwrap();
+        let analyzer = AnalyzerConfig::from_file(&file).unwrap();
+        assert_eq!(analyzer.stopwords, vec!["Permis"]);
+        assert!(!analyzer.stemming);
+        std::fs::write(&file, "stemmer = true").unwrap();
+        assert!(AnalyzerConfig::from_file(&file).is_err());
+
+        let mut tokenizer = analyzer.tokenizer();
+        let mut stream = tokenizer.token_stream("Le permis autorise les constructions à Noël");
+        let mut tokens = vec![];
+        while let Some(token) = tantivy::tokenizer::TokenStream::next(&mut stream) {
+            tokens.push(token.text.clone());
+        }
+        assert_eq!(tokens, vec!["autorise", "noël"]);
+
+        // the text is searchable without being stored
+        let analyzer = AnalyzerConfig { store_body: false, ..Default::default() };
+        let (index, fields) = init_tantivy_ram_with(&analyzer).unwrap();
+        let mut writer: tantivy::IndexWriter = index.writer(15_000_000).unwrap();
+        let re = regex::Regex::new(r"(?<date>\d{4}-\d{2}-\d{2})</DATE").unwrap();
+        let body = "<TEXTE_JURI_ADMIN><TEXTE><BLOC_TEXTUEL><CONTENU>Le permis est annulé.\
+                    </CONTENU></BLOC_TEXTUEL></TEXTE></TEXTE_JURI_ADMIN>";
+        index_files(&writer, &fields, parse_body_as("a.xml".to_string(), body, &re, false).unwrap());
+        writer.commit().unwrap();
+        let results = search_index(&index, &fields, &None, "permis", &SearchOptions::default()).unwrap();
+        assert_eq!(results.hits, vec![("a.xml".to_string(), None)]);
+        assert!(for_each_document(&index, &fields, |_, _| Ok(())).is_err());
+    }
+
+    #[test]
+    fn test_similar_documents() {
+        let (index, fields) = init_tantivy_ram().unwrap();
+        let mut writer: tantivy::IndexWriter = index.writer(15_000_000).unwrap();
+        let re = regex::Regex::new(r"(?<date>\d{4}-\d{2}-\d{2})</DATE").unwrap();
+        for (id, text) in [
+            ("CETATEXT000000000001", "Les antennes relais. Les antennes relais de téléphonie mobile."),
+            ("CETATEXT000000000002", "Une antenne relais. Les antennes relais sont autorisées."),
+            ("CETATEXT000000000003", "Le permis de construire. Le permis de construire est annulé."),
+            ("CETATEXT000000000004", "Le permis de construire des antennes est refusé."),
+        ] {
+            let body = format!(
+                "<TEXTE_JURI_ADMIN><META><META_COMMUN><ID>{}</ID></META_COMMUN></META>\
+                 <TEXTE><BLOC_TEXTUEL><CONTENU>Vu la requête. {}</CONTENU></BLOC_TEXTUEL></TEXTE>\
+                 </TEXTE_JURI_ADMIN>",
+                id, text
+            );
+            let path = format!("jade/{}.xml", id);
+            index_files(&writer, &fields, parse_body_as(path, &body, &re, false).unwrap());
+        }
+        writer.commit().unwrap();
+
+        let indices = [(&index, &fields)];
+        let similar = |key: &str| {
+            similar_documents(&indices, key, 10)
+                .unwrap()
+                .unwrap()
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect::<Vec<_>>()
+        };
+        let antennes = similar("CETATEXT000000000001");
+        assert_eq!(antennes[0], "jade/CETATEXT000000000002.xml");
+        assert!(!antennes.contains(&"jade/CETATEXT000000000001.xml".to_string()));
+        assert!(!antennes.contains(&"jade/CETATEXT000000000003.xml".to_string()));
+        // documents can also be given by path
+        assert_eq!(similar("jade/CETATEXT000000000003.xml"), vec!["jade/CETATEXT000000000004.xml"]);
+        assert_eq!(similar_documents(&indices, "CETATEXT000000000005", 10).unwrap(), None);
+    }
+
+    #[test]
+    fn test_index_files_in_dir() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        for i in 0..20 {
+            let dir = tmp.path().join(format!("jade/{}/{}", i % 3, i % 7));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(
+                dir.join(format!("CETATEXT{:012}.xml", i)),
+                "<TEXTE_JURI_ADMIN><CONTENU>La requête est rejetée.</CONTENU></TEXTE_JURI_ADMIN>",
+            )
+            .unwrap();
+        }
+        // a file that cannot be read is reported
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(tmp.path().join("missing"), tmp.path().join("jade/broken.xml"))
+            .unwrap();
+
+        let (index, fields) = init_tantivy_ram().unwrap();
+        let mut writer: tantivy::IndexWriter = index.writer(15_000_000).unwrap();
+        let options = IndexingOptions {
+            threads: 2,
+            batch_size: 3,
+            ..Default::default()
+        };
+        index_files_in_dir(&mut writer, &fields, tmp.path(), false, &options).unwrap();
+        assert_eq!(index.reader().unwrap().searcher().num_docs(), 20);
+        #[cfg(unix)]
+        assert_eq!(options.failures.failures()[0].path, "jade/broken.xml");
+        // indexing again replaces the documents
+        let delta = "JADE_20250801-210000.tar.gz";
+        let files: Vec<String> = (0..5)
+            .map(|i| format!("jade/{}/{}/CETATEXT{:012}.xml", i % 3, i % 7, i))
+            .collect();
+        crate::dumps::provenance::record(tmp.path(), delta, &files).unwrap();
+        index_files_in_dir(&mut writer, &fields, tmp.path(), false, &options).unwrap();
+        assert_eq!(index.reader().unwrap().searcher().num_docs(), 20);
+
+        let query = format!(r#"tarball:"{}" AND tarball_date:"2025-08-01T00:00:00Z""#, delta);
+        let found = search_index(&index, &fields, &None, &query, &SearchOptions::default());
+        assert_eq!(found.unwrap().count, 5);
+        let matches = matching_documents(&[(&index, &fields)], "requête", &SearchOptions::default()).unwrap();
+        assert_eq!(matches.len(), 20);
+        assert_eq!(matches.iter().filter(|m| m.tarball.as_deref() == Some(delta)).count(), 5);
+        remove_tarball(&writer, &fields, delta).unwrap();
+        writer.commit().unwrap();
+        assert_eq!(index.reader().unwrap().searcher().num_docs(), 15);
+    }
+
+    #[test]
+    fn test_rebuild_index() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let index_path = tmp.path().join("index");
+        let build = |n: usize| {
+            move |path: &Path| {
+                let (index, fields) = init_tantivy(&path.to_path_buf())?;
+                let mut writer: tantivy::IndexWriter = index.writer(15_000_000)?;
+                for i in 0..n {
+                    let file = FondXMLFile {
+                        path: format!("{}.xml", i),
+                        body: String::new(),
+                        date: None,
+                        metadata: None,
+                        tarball: None,
+                        empty: false,
+                    };
+                    index_file(&writer, &fields, file)?;
+                }
+                writer.commit()?;
+                Ok(())
+            }
+        };
+        let num_docs = || {
+            let (index, _) = init_tantivy(&index_path).unwrap();
+            index.reader().unwrap().searcher().num_docs()
+        };
+
+        rebuild_index(&index_path, build(1)).unwrap();
+        rebuild_index(&index_path, build(2)).unwrap();
+        assert_eq!(num_docs(), 2);
+        // a failed build leaves the index untouched
+        let failed = rebuild_index(&index_path, |path: &Path| {
+            build(3)(path)?;
+            Err::<(), _>(anyhow::anyhow!("interrupted"))
+        });
+        assert!(failed.is_err());
+        assert!(!tmp.path().join("index.building").exists());
+        assert_eq!(num_docs(), 2);
+
+        rollback_index(&index_path).unwrap();
+        assert_eq!(num_docs(), 1);
+        rollback_index(&index_path).unwrap();
+        assert_eq!(num_docs(), 2);
+    }
+
+    #[test]
+    fn test_repair_index() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let index_path = tmp.path().to_path_buf();
+        {
+            let (index, fields) = init_tantivy(&index_path).unwrap();
+            let mut writer: tantivy::IndexWriter = index.writer(15_000_000).unwrap();
+            // one segment per commit
+            for batch in [0..2, 2..5] {
+                for i in batch {
+                    let file = FondXMLFile {
+                        path: format!("{}.xml", i),
+                        body: String::new(),
+                        date: None,
+                        metadata: None,
+                        tarball: None,
+                        empty: false,
+                    };
+                    index_file(&writer, &fields, file).unwrap();
+                }
+                writer.commit().unwrap();
+            }
+        }
+        let (index, _) = init_tantivy(&index_path).unwrap();
+        let segments = index.searchable_segment_metas().unwrap();
+        assert_eq!(segments.len(), 2);
+        let damaged = segments.iter().find(|segment| segment.num_docs() == 2).unwrap();
+        let files = segment_files(damaged);
+        let store = files.iter().find(|f| f.extension().is_some_and(|e| e == "store")).unwrap();
+        std::fs::remove_file(index_path.join(store)).unwrap();
+        assert!(open_index(&index_path).is_err());
+
+        // opening the damaged index does not change it
+        let error = init_tantivy(&index_path).err().unwrap().to_string();
+        assert!(error.contains("--repair-index"));
+        assert!(!index_path.join("meta.json.damaged").exists());
+
+        // the damaged segment is dropped, the other one is kept
+        let repair = repair_damaged_index(&index_path).unwrap().unwrap();
+        assert_eq!((repair.dropped_segments, repair.lost_documents), (1, 2));
+        assert_eq!(repair_damaged_index(&index_path).unwrap(), None);
+        let (index, _) = init_tantivy(&index_path).unwrap();
+        assert_eq!(index.reader().unwrap().searcher().num_docs(), 3);
+        assert!(files.iter().all(|file| !index_path.join(file).exists()));
+        assert!(index_path.join("meta.json.damaged").exists());
+        open_index(&index_path).unwrap();
+
+        // without its meta.json, the index is not silently replaced
+        std::fs::remove_file(index_path.join(META_FILE)).unwrap();
+        let error = init_tantivy(&index_path).err().unwrap().to_string();
+        assert!(error.contains("--rebuild-index"));
+    }
+
+    #[test]
+    fn test_reindex_and_remove() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let dir = tmp.path().join("legi/global");
+        std::fs::create_dir_all(&dir).unwrap();
+        let text = dir.join("LEGITEXT000006070158.xml");
+        std::fs::write(
+            &text,
+            "<TEXTE_VERSION><META><META_COMMUN><ID>LEGITEXT000006070158</ID></META_COMMUN></META>\
+             <ARTICLE id=\"LEGIARTI000006335064\" debut=\"2005-03-01\"><BLOC_TEXTUEL><CONTENU>Un.</CONTENU></BLOC_TEXTUEL></ARTICLE>\
+             <ARTICLE id=\"LEGIARTI000006335065\" debut=\"2006-01-01\"><BLOC_TEXTUEL><CONTENU>Deux.</CONTENU></BLOC_TEXTUEL></ARTICLE>\
+             </TEXTE_VERSION>",
+        )
+        .unwrap();
+        let decision = dir.join("CETATEXT000000000001.xml");
+        std::fs::write(
+            &decision,
+            "<TEXTE_JURI_ADMIN><DATE_DEC>2024-03-21</DATE_DEC><CONTENU>Rejet.</CONTENU></TEXTE_JURI_ADMIN>",
+        )
+        .unwrap();
+
+        let (index, fields) = init_tantivy_ram().unwrap();
+        let mut writer: tantivy::IndexWriter = index.writer(15_000_000).unwrap();
+        let num_docs = |writer: &mut tantivy::IndexWriter| {
+            writer.commit().unwrap();
+            index.reader().unwrap().searcher().num_docs()
+        };
+
+        // indexing twice does not create duplicates
+        for _ in 0..2 {
+            reindex_file(&writer, &fields, tmp.path(), &text, true).unwrap();
+            reindex_file(&writer, &fields, tmp.path(), &decision, false).unwrap();
+        }
+        assert_eq!(num_docs(&mut writer), 3);
+
+        // a decision replaced by a placeholder is not indexed anymore
+        std::fs::write(&decision, "<TEXTE_JURI_ADMIN><DATE_DEC>2024-03-21</DATE_DEC></TEXTE_JURI_ADMIN>").unwrap();
+        reindex_file(&writer, &fields, tmp.path(), &decision, false).unwrap();
+        assert_eq!(num_docs(&mut writer), 2);
+        std::fs::write(
+            &decision,
+            "<TEXTE_JURI_ADMIN><DATE_DEC>2024-03-21</DATE_DEC><CONTENU>Rejet.</CONTENU></TEXTE_JURI_ADMIN>",
+        )
+        .unwrap();
+        reindex_file(&writer, &fields, tmp.path(), &decision, false).unwrap();
+
+        // all the articles of a text are removed
+        remove_file(&writer, &fields, "legi/global/LEGITEXT000006070158.xml");
+        assert_eq!(num_docs(&mut writer), 1);
+
+        let list = tmp.path().join("liste_suppression_jade.dat");
+        std::fs::write(&list, "legi/global/CETATEXT000000000001\n\n").unwrap();
+        assert!(is_suppression_list(&list));
+        assert_eq!(apply_suppression_list(&writer, &fields, &list).unwrap(), 1);
+        assert_eq!(num_docs(&mut writer), 0);
+    }
+
+    #[test]
+    fn test_fond_indices() {
+        let extracted = temp_dir::TempDir::new().unwrap();
+        for (fond, date) in [("jade", "2020-01-01"), ("capp", "2021-01-01")] {
+            let dir = extracted.path().join(fond).join("global");
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(
+                dir.join(format!("{}.xml", fond)),
+                format!(
+                    "<TEXTE_JURI_JUDI><META><META_SPEC><META_JURI><DATE_DEC>{}</DATE_DEC>\
+                     </META_JURI></META_SPEC></META><TEXTE><BLOC_TEXTUEL><CONTENU>La requête \
+                     est rejetée.</CONTENU></BLOC_TEXTUEL></TEXTE></TEXTE_JURI_JUDI>",
+                    date
+                ),
+            )
+            .unwrap();
+        }
+        let index_dir = temp_dir::TempDir::new().unwrap();
+        let fonds = index_fonds_in_dir(
+            index_dir.path(),
+            extracted.path(),
+            15_000_000,
+            false,
+            &AnalyzerConfig::default(),
+            &IndexingOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(fonds, vec!["CAPP", "JADE"]);
+        assert_eq!(list_fond_indices(index_dir.path()).unwrap(), fonds);
+
+        let options = SearchOptions {
+            facets: true,
+            ..Default::default()
+        };
+        let search = |fonds: &[String]| {
+            let indices = open_fond_indices(index_dir.path(), fonds, &AnalyzerConfig::default()).unwrap();
+            let indices: Vec<_> = indices.iter().map(|(i, f)| (i, f)).collect();
+            search_indices(&indices, &None, "requête", &options).unwrap()
+        };
+        let jade = search(&["JADE".to_string()]);
+        assert_eq!(jade.count, 1);
+        assert_eq!(jade.hits[0].0, "jade/global/jade.xml");
+
+        let all = search(&fonds);
+        assert_eq!(all.count, 2);
+        assert_eq!(all.hits.len(), 2);
+        assert_eq!(all.facets.unwrap().years, [(2020, 1), (2021, 1)].into());
+
+        // pages of the merged results
+        let indices = open_fond_indices(index_dir.path(), &fonds, &AnalyzerConfig::default()).unwrap();
+        let indices: Vec<_> = indices.iter().map(|(i, f)| (i, f)).collect();
+        let page = |offset| {
+            let options = SearchOptions { limit: 1, offset, ..Default::default() };
+            search_indices(&indices, &None, "requête", &options).unwrap().hits
+        };
+        assert_eq!(page(0).len(), 1);
+        assert_ne!(page(0), page(1));
+        assert!(page(2).is_empty());
+        // the pages follow the merged results, and all count every match
+        let unpaged = search_indices(&indices, &None, "requête", &SearchOptions::default()).unwrap();
+        assert_eq!([page(0), page(1)].concat(), unpaged.hits);
+        let options = SearchOptions { limit: 1, offset: 1, ..Default::default() };
+        assert_eq!(search_indices(&indices, &None, "requête", &options).unwrap().count, 2);
+        // the newest documents of all the indices come first
+        let options = SearchOptions { sort: SortBy::Date, ..Default::default() };
+        let newest = search_indices(&indices, &None, "requête", &options).unwrap().hits;
+        let paths: Vec<_> = newest.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(paths, vec!["capp/global/capp.xml", "jade/global/jade.xml"]);
+        // the counts of the indices are summed
+        assert_eq!(count_matches(&indices, "requête", &SearchOptions::default()).unwrap(), 2);
+        let years = date_histogram(&indices, "requête", &SearchOptions::default(), HistogramBucket::Year);
+        assert_eq!(years.unwrap(), [("2020".to_string(), 1), ("2021".to_string(), 1)].into());
+
+        let mut out = vec![];
+        let count = export_matches(&indices, "requête", &SearchOptions::default(), &mut out).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(String::from_utf8(out).unwrap(), "capp/global/capp.xml\njade/global/jade.xml\n");
+        // the export ignores the pages, but not the dates
+        let options = SearchOptions {
+            from: NaiveDate::from_ymd_opt(2021, 1, 1),
+            limit: 1,
+            ..Default::default()
+        };
+        let mut out = vec![];
+        assert_eq!(export_matches(&indices, "requête", &options, &mut out).unwrap(), 1);
+        assert_eq!(String::from_utf8(out).unwrap(), "capp/global/capp.xml\n");
+    }
+
+    #[test]
+    fn test_search_page_bounds() {
+        let (index, fields) = init_tantivy_ram().unwrap();
+        let mut writer: tantivy::IndexWriter = index.writer(15_000_000).unwrap();
+        let re = regex::Regex::new(r"(?<date>\d{4}-\d{2}-\d{2})</DATE").unwrap();
+        // one segment per commit, c.xml being replaced in the second one
+        for documents in [[("c.xml", "requête"), ("a.xml", "requête")], [("d.xml", "requête"), ("c.xml", "requête")]] {
+            for (path, text) in documents.into_iter().chain([("b.xml", "autre")]) {
+                let body = format!(
+                    "<TEXTE_JURI_ADMIN><TEXTE><BLOC_TEXTUEL><CONTENU>La {} est rejetée.\
+                     </CONTENU></BLOC_TEXTUEL></TEXTE></TEXTE_JURI_ADMIN>",
+                    text
+                );
+                index_files(&writer, &fields, parse_body_as(path.to_string(), &body, &re, false).unwrap());
+            }
+            writer.commit().unwrap();
+        }
+
+        let search = |offset, limit| {
+            let options = SearchOptions { offset, limit, ..Default::default() };
+            search_index(&index, &fields, &None, "requête", &options)
+        };
+        // an empty page still counts the matches
+        let empty = search(0, 0).unwrap();
+        assert_eq!((empty.count, empty.hits.len()), (3, 0));
+        assert!(search(SearchOptions::MAX_WINDOW - 1, 1).unwrap().hits.is_empty());
+        // pages too far away are refused rather than allocated
+        let err = search(SearchOptions::MAX_WINDOW, 1).unwrap_err();
+        assert!(err.to_string().contains("more than 100000"), "{}", err);
+        assert!(search(usize::MAX, 10).is_err());
+
+        // every match is exported once, in order, across segments and indices
+        let indices = [(&index, &fields), (&index, &fields)];
+        let options = SearchOptions { limit: 1, ..Default::default() };
+        let mut out = vec![];
+        assert_eq!(export_matches(&indices, "requête", &options, &mut out).unwrap(), 3);
+        assert_eq!(String::from_utf8(out).unwrap(), "a.xml\nc.xml\nd.xml\n");
+        assert_eq!(matching_paths(&indices, "autre", &options).unwrap(), vec!["b.xml"]);
+        assert!(matching_paths(&indices, "absent", &options).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_search_date_range() {
+        let (index, fields) = init_tantivy_ram().unwrap();
+        let mut writer: tantivy::IndexWriter = index.writer(15_000_000).unwrap();
+        let re = regex::Regex::new(r"(?<date>\d{4}-\d{2}-\d{2})</DATE").unwrap();
+        for (path, date) in [("a.xml", "2019-12-31"), ("b.xml", "2020-06-15"), ("c.xml", "2021-01-01")] {
+            let body = format!(
+                "<TEXTE_JURI_ADMIN><META><META_SPEC><META_JURI><DATE_DEC>{}</DATE_DEC>\
+                 </META_JURI></META_SPEC></META><TEXTE><BLOC_TEXTUEL><CONTENU>La requête est rejetée.\
+                 </CONTENU></BLOC_TEXTUEL></TEXTE></TEXTE_JURI_ADMIN>",
+                date
+            );
+            index_files(&writer, &fields, parse_body_as(path.to_string(), &body, &re, false).unwrap());
+        }
+        writer.commit().unwrap();
+
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok();
+        let search = |from: &str, to: &str| {
+            let options = SearchOptions { from: date(from), to: date(to), ..Default::default() };
+            let mut results = search_index(&index, &fields, &None, "requête", &options).unwrap().hits;
+            results.sort();
+            results
+        };
+        assert_eq!(search("", ""), vec![
+            ("a.xml".to_string(), date("2019-12-31")),
+            ("b.xml".to_string(), date("2020-06-15")),
+            ("c.xml".to_string(), date("2021-01-01")),
+        ]);
+        // bounds are included
+        assert_eq!(search("2020-01-01", "2021-01-01").len(), 2);
+        assert_eq!(search("", "2020-06-15").len(), 2);
+        assert_eq!(search("2020-06-16", "").len(), 1);
+
+        let options = SearchOptions { sort: SortBy::Date, ..Default::default() };
+        let newest = search_index(&index, &fields, &None, "requête", &options).unwrap().hits;
+        let paths: Vec<_> = newest.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(paths, vec!["c.xml", "b.xml", "a.xml"]);
+        let options = SearchOptions { sort: SortBy::Date, offset: 1, limit: 1, ..Default::default() };
+        let second = search_index(&index, &fields, &None, "requête", &options).unwrap().hits;
+        assert_eq!(second[0].0, "b.xml");
+
+        let indices = [(&index, &fields)];
+        let options = SearchOptions { from: date("2020-01-01"), ..Default::default() };
+        assert_eq!(count_matches(&indices, "requête", &options).unwrap(), 2);
+        let years = date_histogram(&indices, "requête", &SearchOptions::default(), HistogramBucket::Year);
+        assert_eq!(
+            years.unwrap(),
+            [("2019".to_string(), 1), ("2020".to_string(), 1), ("2021".to_string(), 1)].into()
+        );
+        let months = date_histogram(&indices, "requête", &options, HistogramBucket::Month).unwrap();
+        assert_eq!(months.keys().collect::<Vec<_>>(), vec!["2020-06", "2021-01"]);
+        assert_eq!(count_matches(&indices, "absent", &options).unwrap(), 0);
+        assert!(date_histogram(&indices, "absent", &options, HistogramBucket::Year).unwrap().is_empty());
+    }
+}
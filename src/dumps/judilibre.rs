@@ -0,0 +1,257 @@
+/// Enrichment of the decisions of the Cour de cassation (CASS, INCA)
+/// and of the cours d'appel (CAPP) with the metadata of Judilibre:
+/// themes, solution, publication and zones of the text. Every decision
+/// of the extraction directory is looked up in Judilibre by its ECLI
+/// or by its number (and date), and the result is written as a JSON
+/// line keyed by the identifier of the decision in the dumps:
+///
+/// ```json
+/// {"id":"JURITEXT000043283367","fond":"CASS","key":{"jurisdiction":"cc","ecli":"ECLI:FR:CCASS:2021:C100214",...},
+///  "judilibre":{"id":"6079a8a79ba5988459c4a0c1","solution":"cassation","themes":["Contrats"],"zones":{...},...}}
+/// ```
+///
+/// so that it can be joined with the exports (`id` column) or with the
+/// index. Decisions that Judilibre does not publish are written with a
+/// `null` `judilibre` field, and are not looked up again when the
+/// command is run a second time on the same file.
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use log::{info, warn};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, HashSet};
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use crate::api::client::AuthenticatedClient;
+use crate::api::judilibre::{JudilibreDecision, JudilibreKey, Jurisdiction, find_decision};
+use crate::dumps::extractor::{self, Document, PreDilaText};
+use crate::dumps::fonds::Fond;
+use crate::dumps::tarballs::walk_files;
+use crate::shutdown;
+
+/// Default name of the file written by `dilarxiv judilibre`
+pub const JUDILIBRE_FILE: &str = "judilibre.jsonl";
+
+/// The fonds whose decisions are published by Judilibre
+pub const JUDILIBRE_FONDS: &[Fond] = &[Fond::CASS, Fond::INCA, Fond::CAPP];
+
+/// The court of the decisions of a fond in Judilibre
+pub fn jurisdiction(fond: Fond) -> Option<Jurisdiction> {
+    match fond {
+        Fond::CASS | Fond::INCA => Some(Jurisdiction::Cc),
+        Fond::CAPP => Some(Jurisdiction::Ca),
+        _ => None,
+    }
+}
+
+/// How `decision` of `fond` is looked up in Judilibre
+pub fn decision_key(fond: Fond, decision: &PreDilaText) -> Option<JudilibreKey> {
+    let non_empty = |s: Option<&String>| s.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+    let key = JudilibreKey {
+        jurisdiction: jurisdiction(fond)?,
+        ecli: decision.ecli().map(|ecli| ecli.to_string()),
+        number: non_empty(decision.juri_code.as_ref()),
+        date: non_empty(decision.decision_date.as_ref()),
+    };
+    (!key.is_empty()).then_some(key)
+}
+
+/// A decision of the dumps and its metadata in Judilibre
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Enrichment {
+    /// Identifier of the decision in the dumps
+    pub id: String,
+    pub fond: Fond,
+    pub key: JudilibreKey,
+    /// `None` when Judilibre does not publish the decision
+    pub judilibre: Option<JudilibreDecision>,
+}
+
+/// The decisions of the given fonds of the extraction directory `dir`
+/// that can be looked up in Judilibre, sorted by identifier
+pub fn decisions(dir: &Path, fonds: &[Fond]) -> Result<Vec<(String, Fond, JudilibreKey)>> {
+    let mut decisions = vec![];
+    for fond in fonds {
+        let fond_dir = dir.join(fond.as_str().to_lowercase());
+        if !fond_dir.exists() {
+            warn!("No extracted documents for {} in {}", fond, fond_dir.display());
+            continue;
+        }
+        let found: Vec<_> = walk_files(&fond_dir)
+            .par_bridge()
+            .filter_map(|file| {
+                let file = file.ok()?;
+                if file.extension().is_none_or(|ext| ext != "xml") {
+                    return None;
+                }
+                // unparsable files are reported by `validate`
+                let Ok(Document::Decision(decision)) = extractor::parse_document_file(&file) else {
+                    return None;
+                };
+                let key = decision_key(*fond, &decision)?;
+                Some((decision.id, *fond, key))
+            })
+            .collect();
+        decisions.extend(found);
+    }
+    decisions.sort_by(|a, b| a.0.cmp(&b.0));
+    decisions.dedup_by(|a, b| a.0 == b.0);
+    Ok(decisions)
+}
+
+/// The enrichments of a file written by [`enrich`], by identifier
+pub fn read_enrichments(path: &Path) -> Result<BTreeMap<String, Enrichment>> {
+    let file = std::fs::File::open(path).context(format!("Failed to open {}", path.display()))?;
+    let mut enrichments = BTreeMap::new();
+    for (number, line) in std::io::BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Enrichment>(&line) {
+            Ok(enrichment) => {
+                enrichments.insert(enrichment.id.clone(), enrichment);
+            }
+            // the last line of an interrupted run can be truncated
+            Err(e) => warn!("Ignoring line {} of {}: {}", number + 1, path.display(), e),
+        }
+    }
+    Ok(enrichments)
+}
+
+/// What [`enrich`] did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EnrichmentSummary {
+    /// Decisions found in Judilibre
+    pub found: usize,
+    /// Decisions not published by Judilibre
+    pub missing: usize,
+    /// Decisions already in the output file
+    pub skipped: usize,
+    /// Decisions whose requests failed, looked up again on the next run
+    pub failed: usize,
+}
+
+/// Look up the decisions of the given fonds of the extraction directory
+/// `dir` in Judilibre, appending the results to `output`, with at most
+/// `requests` simultaneous requests
+pub async fn enrich(
+    aclient: &AuthenticatedClient,
+    dir: &Path,
+    fonds: &[Fond],
+    output: &Path,
+    requests: usize,
+) -> Result<EnrichmentSummary> {
+    let mut summary = EnrichmentSummary::default();
+    let done: HashSet<String> = if output.exists() {
+        read_enrichments(output)?.into_keys().collect()
+    } else {
+        HashSet::new()
+    };
+    let todo: Vec<_> = decisions(dir, fonds)?
+        .into_iter()
+        .filter(|(id, _, _)| {
+            let skip = done.contains(id);
+            summary.skipped += skip as usize;
+            !skip
+        })
+        .collect();
+    info!(
+        "Looking up {} decisions in Judilibre ({} already done)",
+        todo.len(),
+        summary.skipped
+    );
+
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(output)
+        .context(format!("Failed to open {}", output.display()))?;
+    let mut writer = std::io::BufWriter::new(file);
+    let mut results = futures::stream::iter(todo)
+        .map(|(id, fond, key)| async move {
+            let found = find_decision(aclient, &key).await;
+            (id, fond, key, found)
+        })
+        .buffer_unordered(requests.max(1));
+    // the results are written by this loop only, in the order they come
+    while let Some((id, fond, key, found)) = results.next().await {
+        let judilibre = match found {
+            Ok(decision) => decision,
+            Err(e) => {
+                warn!("Failed to look up {} in Judilibre: {:#}", id, e);
+                summary.failed += 1;
+                continue;
+            }
+        };
+        match judilibre {
+            Some(_) => summary.found += 1,
+            None => summary.missing += 1,
+        }
+        let enrichment = Enrichment { id, fond, key, judilibre };
+        serde_json::to_writer(&mut writer, &enrichment)?;
+        writer.write_all(b"\n")?;
+        if shutdown::is_interrupted() {
+            warn!("Interrupted, the remaining decisions are looked up on the next run");
+            break;
+        }
+    }
+    writer.flush()?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_judilibre_decisions() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let cass = tmp.path().join("cass/global/juri/judi/JURI/TEXT");
+        let jade = tmp.path().join("jade/global/juri/admin/CETA/TEXT");
+        std::fs::create_dir_all(&cass).unwrap();
+        std::fs::create_dir_all(&jade).unwrap();
+        let decision = |id: &str, numero: &str, ecli: &str| {
+            format!(
+                "<TEXTE_JURI_JUDI><META><META_COMMUN><ID>{id}</ID></META_COMMUN><META_SPEC><META_JURI>\
+                 <DATE_DEC>2021-03-10</DATE_DEC><NUMERO>{numero}</NUMERO></META_JURI>\
+                 <META_JURI_JUDI><ECLI>{ecli}</ECLI></META_JURI_JUDI></META_SPEC></META>\
+                 <TEXTE><BLOC_TEXTUEL><CONTENU>Cassation.</CONTENU></BLOC_TEXTUEL></TEXTE></TEXTE_JURI_JUDI>"
+            )
+        };
+        std::fs::write(
+            cass.join("JURITEXT000043283367.xml"),
+            decision("JURITEXT000043283367", "19-21.060", "ECLI:FR:CCASS:2021:C100214"),
+        )
+        .unwrap();
+        std::fs::write(cass.join("JURITEXT000000000001.xml"), decision("JURITEXT000000000001", "", ""))
+            .unwrap();
+        std::fs::write(jade.join("CETATEXT000000000001.xml"), decision("CETATEXT000000000001", "1", ""))
+            .unwrap();
+
+        let found = decisions(tmp.path(), JUDILIBRE_FONDS).unwrap();
+        assert_eq!(found.len(), 1);
+        let (id, fond, key) = &found[0];
+        assert_eq!(id, "JURITEXT000043283367");
+        assert_eq!(*fond, Fond::CASS);
+        assert_eq!(key.jurisdiction, Jurisdiction::Cc);
+        assert_eq!(key.ecli.as_deref(), Some("ECLI:FR:CCASS:2021:C100214"));
+        assert_eq!(key.number.as_deref(), Some("19-21.060"));
+        assert_eq!(key.date.as_deref(), Some("2021-03-10"));
+
+        let path = tmp.path().join(JUDILIBRE_FILE);
+        let enrichment = Enrichment {
+            id: id.clone(),
+            fond: *fond,
+            key: key.clone(),
+            judilibre: None,
+        };
+        let line = serde_json::to_string(&enrichment).unwrap();
+        std::fs::write(&path, format!("{}\n{{\"id\":\"JURI", line)).unwrap();
+        let read = read_enrichments(&path).unwrap();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[id], enrichment);
+    }
+}
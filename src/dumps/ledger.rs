@@ -0,0 +1,164 @@
+/// Ledger of the results of `dilarxiv-oneshot`: the documents matching
+/// the query in every chunk of tarballs, appended once the chunk is
+/// searched, one JSON line per document:
+///
+/// ```json
+/// {"path":"jade/global/juri/admin/CETA/TEXT/00/00/49/31/48/CETATEXT000049314894.xml","tarball":"JADE_20240321-211004.tar.gz","year":2024}
+/// ```
+///
+/// The ledger is kept when resuming a run, and the documents of a chunk
+/// interrupted after its matches were appended are recorded twice (the
+/// chunk is processed again), which [`read_ledger`] removes.
+use anyhow::{Context, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// Name of the ledger in the state directory of `dilarxiv-oneshot`
+pub const LEDGER_FILE: &str = "results.jsonl";
+
+/// A document matching the query
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    /// Path of the document, relative to the extraction directory
+    pub path: String,
+    /// Name of the tarball the document was extracted from, if known
+    pub tarball: Option<String>,
+    /// Year of the date of the document, if it has one
+    pub year: Option<i32>,
+}
+
+/// The ledger, open for appending the matches of the chunks
+#[derive(Debug)]
+pub struct Ledger {
+    path: PathBuf,
+    file: std::fs::File,
+}
+
+impl Ledger {
+    /// Open the ledger at `path`, keeping the matches of the previous
+    /// runs. A last line left incomplete by a crash is removed, so that
+    /// the next matches do not end up on the same line.
+    pub fn open(path: &Path) -> Result<Ledger> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .context(format!("Failed to open {}", path.display()))?;
+        let content = std::fs::read(path).context(format!("Failed to read {}", path.display()))?;
+        let complete = content.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+        if complete < content.len() {
+            warn!("Removing the incomplete last line of {}", path.display());
+            file.set_len(complete as u64)?;
+        }
+        Ok(Ledger {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    /// Path of the ledger
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append the matches of a chunk. The ledger is synced to the disk,
+    /// so that the tarballs of the chunk can then be marked as processed.
+    pub fn append(&mut self, entries: &[LedgerEntry]) -> Result<()> {
+        let mut lines = vec![];
+        for entry in entries {
+            serde_json::to_writer(&mut lines, entry)?;
+            lines.push(b'\n');
+        }
+        self.file
+            .write_all(&lines)
+            .context(format!("Failed to write to {}", self.path.display()))?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+/// The documents of the ledger at `path`, in the order they were first
+/// appended. A document recorded several times keeps its last entry,
+/// which is the one of the file left in the results.
+pub fn read_ledger(path: &Path) -> Result<Vec<LedgerEntry>> {
+    let file = std::fs::File::open(path).context(format!("Failed to open {}", path.display()))?;
+    let mut entries: Vec<LedgerEntry> = vec![];
+    let mut positions = HashMap::new();
+    for (number, line) in std::io::BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: LedgerEntry = match serde_json::from_str(&line) {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Ignoring line {} of {}: {}", number + 1, path.display(), e);
+                continue;
+            }
+        };
+        match positions.get(&entry.path) {
+            Some(&position) => entries[position] = entry,
+            None => {
+                positions.insert(entry.path.clone(), entries.len());
+                entries.push(entry);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, tarball: &str, year: Option<i32>) -> LedgerEntry {
+        LedgerEntry {
+            path: path.to_string(),
+            tarball: Some(tarball.to_string()),
+            year,
+        }
+    }
+
+    #[test]
+    fn test_ledger_chunks() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let path = tmp.path().join(LEDGER_FILE);
+        let chunks = [
+            vec![
+                entry("jade/a.xml", "JADE_20240101-210000.tar.gz", Some(2023)),
+                entry("jade/b.xml", "JADE_20240101-210000.tar.gz", None),
+            ],
+            vec![],
+            vec![entry("cass/c.xml", "CASS_20240102-210000.tar.gz", Some(2024))],
+        ];
+        let mut ledger = Ledger::open(&path).unwrap();
+        for chunk in chunks.iter() {
+            ledger.append(chunk).unwrap();
+        }
+        drop(ledger);
+        let all: Vec<LedgerEntry> = chunks.concat();
+        assert_eq!(read_ledger(&path).unwrap(), all);
+
+        // a crash in the middle of a line, then a run resuming with the
+        // last chunk and a new one
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"path":"cass/d.xml","tar"#).unwrap();
+        drop(file);
+        let mut ledger = Ledger::open(&path).unwrap();
+        ledger.append(&chunks[2]).unwrap();
+        let updated = entry("jade/a.xml", "JADE_20240103-210000.tar.gz", Some(2023));
+        ledger.append(&[entry("cass/d.xml", "CASS_20240103-210000.tar.gz", Some(2024)), updated.clone()]).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 6);
+        assert!(content.lines().all(|line| serde_json::from_str::<LedgerEntry>(line).is_ok()));
+
+        let read = read_ledger(&path).unwrap();
+        let paths: Vec<&str> = read.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["jade/a.xml", "jade/b.xml", "cass/c.xml", "cass/d.xml"]);
+        assert_eq!(read[0], updated);
+    }
+}
@@ -0,0 +1,158 @@
+/// Lock of the data directory, so that two runs of `dilarxiv` do not
+/// download, extract or index in the same directory at the same time
+/// (which corrupts the extracted files and the index). The lock is an
+/// exclusive `flock` (see [`std::fs::File::try_lock`]) on a file of the
+/// data directory, which also holds the PID of the process for the error
+/// messages. The system releases the lock when the process ends, even
+/// when it is killed, so a lock is never stale.
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use std::fs::{File, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+
+/// Name of the lock file in the data directory
+pub const LOCK_FILE: &str = "dilarxiv.lock";
+
+/// The process holding the lock
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockOwner {
+    pub pid: u32,
+    /// The host of the process, if known, as the data directory may be
+    /// shared between machines
+    pub host: Option<String>,
+    pub started: DateTime<Utc>,
+    /// What the process is doing (e.g. "update")
+    pub operation: String,
+}
+
+impl LockOwner {
+    fn current(operation: &str) -> LockOwner {
+        LockOwner {
+            pid: std::process::id(),
+            host: hostname(),
+            started: Utc::now(),
+            operation: operation.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for LockOwner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "process {}", self.pid)?;
+        if let Some(host) = &self.host {
+            write!(f, " on {}", host)?;
+        }
+        write!(f, " ({} since {})", self.operation, self.started.format("%Y-%m-%d %H:%M:%S UTC"))
+    }
+}
+
+fn hostname() -> Option<String> {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+}
+
+/// The lock of a data directory, released when dropped
+#[derive(Debug)]
+pub struct DataLock {
+    file: File,
+}
+
+impl DataLock {
+    /// Lock the data directory `dir` for `operation`, failing if
+    /// another process holds the lock
+    pub fn acquire(dir: &Path, operation: &str) -> Result<DataLock> {
+        std::fs::create_dir_all(dir).context(format!("Failed to create {}", dir.display()))?;
+        let path = dir.join(LOCK_FILE);
+        // the file is never removed: a process waiting on a removed file
+        // would hold a lock that nobody else sees
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .context(format!("Failed to open the lock {}", path.display()))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let mut content = String::new();
+                let owner = file
+                    .read_to_string(&mut content)
+                    .ok()
+                    .and_then(|_| serde_json::from_str::<LockOwner>(&content).ok());
+                return Err(match owner {
+                    Some(owner) => anyhow::anyhow!(
+                        "The data directory {} is used by {}. Wait for it to finish",
+                        dir.display(),
+                        owner
+                    ),
+                    None => anyhow::anyhow!(
+                        "The data directory {} is being locked by another process",
+                        dir.display()
+                    ),
+                });
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(e).context(format!("Failed to lock {}", path.display()));
+            }
+        }
+        // the owner left by a process that was killed is replaced
+        file.set_len(0)?;
+        file.rewind()?;
+        serde_json::to_writer(&mut file, &LockOwner::current(operation))?;
+        file.write_all(b"\n")?;
+        file.sync_all()?;
+        debug!("Locked {}", dir.display());
+        Ok(DataLock { file })
+    }
+}
+
+impl Drop for DataLock {
+    /// Forget the owner, the lock itself is released with the file
+    fn drop(&mut self) {
+        if let Err(e) = self.file.set_len(0) {
+            warn!("Failed to clear the lock: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_lock() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let lock = DataLock::acquire(tmp.path(), "update").unwrap();
+        let path = tmp.path().join(LOCK_FILE);
+        let owner: LockOwner = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(owner.pid, std::process::id());
+        assert_eq!(owner.operation, "update");
+
+        // the lock is held by its file, even by the same process
+        let error = DataLock::acquire(tmp.path(), "index").unwrap_err().to_string();
+        assert!(error.contains(&format!("process {}", std::process::id())));
+        assert!(error.contains("update since"));
+        drop(lock);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+
+        // the owner left by a killed process does not hold the lock
+        let killed = LockOwner {
+            pid: u32::MAX,
+            ..LockOwner::current("index")
+        };
+        std::fs::write(&path, serde_json::to_string(&killed).unwrap()).unwrap();
+        let lock = DataLock::acquire(tmp.path(), "mirror").unwrap();
+        let owner: LockOwner = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!((owner.pid, owner.operation.as_str()), (std::process::id(), "mirror"));
+        drop(lock);
+    }
+}
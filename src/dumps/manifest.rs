@@ -0,0 +1,263 @@
+/// Manifests of the corpus, to state exactly which data a study used
+/// and to rebuild the same corpus later. `dilarxiv freeze` lists every
+/// tarball of the `tarballs` folder (name, date, size and SHA-256) and
+/// the settings of the extraction; `dilarxiv verify` checks that the
+/// local tarballs are still the ones of a manifest, and `dilarxiv
+/// restore` downloads the missing ones and extracts them again.
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use log::warn;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use std::path::Path;
+
+use crate::dumps::fonds::Fond;
+use crate::dumps::tarballs::{Tarball, TarballKind, extract_timestamp_from_tarball_name};
+
+/// Default name of the manifest written by `dilarxiv freeze`
+pub const MANIFEST_FILE: &str = "corpus-manifest.json";
+
+/// Hex encoded SHA-256 of a file, read by chunks
+pub fn file_hash(path: &Path) -> Result<String> {
+    let mut file =
+        std::fs::File::open(path).context(format!("Failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).context(format!("Failed to read {}", path.display()))?;
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// How the tarballs were extracted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtractionSettings {
+    /// In the content addressable store (`extract --content-addressed`)
+    pub content_addressed: bool,
+    /// Then packed into shards (`extract --pack`)
+    pub pack: bool,
+}
+
+/// A tarball of the manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestTarball {
+    pub name: String,
+    pub fond: Fond,
+    pub date: NaiveDate,
+    pub kind: TarballKind,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl From<&ManifestTarball> for Tarball {
+    fn from(tarball: &ManifestTarball) -> Self {
+        Tarball {
+            name: tarball.name.clone(),
+            fond: tarball.fond,
+            time: tarball.date,
+            timestamp: extract_timestamp_from_tarball_name(&tarball.name).ok(),
+            size: Some(tarball.size),
+            kind: tarball.kind,
+        }
+    }
+}
+
+/// The tarballs a corpus was built from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Version of the tools that wrote the manifest
+    pub version: String,
+    pub created: DateTime<Utc>,
+    /// The tarballs published after this day were left out
+    pub until: Option<NaiveDate>,
+    pub settings: ExtractionSettings,
+    /// In the order of their extraction: by fond, then by date
+    pub tarballs: Vec<ManifestTarball>,
+}
+
+impl Manifest {
+    /// The manifest of the tarballs of `dir` of the given fonds (all
+    /// when empty), published up to the day `until` if given
+    pub fn freeze(
+        dir: &Path,
+        fonds: &[Fond],
+        until: Option<NaiveDate>,
+        settings: ExtractionSettings,
+    ) -> Result<Manifest> {
+        let mut selected = vec![];
+        for entry in std::fs::read_dir(dir).context(format!("Failed to read {}", dir.display()))? {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            if !name.ends_with(".tar.gz") {
+                continue;
+            }
+            match Tarball::from_name(&name) {
+                Ok(tarball)
+                    if (fonds.is_empty() || fonds.contains(&tarball.fond))
+                        && until.is_none_or(|until| tarball.time <= until) =>
+                {
+                    selected.push(tarball)
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Ignoring {}: {:#}", name, e),
+            }
+        }
+        sort_tarballs(&mut selected);
+        let tarballs = selected
+            .par_iter()
+            .map(|tarball| {
+                let path = dir.join(tarball);
+                Ok(ManifestTarball {
+                    name: tarball.name.clone(),
+                    fond: tarball.fond,
+                    date: tarball.time,
+                    kind: tarball.kind,
+                    size: std::fs::metadata(&path)?.len(),
+                    sha256: file_hash(&path)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Manifest {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            created: Utc::now(),
+            until,
+            settings,
+            tarballs,
+        })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Manifest> {
+        let file = std::fs::File::open(path).context(format!("Failed to open {}", path.display()))?;
+        serde_json::from_reader(std::io::BufReader::new(file))
+            .context(format!("Invalid manifest {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file = std::fs::File::create(path)
+            .context(format!("Failed to create manifest {}", path.display()))?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), self)
+            .context("Failed to serialize manifest")?;
+        Ok(())
+    }
+
+    /// The tarballs of the manifest, in the order of their extraction
+    pub fn tarballs(&self) -> Vec<Tarball> {
+        self.tarballs.iter().map(Tarball::from).collect()
+    }
+
+    /// Check the tarballs of the manifest stored in `dir`,
+    /// returning the ones that are missing or differ
+    pub fn verify(&self, dir: &Path) -> Result<Vec<Mismatch>> {
+        self.tarballs
+            .par_iter()
+            .filter_map(|tarball| {
+                let path = dir.join(&tarball.name);
+                let size = match std::fs::metadata(&path) {
+                    Ok(metadata) => metadata.len(),
+                    Err(_) => return Some(Ok(Mismatch::Missing(tarball.name.clone()))),
+                };
+                if size != tarball.size {
+                    return Some(Ok(Mismatch::Size {
+                        name: tarball.name.clone(),
+                        expected: tarball.size,
+                        found: size,
+                    }));
+                }
+                match file_hash(&path) {
+                    Ok(hash) if hash == tarball.sha256 => None,
+                    Ok(hash) => Some(Ok(Mismatch::Checksum {
+                        name: tarball.name.clone(),
+                        expected: tarball.sha256.clone(),
+                        found: hash,
+                    })),
+                    Err(e) => Some(Err(e)),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The order of extraction of tarballs: by fond, then by time of
+/// publication (see [`Tarball::application_order`])
+pub fn sort_tarballs(tarballs: &mut [Tarball]) {
+    tarballs.sort_by_key(|t| (t.fond, t.application_order()));
+}
+
+/// A tarball of a manifest that is not in the `tarballs` folder as listed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    Missing(String),
+    Size { name: String, expected: u64, found: u64 },
+    Checksum { name: String, expected: String, found: String },
+}
+
+impl Mismatch {
+    pub fn name(&self) -> &str {
+        match self {
+            Mismatch::Missing(name)
+            | Mismatch::Size { name, .. }
+            | Mismatch::Checksum { name, .. } => name,
+        }
+    }
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mismatch::Missing(name) => write!(f, "{} is missing", name),
+            Mismatch::Size { name, expected, found } => {
+                write!(f, "{} has {} bytes instead of {}", name, found, expected)
+            }
+            Mismatch::Checksum { name, expected, found } => {
+                write!(f, "{} has the SHA-256 {} instead of {}", name, found, expected)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let dir = tmp.path().join("tarballs");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("JADE_20240108-210000.tar.gz"), b"delta").unwrap();
+        std::fs::write(dir.join("Freemium_jade_global_20240101-100000.tar.gz"), b"stock").unwrap();
+        std::fs::write(dir.join("CASS_20240108-210000.tar.gz"), b"cass").unwrap();
+        std::fs::write(dir.join("CASS_20240301-210000.tar.gz"), b"later").unwrap();
+        std::fs::write(dir.join("mirror-report.json"), b"{}").unwrap();
+
+        let until = NaiveDate::from_ymd_opt(2024, 2, 1);
+        let settings = ExtractionSettings { content_addressed: false, pack: true };
+        let manifest = Manifest::freeze(&dir, &[], until, settings).unwrap();
+        let names: Vec<&str> = manifest.tarballs.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "Freemium_jade_global_20240101-100000.tar.gz",
+                "JADE_20240108-210000.tar.gz",
+                "CASS_20240108-210000.tar.gz"
+            ]
+        );
+        assert_eq!(manifest.tarballs[2].size, 4);
+        assert_eq!(manifest.tarballs[2].sha256, "cb6af909d62da685d06f86f9943884743430e2d9a1db4e7a1c76aa1d5da49635");
+        let only_jade = Manifest::freeze(&dir, &[Fond::JADE], None, settings).unwrap();
+        assert_eq!(only_jade.tarballs.len(), 2);
+
+        let path = tmp.path().join(MANIFEST_FILE);
+        manifest.save(&path).unwrap();
+        let manifest = Manifest::from_file(&path).unwrap();
+        assert_eq!(manifest.settings, settings);
+        assert!(manifest.verify(&dir).unwrap().is_empty());
+
+        std::fs::write(dir.join("JADE_20240108-210000.tar.gz"), b"other").unwrap();
+        std::fs::remove_file(dir.join("CASS_20240108-210000.tar.gz")).unwrap();
+        let mut problems: Vec<String> =
+            manifest.verify(&dir).unwrap().iter().map(|m| m.to_string()).collect();
+        problems.sort();
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0], "CASS_20240108-210000.tar.gz is missing");
+        assert!(problems[1].starts_with("JADE_20240108-210000.tar.gz has the SHA-256"));
+    }
+}
@@ -0,0 +1,393 @@
+/// MCP (Model Context Protocol) server, so that LLM agents can search
+/// the local index and read documents without custom glue code. The
+/// server speaks JSON-RPC 2.0 on its standard input and output (one
+/// message per line, the logs go to the standard error) and exposes
+/// three tools:
+///
+/// - `search_corpus` the best results of a query on the local index,
+///   with the title, date, permalink and an excerpt of each document
+/// - `get_document` a document of the extracted files by identifier,
+///   as Markdown with a YAML front matter
+/// - `get_full_text_api` the full text of a document from the PISTE
+///   API, when credentials were given
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use log::{debug, error, info};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::api::client::{AuthenticatedClient, get_full_text};
+use crate::api::piste::Fond as ApiFond;
+use crate::dumps::corpus::Corpus;
+use crate::dumps::embeddings::document_key;
+use crate::dumps::extractor::identifiers::permalink;
+use crate::dumps::extractor::markdown::{title, to_markdown};
+use crate::dumps::server::{SearchParams, ServerError};
+use crate::dumps::tarballs::SortBy;
+use crate::dumps::tui;
+
+/// Version of the protocol answered to clients that do not ask for one
+pub const MCP_PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// Largest number of results of `search_corpus`
+pub const MAX_RESULTS: usize = 50;
+
+/// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Arguments of `search_corpus`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SearchArguments {
+    query: String,
+    #[serde(default)]
+    fonds: Vec<String>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    limit: Option<usize>,
+    #[serde(default)]
+    sort: SortBy,
+}
+
+/// Arguments of `get_document`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DocumentArguments {
+    id: String,
+}
+
+/// Arguments of `get_full_text_api`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FullTextArguments {
+    id: String,
+    fond: ApiFond,
+}
+
+/// The description of the tools, answered to `tools/list`
+fn tools() -> Value {
+    json!([
+        {
+            "name": "search_corpus",
+            "description": "Search the local index of the French legal open data (Légifrance: \
+                decisions of the courts, Journal Officiel, codes, CNIL). Returns the number of \
+                results and the best ones, with their identifier, date, title and an excerpt.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "Full text query, e.g. `antenne relais` or `\"permis de construire\" AND jurisdiction:CAA`",
+                    },
+                    "fonds": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Only the documents of these fonds, e.g. [\"JADE\", \"CASS\"]",
+                    },
+                    "from": { "type": "string", "format": "date", "description": "Only documents dated on or after this day" },
+                    "to": { "type": "string", "format": "date", "description": "Only documents dated on or before this day" },
+                    "limit": { "type": "integer", "minimum": 1, "maximum": MAX_RESULTS, "default": 10 },
+                    "sort": { "type": "string", "enum": ["score", "date"], "default": "score" },
+                },
+                "required": ["query"],
+            },
+        },
+        {
+            "name": "get_document",
+            "description": "The metadata and full text of a document of the local corpus, \
+                by identifier (e.g. CETATEXT000049314894), as Markdown.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "id": { "type": "string", "description": "Identifier of the document" },
+                },
+                "required": ["id"],
+            },
+        },
+        {
+            "name": "get_full_text_api",
+            "description": "The full text of a document from the Légifrance API (PISTE), \
+                for documents that are not in the local corpus yet.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "id": { "type": "string", "description": "Identifier of the document" },
+                    "fond": {
+                        "type": "string",
+                        "enum": ["JORF", "CNIL", "CETAT", "JURI", "JUFI", "CONSTIT", "KALI",
+                                 "CODE_DATE", "LODA_DATE", "CIRC", "ACCO"],
+                        "description": "Fond of the API the document belongs to",
+                    },
+                },
+                "required": ["id", "fond"],
+            },
+        },
+    ])
+}
+
+/// The result of a tool, as text
+fn tool_result(text: String, is_error: bool) -> Value {
+    json!({
+        "content": [{ "type": "text", "text": text }],
+        "isError": is_error,
+    })
+}
+
+/// The invalid parameters of a search, as an error of the tool
+fn anyhow_error(e: ServerError) -> anyhow::Error {
+    match e {
+        ServerError::BadRequest(m) | ServerError::NotFound(m) => anyhow::anyhow!(m),
+        ServerError::Internal(e) => e,
+    }
+}
+
+/// Errors answered as JSON-RPC errors
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn invalid_params<E: std::fmt::Display>(e: E) -> Self {
+        RpcError {
+            code: INVALID_PARAMS,
+            message: format!("Invalid arguments: {}", e),
+        }
+    }
+}
+
+/// An MCP server on a corpus, and on the API when a client is given
+pub struct McpServer {
+    corpus: Corpus,
+    api: Option<AuthenticatedClient>,
+}
+
+impl McpServer {
+    pub fn new(corpus: Corpus, api: Option<AuthenticatedClient>) -> Self {
+        McpServer { corpus, api }
+    }
+
+    fn search(&self, arguments: SearchArguments) -> Result<Value> {
+        let params = SearchParams {
+            q: Some(arguments.query.clone()),
+            fond: Some(arguments.fonds.join(",")),
+            from: arguments.from,
+            to: arguments.to,
+            limit: Some(arguments.limit.unwrap_or(10).min(MAX_RESULTS)),
+            sort: arguments.sort,
+            ..Default::default()
+        };
+        let query = params.query().map_err(anyhow_error)?.context("Empty query")?;
+        let options = params.options().map_err(anyhow_error)?;
+        let results = self.corpus.search(&query, &options)?;
+        let edir = self.corpus.extracted_dir();
+        let hits: Vec<Value> = results
+            .hits
+            .iter()
+            .map(|(path, date)| {
+                let id = document_key(path);
+                // the hits whose file cannot be read are listed anyway
+                let document = tui::load_document(&edir, path)
+                    .inspect_err(|e| debug!("Failed to read {}: {:#}", path, e))
+                    .ok();
+                json!({
+                    "id": id,
+                    "date": date,
+                    "title": document.as_ref().and_then(title),
+                    "permalink": permalink(&id).map(String::from),
+                    "excerpt": document.as_ref().and_then(|d| d.text()).map(|text| {
+                        tui::snippet(text, &arguments.query, tui::SNIPPET_LENGTH)
+                    }),
+                })
+            })
+            .collect();
+        Ok(json!({ "count": results.count, "hits": hits }))
+    }
+
+    /// Run a tool, its failures are results with `isError`
+    async fn call_tool(&self, name: &str, arguments: Value) -> Result<Value, RpcError> {
+        let result = match name {
+            "search_corpus" => {
+                let arguments: SearchArguments =
+                    serde_json::from_value(arguments).map_err(RpcError::invalid_params)?;
+                if arguments.limit == Some(0) {
+                    return Err(RpcError::invalid_params("limit must be at least 1"));
+                }
+                self.search(arguments)
+                    .and_then(|answer| Ok(serde_json::to_string_pretty(&answer)?))
+            }
+            "get_document" => {
+                let DocumentArguments { id } =
+                    serde_json::from_value(arguments).map_err(RpcError::invalid_params)?;
+                self.corpus
+                    .document(&id)
+                    .and_then(|document| document.context(format!("No document {} in the corpus", id)))
+                    .map(|document| to_markdown(&document))
+            }
+            "get_full_text_api" => {
+                let FullTextArguments { id, fond } =
+                    serde_json::from_value(arguments).map_err(RpcError::invalid_params)?;
+                match self.api.as_ref() {
+                    Some(api) => get_full_text(api, &id, &fond).await,
+                    None => Err(anyhow::anyhow!("The server was started without API credentials")),
+                }
+            }
+            _ => {
+                return Err(RpcError {
+                    code: INVALID_PARAMS,
+                    message: format!("Unknown tool {}", name),
+                });
+            }
+        };
+        Ok(match result {
+            Ok(text) => tool_result(text, false),
+            Err(e) => {
+                error!("Tool {} failed: {:#}", name, e);
+                tool_result(format!("{:#}", e), true)
+            }
+        })
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        match method {
+            "initialize" => {
+                let version = params["protocolVersion"].as_str().unwrap_or(MCP_PROTOCOL_VERSION);
+                Ok(json!({
+                    "protocolVersion": version,
+                    "capabilities": { "tools": {} },
+                    "serverInfo": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                }))
+            }
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": tools() })),
+            "tools/call" => {
+                let name = params["name"]
+                    .as_str()
+                    .ok_or_else(|| RpcError::invalid_params("missing tool name"))?;
+                let arguments = params.get("arguments").cloned().unwrap_or(json!({}));
+                self.call_tool(name, arguments).await
+            }
+            _ => Err(RpcError {
+                code: METHOD_NOT_FOUND,
+                message: format!("Unknown method {}", method),
+            }),
+        }
+    }
+
+    /// The answer to a JSON-RPC message, `None` for notifications
+    pub async fn handle(&self, message: &str) -> Option<Value> {
+        let message: Value = match serde_json::from_str(message) {
+            Ok(message) => message,
+            Err(e) => {
+                return Some(json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": { "code": PARSE_ERROR, "message": e.to_string() },
+                }));
+            }
+        };
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let Some(id) = message.get("id").cloned() else {
+            debug!("Notification {}", method);
+            return None;
+        };
+        Some(match self.call(method, params).await {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(e) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": e.code, "message": e.message },
+            }),
+        })
+    }
+
+    /// Answer the messages of `input` on `output` until the end of `input`
+    pub async fn run<R, W>(&self, input: R, mut output: W) -> Result<()>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut lines = input.lines();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(answer) = self.handle(&line).await {
+                let mut answer = serde_json::to_vec(&answer)?;
+                answer.push(b'\n');
+                output.write_all(&answer).await?;
+                output.flush().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Answer the messages of the standard input until it is closed
+    pub async fn serve_stdio(&self) -> Result<()> {
+        info!("Serving MCP requests on the standard input");
+        let stdin = tokio::io::BufReader::new(tokio::io::stdin());
+        self.run(stdin, tokio::io::stdout()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dumps::corpus::CorpusConfig;
+
+    #[tokio::test]
+    async fn test_mcp_server() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let corpus = Corpus::open(CorpusConfig::new(tmp.path())).unwrap();
+        let server = McpServer::new(corpus, None);
+
+        let input = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2025-03-26","capabilities":{}}}"#,
+            r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"tools/call","params":{"name":"search_corpus","arguments":{"query":"antenne"}}}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"tools/call","params":{"name":"get_full_text_api","arguments":{"id":"JORFTEXT000049300000","fond":"JORF"}}}"#,
+            r#"{"jsonrpc":"2.0","id":5,"method":"tools/call","params":{"name":"get_document","arguments":{"identifier":"x"}}}"#,
+            r#"{"jsonrpc":"2.0","id":6,"method":"resources/list"}"#,
+            "not json",
+            r#"{"jsonrpc":"2.0","id":7,"method":"tools/call","params":{"name":"search_corpus","arguments":{"query":"antenne","limit":0}}}"#,
+        ]
+        .join("\n");
+        let mut output = vec![];
+        server.run(input.as_bytes(), &mut output).await.unwrap();
+        let answers: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        // no answer to the notification
+        assert_eq!(answers.len(), 8);
+        assert_eq!(answers[0]["result"]["protocolVersion"], "2025-03-26");
+        let names: Vec<&str> = answers[1]["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["search_corpus", "get_document", "get_full_text_api"]);
+        // the empty corpus has no index to search
+        assert_eq!(answers[2]["id"], 3);
+        assert!(answers[2]["result"]["content"][0]["text"].is_string());
+        assert_eq!(answers[3]["result"]["isError"], true);
+        assert_eq!(answers[4]["error"]["code"], INVALID_PARAMS);
+        assert_eq!(answers[5]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(answers[6]["error"]["code"], PARSE_ERROR);
+        assert_eq!(answers[7]["id"], 7);
+        assert_eq!(answers[7]["error"]["code"], INVALID_PARAMS);
+    }
+}
@@ -0,0 +1,256 @@
+/// Keep a local tarball directory in sync with the dila server.
+///
+/// Mirroring a fond means:
+/// 1. downloading the tarballs that are not yet present locally,
+/// 2. deleting local tarballs of that fond that disappeared upstream,
+/// 3. recording what happened in a machine readable report.
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use log::{info, warn};
+use std::path::Path;
+
+use crate::dumps::fonds::Fond;
+use crate::dumps::tarballs::{self, DownloadOptions, TarballListing, UnparsableName};
+
+/// What happened to a single fond during a mirror operation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FondSyncReport {
+    pub fond: Fond,
+    /// Number of tarballs listed on the server
+    pub upstream: usize,
+    /// Tarballs that were downloaded during this run
+    pub downloaded: Vec<String>,
+    /// Tarballs that could not be downloaded, even after retrying
+    #[serde(default)]
+    pub failed: Vec<String>,
+    /// Local tarballs that were deleted because they are gone upstream
+    pub deleted: Vec<String>,
+    /// Files of the listing whose names could not be parsed
+    #[serde(default)]
+    pub unparsable: Vec<UnparsableName>,
+    /// Set when the local tarballs were not pruned, and why
+    #[serde(default)]
+    pub prune_skipped: Option<String>,
+    /// Set when the fond could not be synchronized
+    pub error: Option<String>,
+}
+
+/// The report written at the end of a mirror operation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MirrorReport {
+    pub started: DateTime<Utc>,
+    pub finished: DateTime<Utc>,
+    pub fonds: Vec<FondSyncReport>,
+}
+
+/// Decide whether a tarball name belongs to a given fond.
+/// Names are either `FOND_YYYYMMDD-XXXXX.tar.gz` or
+/// `Freemium_fond_global_YYYYMMDD-XXXXX.tar.gz`.
+pub fn tarball_belongs_to(name: &str, fond: &Fond) -> bool {
+    name.split('_')
+        .any(|part| part.eq_ignore_ascii_case(fond.as_str()))
+}
+
+/// List the names of the tarballs of a fond present in `dir`
+pub fn local_tarballs(dir: &Path, fond: &Fond) -> Result<Vec<String>> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut names = vec![];
+    for entry in std::fs::read_dir(dir)
+        .context(format!("Failed to read directory {}", dir.display()))?
+    {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if path.is_file() && name.ends_with(".tar.gz") && tarball_belongs_to(name, fond) {
+            names.push(name.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Every file name of an upstream listing, including the ones
+/// that could not be parsed as tarball names
+pub fn listed_names(listing: &TarballListing) -> Vec<String> {
+    listing
+        .tarballs
+        .iter()
+        .map(|t| t.name.clone())
+        .chain(listing.unparsable.iter().map(|u| u.name.clone()))
+        .collect()
+}
+
+/// Local tarballs that are not listed upstream anymore
+pub fn stale_tarballs(local: &[String], upstream: &[String]) -> Vec<String> {
+    local
+        .iter()
+        .filter(|name| !upstream.contains(name))
+        .cloned()
+        .collect()
+}
+
+/// Why the local tarballs should not be pruned against `listing`, if
+/// they should not. An empty listing is much more likely to be a server
+/// hiccup than a fond that was wiped, and files with unexpected names
+/// mean that the listing is not understood well enough to delete anything.
+pub fn prune_blocker(listing: &TarballListing) -> Option<String> {
+    if listing.tarballs.is_empty() && listing.unparsable.is_empty() {
+        Some("empty tarball listing".to_string())
+    } else if !listing.unparsable.is_empty() {
+        Some(format!(
+            "{} files of the listing have unexpected names",
+            listing.unparsable.len()
+        ))
+    } else {
+        None
+    }
+}
+
+async fn mirror_fond(
+    client: &Client,
+    dir: &Path,
+    fond: &Fond,
+    opts: &DownloadOptions,
+) -> Result<FondSyncReport> {
+    let listing = tarballs::list_fond_listing(client, fond).await?;
+    for unparsable in listing.unparsable.iter() {
+        warn!("Unexpected file {} in {}: {}", unparsable.name, fond, unparsable.error);
+    }
+    if listing.tarballs.is_empty() && listing.unparsable.is_empty() {
+        return Err(anyhow::anyhow!("Empty tarball listing for {}", fond));
+    }
+
+    let report = tarballs::download_tarball_list(client, &listing.tarballs, dir, opts).await?;
+    let downloaded = report.downloaded().into_iter().map(|t| t.name).collect();
+    let failed = report.failed().into_iter().map(|t| t.name).collect();
+
+    let prune_skipped = prune_blocker(&listing);
+    let deleted = match &prune_skipped {
+        Some(reason) => {
+            warn!("Not pruning the tarballs of {}: {}", fond, reason);
+            vec![]
+        }
+        None => stale_tarballs(&local_tarballs(dir, fond)?, &listed_names(&listing)),
+    };
+    for name in deleted.iter() {
+        info!("Removing {} (not listed upstream anymore)", name);
+        std::fs::remove_file(dir.join(name)).context(format!("Failed to remove {}", name))?;
+    }
+
+    Ok(FondSyncReport {
+        fond: *fond,
+        upstream: listing.tarballs.len() + listing.unparsable.len(),
+        downloaded,
+        failed,
+        deleted,
+        unparsable: listing.unparsable,
+        prune_skipped,
+        error: None,
+    })
+}
+
+/// Make `dir` match the dila server for the given fonds
+pub async fn mirror_fonds(
+    client: &Client,
+    dir: &Path,
+    fonds: &[Fond],
+    opts: &DownloadOptions,
+) -> Result<MirrorReport> {
+    let started = Utc::now();
+    std::fs::create_dir_all(dir).context(format!("Failed to create directory {}", dir.display()))?;
+    let mut reports = vec![];
+    for fond in fonds {
+        info!("Mirroring {}", fond);
+        let report = match mirror_fond(client, dir, fond, opts).await {
+            Ok(report) => report,
+            Err(e) => {
+                warn!("Failed to mirror {}: {}", fond, e);
+                FondSyncReport {
+                    fond: *fond,
+                    upstream: 0,
+                    downloaded: vec![],
+                    failed: vec![],
+                    deleted: vec![],
+                    unparsable: vec![],
+                    prune_skipped: Some("the fond could not be synchronized".to_string()),
+                    error: Some(e.to_string()),
+                }
+            }
+        };
+        reports.push(report);
+    }
+    Ok(MirrorReport {
+        started,
+        finished: Utc::now(),
+        fonds: reports,
+    })
+}
+
+/// Write the report as pretty printed JSON
+pub fn write_report(report: &MirrorReport, path: &Path) -> Result<()> {
+    let file = std::fs::File::create(path)
+        .context(format!("Failed to create report {}", path.display()))?;
+    serde_json::to_writer_pretty(std::io::BufWriter::new(file), report)
+        .context("Failed to serialize mirror report")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use crate::dumps::tarballs::Tarball;
+
+    #[test]
+    fn test_tarball_belongs_to() {
+        assert!(tarball_belongs_to("CASS_20231125-130812.tar.gz", &Fond::CASS));
+        assert!(tarball_belongs_to(
+            "Freemium_jorf_global_20231119-100000.tar.gz",
+            &Fond::JORF
+        ));
+        assert!(!tarball_belongs_to("CASS_20231125-130812.tar.gz", &Fond::CAPP));
+    }
+
+    #[test]
+    fn test_stale_tarballs() {
+        let time = NaiveDate::from_ymd_opt(2023, 11, 25).unwrap();
+        let listing = TarballListing {
+            tarballs: vec![Tarball {
+                name: "CASS_20231125-130812.tar.gz".to_string(),
+                fond: Fond::CASS,
+                time,
+                timestamp: None,
+                size: None,
+                kind: Default::default(),
+            }],
+            unparsable: vec![UnparsableName {
+                name: "CASS_20231126-999999.tar.gz".to_string(),
+                error: "Invalid time".to_string(),
+            }],
+        };
+        let local = vec![
+            "CASS_20231125-130812.tar.gz".to_string(),
+            "CASS_20231126-999999.tar.gz".to_string(),
+            "CASS_20220101-000000.tar.gz".to_string(),
+        ];
+        // unparsable names are still listed upstream, hence not stale
+        assert_eq!(
+            stale_tarballs(&local, &listed_names(&listing)),
+            vec!["CASS_20220101-000000.tar.gz".to_string()]
+        );
+        // but they prevent pruning altogether
+        assert!(prune_blocker(&listing).is_some());
+        let parsed = TarballListing {
+            unparsable: vec![],
+            ..listing.clone()
+        };
+        assert_eq!(prune_blocker(&parsed), None);
+        assert!(prune_blocker(&TarballListing::default()).is_some());
+    }
+}
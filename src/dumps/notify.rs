@@ -0,0 +1,158 @@
+/// Notifications of the new documents matching a saved query, sent by
+/// `dilarxiv watch` after an update:
+///
+/// - a webhook receives the [`Notification`] as JSON in a POST request
+/// - a command is run by `sh -c` with the notification as JSON on its
+///   standard input (and the name of the query and the number of new
+///   documents in `LEGIFRANCE_QUERY` and `LEGIFRANCE_COUNT`)
+/// - an [ntfy](https://ntfy.sh) topic receives the notification as text
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use tokio::io::AsyncWriteExt;
+
+/// Server of the ntfy topics given by name
+pub const NTFY_SERVER: &str = "https://ntfy.sh";
+
+/// A document newly matching a saved query
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewMatch {
+    /// Identifier of the document (e.g. CETATEXT000049314894)
+    pub id: String,
+    /// Path of the document in the index
+    pub path: String,
+    pub title: Option<String>,
+    pub date: Option<NaiveDate>,
+    /// An excerpt of the text of the document
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+}
+
+/// The new documents matching a saved query
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Notification {
+    /// Name of the saved query
+    pub query: String,
+    pub matches: Vec<NewMatch>,
+}
+
+impl Notification {
+    /// Short description of the notification
+    pub fn title(&self) -> String {
+        format!("{} new documents for {}", self.matches.len(), self.query)
+    }
+
+    /// One line per document: its identifier, date and title
+    pub fn message(&self) -> String {
+        self.matches
+            .iter()
+            .map(|m| {
+                let date = m.date.map(|d| format!(" ({})", d)).unwrap_or_default();
+                let title = m.title.as_deref().unwrap_or(&m.path);
+                format!("{}{} {}", m.id, date, title)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Where notifications are sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notifier {
+    /// URL receiving the notification as JSON
+    Webhook(String),
+    /// Shell command receiving the notification as JSON on its input
+    Command(String),
+    /// Name of a topic of [`NTFY_SERVER`], or URL of a topic
+    Ntfy(String),
+}
+
+impl Notifier {
+    pub async fn notify(&self, client: &Client, notification: &Notification) -> Result<()> {
+        match self {
+            Notifier::Webhook(url) => {
+                client
+                    .post(url)
+                    .header("Content-Type", "application/json")
+                    .body(serde_json::to_string(notification)?)
+                    .send()
+                    .await?
+                    .error_for_status()
+                    .context(format!("Webhook {} failed", url))?;
+            }
+            Notifier::Command(command) => {
+                let mut child = tokio::process::Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .env("LEGIFRANCE_QUERY", &notification.query)
+                    .env("LEGIFRANCE_COUNT", notification.matches.len().to_string())
+                    .stdin(std::process::Stdio::piped())
+                    .spawn()
+                    .context(format!("Failed to run {}", command))?;
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(&serde_json::to_vec(notification)?).await?;
+                }
+                let status = child.wait().await?;
+                if !status.success() {
+                    return Err(anyhow::anyhow!("{} failed: {}", command, status));
+                }
+            }
+            Notifier::Ntfy(topic) => {
+                let url = if topic.contains("://") {
+                    topic.clone()
+                } else {
+                    format!("{}/{}", NTFY_SERVER, topic)
+                };
+                client
+                    .post(&url)
+                    .header("Title", notification.title())
+                    .body(notification.message())
+                    .send()
+                    .await?
+                    .error_for_status()
+                    .context(format!("Notification to {} failed", url))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_notify_command() {
+        let notification = Notification {
+            query: "antennes-relais".to_string(),
+            matches: vec![NewMatch {
+                id: "CETATEXT000049314894".to_string(),
+                path: "jade/CETATEXT000049314894.xml".to_string(),
+                title: Some("Conseil d'État, 21/03/2024".to_string()),
+                date: NaiveDate::from_ymd_opt(2024, 3, 21),
+                summary: None,
+            }],
+        };
+        assert_eq!(notification.title(), "1 new documents for antennes-relais");
+        assert_eq!(
+            notification.message(),
+            "CETATEXT000049314894 (2024-03-21) Conseil d'État, 21/03/2024"
+        );
+
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let out = tmp.path().join("out.json");
+        let command = format!("echo $LEGIFRANCE_COUNT > {0}.count; cat > {0}", out.display());
+        let client = Client::new();
+        Notifier::Command(command).notify(&client, &notification).await.unwrap();
+        let received: Notification =
+            serde_json::from_str(&std::fs::read_to_string(&out).unwrap()).unwrap();
+        assert_eq!(received, notification);
+        let count = std::fs::read_to_string(tmp.path().join("out.json.count")).unwrap();
+        assert_eq!(count.trim(), "1");
+
+        let failing = Notifier::Command("exit 3".to_string());
+        assert!(failing.notify(&client, &notification).await.is_err());
+    }
+}
@@ -0,0 +1,274 @@
+/// Packed corpus archives.
+///
+/// Millions of small XML files are hard on file systems and backups.
+/// The `pack` step stores extracted documents in a few large shards:
+/// every document is compressed as an independent zstd frame and the
+/// frames are concatenated in `shard-NNNNN.zst` files. An offset index
+/// (`index.tsv`) records, for every document, its id, its relative path,
+/// the shard it lives in, and the position of its frame, so that any
+/// document can be read back without decompressing the whole shard.
+///
+/// Since concatenated zstd frames form a valid zstd stream, shards
+/// can also be decompressed with the regular `zstd` command line tool.
+use anyhow::{Context, Result};
+
+use log::info;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Default maximal size of a shard (compressed), 256MiB
+pub const DEFAULT_SHARD_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Location of a document inside a packed corpus
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackEntry {
+    /// Document id (file name without extension, e.g. `CETATEXT000049314894`)
+    pub id: String,
+    /// Path of the document relative to the extracted directory
+    pub path: String,
+    pub shard: u32,
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// The id of a document is the stem of its file name
+pub fn document_id(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.to_string())
+}
+
+fn shard_path(dir: &Path, shard: u32) -> PathBuf {
+    dir.join(format!("shard-{:05}.zst", shard))
+}
+
+pub struct PackWriter {
+    dir: PathBuf,
+    shard_size: u64,
+    shard: u32,
+    offset: u64,
+    writer: BufWriter<File>,
+    index: BufWriter<File>,
+    count: usize,
+}
+
+impl PackWriter {
+    /// Create a new packed corpus in `dir`
+    pub fn create(dir: &Path, shard_size: u64) -> Result<Self> {
+        std::fs::create_dir_all(dir).context(format!("Failed to create {}", dir.display()))?;
+        let index = File::create(dir.join("index.tsv")).context("Failed to create pack index")?;
+        let writer = File::create(shard_path(dir, 0)).context("Failed to create shard")?;
+        Ok(PackWriter {
+            dir: dir.to_path_buf(),
+            shard_size: shard_size.max(1),
+            shard: 0,
+            offset: 0,
+            writer: BufWriter::new(writer),
+            index: BufWriter::new(index),
+            count: 0,
+        })
+    }
+
+    /// Append a document to the current shard
+    pub fn add(&mut self, path: &str, content: &[u8]) -> Result<()> {
+        if self.offset >= self.shard_size {
+            self.writer.flush()?;
+            self.shard += 1;
+            self.offset = 0;
+            let file = File::create(shard_path(&self.dir, self.shard))
+                .context("Failed to create shard")?;
+            self.writer = BufWriter::new(file);
+        }
+        let frame = zstd::bulk::compress(content, 3).context("Failed to compress document")?;
+        self.writer.write_all(&frame)?;
+        writeln!(
+            self.index,
+            "{}\t{}\t{}\t{}\t{}",
+            document_id(path),
+            path,
+            self.shard,
+            self.offset,
+            frame.len()
+        )?;
+        self.offset += frame.len() as u64;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Flush everything to disk, returns the number of documents packed
+    pub fn finish(mut self) -> Result<usize> {
+        self.writer.flush()?;
+        self.index.flush()?;
+        Ok(self.count)
+    }
+}
+
+/// Pack every XML file below `src` into a packed corpus in `dst`,
+/// under its path in the archives (see [`crate::dumps::winpath::PathMapping`])
+pub fn pack_directory(src: &Path, dst: &Path, shard_size: u64) -> Result<usize> {
+    let mapping = crate::dumps::winpath::PathMapping::load(src)?;
+    let mut writer = PackWriter::create(dst, shard_size)?;
+    for file in crate::dumps::tarballs::list_files_in_dir(src.to_path_buf())? {
+        if file.extension().is_none_or(|ext| ext != "xml") {
+            continue;
+        }
+        let path = file
+            .strip_prefix(src)
+            .map_err(|_| anyhow::anyhow!("Failed to strip prefix from {}", file.display()))?
+            .to_string_lossy()
+            .to_string();
+        let content = std::fs::read(crate::dumps::winpath::extended_length(&file))
+            .context(format!("Failed to read {}", file.display()))?;
+        writer.add(mapping.original(&path), &content)?;
+    }
+    let count = writer.finish()?;
+    info!("Packed {} documents into {}", count, dst.display());
+    Ok(count)
+}
+
+pub struct PackReader {
+    dir: PathBuf,
+    entries: Vec<PackEntry>,
+    by_id: HashMap<String, usize>,
+    by_path: HashMap<String, usize>,
+}
+
+impl PackReader {
+    pub fn open(dir: &Path) -> Result<Self> {
+        let index = File::open(dir.join("index.tsv"))
+            .context(format!("Failed to open pack index in {}", dir.display()))?;
+        let mut entries = vec![];
+        for line in std::io::BufReader::new(index).lines() {
+            let line = line?;
+            let parts = line.split('\t').collect::<Vec<_>>();
+            if let [id, path, shard, offset, length] = parts[..] {
+                entries.push(PackEntry {
+                    id: id.to_string(),
+                    path: path.to_string(),
+                    shard: shard.parse()?,
+                    offset: offset.parse()?,
+                    length: length.parse()?,
+                });
+            } else {
+                return Err(anyhow::anyhow!("Invalid pack index line: {}", line));
+            }
+        }
+        let by_id = entries
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (e.id.clone(), i))
+            .collect();
+        let by_path = entries
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (e.path.clone(), i))
+            .collect();
+        Ok(PackReader {
+            dir: dir.to_path_buf(),
+            entries,
+            by_id,
+            by_path,
+        })
+    }
+
+    pub fn entries(&self) -> &[PackEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Read the content of a document given its location.
+    /// Documents that are not UTF-8 are transcoded.
+    pub fn read(&self, entry: &PackEntry) -> Result<String> {
+        let mut file = File::open(shard_path(&self.dir, entry.shard))?;
+        file.seek(SeekFrom::Start(entry.offset))?;
+        let mut frame = vec![0; entry.length as usize];
+        file.read_exact(&mut frame)
+            .context(format!("Truncated shard for {}", entry.id))?;
+        let content = zstd::stream::decode_all(&frame[..])
+            .context(format!("Failed to decompress {}", entry.id))?;
+        Ok(crate::dumps::extractor::encoding::decode(&content).into_owned())
+    }
+
+    /// Read a document given its id
+    pub fn get(&self, id: &str) -> Result<String> {
+        let entry = self
+            .by_id
+            .get(id)
+            .map(|&i| &self.entries[i])
+            .ok_or_else(|| anyhow::anyhow!("No document {} in pack", id))?;
+        self.read(entry)
+    }
+
+    /// Read a document given its path relative to the extracted directory
+    pub fn get_by_path(&self, path: &str) -> Result<String> {
+        let entry = self
+            .by_path
+            .get(path.trim())
+            .map(|&i| &self.entries[i])
+            .ok_or_else(|| anyhow::anyhow!("No document {} in pack", path.trim()))?;
+        self.read(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pack_roundtrip() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        // tiny shards so that every document gets its own shard
+        let mut writer = PackWriter::create(tmp.path(), 1).unwrap();
+        writer
+            .add("juri/CETATEXT000000000001.xml", b"<ID>CETATEXT000000000001</ID>")
+            .unwrap();
+        writer
+            .add("juri/CETATEXT000000000002.xml", b"<ID>CETATEXT000000000002</ID>")
+            .unwrap();
+        assert_eq!(writer.finish().unwrap(), 2);
+
+        let reader = PackReader::open(tmp.path()).unwrap();
+        assert_eq!(reader.len(), 2);
+        assert_eq!(reader.entries()[1].shard, 1);
+        assert_eq!(
+            reader.get("CETATEXT000000000002").unwrap(),
+            "<ID>CETATEXT000000000002</ID>"
+        );
+        assert_eq!(
+            reader.get_by_path("juri/CETATEXT000000000001.xml").unwrap(),
+            "<ID>CETATEXT000000000001</ID>"
+        );
+        assert!(reader.get("missing").is_err());
+    }
+
+    #[test]
+    fn test_pack_directory_original_paths() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let src = tmp.path().join("extracted");
+        std::fs::create_dir_all(src.join("legi/CON_")).unwrap();
+        std::fs::write(src.join("legi/CON_/a%3Ab.xml"), "<ID>a</ID>").unwrap();
+        std::fs::write(src.join("legi/b.xml"), "<ID>b</ID>").unwrap();
+        crate::dumps::winpath::record(
+            &src,
+            &[("legi/CON_/a%3Ab.xml".to_string(), "legi/CON/a:b.xml".to_string())],
+        )
+        .unwrap();
+
+        let dst = tmp.path().join("packed");
+        assert_eq!(pack_directory(&src, &dst, DEFAULT_SHARD_SIZE).unwrap(), 2);
+        let reader = PackReader::open(&dst).unwrap();
+        // the renamed files are packed under their path in the archives
+        assert_eq!(reader.get_by_path("legi/CON/a:b.xml").unwrap(), "<ID>a</ID>");
+        assert_eq!(reader.get_by_path("legi/b.xml").unwrap(), "<ID>b</ID>");
+    }
+}
@@ -0,0 +1,192 @@
+/// Stages of the pipeline of `dilarxiv-oneshot`, which downloads,
+/// extracts and indexes the tarballs at once.
+///
+/// The unit of the pipeline is a whole tarball: a stage hands a
+/// tarball to the next one once it is done with all of its documents,
+/// and the entries of a tarball are not streamed between stages. The
+/// stages are linked by bounded channels, so a stage waits while the
+/// next one is behind, and the tarballs leave every stage in the order
+/// they entered it (e.g. a delta is indexed after the previous ones).
+use futures::StreamExt;
+use log::{error, warn};
+use tokio::sync::mpsc::{Receiver, Sender};
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::dumps::failures::{FailureLog, Stage};
+use crate::dumps::provenance;
+use crate::dumps::tarballs::{self, Tarball};
+
+/// A tarball leaving the extraction, with the directory of its
+/// documents if it could be extracted
+pub type Extracted = (Tarball, Option<PathBuf>);
+
+/// Extract the tarball of `dl_dir` in a directory of its own of
+/// `extract_dir`, and remove it. The failures are recorded in
+/// `failures`, and leave no directory.
+pub fn extract_one(tarball: Tarball, dl_dir: &Path, extract_dir: &Path, failures: &FailureLog) -> Extracted {
+    let path = dl_dir.join(&tarball);
+    let dir = extract_dir.join(&tarball.name);
+    failures.processed(1);
+    // the index records the tarball of every document
+    let result = tarballs::extract_tarball(&path, &dir)
+        .and_then(|files| provenance::record(&dir, &tarball.name, &files));
+    if let Err(e) = std::fs::remove_file(&path) {
+        warn!("Failed to remove {}: {}", path.display(), e);
+    }
+    match result {
+        Ok(()) => (tarball, Some(dir)),
+        Err(e) => {
+            error!("Failed to extract {}: {:#}", tarball.name, e);
+            failures.record(tarball.name.clone(), Stage::Extract, e);
+            let _ = std::fs::remove_dir_all(&dir);
+            (tarball, None)
+        }
+    }
+}
+
+/// Extract the tarballs received from `downloaded` (`extractions` at
+/// once, on blocking threads), sending them to `extracted` in the order
+/// they were received. The extractions wait while `extracted` is full.
+pub async fn extract_stage(
+    mut downloaded: Receiver<Tarball>,
+    dl_dir: Arc<PathBuf>,
+    extract_dir: Arc<PathBuf>,
+    extractions: usize,
+    failures: FailureLog,
+    extracted: Sender<Extracted>,
+) {
+    let tarballs = futures::stream::poll_fn(|cx| downloaded.poll_recv(cx))
+        .map(|tarball| {
+            let dl_dir = dl_dir.clone();
+            let extract_dir = extract_dir.clone();
+            let failures = failures.clone();
+            async move {
+                tokio::task::spawn_blocking(move || extract_one(tarball, &dl_dir, &extract_dir, &failures))
+                    .await
+                    .expect("Failed to join the extraction")
+            }
+        })
+        .buffered(extractions.max(1));
+    let mut tarballs = std::pin::pin!(tarballs);
+    while let Some(tarball) = tarballs.next().await {
+        if extracted.send(tarball).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dumps::fonds::Fond;
+    use chrono::NaiveDate;
+
+    /// A tarball of `documents` documents in `dir`
+    fn fixture(dir: &Path, day: u32, documents: usize) -> Tarball {
+        let name = format!("CASS_202401{:02}-000000.tar.gz", day);
+        let file = std::fs::File::create(dir.join(&name)).unwrap();
+        let gz = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        let mut builder = tar::Builder::new(gz);
+        for i in 0..documents {
+            let content = format!("<TEXTE_JURI_JUDI><ID>JURITEXT{:012}</ID></TEXTE_JURI_JUDI>", i);
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_cksum();
+            let path = format!("cass/JURITEXT{:012}.xml", i);
+            builder.append_data(&mut header, path, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+        Tarball {
+            name,
+            fond: Fond::CASS,
+            time: NaiveDate::from_ymd_opt(2024, 1, day).unwrap(),
+            timestamp: None,
+            size: None,
+            kind: Default::default(),
+        }
+    }
+
+    /// Channels already holding `tarballs`, and the stage reading them
+    fn stage(
+        tmp: &Path,
+        tarballs: Vec<Tarball>,
+        capacity: usize,
+        failures: &FailureLog,
+    ) -> (impl Future<Output = ()> + use<>, Receiver<Extracted>) {
+        let (downloaded_tx, downloaded_rx) = tokio::sync::mpsc::channel(tarballs.len());
+        for tarball in tarballs {
+            downloaded_tx.try_send(tarball).unwrap();
+        }
+        let (extracted_tx, extracted_rx) = tokio::sync::mpsc::channel(capacity);
+        let stage = extract_stage(
+            downloaded_rx,
+            Arc::new(tmp.join("tarballs")),
+            Arc::new(tmp.join("extracted")),
+            3,
+            failures.clone(),
+            extracted_tx,
+        );
+        (stage, extracted_rx)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_extract_stage() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let dl_dir = tmp.path().join("tarballs");
+        std::fs::create_dir_all(&dl_dir).unwrap();
+
+        // the first tarball is the longest to extract, the third is corrupted
+        let mut tarballs: Vec<Tarball> = (1..=6).map(|day| fixture(&dl_dir, day, 1)).collect();
+        tarballs[0] = fixture(&dl_dir, 1, 2000);
+        std::fs::write(dl_dir.join(&tarballs[2]), b"not a tarball").unwrap();
+        let failures = FailureLog::new();
+        let (stage, mut extracted) = stage(tmp.path(), tarballs.clone(), 6, &failures);
+        stage.await;
+
+        let mut names = vec![];
+        while let Ok((tarball, dir)) = extracted.try_recv() {
+            assert_eq!(dir.is_none(), tarball == tarballs[2], "{}", tarball.name);
+            if let Some(dir) = dir {
+                assert!(dir.join("cass/JURITEXT000000000000.xml").is_file());
+            }
+            names.push(tarball.name);
+        }
+        // in the order of the downloads, whatever the extraction times
+        let expected: Vec<&str> = tarballs.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, expected);
+        let logged = failures.failures();
+        assert_eq!(logged.len(), 1);
+        assert_eq!((logged[0].path.as_str(), logged[0].stage), (tarballs[2].name.as_str(), Stage::Extract));
+        assert!(!tmp.path().join("extracted").join(&tarballs[2].name).exists());
+        assert!(std::fs::read_dir(&dl_dir).unwrap().next().is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_extract_stage_backpressure() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let dl_dir = tmp.path().join("tarballs");
+        std::fs::create_dir_all(&dl_dir).unwrap();
+        let tarballs: Vec<Tarball> = (1..=8).map(|day| fixture(&dl_dir, day, 1)).collect();
+        let failures = FailureLog::new();
+        let (stage, mut extracted) = stage(tmp.path(), tarballs.clone(), 1, &failures);
+
+        // nothing reads the extracted tarballs: the stage waits with one
+        // tarball in the channel, one being sent and 3 extractions
+        let stage = tokio::spawn(stage);
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        assert!(!stage.is_finished());
+        let waiting = std::fs::read_dir(&dl_dir).unwrap().count();
+        assert!(waiting >= tarballs.len() - 5, "{} tarballs waiting", waiting);
+
+        // and goes on once they are read
+        let mut count = 0;
+        while extracted.recv().await.is_some() {
+            count += 1;
+        }
+        stage.await.unwrap();
+        assert_eq!(count, tarballs.len());
+        assert!(failures.is_empty());
+    }
+}
@@ -0,0 +1,118 @@
+/// Provenance of the extracted documents: the tarball (the DILA
+/// delivery) each file was extracted from.
+///
+/// Every extraction appends the files of the tarball to a log at the
+/// root of the extraction directory (see [`PROVENANCE_FILE`]), one
+/// `path<TAB>tarball` line per file. A file extracted several times
+/// comes from the last tarball that contained it.
+use anyhow::{Context, Result};
+
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+/// Name of the provenance log, in the extraction directory
+pub const PROVENANCE_FILE: &str = ".provenance.tsv";
+
+/// Record that the `files` (relative to the extraction directory
+/// `dir`) were extracted from the tarball `tarball`
+pub fn record(dir: &Path, tarball: &str, files: &[String]) -> Result<()> {
+    let path = dir.join(PROVENANCE_FILE);
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .context(format!("Failed to open {}", path.display()))?;
+    let mut writer = std::io::BufWriter::new(file);
+    for file in files {
+        writeln!(writer, "{}\t{}", file, tarball)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Append the provenance log `log` (e.g. of a temporary extraction
+/// directory) to the log of the extraction directory `dir`
+pub fn merge_into(log: &Path, dir: &Path) -> Result<()> {
+    let mut source =
+        std::fs::File::open(log).context(format!("Failed to open {}", log.display()))?;
+    let path = dir.join(PROVENANCE_FILE);
+    let mut target = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .context(format!("Failed to open {}", path.display()))?;
+    std::io::copy(&mut source, &mut target)?;
+    Ok(())
+}
+
+/// The tarball of every extracted file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    tarballs: Vec<String>,
+    by_path: HashMap<String, usize>,
+}
+
+impl Provenance {
+    /// Read the provenance log of the extraction directory `dir`,
+    /// empty when the files were extracted by older versions
+    pub fn load(dir: &Path) -> Result<Provenance> {
+        let mut provenance = Provenance::default();
+        let path = dir.join(PROVENANCE_FILE);
+        if !path.exists() {
+            return Ok(provenance);
+        }
+        let file = std::fs::File::open(&path).context(format!("Failed to open {}", path.display()))?;
+        let mut ids: HashMap<String, usize> = HashMap::new();
+        for line in std::io::BufReader::new(file).lines() {
+            let line = line?;
+            let Some((file, tarball)) = line.split_once('\t') else {
+                continue;
+            };
+            let id = match ids.get(tarball) {
+                Some(id) => *id,
+                None => {
+                    provenance.tarballs.push(tarball.to_string());
+                    ids.insert(tarball.to_string(), provenance.tarballs.len() - 1);
+                    provenance.tarballs.len() - 1
+                }
+            };
+            provenance.by_path.insert(file.to_string(), id);
+        }
+        Ok(provenance)
+    }
+
+    /// The tarball the file at `path` (relative to the
+    /// extraction directory) was extracted from
+    pub fn get(&self, path: &str) -> Option<&str> {
+        self.by_path.get(path).map(|id| self.tarballs[*id].as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_path.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provenance() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        assert!(Provenance::load(tmp.path()).unwrap().is_empty());
+
+        let full = "Freemium_jade_global_20250713-140000.tar.gz";
+        let delta = "JADE_20250801-210000.tar.gz";
+        record(tmp.path(), full, &["jade/a.xml".to_string(), "jade/b.xml".to_string()]).unwrap();
+        let other = temp_dir::TempDir::new().unwrap();
+        record(other.path(), delta, &["jade/b.xml".to_string()]).unwrap();
+        merge_into(&other.path().join(PROVENANCE_FILE), tmp.path()).unwrap();
+
+        let provenance = Provenance::load(tmp.path()).unwrap();
+        assert_eq!(provenance.get("jade/a.xml"), Some(full));
+        // the last extraction wins
+        assert_eq!(provenance.get("jade/b.xml"), Some(delta));
+        assert_eq!(provenance.get("jade/c.xml"), None);
+    }
+}
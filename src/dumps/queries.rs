@@ -0,0 +1,110 @@
+/// Saved queries: named searches (query, dates and fonds) kept in a
+/// TOML file, so that recurring searches do not have to be retyped.
+///
+/// ```toml
+/// [antennes-relais]
+/// query = "antenne relais"
+/// from = "2015-01-01"
+/// fonds = ["JADE", "CASS"]
+/// ```
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::dumps::fonds::Fond;
+use crate::dumps::tarballs::{SearchOptions, SortBy};
+
+/// Default name of the file of saved queries, in the data directory
+pub const QUERIES_FILE: &str = "queries.toml";
+
+/// A named search. The fields left out take the
+/// defaults of the command line.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SavedQuery {
+    pub query: String,
+    /// Only documents dated on or after this day
+    pub from: Option<NaiveDate>,
+    /// Only documents dated on or before this day
+    pub to: Option<NaiveDate>,
+    /// The fonds searched (with one index per fond),
+    /// the default (empty) list means all fonds
+    pub fonds: Vec<Fond>,
+    /// Order of the results: `score` or `date`
+    pub sort: SortBy,
+    /// Number of results printed
+    pub limit: Option<usize>,
+}
+
+impl SavedQuery {
+    /// The options of the search
+    pub fn options(&self) -> SearchOptions {
+        let defaults = SearchOptions::default();
+        SearchOptions {
+            from: self.from,
+            to: self.to,
+            limit: self.limit.unwrap_or(defaults.limit),
+            sort: self.sort,
+            ..defaults
+        }
+    }
+}
+
+/// The saved queries of a file, by name
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SavedQueries {
+    pub queries: BTreeMap<String, SavedQuery>,
+}
+
+impl SavedQueries {
+    /// Read the saved queries of a TOML file
+    pub fn from_file(path: &Path) -> Result<SavedQueries> {
+        let content =
+            std::fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+        toml::from_str(&content).context(format!("Invalid saved queries in {}", path.display()))
+    }
+
+    /// The saved query called `name`
+    pub fn get(&self, name: &str) -> Result<&SavedQuery> {
+        self.queries.get(name).ok_or_else(|| {
+            let known: Vec<&str> = self.queries.keys().map(String::as_str).collect();
+            anyhow::anyhow!("No saved query named {} (known: {})", name, known.join(", "))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_saved_queries() {
+        let queries: SavedQueries = toml::from_str(
+            r#"
+            [antennes-relais]
+            query = "antenne relais"
+            from = "2015-01-01"
+            fonds = ["JADE", "CASS"]
+            sort = "date"
+
+            [ceseda]
+            query = "ceseda"
+            limit = 50
+            "#,
+        )
+        .unwrap();
+        let antennes = queries.get("antennes-relais").unwrap();
+        assert_eq!(antennes.fonds, vec![Fond::JADE, Fond::CASS]);
+        let options = antennes.options();
+        assert_eq!(options.from, NaiveDate::from_ymd_opt(2015, 1, 1));
+        assert_eq!((options.sort, options.limit), (SortBy::Date, 10));
+        assert_eq!(queries.get("ceseda").unwrap().options().limit, 50);
+
+        assert!(queries.get("unknown").unwrap_err().to_string().contains("antennes-relais"));
+        assert!(toml::from_str::<SavedQueries>("[a]\nquery = \"x\"\ntypo = 1").is_err());
+    }
+}
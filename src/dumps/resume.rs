@@ -0,0 +1,145 @@
+/// State of an interrupted run of `dilarxiv-oneshot`, kept in a
+/// directory so that running the same command again continues
+/// where it stopped:
+///
+/// - `run.json` the query and fonds of the run, a directory
+///   cannot be used to resume a different run
+/// - `processed.txt` the tarballs whose results are accumulated,
+///   one name per line, appended once a chunk is done
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::dumps::fonds::Fond;
+
+/// Name of the description of the run, in the state directory
+pub const RUN_FILE: &str = "run.json";
+
+/// Name of the list of processed tarballs, in the state directory
+pub const PROCESSED_FILE: &str = "processed.txt";
+
+/// What a run computes: resuming only makes sense for the same query
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Run {
+    pub query: String,
+    pub fonds: Vec<Fond>,
+}
+
+/// The tarballs already processed by a run
+#[derive(Debug)]
+pub struct ResumeState {
+    dir: PathBuf,
+    processed: HashSet<String>,
+}
+
+impl ResumeState {
+    /// Open the state of `run` in `dir`, creating it when the
+    /// directory is empty. Fails when `dir` holds another run.
+    pub fn open(dir: &Path, run: &Run) -> Result<ResumeState> {
+        std::fs::create_dir_all(dir).context(format!("Failed to create {}", dir.display()))?;
+        let run_path = dir.join(RUN_FILE);
+        if run_path.is_file() {
+            let content = std::fs::read_to_string(&run_path)?;
+            let previous: Run = serde_json::from_str(&content)
+                .context(format!("Invalid run description {}", run_path.display()))?;
+            if previous != *run {
+                return Err(anyhow::anyhow!(
+                    "{} holds the state of another run (query {:?}), use another directory",
+                    dir.display(),
+                    previous.query
+                ));
+            }
+        } else {
+            std::fs::write(&run_path, serde_json::to_string_pretty(run)?)?;
+        }
+
+        let mut processed = HashSet::new();
+        let processed_path = dir.join(PROCESSED_FILE);
+        if processed_path.is_file() {
+            let file = std::fs::File::open(&processed_path)?;
+            for line in std::io::BufReader::new(file).lines() {
+                let line = line?;
+                if !line.trim().is_empty() {
+                    processed.insert(line.trim().to_string());
+                }
+            }
+        }
+        Ok(ResumeState {
+            dir: dir.to_path_buf(),
+            processed,
+        })
+    }
+
+    /// The directory of the state, where the run keeps its files
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Number of processed tarballs
+    pub fn len(&self) -> usize {
+        self.processed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processed.is_empty()
+    }
+
+    pub fn is_processed(&self, tarball: &str) -> bool {
+        self.processed.contains(tarball)
+    }
+
+    /// Record that the tarballs are processed. The list is synced to
+    /// the disk, so this should be called once their results are saved.
+    pub fn mark_processed<'a>(&mut self, tarballs: impl IntoIterator<Item = &'a str>) -> Result<()> {
+        let path = self.dir.join(PROCESSED_FILE);
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .context(format!("Failed to open {}", path.display()))?;
+        let mut lines = String::new();
+        for tarball in tarballs {
+            if self.processed.insert(tarball.to_string()) {
+                lines.push_str(tarball);
+                lines.push('\n');
+            }
+        }
+        file.write_all(lines.as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume_state() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let run = Run {
+            query: "antennes relais".to_string(),
+            fonds: vec![Fond::JADE],
+        };
+        let mut state = ResumeState::open(tmp.path(), &run).unwrap();
+        assert!(state.is_empty());
+        state
+            .mark_processed(["JADE_20240101-000000.tar.gz", "JADE_20240102-000000.tar.gz"])
+            .unwrap();
+        state.mark_processed(["JADE_20240102-000000.tar.gz"]).unwrap();
+
+        let state = ResumeState::open(tmp.path(), &run).unwrap();
+        assert_eq!(state.len(), 2);
+        assert!(state.is_processed("JADE_20240102-000000.tar.gz"));
+        assert!(!state.is_processed("JADE_20240103-000000.tar.gz"));
+
+        let other = Run {
+            query: "ceseda".to_string(),
+            ..run
+        };
+        assert!(ResumeState::open(tmp.path(), &other).is_err());
+    }
+}
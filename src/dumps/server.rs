@@ -0,0 +1,332 @@
+/// HTTP server answering queries on the local index with JSON, so that
+/// a mirror can be queried from notebooks without installing anything:
+///
+/// - `GET /search?q=…&fond=JADE,CASS&from=2020-01-01&to=…&limit=10&offset=0&sort=date&facets=true`
+///   the best results of a query (see [`tarballs::search_indices`]),
+///   at most [`MAX_LIMIT`] per page and [`MAX_WINDOW`] in all
+/// - `GET /doc/{id}` the stored document (see [`tarballs::get_by_id`])
+/// - `GET /stats` the number of documents of each index, and with
+///   `q=…` (and the same filters as `/search`) the number of results
+///   of the query per year
+///
+/// Searches run on the blocking thread pool of tokio.
+use anyhow::Result;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::NaiveDate;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::dumps::tarballs::{self, HistogramBucket, IndexFields, SearchOptions, SortBy};
+
+/// An index, named after its fond when there is one index per fond
+pub struct NamedIndex {
+    pub name: String,
+    pub index: tantivy::Index,
+    pub fields: IndexFields,
+}
+
+type Indices = Arc<Vec<NamedIndex>>;
+
+/// Largest number of results of a page of `/search`
+pub const MAX_LIMIT: usize = 1000;
+
+/// Largest `offset + limit` of `/search`: deeper pages are refused
+/// rather than making the index rank all the skipped results
+pub const MAX_WINDOW: usize = 10 * MAX_LIMIT;
+
+/// Parameters of `/search` and `/stats`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SearchParams {
+    /// The query, in the syntax of the command line
+    pub q: Option<String>,
+    /// Comma separated fonds (e.g. `JADE,CASS`)
+    pub fond: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default)]
+    pub sort: SortBy,
+    #[serde(default)]
+    pub facets: bool,
+}
+
+impl SearchParams {
+    /// The query restricted to the fonds, `None` without query
+    pub(crate) fn query(&self) -> Result<Option<String>, ServerError> {
+        let Some(q) = self.q.as_ref().filter(|q| !q.trim().is_empty()) else {
+            return Ok(None);
+        };
+        let Some(fonds) = self.fond.as_ref().filter(|f| !f.is_empty()) else {
+            return Ok(Some(q.clone()));
+        };
+        let mut terms = vec![];
+        for fond in fonds.split(',').map(str::trim) {
+            if fond.is_empty() || !fond.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(ServerError::BadRequest(format!("Invalid fond {}", fond)));
+            }
+            terms.push(format!("fond:{}", fond.to_uppercase()));
+        }
+        Ok(Some(format!("({}) AND ({})", q, terms.join(" OR "))))
+    }
+
+    /// The options of the search, refusing empty pages and pages past
+    /// [`MAX_WINDOW`]
+    pub(crate) fn options(&self) -> Result<SearchOptions, ServerError> {
+        let limit = self.limit.unwrap_or(10).min(MAX_LIMIT);
+        if limit == 0 {
+            return Err(ServerError::BadRequest("limit must be at least 1".to_string()));
+        }
+        if self.offset.checked_add(limit).is_none_or(|end| end > MAX_WINDOW) {
+            return Err(ServerError::BadRequest(format!(
+                "offset + limit must be at most {}",
+                MAX_WINDOW
+            )));
+        }
+        Ok(SearchOptions {
+            from: self.from,
+            to: self.to,
+            facets: self.facets,
+            limit,
+            offset: self.offset,
+            sort: self.sort,
+        })
+    }
+}
+
+/// A result of `/search`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hit {
+    pub path: String,
+    pub date: Option<NaiveDate>,
+}
+
+/// The answer of `/search`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchAnswer {
+    pub count: usize,
+    pub hits: Vec<Hit>,
+    pub facets: Option<tarballs::facet_collector::Facets>,
+}
+
+/// The answer of `/stats`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatsAnswer {
+    /// Number of documents of each index
+    pub documents: BTreeMap<String, u64>,
+    /// Number of results of the query
+    pub count: Option<usize>,
+    /// Number of results of the query per year
+    pub years: Option<BTreeMap<String, usize>>,
+}
+
+/// Errors answered as `{"error": "…"}`
+#[derive(Debug)]
+pub enum ServerError {
+    BadRequest(String),
+    NotFound(String),
+    Internal(anyhow::Error),
+}
+
+impl From<anyhow::Error> for ServerError {
+    fn from(e: anyhow::Error) -> Self {
+        // invalid queries are the fault of the client
+        if e.downcast_ref::<tantivy::query::QueryParserError>().is_some() {
+            ServerError::BadRequest(e.to_string())
+        } else {
+            ServerError::Internal(e)
+        }
+    }
+}
+
+impl IntoResponse for ServerError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            ServerError::BadRequest(m) => (StatusCode::BAD_REQUEST, m),
+            ServerError::NotFound(m) => (StatusCode::NOT_FOUND, m),
+            ServerError::Internal(e) => {
+                error!("Error answering request: {:#}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+            }
+        };
+        (status, Json(serde_json::json!({ "error": message }))).into_response()
+    }
+}
+
+/// Run `f` on the indices in the blocking thread pool
+async fn with_indices<T, F>(indices: Indices, f: F) -> Result<T, ServerError>
+where
+    T: Send + 'static,
+    F: FnOnce(&[(&tantivy::Index, &IndexFields)]) -> Result<T, ServerError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        let indices: Vec<_> = indices.iter().map(|i| (&i.index, &i.fields)).collect();
+        f(&indices)
+    })
+    .await
+    .map_err(|e| ServerError::Internal(e.into()))?
+}
+
+async fn search(
+    State(indices): State<Indices>,
+    Query(params): Query<SearchParams>,
+) -> Result<Json<SearchAnswer>, ServerError> {
+    let query = params
+        .query()?
+        .ok_or_else(|| ServerError::BadRequest("Missing query q".to_string()))?;
+    let options = params.options()?;
+    let results = with_indices(indices, move |indices| {
+        Ok(tarballs::search_indices(indices, &None, &query, &options)?)
+    })
+    .await?;
+    Ok(Json(SearchAnswer {
+        count: results.count,
+        hits: results
+            .hits
+            .into_iter()
+            .map(|(path, date)| Hit { path, date })
+            .collect(),
+        facets: results.facets,
+    }))
+}
+
+async fn document(
+    State(indices): State<Indices>,
+    Path(id): Path<String>,
+) -> Result<Json<tarballs::StoredDocument>, ServerError> {
+    let found = with_indices(indices, {
+        let id = id.clone();
+        move |indices| {
+            for (index, fields) in indices {
+                if let Some(doc) = tarballs::get_by_id(index, fields, &id)? {
+                    return Ok(Some(doc));
+                }
+            }
+            Ok(None)
+        }
+    })
+    .await?;
+    found
+        .map(Json)
+        .ok_or_else(|| ServerError::NotFound(format!("Document {} is not in the index", id)))
+}
+
+async fn stats(
+    State(indices): State<Indices>,
+    Query(params): Query<SearchParams>,
+) -> Result<Json<StatsAnswer>, ServerError> {
+    let documents = indices
+        .iter()
+        .map(|i| Ok((i.name.clone(), i.index.reader()?.searcher().num_docs())))
+        .collect::<Result<BTreeMap<_, _>>>()?;
+    let Some(query) = params.query()? else {
+        return Ok(Json(StatsAnswer {
+            documents,
+            count: None,
+            years: None,
+        }));
+    };
+    let options = params.options()?;
+    let (count, years) = with_indices(indices, move |indices| {
+        let count = tarballs::count_matches(indices, &query, &options)?;
+        let years = tarballs::date_histogram(indices, &query, &options, HistogramBucket::Year)?;
+        Ok((count, years))
+    })
+    .await?;
+    Ok(Json(StatsAnswer {
+        documents,
+        count: Some(count),
+        years: Some(years),
+    }))
+}
+
+/// The routes of the server
+pub fn router(indices: Vec<NamedIndex>) -> Router {
+    Router::new()
+        .route("/search", get(search))
+        .route("/doc/{id}", get(document))
+        .route("/stats", get(stats))
+        .with_state(Arc::new(indices))
+}
+
+/// Answer requests on `address` (e.g. `127.0.0.1:3000`) until the
+/// process is stopped
+pub async fn serve(indices: Vec<NamedIndex>, address: &str) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(address).await?;
+    info!("Listening on http://{}", listener.local_addr()?);
+    axum::serve(listener, router(indices))
+        .with_graceful_shutdown(crate::shutdown::wait())
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn json<T: serde::de::DeserializeOwned>(response: reqwest::Response) -> T {
+        serde_json::from_str(&response.text().await.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_server() {
+        let (index, fields) = tarballs::init_tantivy_ram().unwrap();
+        let mut writer: tantivy::IndexWriter = index.writer(15_000_000).unwrap();
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let dir = tmp.path().join("jade");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("CETATEXT000000000001.xml"),
+            "<TEXTE_JURI_ADMIN><META><META_COMMUN><ID>CETATEXT000000000001</ID></META_COMMUN>\
+             <META_SPEC><META_JURI><DATE_DEC>2024-03-21</DATE_DEC></META_JURI></META_SPEC></META>\
+             <TEXTE><BLOC_TEXTUEL><CONTENU>Les antennes relais.</CONTENU></BLOC_TEXTUEL></TEXTE>\
+             </TEXTE_JURI_ADMIN>",
+        )
+        .unwrap();
+        tarballs::index_files_in_dir(&mut writer, &fields, tmp.path(), false, &Default::default())
+            .unwrap();
+        let indices = vec![NamedIndex {
+            name: "index".to_string(),
+            index,
+            fields,
+        }];
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router(indices)).await });
+        let get = |path: &str| {
+            let url = format!("{}{}", base, path);
+            async move { reqwest::get(url).await.unwrap() }
+        };
+
+        let answer: SearchAnswer = json(get("/search?q=antennes&fond=jade&from=2024-01-01").await).await;
+        assert_eq!(answer.count, 1);
+        assert_eq!(answer.hits[0].date, NaiveDate::from_ymd_opt(2024, 3, 21));
+        let answer: SearchAnswer = json(get("/search?q=antennes&fond=CASS").await).await;
+        assert_eq!(answer.count, 0);
+        assert_eq!(get("/search?q=antennes&fond=a:b").await.status(), 400);
+        assert_eq!(get("/search?q=body:(").await.status(), 400);
+        assert_eq!(get("/search?q=antennes&limit=0").await.status(), 400);
+        assert_eq!(get("/search?q=antennes&offset=100000000000").await.status(), 400);
+        assert_eq!(get("/search?q=antennes&offset=9000&limit=1000").await.status(), 200);
+        assert_eq!(get("/search?q=antennes&offset=9001&limit=1000").await.status(), 400);
+        let url = format!("/search?q=antennes&offset={}", usize::MAX);
+        assert_eq!(get(&url).await.status(), 400);
+
+        let doc: serde_json::Value = json(get("/doc/CETATEXT000000000001").await).await;
+        assert_eq!(doc["path"], "jade/CETATEXT000000000001.xml");
+        assert_eq!(get("/doc/CETATEXT000000000002").await.status(), 404);
+
+        let stats: StatsAnswer = json(get("/stats?q=antennes").await).await;
+        assert_eq!(stats.documents["index"], 1);
+        assert_eq!((stats.count, stats.years), (Some(1), Some([("2024".to_string(), 1)].into())));
+    }
+}
@@ -7,17 +7,26 @@
 use anyhow::{Context, Result};
 use reqwest::{Url, Client};
 
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressStyle};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use futures::stream::StreamExt;
+use rayon::prelude::*;
 
 use log::{debug, warn};
 use serde::{Serialize, Deserialize};
 
-use chrono::NaiveDate;
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
 
 use crate::dumps::fonds::Fond;
+use crate::dumps::winpath;
+use crate::logging::{multi_progress, progress_bar};
+use crate::shutdown;
+
+#[cfg(feature = "index")]
+pub use crate::dumps::index::*;
 
 /// Base URL for the dila server
 pub const BASE_URL: &str = "https://echanges.dila.gouv.fr/OPENDATA";
@@ -40,6 +49,37 @@ pub struct Tarball {
     pub fond: Fond,
     /// Date of the tarball, extracted from the name
     pub time: NaiveDate,
+    /// Time of publication of the tarball, extracted from the name
+    /// (in the Europe/Paris timezone of the dila server)
+    #[serde(default)]
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    /// Size in bytes as announced by the server listing, if known
+    /// (the listing rounds sizes, so this is an approximation)
+    #[serde(default)]
+    pub size: Option<u64>,
+    /// Whether this is a full dump or an incremental update
+    #[serde(default)]
+    pub kind: TarballKind,
+}
+
+/// The dila server publishes one full dump (“stock”) per fond,
+/// named `Freemium_fond_global_…`, followed by incremental updates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TarballKind {
+    Stock,
+    #[default]
+    Delta,
+}
+
+impl TarballKind {
+    pub fn from_name(name: &str) -> Self {
+        if name.to_ascii_lowercase().contains("_global_") {
+            TarballKind::Stock
+        } else {
+            TarballKind::Delta
+        }
+    }
 }
 
 /// Display implementation for Tarball
@@ -58,6 +98,35 @@ impl From<&Tarball> for Url {
     }
 }
 
+impl Tarball {
+    /// Rebuild a tarball from its file name, guessing the fond
+    /// it belongs to from the name itself.
+    pub fn from_name(name: &str) -> Result<Tarball> {
+        let parsed = parse_tarball_name(name)?;
+        Ok(Tarball {
+            name: name.to_string(),
+            fond: parsed.fond,
+            time: parsed.date,
+            timestamp: Some(parsed.published()),
+            size: None,
+            kind: parsed.kind,
+        })
+    }
+
+    /// The time of publication of the tarball, or the beginning of its
+    /// day when the name has no time
+    pub fn published(&self) -> DateTime<FixedOffset> {
+        self.timestamp.unwrap_or_else(|| paris_datetime(self.time.and_time(NaiveTime::MIN)))
+    }
+
+    /// The order in which the tarballs of a fond are applied: by time
+    /// of publication, a stock before the deltas published at the
+    /// same time
+    pub fn application_order(&self) -> (DateTime<FixedOffset>, bool, String) {
+        (self.published(), self.kind != TarballKind::Stock, self.name.clone())
+    }
+}
+
 /// A tarball can naturally be seen as a path 
 /// from its name, allowing us to download it
 impl AsRef<Path> for Tarball {
@@ -66,79 +135,428 @@ impl AsRef<Path> for Tarball {
     }
 }
 
-/// Extract the date from a tarball name as a `NaiveDateTime`.
-/// The tarball names are of the form FOND_YYYYMMDD-XXXXX.tar.gz
-/// where the date is using the Gregorian calendar with paris timezone.
-/// The output is a `NaiveDateTime` representing the date and time
-/// without timezone information.
-fn extract_date_from_tarball_name(name: &str) -> Result<NaiveDate> {
-    debug!("Extracting date from tarball name: {}", name);
-    let date_part = name.split('_').collect::<Vec<_>>()
-        .last()
-        .ok_or_else(|| anyhow::anyhow!("Failed to extract date part from tarball name"))?
-        .split('-')
-        .next()
-        .ok_or_else(|| anyhow::anyhow!("Failed to extract date part from tarball name"))?;
+/// The parts of the name of a tarball of the dila server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TarballName {
+    pub fond: Fond,
+    pub kind: TarballKind,
+    /// Day of publication
+    pub date: NaiveDate,
+    /// Time of publication, for the names that have one
+    pub time: Option<NaiveTime>,
+}
+
+impl TarballName {
+    /// The time of publication in the Europe/Paris timezone of the
+    /// server, the beginning of the day when the name has no time
+    pub fn published(&self) -> DateTime<FixedOffset> {
+        paris_datetime(self.date.and_time(self.time.unwrap_or(NaiveTime::MIN)))
+    }
+}
+
+fn tarball_name_regex() -> &'static regex::Regex {
+    static INSTANCE: once_cell::sync::OnceCell<regex::Regex> = once_cell::sync::OnceCell::new();
+    INSTANCE.get_or_init(|| {
+        regex::Regex::new(
+            r"^(?:Freemium_(?<stock>[A-Za-z]+)_global|(?<delta>[A-Za-z]+))_(?<date>\d{8})(?:-(?<time>\d{6}))?\.tar\.gz$",
+        )
+        .expect("Unable to construct tarball name regex")
+    })
+}
 
-    debug!("Date part extracted: {}", date_part);
+/// Parse the name of a tarball of the dila server, which is either
+/// `FOND_YYYYMMDD-HHMMSS.tar.gz` for the deltas (e.g.
+/// `CASS_20240108-211850.tar.gz`) or `Freemium_fond_global_YYYYMMDD-HHMMSS.tar.gz`
+/// for the stocks (e.g. `Freemium_jade_global_20231119-100000.tar.gz`),
+/// where the fond is one of [`crate::dumps::fonds::FONDS`] in any case
+/// and the time is optional. Other names are errors.
+pub fn parse_tarball_name(name: &str) -> Result<TarballName> {
+    let captures = tarball_name_regex()
+        .captures(name)
+        .ok_or_else(|| anyhow::anyhow!("Unexpected tarball name {}", name))?;
+    let (fond, kind) = match (captures.name("stock"), captures.name("delta")) {
+        (Some(fond), _) => (fond.as_str(), TarballKind::Stock),
+        (None, Some(fond)) => (fond.as_str(), TarballKind::Delta),
+        (None, None) => unreachable!("the regex has one of the groups"),
+    };
+    let fond = crate::dumps::fonds::FONDS
+        .iter()
+        .find(|f| f.as_str().eq_ignore_ascii_case(fond))
+        .ok_or_else(|| anyhow::anyhow!("Unknown fond {} in tarball name {}", fond, name))?;
+    let date = NaiveDate::parse_from_str(&captures["date"], "%Y%m%d")
+        .context(format!("Invalid date in tarball name {}", name))?;
+    let time = captures
+        .name("time")
+        .map(|time| NaiveTime::parse_from_str(time.as_str(), "%H%M%S"))
+        .transpose()
+        .context(format!("Invalid time in tarball name {}", name))?;
+    Ok(TarballName {
+        fond: *fond,
+        kind,
+        date,
+        time,
+    })
+}
 
-    use chrono::NaiveDate;
-    let dt = NaiveDate::parse_from_str(date_part, "%Y%m%d")
-        .expect("Failed to parse date from tarball name");
-    debug!("DateTime parsed: {}", dt);
-    Ok(dt)
+/// The last Sunday of a month, when the changes of time happen
+fn last_sunday(year: i32, month: u32) -> NaiveDate {
+    let next_month = match month {
+        12 => NaiveDate::from_ymd_opt(year + 1, 1, 1),
+        _ => NaiveDate::from_ymd_opt(year, month + 1, 1),
+    }
+    .expect("valid month");
+    let last_day = next_month.pred_opt().expect("valid date");
+    last_day - chrono::Days::new(last_day.weekday().num_days_from_sunday() as u64)
 }
 
-/// List all tarballs in the dila server that are listed
-/// in the page content given as a string
-pub fn get_tarballs_from_page_content(fond : &Fond, content: &str) -> Vec<Tarball> {
+/// A local time of the Europe/Paris timezone with its offset: UTC+2
+/// from the last Sunday of March to the last Sunday of October (at 1:00
+/// UTC), UTC+1 otherwise. These are the rules of the European Union
+/// since 1996, which cover all the tarballs of the dila server. The
+/// repeated hour of October is read as summer time.
+pub fn paris_datetime(local: NaiveDateTime) -> DateTime<FixedOffset> {
+    let summer = FixedOffset::east_opt(2 * 3600).expect("valid offset");
+    let winter = FixedOffset::east_opt(3600).expect("valid offset");
+    let year = local.year();
+    let start = last_sunday(year, 3).and_hms_opt(1, 0, 0).expect("valid time");
+    let end = last_sunday(year, 10).and_hms_opt(1, 0, 0).expect("valid time");
+    let utc = local - chrono::TimeDelta::hours(2);
+    let offset = if (start..end).contains(&utc) { summer } else { winter };
+    offset
+        .from_local_datetime(&local)
+        .single()
+        .expect("fixed offsets are never ambiguous")
+}
+
+/// Extract the time of publication from a tarball name, in the
+/// Europe/Paris timezone of the server (see [`TarballName::published`])
+pub(crate) fn extract_timestamp_from_tarball_name(name: &str) -> Result<DateTime<FixedOffset>> {
+    Ok(parse_tarball_name(name)?.published())
+}
+
+/// Parse the size column of the server listing (e.g. `261K`, `1.2M`)
+fn parse_listing_size(size: &str) -> Option<u64> {
+    let (number, multiplier) = match size.chars().last()? {
+        'K' => (&size[..size.len() - 1], 1024.0),
+        'M' => (&size[..size.len() - 1], 1024.0 * 1024.0),
+        'G' => (&size[..size.len() - 1], 1024.0 * 1024.0 * 1024.0),
+        _ => (size, 1.0),
+    };
+    let number: f64 = number.parse().ok()?;
+    Some((number * multiplier) as u64)
+}
+
+/// A file of a listing of the dila server that looks like a tarball
+/// but whose name could not be parsed (see [`parse_tarball_name`])
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnparsableName {
+    pub name: String,
+    pub error: String,
+}
+
+/// The tarballs of listings of the dila server, and the files left out
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TarballListing {
+    pub tarballs: Vec<Tarball>,
+    pub unparsable: Vec<UnparsableName>,
+}
+
+impl TarballListing {
+    fn extend(&mut self, other: TarballListing) {
+        self.tarballs.extend(other.tarballs);
+        self.unparsable.extend(other.unparsable);
+    }
+
+    /// The tarballs, logging the files left out
+    fn into_tarballs(self) -> Vec<Tarball> {
+        for unparsable in self.unparsable.iter() {
+            warn!("Skipping {}: {}", unparsable.name, unparsable.error);
+        }
+        self.tarballs
+    }
+}
+
+/// List all tarballs in the dila server that are listed in the page
+/// content given as a string, with the files whose names are unexpected
+pub fn parse_listing(fond: &Fond, content: &str) -> TarballListing {
     // fetch all strings matching the regex
-    // \w*-\w*.tar.gz
-    // and return them
+    // [\w-]+.tar.gz
+    // and return them, together with the size
+    // found at the end of the line (if any)
     debug!("Extracting tarballs from content for fond: {}", fond);
-    let re = regex::Regex::new(r"\w*-\w*.tar.gz").unwrap();
-    let mut names: Vec<String> = re
-        .captures_iter(content)
-        .filter_map(|cap| cap.get(0))
-        .map(|m| m.as_str().to_string())
+    let re = regex::Regex::new(r"[\w-]+\.tar\.gz").unwrap();
+    let size_re = regex::Regex::new(r"\s(?<size>[0-9.]+[KMG]?)\s*$").unwrap();
+    let mut names: Vec<(String, Option<u64>)> = content
+        .lines()
+        .flat_map(|line| {
+            let size = size_re
+                .captures(line)
+                .and_then(|cap| parse_listing_size(&cap["size"]));
+            re.find_iter(line)
+                .map(move |m| (m.as_str().to_string(), size))
+        })
         .collect();
     names.sort();
-    names.dedup();
-    names.into_iter()
-        .filter_map(|name| {
-            let time = extract_date_from_tarball_name(&name).ok()?;
-            Some(Tarball { name, fond: fond.clone(), time })
-        })
-        .collect()
+    names.dedup_by(|a, b| a.0 == b.0);
+    let mut listing = TarballListing::default();
+    for (name, size) in names {
+        match parse_tarball_name(&name) {
+            Ok(parsed) if parsed.fond == *fond => listing.tarballs.push(Tarball {
+                fond: *fond,
+                time: parsed.date,
+                timestamp: Some(parsed.published()),
+                size,
+                kind: parsed.kind,
+                name,
+            }),
+            Ok(parsed) => listing.unparsable.push(UnparsableName {
+                error: format!("Tarball of {} listed in {}", parsed.fond, fond),
+                name,
+            }),
+            Err(e) => listing.unparsable.push(UnparsableName {
+                error: format!("{:#}", e),
+                name,
+            }),
+        }
+    }
+    listing
+}
+
+/// List all tarballs in the dila server that are listed
+/// in the page content given as a string
+pub fn get_tarballs_from_page_content(fond : &Fond, content: &str) -> Vec<Tarball> {
+    parse_listing(fond, content).into_tarballs()
 }
 
 pub async fn list_tarballs(client: &Client, fond: &Fond) -> Result<Vec<Tarball>> {
+    Ok(list_fond_listing(client, fond).await?.into_tarballs())
+}
+
+/// The listing of the tarballs of a fond on the dila server
+pub async fn list_fond_listing(client: &Client, fond: &Fond) -> Result<TarballListing> {
     let url : Url = fond.into();
     let response = client.get(url).send().await?;
     if response.status().is_success() {
         let body = response.text().await?;
-        Ok(get_tarballs_from_page_content(fond, &body))
+        Ok(parse_listing(fond, &body))
     } else {
         warn!("Failed to fetch tarballs from {}", fond);
         Err(anyhow::anyhow!("Failed to fetch tarballs from {}", fond))
     }
 }
 
+/// A bandwidth limiter shared by all concurrent downloads: a token
+/// bucket filled at `bytes_per_second`, holding at most one second of
+/// transfer, so that the time spent idle (e.g. between two cycles of
+/// `watch`) does not allow a longer burst.
+#[derive(Debug)]
+pub struct Throttle {
+    bytes_per_second: u64,
+    /// The time of the last refill, and the bytes that can be received
+    /// without waiting (negative when the downloads are ahead)
+    state: Mutex<(Instant, f64)>,
+}
+
+impl Throttle {
+    pub fn new(bytes_per_second: u64) -> Self {
+        let bytes_per_second = bytes_per_second.max(1);
+        Throttle {
+            bytes_per_second,
+            state: Mutex::new((Instant::now(), bytes_per_second as f64)),
+        }
+    }
+
+    /// Take `bytes` from the bucket at `now`, returning how long to
+    /// wait for them
+    fn reserve(&self, bytes: u64, now: Instant) -> Duration {
+        let rate = self.bytes_per_second as f64;
+        let mut state = self.state.lock().expect("Throttle lock poisoned");
+        let elapsed = now.saturating_duration_since(state.0).as_secs_f64();
+        state.0 = state.0.max(now);
+        state.1 = (state.1 + elapsed * rate).min(rate) - bytes as f64;
+        if state.1 < 0.0 {
+            Duration::from_secs_f64(-state.1 / rate)
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    /// Record that `bytes` were received and wait if needed
+    pub async fn consume(&self, bytes: u64) {
+        let wait = self.reserve(bytes, Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Options controlling how tarballs are downloaded.
+#[derive(Debug, Clone)]
+pub struct DownloadOptions {
+    /// Maximal number of simultaneous downloads
+    pub concurrency: usize,
+    /// Global bandwidth limit, shared by every download
+    /// made with these options
+    pub throttle: Option<Arc<Throttle>>,
+    /// How many times failed downloads are retried
+    pub retries: usize,
+}
+
+impl DownloadOptions {
+    pub fn new(concurrency: usize, bytes_per_second: Option<u64>) -> Self {
+        DownloadOptions {
+            concurrency: concurrency.max(1),
+            throttle: bytes_per_second.map(|bps| Arc::new(Throttle::new(bps))),
+            retries: 2,
+        }
+    }
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        DownloadOptions::new(10, None)
+    }
+}
+
+/// Parse a human readable amount of bytes such as `500K`, `2M` or `1G`
+/// (powers of 1024). This is used to parse bandwidth limits.
+pub fn parse_bytes(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, ""),
+    };
+    let number: u64 = number
+        .parse()
+        .context(format!("Invalid amount of bytes: {}", s))?;
+    let multiplier = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1024,
+        "M" | "MB" => 1024 * 1024,
+        "G" | "GB" => 1024 * 1024 * 1024,
+        _ => return Err(anyhow::anyhow!("Unknown unit in {}", s)),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| anyhow::anyhow!("Too many bytes: {}", s))
+}
+
+/// Parse a human readable duration such as `90s`, `30m`, `24h` or `7d`
+/// (a number without unit is a number of seconds). This is used to
+/// parse the interval of `dilarxiv watch`.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, ""),
+    };
+    let number: u64 = number
+        .parse()
+        .context(format!("Invalid duration: {}", s))?;
+    let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "s" => 1,
+        "m" | "min" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(anyhow::anyhow!("Unknown unit in {}", s)),
+    };
+    number
+        .checked_mul(multiplier)
+        .map(Duration::from_secs)
+        .ok_or_else(|| anyhow::anyhow!("Duration too long: {}", s))
+}
+
+/// List the tarballs of several fonds at once.
+/// This is what `dilarxiv list` serializes to JSON.
+pub async fn list_fonds_tarballs(client: &Client, fonds: &[Fond]) -> Result<Vec<Tarball>> {
+    Ok(list_fonds_listing(client, fonds).await?.into_tarballs())
+}
+
+/// The listings of several fonds at once
+pub async fn list_fonds_listing(client: &Client, fonds: &[Fond]) -> Result<TarballListing> {
+    let mut listing = TarballListing::default();
+    for fond in fonds {
+        listing.extend(list_fond_listing(client, fond).await?);
+    }
+    Ok(listing)
+}
+
+/// Rough ratio between the size of the extracted XML files
+/// and the size of the compressed tarballs.
+pub const UNCOMPRESSED_RATIO: f64 = 8.0;
+
+/// Rough ratio between the size of the index and the size of
+/// the extracted XML files (JADE: 8GB extracted, 4GB index).
+pub const INDEX_RATIO: f64 = 0.5;
+
+/// What a download would do, without doing it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DownloadPlan {
+    /// Tarballs that are not present locally
+    pub tarballs: Vec<Tarball>,
+    /// Sum of the sizes announced by the server
+    pub compressed_bytes: u64,
+    /// Tarballs for which the server did not announce a size
+    pub unknown_sizes: usize,
+    pub estimated_uncompressed_bytes: u64,
+    pub estimated_index_bytes: u64,
+}
+
+/// Compute the download plan of `upstream` tarballs into `dir`
+pub fn plan_downloads(dir: &Path, upstream: &[Tarball]) -> DownloadPlan {
+    let tarballs: Vec<Tarball> = upstream
+        .iter()
+        .filter(|t| !dir.join(t).exists())
+        .cloned()
+        .collect();
+    let compressed_bytes = tarballs.iter().filter_map(|t| t.size).sum();
+    let unknown_sizes = tarballs.iter().filter(|t| t.size.is_none()).count();
+    let estimated_uncompressed_bytes = (compressed_bytes as f64 * UNCOMPRESSED_RATIO) as u64;
+    let estimated_index_bytes = (estimated_uncompressed_bytes as f64 * INDEX_RATIO) as u64;
+    DownloadPlan {
+        tarballs,
+        compressed_bytes,
+        unknown_sizes,
+        estimated_uncompressed_bytes,
+        estimated_index_bytes,
+    }
+}
+
+/// Human readable amount of bytes (e.g. `1.5 GiB`)
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
 async fn download_tarball(
     client: &Client,
-    outdir: &PathBuf,
+    outdir: &Path,
     tarball: &Tarball,
     mp: &MultiProgress,
+    throttle: Option<&Throttle>,
 ) -> Result<bool> {
     let path = outdir.join(tarball);
     if path.exists() {
         debug!("{} already exists, skipping download", path.display());
         return Ok(false);
     }
+    // download into a temporary file, so that an interrupted
+    // download is never mistaken for a complete tarball
+    let part = outdir.join(format!("{}.part", tarball.name));
     let url : Url = tarball.into();
 
     // Create a progress bar for the download
-    let pb = mp.add(ProgressBar::new(0));
+    let pb = mp.add(progress_bar(0));
     let pbstyle = ProgressStyle::default_bar()
         .template("{msg} [{wide_bar}] {bytes}/{total_bytes} ({eta})")
         .context("Failed to create progress bar template")?
@@ -152,498 +570,496 @@ async fn download_tarball(
         pb.set_length(content_length);
     }
 
-    if response.status().is_success() {
-        let mut file = tokio::fs::File::create(path.as_path())
-            .await
-            .context(format!("Failed to create file {}", path.display()))?;
-        let mut buf_writer = tokio::io::BufWriter::new(&mut file);
-        let mut bs = response.bytes_stream();
-
-        while let Some(item) = bs.next().await {
-            match item {
-                Ok(bytes) => {
-                    // Update the progress bar
-                    pb.set_position(pb.position() + bytes.len() as u64);
-                    tokio::io::copy(&mut bytes.as_ref(), &mut buf_writer)
-                        .await
-                        .context(format!("Failed to copy bytes to {}", path.display()))?;
-                }
-                Err(e) => {
-                    warn!("Error downloading {}: {}", tarball, e);
-                    return Err(e.into());
+    if !response.status().is_success() {
+        warn!("Failed to download {}: {}", tarball, response.status());
+        return Err(anyhow::anyhow!("HTTP status {}", response.status()));
+    }
+
+    let mut file = tokio::fs::File::create(part.as_path())
+        .await
+        .context(format!("Failed to create file {}", part.display()))?;
+    let mut buf_writer = tokio::io::BufWriter::new(&mut file);
+    let mut bs = response.bytes_stream();
+
+    while let Some(item) = bs.next().await {
+        match item {
+            Ok(bytes) => {
+                // Update the progress bar
+                pb.set_position(pb.position() + bytes.len() as u64);
+                if let Some(throttle) = throttle {
+                    throttle.consume(bytes.len() as u64).await;
                 }
+                tokio::io::copy(&mut bytes.as_ref(), &mut buf_writer)
+                    .await
+                    .context(format!("Failed to copy bytes to {}", part.display()))?;
+            }
+            Err(e) => {
+                warn!("Error downloading {}: {}", tarball, e);
+                drop(buf_writer);
+                let _ = tokio::fs::remove_file(&part).await;
+                return Err(e.into());
             }
         }
-    } else {
-        warn!("Failed to download {}: {}", tarball, response.status());
     }
+    use tokio::io::AsyncWriteExt;
+    buf_writer.flush().await?;
+    drop(buf_writer);
+    tokio::fs::rename(&part, &path)
+        .await
+        .context(format!("Failed to move {} to {}", part.display(), path.display()))?;
     Ok(true)
 }
 
+/// What happened to a tarball during a download
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "status", content = "error")]
+pub enum DownloadStatus {
+    /// The tarball was already present locally
+    SkippedExists,
+    Downloaded,
+    /// Every attempt failed, with the last error
+    Failed(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DownloadEntry {
+    pub tarball: Tarball,
+    #[serde(flatten)]
+    pub status: DownloadStatus,
+    /// Number of download attempts (0 when skipped)
+    pub attempts: usize,
+}
+
+/// Per tarball outcome of a download
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DownloadReport {
+    pub entries: Vec<DownloadEntry>,
+    /// Files of the listing whose names could not be parsed, hence
+    /// not downloaded: a delta left out here is missing from the corpus
+    #[serde(default)]
+    pub unparsable: Vec<UnparsableName>,
+}
+
+impl DownloadReport {
+    fn with_status(&self, f: impl Fn(&DownloadStatus) -> bool) -> Vec<Tarball> {
+        self.entries
+            .iter()
+            .filter(|e| f(&e.status))
+            .map(|e| e.tarball.clone())
+            .collect()
+    }
+
+    /// Tarballs that were actually downloaded
+    pub fn downloaded(&self) -> Vec<Tarball> {
+        self.with_status(|s| *s == DownloadStatus::Downloaded)
+    }
+
+    pub fn skipped(&self) -> Vec<Tarball> {
+        self.with_status(|s| *s == DownloadStatus::SkippedExists)
+    }
+
+    pub fn failed(&self) -> Vec<Tarball> {
+        self.with_status(|s| matches!(s, DownloadStatus::Failed(_)))
+    }
+
+    pub fn extend(&mut self, other: DownloadReport) {
+        self.entries.extend(other.entries);
+        self.unparsable.extend(other.unparsable);
+    }
+
+    /// One line summary, e.g. `3 downloaded, 10 skipped, 1 failed`,
+    /// with the number of unexpected names if any
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} downloaded, {} skipped, {} failed",
+            self.downloaded().len(),
+            self.skipped().len(),
+            self.failed().len()
+        );
+        if !self.unparsable.is_empty() {
+            summary.push_str(&format!(", {} unexpected names", self.unparsable.len()));
+        }
+        summary
+    }
+}
+
 /// Download the tarballs from the dila server
-/// if they are not already present
+/// if they are not already present.
+///
+/// Failed downloads are retried `opts.retries` times once every
+/// other tarball has been processed.
 pub async fn download_tarball_list(
     client: &Client,
     tarballs: &[Tarball],
-    dir: &PathBuf,
-) -> Result<Vec<Tarball>> {
+    dir: &Path,
+    opts: &DownloadOptions,
+) -> Result<DownloadReport> {
     if !dir.exists() {
         std::fs::create_dir_all(dir)
             .context(format!("Failed to create directory {}", dir.display()))?;
     }
 
     // Create a multi-progress bar
-    let m = MultiProgress::new();
+    let m = multi_progress();
+
+    let mut entries: Vec<DownloadEntry> = tarballs
+        .iter()
+        .map(|tarball| DownloadEntry {
+            tarball: tarball.clone(),
+            status: DownloadStatus::Failed("not attempted".to_string()),
+            attempts: 0,
+        })
+        .collect();
 
-    let tasks = tarballs.into_iter().map(async |tarball| {
-        let result = download_tarball(client, dir, &tarball, &m).await;
-        match result {
-            Ok(true) => Some(tarball.clone()),
-            _ => None,
+    for pass in 0..=opts.retries {
+        let pending = entries
+            .iter_mut()
+            .filter(|e| matches!(e.status, DownloadStatus::Failed(_)))
+            .collect::<Vec<_>>();
+        if pending.is_empty() {
+            break;
+        }
+        if shutdown::is_interrupted() {
+            warn!("Interrupted, {} tarballs are not downloaded", pending.len());
+            break;
+        }
+        if pass > 0 {
+            warn!("Retrying {} failed downloads (pass {})", pending.len(), pass);
         }
-    });
 
-    let results = futures::stream::iter(tasks)
-        .buffer_unordered(10) // Limit the number of concurrent downloads
-        .filter_map(async |r| r)
-        .collect::<Vec<_>>()
-        .await;
+        let tasks = pending.into_iter().map(async |entry| {
+            // the downloads in progress are completed
+            if shutdown::is_interrupted() {
+                entry.status = DownloadStatus::Failed("interrupted".to_string());
+                return;
+            }
+            entry.attempts += 1;
+            entry.status = match download_tarball(
+                client,
+                dir,
+                &entry.tarball,
+                &m,
+                opts.throttle.as_deref(),
+            )
+            .await
+            {
+                Ok(true) => DownloadStatus::Downloaded,
+                Ok(false) => {
+                    entry.attempts -= 1;
+                    DownloadStatus::SkippedExists
+                }
+                Err(e) => DownloadStatus::Failed(format!("{:#}", e)),
+            };
+        });
+
+        futures::stream::iter(tasks)
+            .buffer_unordered(opts.concurrency) // Limit the number of concurrent downloads
+            .collect::<Vec<_>>()
+            .await;
+    }
 
-    Ok(results)
+    Ok(DownloadReport {
+        entries,
+        unparsable: vec![],
+    })
 }
 
+/// Download the tarballs of `fond` that are not in `dir` yet. The files
+/// of the listing with unexpected names are left in the report, for the
+/// caller to decide whether the run can go on without them.
 pub async fn download_tarballs(
     client: &Client,
-    dir: &PathBuf,
+    dir: &Path,
     fond: &Fond,
-) -> Result<Vec<Tarball>> {
-    let tarballs = list_tarballs(client, fond).await?;
-    if tarballs.is_empty() {
+    opts: &DownloadOptions,
+) -> Result<DownloadReport> {
+    let listing = list_fond_listing(client, fond).await?;
+    if listing.tarballs.is_empty() {
         warn!("No tarballs found at {}", fond);
-        return Ok(vec![]);
     }
-    debug!("Found {} tarballs", tarballs.len());
-    let tarballs = download_tarball_list(client, &tarballs, dir).await?;
-    Ok(tarballs)
-}
-
-/// SECOND PART
-/// extract tarballs
-
-pub fn extract_tarball(tarball: &PathBuf, dir: &PathBuf) -> Result<()> {
-    let file = std::fs::File::open(tarball)
-        .context(format!("Failed to open tarball {}", tarball.display()))?;
-
-    let gzip = flate2::read::GzDecoder::new(file);
-
-    let mut tar = tar::Archive::new(gzip);
-
-    // Extract the tarball to the specified directory
-    tar.unpack(dir)
-        .context(format!("Failed to extract tarball {}", tarball.display()))?;
-    Ok(())
+    debug!("Found {} tarballs", listing.tarballs.len());
+    let mut report = if listing.tarballs.is_empty() {
+        DownloadReport::default()
+    } else {
+        download_tarball_list(client, &listing.tarballs, dir, opts).await?
+    };
+    report.unparsable = listing.unparsable;
+    Ok(report)
 }
 
-/// List all files recursively in a directory
-pub fn list_files_in_dir(dir: PathBuf) -> Result<Vec<PathBuf>> {
-    let mut dir_stack = Vec::new();
-    let mut files = Vec::new();
-    dir_stack.push(dir);
-    while let Some(current_dir) = dir_stack.pop() {
-        let dir_list = std::fs::read_dir(&current_dir).context(format!(
-            "Failed to read directory {}",
-            current_dir.display()
-        ))?;
-        for entry in dir_list {
-            let entry = entry.expect("Failed to read directory entry");
-            let path = entry.path();
-            if path.is_dir() {
-                dir_stack.push(path);
-            } else {
-                files.push(path);
+// SECOND PART
+// extract tarballs
+
+/// The path of a tarball entry relative to the extraction directory,
+/// without its `.` components. Absolute paths and paths going up with
+/// `..` would be written outside of the extraction directory by a
+/// hostile or corrupted archive, and are rejected.
+pub fn safe_entry_path(path: &Path) -> Result<PathBuf> {
+    use std::path::Component;
+
+    let mut safe = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => safe.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                return Err(anyhow::anyhow!("{} goes up with ..", path.display()));
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(anyhow::anyhow!("{} is absolute", path.display()));
             }
         }
     }
-    Ok(files)
+    if safe.as_os_str().is_empty() {
+        return Err(anyhow::anyhow!("empty path"));
+    }
+    Ok(safe)
 }
 
-/// Naïve search for a string in a file
-fn search_in_file(file: &PathBuf, query: &str) -> Result<bool> {
-    let ctn =
-        std::fs::read_to_string(file).context(format!("Could not open file {}", file.display()))?;
-    if ctn.contains(query) {
-        Ok(true)
-    } else {
-        Ok(false)
+/// The safe path (see [`safe_entry_path`]) of a tarball entry. Links
+/// are rejected when their target is outside of the extraction
+/// directory, as the next entries could be written through them.
+pub fn sanitize_entry<R: std::io::Read>(entry: &tar::Entry<R>) -> Result<PathBuf> {
+    let path = safe_entry_path(&entry.path()?)?;
+    let kind = entry.header().entry_type();
+    if (kind.is_symlink() || kind.is_hard_link())
+        && let Some(target) = entry.link_name()?
+    {
+        safe_entry_path(&target)
+            .context(format!("{} links outside of the extraction directory", path.display()))?;
     }
+    Ok(path)
 }
 
-/// Search for a string in all files in a directory
-/// and return the files that match
-pub fn search_in_dir(dir: &PathBuf, query: &str) -> Result<Vec<PathBuf>> {
-    let mut results = Vec::new();
+/// Extract a tarball in `dir`, returning the paths (relative
+/// to `dir`) of the files it contained. The entries that would be
+/// written outside of `dir` are skipped (see [`sanitize_entry`]). On
+/// Windows, the files are extracted under their portable paths (see
+/// [`crate::dumps::winpath`]).
+pub fn extract_tarball(tarball: &PathBuf, dir: &PathBuf) -> Result<Vec<String>> {
+    // the tarball, and the entry being unpacked
+    let _open = crate::open_files::acquire(2);
+    let file = std::fs::File::open(tarball)
+        .context(format!("Failed to open tarball {}", tarball.display()))?;
 
-    let mut candidates = vec![dir.clone()];
-    let mut total = 1;
+    let gzip = flate2::read::GzDecoder::new(file);
 
-    let pb = ProgressBar::new(0);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{msg} [{wide_bar}] {pos}/{len} ({eta})")
-            .context("Failed to create progress bar template")?
-            .progress_chars("##-"),
-    );
-    pb.set_message(format!("Searching in {}", dir.display()));
-    pb.set_length(total);
-
-    while let Some(candidate) = candidates.pop() {
-        pb.set_message(format!("Searching in {}", candidate.display()));
-        pb.inc(1);
-        for entry in std::fs::read_dir(candidate)
-            .context(format!("Failed to read directory {}", dir.display()))?
-        {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_dir() {
-                candidates.push(path);
-                total += 1;
-            } else {
-                if search_in_file(&path, query)? {
-                    results.push(path);
-                }
+    let mut tar = tar::Archive::new(gzip);
+
+    // Extract the tarball to the specified directory,
+    // remembering the files it contained
+    std::fs::create_dir_all(dir)
+        .context(format!("Failed to create directory {}", dir.display()))?;
+    let mut files = vec![];
+    let mut renamed = vec![];
+    let context = || format!("Failed to extract tarball {}", tarball.display());
+    for entry in tar.entries().with_context(context)? {
+        let mut entry = entry.with_context(context)?;
+        let mut path = match sanitize_entry(&entry) {
+            Ok(path) => path,
+            Err(e) => {
+                warn!("Rejected entry of {}: {:#}", tarball.display(), e);
+                continue;
             }
-        }
-        pb.set_length(total);
-    }
-    Ok(results)
-}
-
-/// STEP 3 create the index
-/// using tantivy
-
-pub mod file_collector {
-
-    use std::fs::OpenOptions;
-    use std::io::BufWriter;
-    use std::io::Write;
-    use std::path::PathBuf;
-    use std::sync::{Arc, Mutex};
-
-    use tantivy::collector::Collector;
-    use tantivy::collector::SegmentCollector;
-    use tantivy::index::SegmentReader;
-    use tantivy::schema::Field;
-    use tantivy::schema::{OwnedValue, TantivyDocument};
-    use tantivy::store::StoreReader;
-    use tantivy::{DocId, Result, Score, SegmentOrdinal};
-
-    /// In order to write the list of all matches into
-    /// a file we will create our own Collector / SegmentCollector
-    /// that will buffer-write the results to a file
-    ///
-    /// FileList collector is an empty struct used to implement the traits
-    pub struct FileListCollector {
-        path_field: Field,
-        bufwriter: FileCollectorFruit,
-    }
-
-    impl FileListCollector {
-        pub fn new(path_field: Field, file_path: &PathBuf) -> FileListCollector {
-            let file = OpenOptions::new()
-                .create(true)
-                .read(true)
-                .append(true)
-                .open(file_path)
-                .expect("could not open file");
-            let bufwriter = Arc::new(Mutex::new(BufWriter::new(file)));
-            FileListCollector {
-                path_field,
-                bufwriter,
+        };
+        let kind = entry.header().entry_type();
+        if cfg!(windows) {
+            if kind.is_symlink() || kind.is_hard_link() {
+                warn!("Skipping the link {} of {}", path.display(), tarball.display());
+                continue;
             }
-        }
-    }
-
-    // this will be shared between all segments
-    // so we need to make sure that it is thread-safe
-    // and we need to use a Mutex to protect it
-    type FileCollectorFruit = Arc<Mutex<std::io::BufWriter<std::fs::File>>>;
-
-    pub struct FileListSegmentCollector {
-        path_field: Field,
-        bufwriter: FileCollectorFruit,
-        store_reader: StoreReader,
-    }
-
-    impl SegmentCollector for FileListSegmentCollector {
-        type Fruit = ();
-
-        fn collect(&mut self, doc: DocId, _: Score) {
-            let doc: TantivyDocument = self
-                .store_reader
-                .get(doc)
-                .expect(format!("Could not get document {doc}").as_str());
-
-            match doc.get_first(self.path_field) {
-                Some(OwnedValue::Str(s)) => {
-                    let mut lock = self.bufwriter.lock().expect("Unable to acquire lock");
-                    write!(lock, "{}\n", s).expect("Unable to write to buffer");
-                }
-                _ => {}
+            let portable = winpath::unpack_portable(&mut entry, dir, &path).with_context(context)?;
+            if portable != path && kind.is_file() {
+                renamed.push((portable.to_string_lossy().to_string(), path.to_string_lossy().to_string()));
             }
+            path = portable;
+        } else {
+            // `unpack_in` also refuses to write through the links
+            // extracted before
+            entry.unpack_in(dir).with_context(context)?;
         }
-
-        fn harvest(self) {
-            let mut lock = self.bufwriter.lock().expect("Unable to acquire lock");
-            lock.flush().expect("unable to flush buffer");
+        if kind.is_file() {
+            files.push(path.to_string_lossy().to_string());
         }
     }
-
-    impl Collector for FileListCollector {
-        type Fruit = ();
-        type Child = FileListSegmentCollector;
-
-        fn requires_scoring(&self) -> bool {
-            false
-        }
-
-        fn for_segment(
-            &self,
-            _: SegmentOrdinal,
-            segment_reader: &SegmentReader,
-        ) -> Result<Self::Child> {
-            let store = segment_reader.get_store_reader(100)?;
-            Ok(FileListSegmentCollector {
-                path_field: self.path_field,
-                bufwriter: self.bufwriter.clone(),
-                store_reader: store,
-            })
-        }
-
-        fn merge_fruits(&self, _: Vec<()>) -> Result<()> {
-            Ok(())
-        }
+    if !renamed.is_empty() {
+        winpath::record(dir, &renamed)?;
     }
+    Ok(files)
 }
 
-pub struct IndexFields {
-    path: tantivy::schema::Field,
-    body: tantivy::schema::Field,
-    year: tantivy::schema::Field,
+/// Whether a file is a suppression list of a delta tarball
+pub fn is_suppression_list(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with("liste_suppression") && name.ends_with(".dat"))
 }
 
-#[inline(always)]
-fn build_schema_and_tokenizer() -> (
-    tantivy::schema::Schema,
-    tantivy::tokenizer::TextAnalyzer,
-    IndexFields,
-) {
-    use tantivy::schema::*;
-    use tantivy::tokenizer::*;
-
-    let tok_fr = TextAnalyzer::builder(SimpleTokenizer::default())
-        .filter(RemoveLongFilter::limit(40))
-        .filter(LowerCaser)
-        .filter(AsciiFoldingFilter)
-        .filter(StopWordFilter::new(Language::French).unwrap())
-        .build();
-
-    let idx_fr = TextFieldIndexing::default()
-        .set_tokenizer("custom_fr")
-        .set_index_option(IndexRecordOption::WithFreqsAndPositions);
+/// The documents listed in a suppression list of a delta tarball
+/// (e.g. `liste_suppression_jade.dat`), one path relative to the
+/// extraction directory per line, with or without the `.xml` extension
+pub fn suppressed_paths(list: &str) -> Vec<String> {
+    list.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(|line| {
+            let line = line.trim_start_matches('/');
+            if line.ends_with(".xml") {
+                line.to_string()
+            } else {
+                format!("{}.xml", line)
+            }
+        })
+        .collect()
+}
 
-    let opts_fr = TextOptions::default()
-        .set_indexing_options(idx_fr)
-        .set_stored();
+/// Check the integrity of a tarball without unpacking it.
+///
+/// Every entry is read to the end, which detects truncated
+/// archives, and the gzip stream is consumed entirely so that
+/// its CRC is verified. Returns the number of entries.
+pub fn validate_tarball(tarball: &Path) -> Result<usize> {
+    use std::io::Read;
 
-    let mut schema_builder = Schema::builder();
-    let path = schema_builder.add_text_field("path", STRING | STORED);
-    let body = schema_builder.add_text_field("body", opts_fr);
-    let year = schema_builder.add_u64_field("year", FAST | INDEXED | STORED);
-    let schema = schema_builder.build();
+    let file = std::fs::File::open(tarball)
+        .context(format!("Failed to open tarball {}", tarball.display()))?;
+    let gzip = flate2::read::GzDecoder::new(file);
+    let mut tar = tar::Archive::new(gzip);
 
-    (schema, tok_fr, IndexFields { path, body, year })
+    let mut count = 0;
+    for entry in tar
+        .entries()
+        .context(format!("Failed to read entries of {}", tarball.display()))?
+    {
+        let mut entry = entry.context(format!("Corrupted entry in {}", tarball.display()))?;
+        std::io::copy(&mut entry, &mut std::io::sink())
+            .context(format!("Truncated entry in {}", tarball.display()))?;
+        count += 1;
+    }
+    // consume the trailing padding so that the gzip checksum is verified
+    tar.into_inner()
+        .read_to_end(&mut vec![])
+        .context(format!("Invalid gzip stream in {}", tarball.display()))?;
+    Ok(count)
 }
 
-pub fn init_tantivy(index_path: &PathBuf) -> Result<(tantivy::Index, IndexFields)> {
-    use tantivy::Index;
+/// Move a tarball to the `quarantine` subdirectory of its parent,
+/// so that it is not extracted and gets downloaded again.
+pub fn quarantine_tarball(tarball: &Path) -> Result<PathBuf> {
+    let parent = tarball.parent().unwrap_or(Path::new("."));
+    let qdir = parent.join("quarantine");
+    std::fs::create_dir_all(&qdir)
+        .context(format!("Failed to create directory {}", qdir.display()))?;
+    let name = tarball
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Invalid tarball path {}", tarball.display()))?;
+    let target = qdir.join(name);
+    std::fs::rename(tarball, &target).context(format!(
+        "Failed to move {} to {}",
+        tarball.display(),
+        target.display()
+    ))?;
+    Ok(target)
+}
 
-    let (schema, tokenizer, fields) = build_schema_and_tokenizer();
-    // If the index does not exist, create it
-    // otherwise open it
-    let index = match Index::open_in_dir(index_path) {
-        Ok(index) => index,
-        Err(_) => {
-            // Create the index
-            Index::create_in_dir(index_path, schema)?
+/// Validate the given tarballs stored in `dir`. Corrupted ones are
+/// quarantined and downloaded again once. The tarballs that are still
+/// corrupted after this second attempt are quarantined and returned.
+pub async fn repair_tarballs(
+    client: &Client,
+    dir: &Path,
+    tarballs: &[Tarball],
+    opts: &DownloadOptions,
+) -> Result<Vec<Tarball>> {
+    let mut broken = vec![];
+    for tarball in tarballs {
+        let path = dir.join(tarball);
+        if let Err(e) = validate_tarball(&path) {
+            warn!("{}: {:#}", tarball.name, e);
+            quarantine_tarball(&path)?;
+            broken.push(tarball.clone());
         }
-    };
-
-    index.tokenizers().register("custom_fr", tokenizer);
+    }
+    if broken.is_empty() {
+        return Ok(broken);
+    }
 
-    Ok((index, fields))
+    warn!("Downloading {} corrupted tarballs again", broken.len());
+    download_tarball_list(client, &broken, dir, opts).await?;
+
+    let mut still_broken = vec![];
+    for tarball in broken {
+        let path = dir.join(&tarball);
+        if !path.exists() {
+            still_broken.push(tarball);
+        } else if let Err(e) = validate_tarball(&path) {
+            warn!("{} is still corrupted: {:#}", tarball.name, e);
+            quarantine_tarball(&path)?;
+            still_broken.push(tarball);
+        }
+    }
+    Ok(still_broken)
 }
 
-pub fn init_tantivy_ram() -> Result<(tantivy::Index, IndexFields)> {
-    use tantivy::Index;
-
-    let (schema, tokenizer, fields) = build_schema_and_tokenizer();
-
-    // Create the index in RAM
-    let index = Index::create_in_ram(schema);
-    index.tokenizers().register("custom_fr", tokenizer);
+/// Iterate over all files below `dir`, recursively.
+/// Directories are read in parallel, and files are
+/// yielded as soon as they are found.
+pub fn walk_files(dir: &Path) -> impl Iterator<Item = Result<PathBuf>> + Send {
+    jwalk::WalkDir::new(dir)
+        .skip_hidden(false)
+        .into_iter()
+        .filter_map(|entry| match entry {
+            Ok(entry) if entry.file_type().is_dir() => None,
+            Ok(entry) => Some(Ok(entry.path())),
+            Err(e) => Some(Err(anyhow::anyhow!("Failed to walk directory: {}", e))),
+        })
+}
 
-    Ok((index, fields))
+/// List all files recursively in a directory
+pub fn list_files_in_dir(dir: PathBuf) -> Result<Vec<PathBuf>> {
+    walk_files(&dir).collect()
 }
 
-fn get_year_juri(doc: &str, re: &regex::Regex) -> Result<u64> {
-    let names: Vec<u64> = re
-        .captures_iter(doc)
-        .map(|cap| cap["year"].to_owned())
-        .filter_map(|s| s.parse().ok())
-        .take(1)
-        .collect();
-    if names.len() > 0 {
-        Ok(names[0])
+/// Naïve search for a string in a file
+fn search_in_file(file: &Path, query: &str) -> Result<bool> {
+    let ctn = std::fs::read(winpath::extended_length(file))
+        .context(format!("Could not open file {}", file.display()))?;
+    let ctn = crate::dumps::extractor::encoding::decode(&ctn);
+    if ctn.contains(query) {
+        Ok(true)
     } else {
-        Err(anyhow::anyhow!("Cannot find date in juri document"))
+        Ok(false)
     }
 }
 
-#[derive(Debug, Clone)]
-struct FondXMLFile {
-    path: String,
-    body: String,
-    year: u64,
-}
-
-fn parse_file(dir: &PathBuf, file: &PathBuf, re: &regex::Regex) -> Result<FondXMLFile> {
-    let body = std::fs::read_to_string(file).context("Could not open file")?;
-    let year = get_year_juri(&body, re)
-        .context(format!("Could not get year in {}", file.to_string_lossy()))?;
-    let path = file
-        .strip_prefix(dir)
-        .map_err(|_| anyhow::anyhow!("Failed to strip prefix from {}", file.display()))?
-        .to_string_lossy()
-        .to_string();
-    Ok(FondXMLFile { path, body, year })
-}
-
-/// Index a file in the tantivy index
-fn index_file(
-    index_writer: &mut tantivy::IndexWriter,
-    fields: &IndexFields,
-    file: FondXMLFile,
-) -> Result<()> {
-    let mut doc = tantivy::TantivyDocument::default();
-
-    doc.add_text(fields.path, file.path);
-    doc.add_text(fields.body, file.body);
-    doc.add_u64(fields.year, file.year);
-    index_writer.add_document(doc)?;
-    Ok(())
-}
-
-/// Index all files in a directory using tantivy,
-/// recursively
-pub fn index_files_in_dir(
-    index_writer: &mut tantivy::IndexWriter,
-    fields: &IndexFields,
-    dir: &PathBuf,
-) -> Result<()> {
-    // create a progress bar
-    let pb = ProgressBar::new(0);
-    let re = regex::Regex::new(r"(?<year>\d*)-\d*-\d*</DATE").unwrap();
-    let files: Vec<PathBuf> = list_files_in_dir(dir.clone())?
-        .into_iter()
-        .filter(|p| p.is_file() && p.extension().map_or(false, |ext| ext == "xml"))
-        .collect();
-
-    pb.set_length(files.len() as u64);
+/// Search for a string in all files in a directory
+/// and return the files that match
+pub fn search_in_dir(dir: &Path, query: &str) -> Result<Vec<PathBuf>> {
+    let pb = progress_bar(0);
     pb.set_style(
         ProgressStyle::default_bar()
             .template("{msg} [{wide_bar}] {pos}/{len} ({eta})")
             .context("Failed to create progress bar template")?
             .progress_chars("##-"),
     );
-    pb.set_message(format!("Indexing {} files", files.len()));
-
-    for file in files {
-        if let Ok(doc) = parse_file(dir, &file, &re) {
-            match index_file(index_writer, fields, doc) {
-                Ok(_) => {
-                    pb.set_message(format!("Indexed {}", file.display()));
-                }
-                Err(e) => {
-                    warn!("Failed to index {}: {}", file.display(), e);
-                }
-            }
-        } else {
-            warn!("Failed to parse {}", file.display());
-        }
-        pb.inc(1);
-    }
-    index_writer.commit()?;
-    Ok(())
-}
-
-/// search all files in the index
-pub fn search_index(
-    index: &tantivy::Index,
-    fields: &IndexFields,
-    save: &Option<String>,
-    query: &str,
-) -> Result<(usize, Vec<(String, u64)>)> {
-    use tantivy::schema::document::Value;
-    let reader = index
-        .reader_builder()
-        .reload_policy(tantivy::ReloadPolicy::OnCommitWithDelay)
-        .try_into()?;
-    let searcher = reader.searcher();
-    let query_parser = tantivy::query::QueryParser::for_index(index, vec![fields.body]);
-    let query = query_parser.parse_query(query)?;
-
-    let (doc_count, top_docs) = if let Some(savepath) = save {
-        let fpath = PathBuf::from(savepath);
-        let fcol = file_collector::FileListCollector::new(fields.path, &fpath);
-        let (d, t, _) = searcher.search(
-            &query,
-            &(
-                tantivy::collector::Count,
-                tantivy::collector::TopDocs::with_limit(10),
-                fcol,
-            ),
-        )?;
-        (d, t)
-    } else {
-        searcher.search(
-            &query,
-            &(
-                tantivy::collector::Count,
-                tantivy::collector::TopDocs::with_limit(10),
-            ),
-        )?
-    };
-
-    let mut results = Vec::new();
-    for (_, doc_address) in top_docs {
-        let doc: tantivy::TantivyDocument = searcher.doc(doc_address)?;
-        let title = doc
-            .get_first(fields.path)
-            .ok_or_else(|| anyhow::anyhow!("Failed to get path"))?
-            .as_str()
-            .ok_or_else(|| anyhow::anyhow!("Path is not a string"))?;
-        let year = doc
-            .get_first(fields.year)
-            .ok_or_else(|| anyhow::anyhow!("Failed to get year"))?
-            .as_u64()
-            .ok_or_else(|| anyhow::anyhow!("Year is not u64"))?;
+    pb.set_message(format!("Searching in {}", dir.display()));
 
-        results.push((title.to_owned(), year));
-    }
-    Ok((doc_count, results))
+    let results: Result<Vec<Option<PathBuf>>> = walk_files(dir)
+        .inspect(|_| pb.inc_length(1))
+        .par_bridge()
+        .map(|path| {
+            let path = path?;
+            let found = search_in_file(&path, query)?;
+            pb.inc(1);
+            Ok(found.then_some(path))
+        })
+        .collect();
+    pb.finish();
+    Ok(results?.into_iter().flatten().collect())
 }
 
 
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -663,22 +1079,95 @@ mod tests {
     fn test_date_parsing() {
         use chrono::Datelike;
         let name = "CASS_20231125-130812.tar.gz";
-        let date = extract_date_from_tarball_name(name).unwrap();
+        let date = parse_tarball_name(name).unwrap().date;
         assert_eq!(date.day(), 25);
         assert_eq!(date.month(), 11);
         assert_eq!(date.year(), 2023);
 
         let name = "CASS_20240101-200918.tar.gz";
-        let date = extract_date_from_tarball_name(name).unwrap();
+        let date = parse_tarball_name(name).unwrap().date;
         assert_eq!(date.day(), 1);
         assert_eq!(date.month(), 1);
         assert_eq!(date.year(), 2024);
 
         let name = "Freemium_jorf_global_20231119-100000.tar.gz"; 
-        let date = extract_date_from_tarball_name(name).unwrap();
+        let date = parse_tarball_name(name).unwrap().date;
         assert_eq!(date.day(), 19);
         assert_eq!(date.month(), 11);
         assert_eq!(date.year(), 2023);
+
+        let stock = parse_tarball_name("Freemium_legi_global_20240101-100000.tar.gz").unwrap();
+        assert_eq!((stock.fond, stock.kind), (Fond::LEGI, TarballKind::Stock));
+        assert_eq!(stock.time, NaiveTime::from_hms_opt(10, 0, 0));
+        let day = parse_tarball_name("JORF_20240101.tar.gz").unwrap();
+        assert_eq!((day.fond, day.kind, day.time), (Fond::JORF, TarballKind::Delta, None));
+        // unexpected names are errors, not panics
+        for name in [
+            "CASS_20231325-130812.tar.gz",
+            "CASS_20231125-250000.tar.gz",
+            "KALI_20240101-100000.tar.gz",
+            "Freemium_cass_20240101-100000.tar.gz",
+            "CASS_latest.tar.gz",
+            "CASS_20231125-130812.tar",
+        ] {
+            assert!(parse_tarball_name(name).is_err(), "{}", name);
+            assert!(Tarball::from_name(name).is_err(), "{}", name);
+        }
+
+        let content = format!(
+            "{}\n<a href=\"CASS_latest.tar.gz\">CASS_latest.tar.gz</a> 2024-01-15 20:47  1K\n\
+             <a href=\"CAPP_20240101-100000.tar.gz\">CAPP_20240101-100000.tar.gz</a> 2024-01-01 10:00  1K\n\
+             <a href=\"Freemium_cass_global_20231119-100000.tar.gz\">Freemium_cass_global_20231119-100000.tar.gz</a> 2023-11-19 10:00  1G",
+            MOCK_CASS_CONTENT
+        );
+        let listing = parse_listing(&Fond::CASS, &content);
+        assert_eq!(listing.tarballs.len(), 9);
+        assert!(listing.tarballs.iter().any(|t| t.kind == TarballKind::Stock));
+        let unparsable: Vec<&str> = listing.unparsable.iter().map(|u| u.name.as_str()).collect();
+        assert_eq!(unparsable, ["CAPP_20240101-100000.tar.gz", "CASS_latest.tar.gz"]);
+    }
+
+    #[test]
+    fn test_tarball_timestamps() {
+        let winter = extract_timestamp_from_tarball_name("CASS_20240108-211850.tar.gz").unwrap();
+        assert_eq!(winter.to_rfc3339(), "2024-01-08T21:18:50+01:00");
+        let summer =
+            extract_timestamp_from_tarball_name("Freemium_jade_global_20240715-100000.tar.gz").unwrap();
+        assert_eq!(summer.to_rfc3339(), "2024-07-15T10:00:00+02:00");
+        // the changes of time of 2024: March 31 and October 27
+        let offset = |month, day, hour, minute| {
+            let date = NaiveDate::from_ymd_opt(2024, month, day).unwrap();
+            paris_datetime(date.and_hms_opt(hour, minute, 0).unwrap()).offset().local_minus_utc()
+        };
+        assert_eq!(offset(3, 31, 1, 59), 3600);
+        assert_eq!(offset(3, 31, 3, 0), 7200);
+        assert_eq!(offset(10, 27, 2, 30), 7200);
+        assert_eq!(offset(10, 27, 3, 0), 3600);
+        let day = extract_timestamp_from_tarball_name("JORF_20240101.tar.gz").unwrap();
+        assert_eq!(day.to_rfc3339(), "2024-01-01T00:00:00+01:00");
+        assert!(extract_timestamp_from_tarball_name("JORF_latest.tar.gz").is_err());
+
+        // deltas of the same day are applied in the order of publication
+        let mut tarballs: Vec<Tarball> = [
+            "JADE_20240101-210000.tar.gz",
+            "JADE_20240101-093000.tar.gz",
+            "Freemium_jade_global_20240101-093000.tar.gz",
+        ]
+        .iter()
+        .map(|name| Tarball::from_name(name).unwrap())
+        .collect();
+        tarballs.sort_by_key(Tarball::application_order);
+        let names: Vec<&str> = tarballs.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "Freemium_jade_global_20240101-093000.tar.gz",
+                "JADE_20240101-093000.tar.gz",
+                "JADE_20240101-210000.tar.gz"
+            ]
+        );
+        let json = serde_json::to_value(&tarballs[2]).unwrap();
+        assert_eq!(json["timestamp"], "2024-01-01T21:00:00+01:00");
     }
 
     #[test]
@@ -691,14 +1180,213 @@ mod tests {
         assert_eq!(tarballs[0].time.day(), 25);
         assert_eq!(tarballs[0].time.month(), 11);
         assert_eq!(tarballs[0].time.year(), 2023);
+        assert_eq!(tarballs[0].size, Some(261 * 1024));
+        assert_eq!(tarballs[0].kind, TarballKind::Delta);
+        assert_eq!(tarballs[7].size, Some(306 * 1024));
     }
 
     #[test]
-    fn test_get_year_juri() {
-        let re = regex::Regex::new(r"(?<year>\d*)-\d*-\d*</DATE").unwrap();
-        let doc = r#"<DATE_JURI>2023-01-01</DATE_JURI>"#;
-        let year = get_year_juri(doc, &re).unwrap();
-        assert_eq!(year, 2023);
+    fn test_plan_downloads() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let tarballs = get_tarballs_from_page_content(&Fond::CASS, MOCK_CASS_CONTENT);
+        std::fs::write(tmp.path().join(&tarballs[0].name), b"").unwrap();
+        let plan = plan_downloads(tmp.path(), &tarballs);
+        assert_eq!(plan.tarballs.len(), 7);
+        assert_eq!(plan.compressed_bytes, (130 + 145 + 212 + 311 + 408 + 165 + 306) * 1024);
+        assert_eq!(plan.unknown_sizes, 0);
+        assert!(plan.estimated_uncompressed_bytes > plan.compressed_bytes);
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(12), "12 B");
+    }
+
+    #[test]
+    fn test_tarball_kind_and_size() {
+        assert_eq!(
+            TarballKind::from_name("Freemium_jorf_global_20231119-100000.tar.gz"),
+            TarballKind::Stock
+        );
+        assert_eq!(parse_listing_size("1.5M"), Some(1024 * 1024 * 3 / 2));
+        assert_eq!(parse_listing_size("12"), Some(12));
+        assert_eq!(parse_listing_size("-"), None);
+    }
+
+    #[test]
+    fn test_download_report() {
+        let tarball = |name: &str| Tarball::from_name(name).unwrap();
+        let report = DownloadReport {
+            entries: vec![
+                DownloadEntry {
+                    tarball: tarball("CASS_20240101-000000.tar.gz"),
+                    status: DownloadStatus::Downloaded,
+                    attempts: 1,
+                },
+                DownloadEntry {
+                    tarball: tarball("CASS_20240102-000000.tar.gz"),
+                    status: DownloadStatus::SkippedExists,
+                    attempts: 0,
+                },
+                DownloadEntry {
+                    tarball: tarball("CASS_20240103-000000.tar.gz"),
+                    status: DownloadStatus::Failed("HTTP status 404".to_string()),
+                    attempts: 3,
+                },
+            ],
+            unparsable: vec![],
+        };
+        assert_eq!(report.downloaded()[0].name, "CASS_20240101-000000.tar.gz");
+        assert_eq!(report.skipped()[0].name, "CASS_20240102-000000.tar.gz");
+        assert_eq!(report.failed()[0].name, "CASS_20240103-000000.tar.gz");
+        assert_eq!(report.summary(), "1 downloaded, 1 skipped, 1 failed");
+
+        // the names left out of the listing are reported
+        let mut all = report.clone();
+        all.extend(DownloadReport {
+            entries: vec![],
+            unparsable: parse_listing(&Fond::CASS, "CASS_latest.tar.gz").unparsable,
+        });
+        assert_eq!(all.unparsable[0].name, "CASS_latest.tar.gz");
+        assert_eq!(all.summary(), "1 downloaded, 1 skipped, 1 failed, 1 unexpected names");
+    }
+
+    fn write_test_tarball(path: &Path) {
+        let file = std::fs::File::create(path).unwrap();
+        let gz = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        let mut builder = tar::Builder::new(gz);
+        let content = "<TEXTE_JURI_ADMIN></TEXTE_JURI_ADMIN>".repeat(100);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "juri/doc.xml", content.as_bytes())
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn test_validate_tarball() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let path = tmp.path().join("CASS_20231125-130812.tar.gz");
+        write_test_tarball(&path);
+        assert_eq!(validate_tarball(&path).unwrap(), 1);
+
+        // truncate the archive
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        assert!(validate_tarball(&path).is_err());
+
+        let target = quarantine_tarball(&path).unwrap();
+        assert!(!path.exists());
+        assert_eq!(target, tmp.path().join("quarantine").join("CASS_20231125-130812.tar.gz"));
+    }
+
+    /// Append an entry whose name (and link target) are written as is,
+    /// as `tar::Header::set_path` refuses the unsafe ones
+    fn append_raw<W: std::io::Write>(
+        builder: &mut tar::Builder<W>,
+        kind: tar::EntryType,
+        name: &str,
+        link: &str,
+        data: &[u8],
+    ) {
+        let mut header = tar::Header::new_old();
+        header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+        header.as_old_mut().linkname[..link.len()].copy_from_slice(link.as_bytes());
+        header.set_entry_type(kind);
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, data).unwrap();
     }
 
+    #[test]
+    fn test_extract_hostile_tarball() {
+        assert_eq!(safe_entry_path(Path::new("./jade/a.xml")).unwrap(), Path::new("jade/a.xml"));
+        assert!(safe_entry_path(Path::new("jade/../../a.xml")).is_err());
+        assert!(safe_entry_path(Path::new("/etc/passwd")).is_err());
+        assert!(safe_entry_path(Path::new("./")).is_err());
+
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let path = tmp.path().join("JADE_20240101-000000.tar.gz");
+        let file = std::fs::File::create(&path).unwrap();
+        let gzip = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        let mut builder = tar::Builder::new(gzip);
+        let regular = tar::EntryType::Regular;
+        append_raw(&mut builder, regular, "./jade/global/a.xml", "", b"<ID>a</ID>");
+        append_raw(&mut builder, regular, "../evil.xml", "", b"evil");
+        append_raw(&mut builder, regular, "jade/../../evil.xml", "", b"evil");
+        append_raw(&mut builder, regular, "/tmp/legifrance-evil.xml", "", b"evil");
+        append_raw(&mut builder, tar::EntryType::Symlink, "jade/out", "../..", b"");
+        append_raw(&mut builder, tar::EntryType::Symlink, "jade/root", "/", b"");
+        append_raw(&mut builder, tar::EntryType::Link, "jade/passwd", "/etc/passwd", b"");
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let dir = tmp.path().join("extracted");
+        let files = extract_tarball(&path, &dir).unwrap();
+        assert_eq!(files, ["jade/global/a.xml"]);
+        assert!(dir.join("jade/global/a.xml").exists());
+        assert!(!tmp.path().join("evil.xml").exists());
+        assert!(!Path::new("/tmp/legifrance-evil.xml").exists());
+        for link in ["jade/out", "jade/root", "jade/passwd"] {
+            assert!(std::fs::symlink_metadata(dir.join(link)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_walk_and_search() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let nested = tmp.path().join("a").join("b");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(tmp.path().join("one.xml"), "<DATE>2020-01-01</DATE>").unwrap();
+        std::fs::write(nested.join("two.xml"), "<DATE>2021-01-01</DATE>").unwrap();
+
+        let mut files = list_files_in_dir(tmp.path().to_path_buf()).unwrap();
+        files.sort();
+        assert_eq!(files, vec![nested.join("two.xml"), tmp.path().join("one.xml")]);
+
+        let found = search_in_dir(tmp.path(), "2021").unwrap();
+        assert_eq!(found, vec![nested.join("two.xml")]);
+    }
+
+    #[test]
+    fn test_tarball_from_name() {
+        let tb = Tarball::from_name("Freemium_jorf_global_20231119-100000.tar.gz").unwrap();
+        assert_eq!(tb.fond, Fond::JORF);
+        assert!(Tarball::from_name("unknown_20231119-100000.tar.gz").is_err());
+    }
+
+    #[test]
+    fn test_parse_bytes() {
+        assert_eq!(parse_bytes("512").unwrap(), 512);
+        assert_eq!(parse_bytes("500K").unwrap(), 500 * 1024);
+        assert_eq!(parse_bytes("2M").unwrap(), 2 * 1024 * 1024);
+        assert_eq!(parse_bytes("1gb").unwrap(), 1024 * 1024 * 1024);
+        assert!(parse_bytes("12X").is_err());
+        assert!(parse_bytes("M").is_err());
+        assert!(parse_bytes("99999999999999999G").is_err());
+    }
+
+    #[test]
+    fn test_throttle_burst() {
+        let throttle = Throttle::new(1000);
+        let start = throttle.state.lock().unwrap().0;
+        // one second of transfer is available at once
+        assert_eq!(throttle.reserve(1000, start), Duration::ZERO);
+        assert_eq!(throttle.reserve(500, start), Duration::from_millis(500));
+        // after an hour idle, the burst is still one second of transfer
+        let later = start + Duration::from_secs(3600);
+        assert_eq!(throttle.reserve(1000, later), Duration::ZERO);
+        assert_eq!(throttle.reserve(2000, later), Duration::from_secs(2));
+        assert_eq!(throttle.reserve(1000, later + Duration::from_secs(1)), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(parse_duration("24h").unwrap(), Duration::from_secs(24 * 3600));
+        assert_eq!(parse_duration("7D").unwrap(), Duration::from_secs(7 * 24 * 3600));
+        assert!(parse_duration("1w").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("99999999999999999d").is_err());
+    }
 }
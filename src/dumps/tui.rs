@@ -0,0 +1,412 @@
+/// Terminal interface to browse the results of searches: a query box,
+/// the list of results with a snippet of their text, and a viewer of
+/// the cleaned text and metadata of a document.
+///
+/// - in the query box, `Enter` runs the search and `Down` goes to the results
+/// - in the results, `Up`/`Down` select a result, `Enter` opens
+///   it and `/` goes back to the query box
+/// - in the viewer, `Up`/`Down`/`PageUp`/`PageDown` scroll and `Esc` closes it
+///
+/// `Esc` (or `q` outside of the query box) and `Ctrl-C` quit.
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use ratatui::Frame;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::{Line, Text};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+
+use std::path::{Path, PathBuf};
+
+use crate::dumps::extractor::{self, Document, ExportRecord};
+use crate::dumps::tarballs::{self, IndexFields, SearchOptions};
+
+/// Number of results listed
+pub const RESULTS_LIMIT: usize = 100;
+
+/// Number of characters of the snippets
+pub const SNIPPET_LENGTH: usize = 160;
+
+/// The part of the interface that receives the keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    Query,
+    Results,
+    Document,
+}
+
+/// A result of the search
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResultItem {
+    pub path: String,
+    pub date: Option<NaiveDate>,
+    pub snippet: String,
+}
+
+/// The document opened in the viewer
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Viewer {
+    path: String,
+    text: String,
+    scroll: u16,
+}
+
+/// The state of the interface
+pub struct App {
+    indices: Vec<(tantivy::Index, IndexFields)>,
+    /// Directory of the extracted documents
+    edir: PathBuf,
+    pub query: String,
+    pub focus: Focus,
+    pub results: Vec<ResultItem>,
+    /// Number of documents matching the query
+    pub count: usize,
+    list: ListState,
+    viewer: Option<Viewer>,
+    status: String,
+    pub quit: bool,
+}
+
+/// The words of a query, without operators nor field names
+fn query_words(query: &str) -> Vec<Vec<char>> {
+    query
+        .split_whitespace()
+        .map(|word| word.rsplit(':').next().unwrap_or_default())
+        .filter(|word| !["AND", "OR", "NOT", "TO"].contains(word))
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect::<Vec<_>>()
+        })
+        .filter(|word| word.len() >= 3)
+        .collect()
+}
+
+/// An excerpt of about `length` characters of `text` around the first
+/// word of the query it contains (or its beginning), on one line
+pub fn snippet(text: &str, query: &str, length: usize) -> String {
+    let chars: Vec<char> = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .collect();
+    let lower: Vec<char> = chars
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    let found = query_words(query)
+        .iter()
+        .filter_map(|word| lower.windows(word.len()).position(|w| w == &word[..]))
+        .min();
+    let start = found.map(|i| i.saturating_sub(length / 4)).unwrap_or(0);
+    let end = (start + length).min(chars.len());
+    let mut excerpt: String = chars[start..end].iter().collect();
+    if start > 0 {
+        excerpt.insert(0, '…');
+    }
+    if end < chars.len() {
+        excerpt.push('…');
+    }
+    excerpt
+}
+
+/// Read and clean the document indexed under `path` (see
+/// [`extractor::article_path`]) from the extraction directory
+pub fn load_document(edir: &Path, path: &str) -> Result<Document> {
+    let (file, article_id) = extractor::split_article_path(path);
+    let file = edir.join(file);
+    let mut document = match article_id {
+        Some(id) => extractor::parse_articles_file(&file)?
+            .into_iter()
+            .find(|document| document.id() == id)
+            .context(format!("No article {} in {}", id, file.display()))?,
+        None => extractor::parse_document_file(&file)?,
+    };
+    document.clean();
+    Ok(document)
+}
+
+/// The metadata of the document followed by its text
+fn document_text(document: &Document) -> Result<String> {
+    let record = ExportRecord::try_from(document)?;
+    let mut text = String::new();
+    for (column, value) in record.metadata().filter(|(_, v)| !v.is_empty()) {
+        text.push_str(&format!("{}: {}\n", column, value));
+    }
+    if let Some(body) = document.text() {
+        text.push('\n');
+        text.push_str(body);
+    }
+    Ok(text)
+}
+
+impl App {
+    pub fn new(indices: Vec<(tantivy::Index, IndexFields)>, edir: PathBuf) -> App {
+        App {
+            indices,
+            edir,
+            query: String::new(),
+            focus: Focus::Query,
+            results: vec![],
+            count: 0,
+            list: ListState::default(),
+            viewer: None,
+            status: "Type a query and press Enter".to_string(),
+            quit: false,
+        }
+    }
+
+    /// Run the query and list the best results
+    pub fn search(&mut self) {
+        let indices: Vec<_> = self.indices.iter().map(|(index, flds)| (index, flds)).collect();
+        let options = SearchOptions {
+            limit: RESULTS_LIMIT,
+            ..Default::default()
+        };
+        let found = match tarballs::search_indices(&indices, &None, &self.query, &options) {
+            Ok(found) => found,
+            Err(e) => {
+                self.status = format!("Invalid query: {}", e);
+                return;
+            }
+        };
+        self.count = found.count;
+        self.results = found
+            .hits
+            .into_iter()
+            .map(|(path, date)| {
+                let snippet = match load_document(&self.edir, &path) {
+                    Ok(document) => {
+                        let text = document.text().unwrap_or_default();
+                        snippet(text, &self.query, SNIPPET_LENGTH)
+                    }
+                    Err(e) => format!("(unreadable: {})", e),
+                };
+                ResultItem { path, date, snippet }
+            })
+            .collect();
+        self.list.select((!self.results.is_empty()).then_some(0));
+        self.status = format!("{} results, {} listed", self.count, self.results.len());
+        if !self.results.is_empty() {
+            self.focus = Focus::Results;
+        }
+    }
+
+    /// Open the selected result in the viewer
+    fn open(&mut self) {
+        let Some(item) = self.list.selected().and_then(|i| self.results.get(i)) else {
+            return;
+        };
+        let text = load_document(&self.edir, &item.path).and_then(|d| document_text(&d));
+        let text = text.unwrap_or_else(|e| format!("Unable to read {}: {:#}", item.path, e));
+        self.viewer = Some(Viewer {
+            path: item.path.clone(),
+            text,
+            scroll: 0,
+        });
+        self.focus = Focus::Document;
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+        match self.focus {
+            Focus::Query => match key.code {
+                KeyCode::Enter => self.search(),
+                KeyCode::Char(c) => self.query.push(c),
+                KeyCode::Backspace => {
+                    self.query.pop();
+                }
+                KeyCode::Down | KeyCode::Tab if !self.results.is_empty() => {
+                    self.focus = Focus::Results
+                }
+                KeyCode::Esc => self.quit = true,
+                _ => {}
+            },
+            Focus::Results => match key.code {
+                KeyCode::Up | KeyCode::Char('k') => self.list.select_previous(),
+                KeyCode::Down | KeyCode::Char('j') => self.list.select_next(),
+                KeyCode::Enter => self.open(),
+                KeyCode::Char('/') | KeyCode::Tab => self.focus = Focus::Query,
+                KeyCode::Esc | KeyCode::Char('q') => self.quit = true,
+                _ => {}
+            },
+            Focus::Document => {
+                let Some(viewer) = self.viewer.as_mut() else {
+                    self.focus = Focus::Results;
+                    return;
+                };
+                match key.code {
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        viewer.scroll = viewer.scroll.saturating_sub(1)
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        viewer.scroll = viewer.scroll.saturating_add(1)
+                    }
+                    KeyCode::PageUp => viewer.scroll = viewer.scroll.saturating_sub(20),
+                    KeyCode::PageDown | KeyCode::Char(' ') => {
+                        viewer.scroll = viewer.scroll.saturating_add(20)
+                    }
+                    KeyCode::Home => viewer.scroll = 0,
+                    KeyCode::Esc | KeyCode::Char('q') | KeyCode::Backspace => {
+                        self.focus = Focus::Results
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    pub fn render(&mut self, frame: &mut Frame) {
+        let [query_area, main_area, status_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let focused = |focus: Focus| {
+            if self.focus == focus {
+                Style::new().fg(Color::Yellow)
+            } else {
+                Style::new()
+            }
+        };
+
+        let query = Paragraph::new(self.query.as_str())
+            .block(Block::bordered().title("Query").border_style(focused(Focus::Query)));
+        frame.render_widget(query, query_area);
+
+        match (&self.viewer, self.focus) {
+            (Some(viewer), Focus::Document) => {
+                let document = Paragraph::new(viewer.text.as_str())
+                    .block(
+                        Block::bordered()
+                            .title(viewer.path.as_str())
+                            .border_style(focused(Focus::Document)),
+                    )
+                    .wrap(Wrap { trim: false })
+                    .scroll((viewer.scroll, 0));
+                frame.render_widget(document, main_area);
+            }
+            _ => {
+                let items = self.results.iter().map(|item| {
+                    let date = item.date.map(|d| d.to_string()).unwrap_or("?".to_string());
+                    ListItem::new(Text::from(vec![
+                        Line::from(format!("[{}] {}", date, item.path)).bold(),
+                        Line::from(item.snippet.clone()).dark_gray(),
+                    ]))
+                });
+                let list = List::new(items)
+                    .block(
+                        Block::bordered()
+                            .title(format!("Results ({})", self.count))
+                            .border_style(focused(Focus::Results)),
+                    )
+                    .highlight_style(Style::new().reversed());
+                frame.render_stateful_widget(list, main_area, &mut self.list);
+            }
+        }
+
+        let help = match self.focus {
+            Focus::Query => "Enter: search  Down: results  Esc: quit",
+            Focus::Results => "Enter: open  /: query  q: quit",
+            Focus::Document => "Up/Down/PageUp/PageDown: scroll  Esc: back",
+        };
+        frame.render_widget(Line::from(format!("{}  |  {}", self.status, help)), status_area);
+    }
+}
+
+/// Run the interface on the indices until the user quits, starting
+/// with the results of `query` if any
+pub fn run(
+    indices: Vec<(tantivy::Index, IndexFields)>,
+    edir: PathBuf,
+    query: Option<String>,
+) -> Result<()> {
+    let mut app = App::new(indices, edir);
+    if let Some(query) = query {
+        app.query = query;
+        app.search();
+    }
+    let mut terminal = ratatui::init();
+    let result = (|| -> Result<()> {
+        while !app.quit {
+            terminal.draw(|frame| app.render(frame))?;
+            if let Event::Key(key) = event::read()? {
+                app.handle_key(key);
+            }
+        }
+        Ok(())
+    })();
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+
+    #[test]
+    fn test_snippet() {
+        let text = "Considérant que   la commune a autorisé l'installation d'une antenne relais";
+        assert_eq!(snippet(text, "fond:JADE AND Antenne", 20), "…'une antenne relais");
+        assert_eq!(snippet(text, "absent", 11), "Considérant…");
+        assert_eq!(snippet("court", "court", 20), "court");
+    }
+
+    #[test]
+    fn test_app() {
+        let (index, fields) = tarballs::init_tantivy_ram().unwrap();
+        let mut writer: tantivy::IndexWriter = index.writer(15_000_000).unwrap();
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let dir = tmp.path().join("jade");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("CETATEXT000000000001.xml"),
+            "<TEXTE_JURI_ADMIN><META><META_COMMUN><ID>CETATEXT000000000001</ID></META_COMMUN>\
+             <META_SPEC><META_JURI><DATE_DEC>2024-03-21</DATE_DEC></META_JURI></META_SPEC></META>\
+             <TEXTE><BLOC_TEXTUEL><CONTENU>Les antennes relais.</CONTENU></BLOC_TEXTUEL></TEXTE>\
+             </TEXTE_JURI_ADMIN>",
+        )
+        .unwrap();
+        tarballs::index_files_in_dir(&mut writer, &fields, tmp.path(), false, &Default::default())
+            .unwrap();
+
+        let mut app = App::new(vec![(index, fields)], tmp.path().to_path_buf());
+        let press = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        for c in "antennes".chars() {
+            app.handle_key(press(KeyCode::Char(c)));
+        }
+        app.handle_key(press(KeyCode::Enter));
+        assert_eq!(app.count, 1);
+        assert_eq!(app.focus, Focus::Results);
+        assert_eq!(app.results[0].snippet, "Les antennes relais.");
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 12)).unwrap();
+        let screen = |terminal: &Terminal<TestBackend>| {
+            let buffer = terminal.backend().buffer();
+            buffer.content().iter().map(|c| c.symbol()).collect::<String>()
+        };
+        terminal.draw(|frame| app.render(frame)).unwrap();
+        assert!(screen(&terminal).contains("[2024-03-21] jade/CETATEXT000000000001.xml"));
+
+        app.handle_key(press(KeyCode::Enter));
+        assert_eq!(app.focus, Focus::Document);
+        terminal.draw(|frame| app.render(frame)).unwrap();
+        assert!(screen(&terminal).contains("id: CETATEXT000000000001"));
+
+        app.handle_key(press(KeyCode::Esc));
+        app.handle_key(press(KeyCode::Char('q')));
+        assert!(app.quit);
+    }
+}
@@ -0,0 +1,283 @@
+/// Data quality checks over an extracted corpus.
+///
+/// Every document is parsed and checked for the metadata that
+/// downstream users rely on (identifier, date, jurisdiction for
+/// decisions, NOR for texts of the Journal Officiel, ...). The
+/// problems are counted per fond, with a few example files for each,
+/// in a report that can be written to JSON.
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::dumps::extractor::{self, Document};
+use crate::dumps::fonds::fond_of_extracted_path;
+use crate::dumps::tarballs::walk_files;
+
+/// How many offending files are kept as examples for each problem
+pub const MAX_EXAMPLES: usize = 5;
+
+/// Data quality of the documents of a fond.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FondQuality {
+    /// Number of XML files checked
+    pub documents: usize,
+    /// Number of files that could not be parsed at all
+    pub unparsable: usize,
+    /// Number of documents of each kind (see [`extractor::DocumentKind`])
+    pub kinds: BTreeMap<String, usize>,
+    /// Number of documents where a required field is missing, per field
+    pub missing: BTreeMap<String, usize>,
+    /// Number of documents where a date cannot be parsed, per field
+    pub invalid_dates: BTreeMap<String, usize>,
+    /// Example files for each problem (e.g. "missing jurisdiction")
+    pub examples: BTreeMap<String, Vec<String>>,
+}
+
+impl FondQuality {
+    fn add_example(&mut self, problem: String, path: &str) {
+        let examples = self.examples.entry(problem).or_default();
+        if examples.len() < MAX_EXAMPLES {
+            examples.push(path.to_string());
+        }
+    }
+
+    /// Record the document at `path`, or why it could not be parsed
+    fn record(&mut self, path: &str, document: Result<Document, extractor::ExtractError>) {
+        self.documents += 1;
+        let document = match document {
+            Ok(document) => document,
+            Err(e) => {
+                self.unparsable += 1;
+                self.add_example("unparsable".to_string(), &format!("{} ({})", path, e));
+                return;
+            }
+        };
+        *self.kinds.entry(document.kind().to_string()).or_insert(0) += 1;
+        for problem in check_document(&document) {
+            let counts = match problem {
+                Problem::Missing(_) => &mut self.missing,
+                Problem::InvalidDate(_) => &mut self.invalid_dates,
+            };
+            *counts.entry(problem.field().to_string()).or_insert(0) += 1;
+            self.add_example(problem.to_string(), path);
+        }
+    }
+
+    fn merge(&mut self, other: FondQuality) {
+        self.documents += other.documents;
+        self.unparsable += other.unparsable;
+        for (kind, count) in other.kinds {
+            *self.kinds.entry(kind).or_insert(0) += count;
+        }
+        for (field, count) in other.missing {
+            *self.missing.entry(field).or_insert(0) += count;
+        }
+        for (field, count) in other.invalid_dates {
+            *self.invalid_dates.entry(field).or_insert(0) += count;
+        }
+        for (problem, examples) in other.examples {
+            for path in examples {
+                self.add_example(problem.clone(), &path);
+            }
+        }
+    }
+}
+
+/// The report of a validation pass, per fond.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationReport {
+    pub fonds: BTreeMap<String, FondQuality>,
+}
+
+impl ValidationReport {
+    fn merge(mut self, other: ValidationReport) -> ValidationReport {
+        for (fond, quality) in other.fonds {
+            self.fonds.entry(fond).or_default().merge(quality);
+        }
+        self
+    }
+
+    /// Total number of documents checked
+    pub fn documents(&self) -> usize {
+        self.fonds.values().map(|f| f.documents).sum()
+    }
+
+    /// Total number of problems found
+    pub fn problems(&self) -> usize {
+        self.fonds
+            .values()
+            .map(|f| {
+                f.unparsable
+                    + f.missing.values().sum::<usize>()
+                    + f.invalid_dates.values().sum::<usize>()
+            })
+            .sum()
+    }
+}
+
+/// A data quality problem of a document
+enum Problem {
+    /// A required field is missing or empty
+    Missing(&'static str),
+    /// A date is not of the form YYYY-MM-DD
+    InvalidDate(&'static str),
+}
+
+impl Problem {
+    fn field(&self) -> &'static str {
+        match self {
+            Problem::Missing(field) | Problem::InvalidDate(field) => field,
+        }
+    }
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::Missing(field) => write!(f, "missing {}", field),
+            Problem::InvalidDate(field) => write!(f, "invalid {}", field),
+        }
+    }
+}
+
+/// Check that a required field is present
+fn require(problems: &mut Vec<Problem>, field: &'static str, value: Option<&str>) {
+    if value.is_none_or(|v| v.trim().is_empty()) {
+        problems.push(Problem::Missing(field));
+    }
+}
+
+/// Check that a required date is present and valid
+fn require_date(problems: &mut Vec<Problem>, field: &'static str, value: Option<&str>) {
+    match value.map(str::trim).filter(|v| !v.is_empty()) {
+        None => problems.push(Problem::Missing(field)),
+        Some(date) => check_date(problems, field, Some(date)),
+    }
+}
+
+/// Check that an optional date is valid
+fn check_date(problems: &mut Vec<Problem>, field: &'static str, value: Option<&str>) {
+    if let Some(date) = value
+        && NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").is_err()
+    {
+        problems.push(Problem::InvalidDate(field));
+    }
+}
+
+/// The problems of a document
+fn check_document(document: &Document) -> Vec<Problem> {
+    let mut problems = vec![];
+    let p = &mut problems;
+    match document {
+        Document::Decision(d) => {
+            require(p, "id", Some(&d.id));
+            require_date(p, "decision_date", d.decision_date.as_deref());
+            require(p, "jurisdiction", d.jurisdiction.as_deref());
+        }
+        Document::Jorf(j) => {
+            require(p, "id", Some(&j.id));
+            require(p, "nor", j.nor.as_deref());
+            check_date(p, "text_date", j.text_date.as_deref());
+            check_date(p, "publication_date", j.publication_date.as_deref());
+        }
+        Document::LegiArticle(a) => {
+            require(p, "id", Some(&a.id));
+            require_date(p, "date_debut", a.date_debut.as_deref());
+            check_date(p, "date_fin", a.date_fin.as_deref());
+        }
+        Document::LegiSection(s) => {
+            require(p, "id", Some(&s.id));
+        }
+        Document::Cnil(c) => {
+            require(p, "id", Some(&c.id));
+            require_date(p, "date", c.date.as_deref());
+        }
+    }
+    problems
+}
+
+/// Check all the XML documents of an extracted directory, in parallel
+pub fn validate_dir(dir: &Path) -> Result<ValidationReport> {
+    let report = walk_files(dir)
+        .par_bridge()
+        .try_fold(ValidationReport::default, |mut report, file| {
+            let file = file?;
+            if file.extension().is_none_or(|ext| ext != "xml") {
+                return Ok(report);
+            }
+            let relative = file.strip_prefix(dir).unwrap_or(&file);
+            report
+                .fonds
+                .entry(fond_of_extracted_path(relative))
+                .or_default()
+                .record(&relative.to_string_lossy(), extractor::parse_document_file(&file));
+            Ok::<_, anyhow::Error>(report)
+        })
+        .try_reduce(ValidationReport::default, |a, b| Ok(a.merge(b)))?;
+    Ok(report)
+}
+
+/// Write a validation report as JSON
+pub fn write_report(report: &ValidationReport, path: &Path) -> Result<()> {
+    let file = std::fs::File::create(path)
+        .context(format!("Failed to create report {}", path.display()))?;
+    serde_json::to_writer_pretty(std::io::BufWriter::new(file), report)
+        .context("Failed to serialize validation report")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_dir() {
+        let tmp = temp_dir::TempDir::new().unwrap();
+        let jade = tmp.path().join("jade/global");
+        std::fs::create_dir_all(&jade).unwrap();
+        std::fs::write(
+            jade.join("CETATEXT000000000001.xml"),
+            "<TEXTE_JURI_ADMIN><META><META_COMMUN><ID>CETATEXT000000000001</ID></META_COMMUN>\
+             <META_SPEC><META_JURI><DATE_DEC>2024-03-21</DATE_DEC>\
+             <JURIDICTION>Conseil d'État</JURIDICTION></META_JURI></META_SPEC></META></TEXTE_JURI_ADMIN>",
+        )
+        .unwrap();
+        std::fs::write(
+            jade.join("CETATEXT000000000002.xml"),
+            "<TEXTE_JURI_ADMIN><META><META_COMMUN><ID>CETATEXT000000000002</ID></META_COMMUN>\
+             <META_SPEC><META_JURI><DATE_DEC>2024-13-41</DATE_DEC></META_JURI></META_SPEC></META></TEXTE_JURI_ADMIN>",
+        )
+        .unwrap();
+        let jorf = tmp.path().join("jorf");
+        std::fs::create_dir_all(&jorf).unwrap();
+        std::fs::write(
+            jorf.join("JORFTEXT000000000003.xml"),
+            "<TEXTE_VERSION><META><ID>JORFTEXT000000000003</ID></META></TEXTE_VERSION>",
+        )
+        .unwrap();
+        std::fs::write(jorf.join("broken.xml"), "<TEXTE_VERSION><META></TEXTE_VERSION>").unwrap();
+        std::fs::write(jorf.join("README.txt"), "not a document").unwrap();
+
+        let report = validate_dir(tmp.path()).unwrap();
+        assert_eq!(report.documents(), 4);
+
+        let jade = &report.fonds["JADE"];
+        assert_eq!(jade.documents, 2);
+        assert_eq!(jade.kinds["decision"], 2);
+        assert_eq!(jade.missing.get("jurisdiction"), Some(&1));
+        assert_eq!(jade.invalid_dates.get("decision_date"), Some(&1));
+        assert_eq!(
+            jade.examples["missing jurisdiction"],
+            vec!["jade/global/CETATEXT000000000002.xml"]
+        );
+
+        let jorf = &report.fonds["JORF"];
+        assert_eq!(jorf.unparsable, 1);
+        assert_eq!(jorf.missing.get("nor"), Some(&1));
+        assert_eq!(report.problems(), 4);
+    }
+}
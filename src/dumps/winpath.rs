@@ -0,0 +1,242 @@
+/// Extraction of the tarballs on Windows, where some paths of the
+/// archives cannot be written as they are.
+///
+/// The components of the paths that are reserved on Windows (device
+/// names such as `CON` or `aux.xml`, characters such as `:` or `?`,
+/// trailing dots and spaces) are renamed by [`portable_path`], and every
+/// renamed file is appended to a mapping at the root of the extraction
+/// directory (see [`PATH_MAPPING_FILE`]), one `extracted<TAB>original`
+/// line per file, from which [`PathMapping`] recovers the paths of the
+/// archives (e.g. to pack the documents under these paths). The files
+/// are written and read through their extended-length path (see
+/// [`extended_length`]), which is not limited to the 260 characters of
+/// `MAX_PATH`, as the deepest paths of the DILA archives are. On other
+/// systems, the paths are kept as they are.
+use anyhow::{Context, Result};
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// Name of the mapping of the renamed files, in the extraction directory
+pub const PATH_MAPPING_FILE: &str = ".paths.tsv";
+
+/// Characters that cannot be part of a file name on Windows, besides
+/// the control characters and the path separators
+const RESERVED_CHARS: &[char] = &['<', '>', ':', '"', '|', '?', '*'];
+
+/// Character introducing the code of a replaced character, escaped
+/// as well so that a renamed name cannot be the name of another file
+const ESCAPE_CHAR: char = '%';
+
+/// Whether `name` is the name of a device on Windows, with or without
+/// an extension (e.g. `CON`, `nul.xml`, `COM1.txt`)
+fn is_device_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or(name).trim_end_matches(' ');
+    let stem = stem.to_ascii_uppercase();
+    matches!(
+        stem.as_bytes(),
+        b"CON" | b"PRN" | b"AUX" | b"NUL" | [b'C', b'O', b'M', b'1'..=b'9'] | [b'L', b'P', b'T', b'1'..=b'9']
+    )
+}
+
+/// The file name `name` renamed to be valid on Windows, or `name` if
+/// it already is. The reserved characters, `%` and the trailing dots
+/// and spaces are replaced by their code (`%3A` for `:`, `%25` for `%`),
+/// and device names get an `_` after their stem (`CON_.xml`).
+pub fn portable_name(name: &str) -> Cow<'_, str> {
+    let reserved = |c: char| c.is_control() || c == ESCAPE_CHAR || RESERVED_CHARS.contains(&c);
+    let kept = name.trim_end_matches(['.', ' ']);
+    if kept.len() == name.len() && !name.contains(reserved) && !is_device_name(name) {
+        return Cow::Borrowed(name);
+    }
+    let mut portable = String::with_capacity(name.len());
+    for c in kept.chars() {
+        if reserved(c) {
+            let _ = write!(portable, "%{:02X}", c as u32);
+        } else {
+            portable.push(c);
+        }
+    }
+    for c in name[kept.len()..].chars() {
+        let _ = write!(portable, "%{:02X}", c as u32);
+    }
+    if is_device_name(&portable) {
+        let stem = portable.find('.').unwrap_or(portable.len());
+        portable.insert(stem, '_');
+    }
+    Cow::Owned(portable)
+}
+
+/// The relative path `path` (see [`crate::dumps::tarballs::safe_entry_path`])
+/// with every component renamed by [`portable_name`], or `path` if none
+/// needs to be
+pub fn portable_path(path: &Path) -> Cow<'_, Path> {
+    let names: Vec<Cow<str>> = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+    let portable: Vec<Cow<str>> = names.iter().map(|name| portable_name(name)).collect();
+    if portable.iter().zip(names.iter()).all(|(p, n)| p == n) {
+        return Cow::Borrowed(path);
+    }
+    Cow::Owned(portable.iter().map(|name| name.as_ref()).collect())
+}
+
+/// The extended-length form of `path` on Windows (`\\?\C:\...` or
+/// `\\?\UNC\server\share\...`), which is not limited to `MAX_PATH`.
+/// Other systems have no such limit, and `path` is kept.
+pub fn extended_length(path: &Path) -> PathBuf {
+    #[cfg(windows)]
+    {
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let text = path.to_string_lossy().into_owned();
+        if text.starts_with(r"\\?\") {
+            path
+        } else if let Some(unc) = text.strip_prefix(r"\\") {
+            PathBuf::from(format!(r"\\?\UNC\{}", unc))
+        } else {
+            PathBuf::from(format!(r"\\?\{}", text))
+        }
+    }
+    #[cfg(not(windows))]
+    {
+        path.to_path_buf()
+    }
+}
+
+/// Unpack the tarball `entry`, whose safe path is `path` (see
+/// [`crate::dumps::tarballs::sanitize_entry`]), in `dir` under its
+/// portable path, which is returned
+pub fn unpack_portable<R: std::io::Read>(
+    entry: &mut tar::Entry<R>,
+    dir: &Path,
+    path: &Path,
+) -> Result<PathBuf> {
+    let portable = portable_path(path).into_owned();
+    let target = extended_length(&dir.join(&portable));
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)
+            .context(format!("Failed to create directory {}", parent.display()))?;
+    }
+    entry
+        .unpack(&target)
+        .context(format!("Failed to unpack {}", portable.display()))?;
+    Ok(portable)
+}
+
+/// Record that the files of the extraction directory `dir` were
+/// renamed, as `(extracted, original)` paths relative to `dir`
+pub fn record(dir: &Path, renamed: &[(String, String)]) -> Result<()> {
+    let path = dir.join(PATH_MAPPING_FILE);
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .context(format!("Failed to open {}", path.display()))?;
+    let mut writer = std::io::BufWriter::new(file);
+    for (extracted, original) in renamed {
+        writeln!(writer, "{}\t{}", extracted, original)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Append the mapping `mapping` (e.g. of a temporary extraction
+/// directory) to the mapping of the extraction directory `dir`
+pub fn merge_into(mapping: &Path, dir: &Path) -> Result<()> {
+    let mut source = std::fs::File::open(mapping)
+        .context(format!("Failed to open {}", mapping.display()))?;
+    let path = dir.join(PATH_MAPPING_FILE);
+    let mut target = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .context(format!("Failed to open {}", path.display()))?;
+    std::io::copy(&mut source, &mut target)?;
+    Ok(())
+}
+
+/// The paths in the archives of the renamed files
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PathMapping {
+    originals: HashMap<String, String>,
+}
+
+impl PathMapping {
+    /// Read the mapping of the extraction directory `dir`, empty when
+    /// no file was renamed
+    pub fn load(dir: &Path) -> Result<PathMapping> {
+        let mut mapping = PathMapping::default();
+        let path = dir.join(PATH_MAPPING_FILE);
+        if !path.exists() {
+            return Ok(mapping);
+        }
+        let file = std::fs::File::open(&path).context(format!("Failed to open {}", path.display()))?;
+        for line in std::io::BufReader::new(file).lines() {
+            let line = line?;
+            if let Some((extracted, original)) = line.split_once('\t') {
+                mapping.originals.insert(extracted.to_string(), original.to_string());
+            }
+        }
+        Ok(mapping)
+    }
+
+    /// The path in the archive of the extracted file `extracted`
+    /// (relative to the extraction directory)
+    pub fn original<'a>(&'a self, extracted: &'a str) -> &'a str {
+        self.originals.get(extracted).map_or(extracted, String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.originals.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_portable_paths() {
+        assert_eq!(portable_name("JORFTEXT000000000001.xml"), "JORFTEXT000000000001.xml");
+        assert!(matches!(portable_name("CONSOLIDATION"), Cow::Borrowed(_)));
+        assert_eq!(portable_name("CON"), "CON_");
+        assert_eq!(portable_name("aux.xml"), "aux_.xml");
+        assert_eq!(portable_name("com7.tar.gz"), "com7_.tar.gz");
+        assert_eq!(portable_name("COM0"), "COM0");
+        assert_eq!(portable_name("a:b?.xml"), "a%3Ab%3F.xml");
+        // the escapes of a name cannot collide with another name
+        assert_eq!(portable_name("a%3Ab.xml"), "a%253Ab.xml");
+        assert_ne!(portable_name("a%3Ab.xml"), portable_name("a:b.xml"));
+        assert_eq!(portable_name("notes. "), "notes%2E%20");
+        assert_eq!(portable_name("nul."), "nul%2E");
+
+        let dila = Path::new("legi/global/code_et_TNC_en_vigueur/LEGITEXT000006070721.xml");
+        assert!(matches!(portable_path(dila), Cow::Borrowed(_)));
+        assert_eq!(
+            portable_path(Path::new("legi/CON/a:b.xml")),
+            Path::new("legi/CON_/a%3Ab.xml")
+        );
+        #[cfg(not(windows))]
+        assert_eq!(extended_length(dila), dila);
+
+        let tmp = temp_dir::TempDir::new().unwrap();
+        assert!(PathMapping::load(tmp.path()).unwrap().is_empty());
+        let renamed = [
+            ("legi/CON_/a%3Ab.xml".to_string(), "legi/CON/a:b.xml".to_string()),
+            ("jade/aux_.xml".to_string(), "jade/aux.xml".to_string()),
+        ];
+        record(tmp.path(), &renamed[..1]).unwrap();
+        record(tmp.path(), &renamed[1..]).unwrap();
+        let mapping = PathMapping::load(tmp.path()).unwrap();
+        assert_eq!(mapping.original("legi/CON_/a%3Ab.xml"), "legi/CON/a:b.xml");
+        assert_eq!(mapping.original("jade/aux_.xml"), "jade/aux.xml");
+        assert_eq!(mapping.original("jade/other.xml"), "jade/other.xml");
+    }
+}
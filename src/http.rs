@@ -0,0 +1,60 @@
+/// Shared configuration of the HTTP clients used to talk
+/// to the dila server (tarballs) and to the PISTE API.
+///
+/// Every option can be given on the command line or through
+/// an environment variable, which is convenient on machines
+/// where outbound traffic must go through a proxy.
+use anyhow::{Context, Result};
+use clap::Args;
+use reqwest::{Certificate, Client, Proxy};
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct HttpConfig {
+    /// Proxy used for every outgoing HTTP(S) request
+    /// (e.g. `http://proxy.example.org:3128`)
+    #[arg(long, env = "LEGIFRANCE_PROXY", global = true)]
+    pub proxy: Option<String>,
+
+    /// PEM file containing extra root certificates to trust
+    #[arg(long, env = "LEGIFRANCE_CA_BUNDLE", global = true)]
+    pub ca_bundle: Option<PathBuf>,
+
+    /// Timeout (in seconds) to establish a connection
+    #[arg(long, env = "LEGIFRANCE_CONNECT_TIMEOUT", global = true)]
+    pub connect_timeout: Option<u64>,
+
+    /// Timeout (in seconds) for a whole request,
+    /// including the download of the response body
+    #[arg(long, env = "LEGIFRANCE_TIMEOUT", global = true)]
+    pub timeout: Option<u64>,
+}
+
+impl HttpConfig {
+    /// Build a `reqwest::Client` honoring this configuration
+    pub fn build_client(&self) -> Result<Client> {
+        let mut builder = Client::builder();
+        if let Some(proxy) = &self.proxy {
+            let proxy = Proxy::all(proxy).context(format!("Invalid proxy URL {}", proxy))?;
+            builder = builder.proxy(proxy);
+        }
+        if let Some(path) = &self.ca_bundle {
+            let pem = std::fs::read(path)
+                .context(format!("Failed to read CA bundle {}", path.display()))?;
+            let certs = Certificate::from_pem_bundle(&pem)
+                .context(format!("Invalid CA bundle {}", path.display()))?;
+            for cert in certs {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+        if let Some(secs) = self.connect_timeout {
+            builder = builder.connect_timeout(Duration::from_secs(secs));
+        }
+        if let Some(secs) = self.timeout {
+            builder = builder.timeout(Duration::from_secs(secs));
+        }
+        builder.build().context("Failed to build HTTP client")
+    }
+}
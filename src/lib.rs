@@ -1,2 +1,10 @@
+#[cfg(feature = "api")]
 pub mod api;
+pub mod concurrency;
+#[cfg(feature = "dumps")]
 pub mod dumps;
+pub mod http;
+#[cfg(any(feature = "api", feature = "dumps"))]
+pub mod logging;
+pub mod open_files;
+pub mod shutdown;
@@ -0,0 +1,95 @@
+/// Logging and progress bars, shared by the binaries.
+///
+/// The messages are filtered by `RUST_LOG` as usual (e.g.
+/// `RUST_LOG=info`), and written to the standard error either as text,
+/// or as one JSON object per line for log collectors:
+///
+/// ```json
+/// {"timestamp":"2024-03-21T10:00:00.000Z","level":"INFO","target":"dilarxiv","message":"…"}
+/// ```
+///
+/// Progress bars interleave badly with logs in cron jobs and CI runs:
+/// they are hidden with `--quiet` and with the JSON format.
+use clap::{Args, ValueEnum};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget};
+
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether the progress bars are hidden
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Format of the log messages
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// The human readable format of env_logger
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct LogConfig {
+    /// Do not show progress bars
+    #[arg(long, env = "LEGIFRANCE_QUIET", global = true)]
+    pub quiet: bool,
+
+    /// Format of the log messages
+    #[arg(
+        long,
+        value_enum,
+        default_value = "text",
+        env = "LEGIFRANCE_LOG_FORMAT",
+        global = true
+    )]
+    pub log_format: LogFormat,
+}
+
+impl LogConfig {
+    /// Install the logger, and hide the progress bars if requested
+    pub fn init(&self) {
+        let mut builder = env_logger::Builder::from_default_env();
+        if self.log_format == LogFormat::Json {
+            builder.format(|buf, record| {
+                let now = chrono::Utc::now();
+                let line = serde_json::json!({
+                    "timestamp": now.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                    "level": record.level().as_str(),
+                    "target": record.target(),
+                    "message": record.args().to_string(),
+                });
+                writeln!(buf, "{}", line)
+            });
+        }
+        builder.init();
+        set_quiet(self.quiet || self.log_format == LogFormat::Json);
+    }
+}
+
+/// Hide (or show) the progress bars created from now on
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// A progress bar of length `len`, hidden when quiet
+pub fn progress_bar(len: u64) -> ProgressBar {
+    if is_quiet() {
+        ProgressBar::with_draw_target(Some(len), ProgressDrawTarget::hidden())
+    } else {
+        ProgressBar::new(len)
+    }
+}
+
+/// A group of progress bars, hidden when quiet
+pub fn multi_progress() -> MultiProgress {
+    if is_quiet() {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    } else {
+        MultiProgress::new()
+    }
+}
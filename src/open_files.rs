@@ -0,0 +1,192 @@
+/// Limit of the files open at once by the extractions and the indexing.
+///
+/// Large updates extract and parse many files in parallel, which could
+/// exhaust the file descriptors of the process (`RLIMIT_NOFILE`, often
+/// 1024). Every step opening files first takes permits from [`acquire`],
+/// which waits while [`limit`] files are already open. The limit
+/// defaults to half of the soft `RLIMIT_NOFILE`, the other half being
+/// left to the index, the sockets and the logs, and is set by
+/// `--open-files`.
+use log::debug;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, OnceLock};
+
+/// Limit when `RLIMIT_NOFILE` is unknown, half of the lowest usual one
+/// (256 on macOS)
+const FALLBACK_LIMIT: usize = 128;
+
+/// Smallest limit, an extraction needs two files
+const MIN_LIMIT: usize = 2;
+
+/// Largest default limit, when `RLIMIT_NOFILE` is (almost) unlimited
+const MAX_DEFAULT_LIMIT: usize = 65536;
+
+/// The soft limit of open files of the process, if it is known
+pub fn nofile_limit() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        // Limit                     Soft Limit           Hard Limit           Units
+        // Max open files            1024                 524288               files
+        let limits = std::fs::read_to_string("/proc/self/limits").ok()?;
+        let line = limits.lines().find(|line| line.starts_with("Max open files"))?;
+        match line["Max open files".len()..].split_whitespace().next()? {
+            "unlimited" => Some(u64::MAX),
+            soft => soft.parse().ok(),
+        }
+    }
+    #[cfg(all(unix, not(target_os = "linux")))]
+    {
+        let output = std::process::Command::new("sh").args(["-c", "ulimit -n"]).output().ok()?;
+        match String::from_utf8_lossy(&output.stdout).trim() {
+            "unlimited" => Some(u64::MAX),
+            soft => soft.parse().ok(),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        None
+    }
+}
+
+/// The limit used without `--open-files`: half of [`nofile_limit`]
+pub fn default_limit() -> usize {
+    static DEFAULT: OnceLock<usize> = OnceLock::new();
+    *DEFAULT.get_or_init(|| match nofile_limit() {
+        Some(soft) => (soft / 2).min(MAX_DEFAULT_LIMIT as u64) as usize,
+        None => FALLBACK_LIMIT,
+    })
+}
+
+/// A counting semaphore of open files
+#[derive(Debug)]
+pub struct OpenFiles {
+    /// The limit, 0 for [`default_limit`]
+    limit: AtomicUsize,
+    open: Mutex<usize>,
+    closed: Condvar,
+}
+
+/// Permits to keep files open, given back when dropped
+#[derive(Debug)]
+pub struct OpenFilesPermit<'a> {
+    files: &'a OpenFiles,
+    count: usize,
+}
+
+impl OpenFiles {
+    pub const fn new() -> Self {
+        OpenFiles {
+            limit: AtomicUsize::new(0),
+            open: Mutex::new(0),
+            closed: Condvar::new(),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        match self.limit.load(Ordering::Relaxed) {
+            0 => default_limit(),
+            limit => limit,
+        }
+        .max(MIN_LIMIT)
+    }
+
+    /// Set the limit, 0 for [`default_limit`]. The permits already
+    /// taken are kept.
+    pub fn set_limit(&self, limit: usize) {
+        self.limit.store(limit, Ordering::Relaxed);
+        self.closed.notify_all();
+    }
+
+    /// Number of files open with the permits taken
+    pub fn open(&self) -> usize {
+        *self.open.lock().expect("open files lock poisoned")
+    }
+
+    /// Take permits to keep `count` files open at once (at most the
+    /// limit), waiting for other files to be closed if needed. The
+    /// permits of a step are taken at once: taking them one by one
+    /// could wait forever for the permits of the other steps.
+    pub fn acquire(&self, count: usize) -> OpenFilesPermit<'_> {
+        let mut open = self.open.lock().expect("open files lock poisoned");
+        let count = count.min(self.limit());
+        if *open + count > self.limit() {
+            debug!("Waiting for {} of the {} open files to be closed", count, *open);
+        }
+        while *open + count > self.limit() {
+            open = self.closed.wait(open).expect("open files lock poisoned");
+        }
+        *open += count;
+        OpenFilesPermit { files: self, count }
+    }
+}
+
+impl Default for OpenFiles {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for OpenFilesPermit<'_> {
+    fn drop(&mut self) {
+        let mut open = self.files.open.lock().expect("open files lock poisoned");
+        *open -= self.count;
+        self.files.closed.notify_all();
+    }
+}
+
+static OPEN_FILES: OpenFiles = OpenFiles::new();
+
+/// The limit of the files open at once by the process
+pub fn limit() -> usize {
+    OPEN_FILES.limit()
+}
+
+/// Set the limit of the files open at once by the process, 0 for
+/// [`default_limit`]
+pub fn set_limit(limit: usize) {
+    OPEN_FILES.set_limit(limit);
+}
+
+/// Take permits to keep `count` files open (see [`OpenFiles::acquire`])
+/// within the limit of the process
+pub fn acquire(count: usize) -> OpenFilesPermit<'static> {
+    OPEN_FILES.acquire(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_files_limit() {
+        assert!(default_limit() >= MIN_LIMIT);
+        #[cfg(target_os = "linux")]
+        assert!(nofile_limit().is_some());
+
+        let files = OpenFiles::new();
+        assert_eq!(files.limit(), default_limit());
+        files.set_limit(3);
+        let most = std::sync::atomic::AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..20 {
+                        let _permit = files.acquire(2);
+                        most.fetch_max(files.open(), Ordering::SeqCst);
+                        std::thread::yield_now();
+                    }
+                });
+            }
+        });
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+        assert_eq!(files.open(), 0);
+
+        // more files than the limit are opened one step at a time
+        let permit = files.acquire(10);
+        assert_eq!(files.open(), 3);
+        drop(permit);
+        files.set_limit(0);
+        assert_eq!(files.limit(), default_limit());
+    }
+}
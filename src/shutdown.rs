@@ -0,0 +1,57 @@
+/// Graceful interruption of the binaries by Ctrl-C.
+///
+/// The first Ctrl-C only records the interruption: the long running
+/// steps check [`is_interrupted`] to stop accepting new work (new
+/// downloads, files to index, pages to request), finish the work in
+/// progress, and then flush their writers, commit the index and write
+/// the resume ledger as if the run was complete. A second Ctrl-C stops
+/// the process immediately.
+use log::{error, warn};
+use tokio::sync::Notify;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Exit code of a process stopped by SIGINT
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+static NOTIFY: Notify = Notify::const_new();
+
+/// Handle Ctrl-C until the end of the process. Must be called
+/// from the tokio runtime.
+pub fn install() {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            warn!("Unable to handle Ctrl-C, it will stop the process immediately");
+            return;
+        }
+        warn!("Interrupted, finishing the current work (press Ctrl-C again to stop now)");
+        interrupt();
+        if tokio::signal::ctrl_c().await.is_ok() {
+            error!("Interrupted again, stopping now");
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+    });
+}
+
+/// Record an interruption, as a first Ctrl-C does
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    NOTIFY.notify_waiters();
+}
+
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Wait until the process is interrupted
+pub async fn wait() {
+    let notified = NOTIFY.notified();
+    tokio::pin!(notified);
+    notified.as_mut().enable();
+    if is_interrupted() {
+        return;
+    }
+    notified.await;
+}